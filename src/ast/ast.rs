@@ -0,0 +1,318 @@
+use crate::lexer::tokens::TokenKind;
+use crate::source::span::Span;
+use std::fmt;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub stmts: Vec<Stmt>,
+    pub span: Span,
+}
+
+/* `def name(params) -> ret body`. Without a return type the function
+ * returns `()` and `ret_span` is the empty span after the parameters. */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Function {
+    pub name: Ident,
+    pub params: Vec<Param>,
+    pub ret: Option<Type>,
+    pub ret_span: Span,
+    pub body: Block,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Param {
+    pub name: Ident,
+    pub ty: Type,
+    pub ty_span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Type {
+    Int,
+    Bool,
+    Str,
+    /* `[int]` */
+    List(Box<Type>),
+}
+
+/* A block of statements, optionally ending in an expression whose value
+ * becomes the value of the block */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub tail: Option<Box<Expr>>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Stmt {
+    Let {
+        name: Ident,
+        ty: Type,
+        ty_span: Span,
+        value: Expr,
+        span: Span,
+    },
+    Assign {
+        target: Ident,
+        value: Expr,
+        span: Span,
+    },
+    /* `list[index] = value` */
+    IndexAssign {
+        list: Expr,
+        index: Expr,
+        value: Expr,
+        span: Span,
+    },
+    While {
+        cond: Expr,
+        body: Block,
+        span: Span,
+    },
+    /* `for var in iter body`, `var` is only in scope inside `body` */
+    For {
+        var: Ident,
+        iter: Expr,
+        body: Block,
+        span: Span,
+    },
+    Return {
+        value: Option<Expr>,
+        span: Span,
+    },
+    Block(Block),
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expr {
+    Binary {
+        op: BinOp,
+        op_span: Span,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        span: Span,
+    },
+    Unary {
+        op: UnOp,
+        op_span: Span,
+        operand: Box<Expr>,
+        span: Span,
+    },
+    If {
+        cond: Box<Expr>,
+        then_branch: Block,
+        else_branch: Option<Box<Expr>>,
+        span: Span,
+    },
+    Call {
+        callee: Ident,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    Index {
+        list: Box<Expr>,
+        index: Box<Expr>,
+        span: Span,
+    },
+    List {
+        elements: Vec<Expr>,
+        span: Span,
+    },
+    /* `"x = {x}"`, the string is the values of the parts one after the
+     * other, the text between interpolations being string literals */
+    Template {
+        parts: Vec<Expr>,
+        span: Span,
+    },
+    /* `start..end`, only written as the iterable of a for loop or as
+     * the index of a slice */
+    Range {
+        start: Box<Expr>,
+        end: Box<Expr>,
+        span: Span,
+    },
+    Block(Block),
+    Literal {
+        value: Literal,
+        span: Span,
+    },
+    Ident(Ident),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Literal {
+    Int(i64),
+    Bool(bool),
+    Str(String),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum UnOp {
+    Not,
+    BitNot,
+    Neg,
+    Plus,
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Let { span, .. }
+            | Stmt::Assign { span, .. }
+            | Stmt::IndexAssign { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::For { span, .. }
+            | Stmt::Return { span, .. } => *span,
+            Stmt::Block(block) => block.span,
+            Stmt::Expr(expr) => expr.span(),
+        }
+    }
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Binary { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::If { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::List { span, .. }
+            | Expr::Template { span, .. }
+            | Expr::Range { span, .. }
+            | Expr::Literal { span, .. } => *span,
+            Expr::Block(block) => block.span,
+            Expr::Ident(ident) => ident.span,
+        }
+    }
+}
+
+impl Type {
+    pub fn from_token(kind: TokenKind) -> Option<Type> {
+        match kind {
+            TokenKind::INT => Some(Type::Int),
+            TokenKind::BOOL => Some(Type::Bool),
+            TokenKind::STR => Some(Type::Str),
+            _ => None,
+        }
+    }
+}
+
+impl BinOp {
+    pub fn from_token(kind: TokenKind) -> Option<BinOp> {
+        match kind {
+            TokenKind::BOOL_OR => Some(BinOp::Or),
+            TokenKind::BOOL_AND => Some(BinOp::And),
+            TokenKind::EQ => Some(BinOp::Eq),
+            TokenKind::NE => Some(BinOp::Ne),
+            TokenKind::LT => Some(BinOp::Lt),
+            TokenKind::LE => Some(BinOp::Le),
+            TokenKind::GT => Some(BinOp::Gt),
+            TokenKind::GE => Some(BinOp::Ge),
+            TokenKind::BIT_OR => Some(BinOp::BitOr),
+            TokenKind::BIT_XOR => Some(BinOp::BitXor),
+            TokenKind::BIT_AND => Some(BinOp::BitAnd),
+            TokenKind::BIT_LEFT => Some(BinOp::Shl),
+            TokenKind::BIT_RIGHT => Some(BinOp::Shr),
+            TokenKind::PLUS => Some(BinOp::Add),
+            TokenKind::MINUS => Some(BinOp::Sub),
+            TokenKind::MULTIPLY => Some(BinOp::Mul),
+            TokenKind::DIVIDE => Some(BinOp::Div),
+            TokenKind::MODULUS => Some(BinOp::Mod),
+            _ => None,
+        }
+    }
+}
+
+impl UnOp {
+    pub fn from_token(kind: TokenKind) -> Option<UnOp> {
+        match kind {
+            TokenKind::BOOL_NOT => Some(UnOp::Not),
+            TokenKind::BIT_NOT => Some(UnOp::BitNot),
+            TokenKind::MINUS => Some(UnOp::Neg),
+            TokenKind::PLUS => Some(UnOp::Plus),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "str"),
+            Type::List(element) => write!(f, "[{}]", element),
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            BinOp::Or => "||",
+            BinOp::And => "&&",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::BitAnd => "&",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for UnOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            UnOp::Not => "!",
+            UnOp::BitNot => "~",
+            UnOp::Neg => "-",
+            UnOp::Plus => "+",
+        };
+        write!(f, "{}", symbol)
+    }
+}
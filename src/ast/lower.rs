@@ -0,0 +1,554 @@
+use super::ast::{BinOp, Block, Expr, Function, Ident, Literal, Param, Program, Stmt, Type, UnOp};
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::lexer::strings::unescape;
+use crate::lexer::tokens::{Token, TokenKind};
+use crate::parser::node::{Node, NodeKind, NodeType};
+use crate::source::span::Span;
+
+/* Lowers a parse tree from either the packrat or the Pratt expression
+ * parser into the typed AST. Error nodes left behind by error recovery are
+ * skipped, they have already been reported by the parser. */
+pub fn lower(tree: &Node) -> Result<Program, Vec<Diagnostic>> {
+    let mut lowerer = Lowerer { diagnostics: vec![] };
+    let program = lowerer.program(tree);
+    if lowerer.diagnostics.is_empty() {
+        Ok(program)
+    } else {
+        Err(lowerer.diagnostics)
+    }
+}
+
+struct Lowerer {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Lowerer {
+    fn program(&mut self, node: &Node) -> Program {
+        let mut program = Program {
+            functions: vec![],
+            stmts: vec![],
+            span: node.span(),
+        };
+        for child in node.children() {
+            if !is_token(child, TokenKind::EOF) {
+                self.items(child, &mut program);
+            }
+        }
+        program
+    }
+
+    /* Flattens the right-nested items rule */
+    fn items(&mut self, node: &Node, program: &mut Program) {
+        match cons(node) {
+            Some(NodeKind::Items) => {
+                for child in node.children() {
+                    self.items(child, program);
+                }
+            }
+            Some(NodeKind::FunctionDef) => program.functions.push(self.function(node)),
+            _ => self.statements(node, &mut program.stmts),
+        }
+    }
+
+    /* 'def' ID '(' parameters? ')' ('->' datatype)? block */
+    fn function(&mut self, node: &Node) -> Function {
+        let children = node.children();
+        let mut params = vec![];
+        let mut next = 3;
+        if cons(&children[next]).is_some() {
+            self.parameters(&children[next], &mut params);
+            next += 1;
+        }
+        let rparen = token(&children[next]);
+        let (ret, ret_span) = match children.get(next + 1) {
+            Some(arrow) if is_token(arrow, TokenKind::ARROW) => {
+                let datatype = &children[next + 2];
+                (Some(datatype_of(datatype)), datatype.span())
+            }
+            _ => (None, Span::point(rparen.span.file, rparen.span.end)),
+        };
+        Function {
+            name: ident(token(&children[1])),
+            params,
+            ret,
+            ret_span,
+            body: self.block(children.last().unwrap_or_else(|| malformed(node))),
+            span: node.span(),
+        }
+    }
+
+    /* Flattens the right-nested parameters rule */
+    fn parameters(&mut self, node: &Node, params: &mut Vec<Param>) {
+        let children = node.children();
+        match cons(node) {
+            Some(NodeKind::Parameters) => {
+                self.parameters(&children[0], params);
+                self.parameters(&children[2], params);
+            }
+            /* ID ':' datatype */
+            Some(NodeKind::Parameter) => params.push(Param {
+                name: ident(token(&children[0])),
+                ty: datatype_of(&children[2]),
+                ty_span: children[2].span(),
+            }),
+            _ => malformed(node),
+        }
+    }
+
+    /* Flattens the right-nested statements rule */
+    fn statements(&mut self, node: &Node, stmts: &mut Vec<Stmt>) {
+        match cons(node) {
+            Some(NodeKind::Statements) => {
+                for child in node.children() {
+                    self.statements(child, stmts);
+                }
+            }
+            Some(NodeKind::Error) => {}
+            _ => stmts.push(self.statement(node)),
+        }
+    }
+
+    fn statement(&mut self, node: &Node) -> Stmt {
+        match cons(node) {
+            Some(NodeKind::Statement) => {
+                let inner = &node.children()[0];
+                match cons(inner) {
+                    Some(NodeKind::Declaration) => self.declaration(inner, node),
+                    Some(NodeKind::Assignment) => self.assignment(inner, node),
+                    _ => Stmt::Expr(self.expr(inner)),
+                }
+            }
+            /* 'return' expression? ';' */
+            Some(NodeKind::ReturnStmt) => {
+                let children = node.children();
+                Stmt::Return {
+                    value: (children.len() == 3).then(|| self.expr(&children[1])),
+                    span: node.span(),
+                }
+            }
+            Some(NodeKind::IfStmt) => Stmt::Expr(self.if_expr(node)),
+            Some(NodeKind::WhileStmt) => {
+                let children = node.children();
+                Stmt::While {
+                    cond: self.expr(&children[1]),
+                    body: self.block(&children[2]),
+                    span: node.span(),
+                }
+            }
+            /* 'for' ID 'in' (range | expression) block */
+            Some(NodeKind::ForStmt) => {
+                let children = node.children();
+                Stmt::For {
+                    var: ident(token(&children[1])),
+                    iter: self.expr(&children[3]),
+                    body: self.block(&children[4]),
+                    span: node.span(),
+                }
+            }
+            Some(NodeKind::Block | NodeKind::BlockExpr) => Stmt::Block(self.block(node)),
+            _ => malformed(node),
+        }
+    }
+
+    /* 'let' ID ':' datatype '=' expression, `statement` includes the ';' */
+    fn declaration(&mut self, node: &Node, statement: &Node) -> Stmt {
+        let children = node.children();
+        Stmt::Let {
+            name: ident(token(&children[1])),
+            ty: datatype_of(&children[3]),
+            ty_span: children[3].span(),
+            value: self.expr(&children[5]),
+            span: statement.span(),
+        }
+    }
+
+    /* index '=' expression, where the index is an ID or `list[index]` */
+    fn assignment(&mut self, node: &Node, statement: &Node) -> Stmt {
+        let children = node.children();
+        let target = &children[0];
+        let value = self.expr(&children[2]);
+        let span = statement.span();
+        match (target.kind(), target.children()) {
+            (NodeType::Atom(tok), []) if tok.kind == TokenKind::ID => Stmt::Assign {
+                target: ident(tok),
+                value,
+                span,
+            },
+            (NodeType::Cons(NodeKind::Index), [list, _, index, _]) => Stmt::IndexAssign {
+                list: self.expr(list),
+                index: self.expr(index),
+                value,
+                span,
+            },
+            _ => {
+                self.diagnostics.push(
+                    Diagnostic::error("invalid left-hand side of assignment", target.span())
+                        .with_code("E0019")
+                        .with_label(target.span(), "cannot assign to this")
+                        .with_help("only variables and list elements can be assigned to"),
+                );
+                Stmt::Expr(value)
+            }
+        }
+    }
+
+    fn block(&mut self, node: &Node) -> Block {
+        let children = node.children();
+        let mut stmts = vec![];
+        let mut tail = None;
+        match (cons(node), children.len()) {
+            /* '{' '}' */
+            (Some(NodeKind::Block), 2) => {}
+            /* '{' statements '}' */
+            (Some(NodeKind::Block), 3) => self.statements(&children[1], &mut stmts),
+            /* '{' expression '}' */
+            (Some(NodeKind::BlockExpr), 3) => tail = Some(Box::new(self.expr(&children[1]))),
+            /* '{' statements expression '}' */
+            (Some(NodeKind::BlockExpr), 4) => {
+                self.statements(&children[1], &mut stmts);
+                tail = Some(Box::new(self.expr(&children[2])));
+            }
+            _ => malformed(node),
+        }
+        /* `{ a = 1; if b { 1 } else { 2 } }` parses the if as a statement,
+         * it is still the value of the block */
+        let trailing_if = matches!(
+            stmts.last(),
+            Some(Stmt::Expr(Expr::If {
+                else_branch: Some(_),
+                ..
+            }))
+        );
+        if tail.is_none() && trailing_if {
+            if let Some(Stmt::Expr(expr)) = stmts.pop() {
+                tail = Some(Box::new(expr));
+            }
+        }
+        Block {
+            stmts,
+            tail,
+            span: node.span(),
+        }
+    }
+
+    /* 'if' expression block else_stmt? */
+    fn if_expr(&mut self, node: &Node) -> Expr {
+        let children = node.children();
+        let else_branch = children.get(3).map(|else_stmt| {
+            let branch = &else_stmt.children()[1];
+            Box::new(match cons(branch) {
+                Some(NodeKind::IfStmt) => self.if_expr(branch),
+                _ => Expr::Block(self.block(branch)),
+            })
+        });
+        Expr::If {
+            cond: Box::new(self.expr(&children[1])),
+            then_branch: self.block(&children[2]),
+            else_branch,
+            span: node.span(),
+        }
+    }
+
+    fn expr(&mut self, node: &Node) -> Expr {
+        let children = node.children();
+        match node.kind() {
+            NodeType::Atom(tok) => match children {
+                [] => self.atom(tok),
+                /* Operators from the Pratt parser keep their operands as children */
+                [operand] => self.unary(tok, operand, node),
+                [lhs, rhs] => self.binary(lhs, tok, rhs, node),
+                _ => malformed(node),
+            },
+            NodeType::Cons(kind) => match (kind, children) {
+                (NodeKind::Primary, [_, inner, _]) => self.expr(inner),
+                (NodeKind::Block | NodeKind::BlockExpr, _) => Expr::Block(self.block(node)),
+                (NodeKind::IfStmt, _) => self.if_expr(node),
+                (NodeKind::Call, [callee, _, rest @ ..]) => self.call(callee, rest, node),
+                (NodeKind::Index, [list, _, index, _]) => Expr::Index {
+                    list: Box::new(self.expr(list)),
+                    index: Box::new(self.expr(index)),
+                    span: node.span(),
+                },
+                (NodeKind::Template, parts) => self.template(parts, node),
+                (NodeKind::List, [_, rest @ ..]) => {
+                    let mut elements = vec![];
+                    if let [elements_node, _] = rest {
+                        self.elements(elements_node, &mut elements);
+                    }
+                    Expr::List {
+                        elements,
+                        span: node.span(),
+                    }
+                }
+                (NodeKind::Range, [start, _, end]) => Expr::Range {
+                    start: Box::new(self.expr(start)),
+                    end: Box::new(self.expr(end)),
+                    span: node.span(),
+                },
+                (NodeKind::LogicNot | NodeKind::Factor, [op, operand]) => {
+                    self.unary(token(op), operand, node)
+                }
+                (
+                    NodeKind::LogicOr
+                    | NodeKind::LogicAnd
+                    | NodeKind::Comparison
+                    | NodeKind::BitwiseOr
+                    | NodeKind::BitwiseXor
+                    | NodeKind::BitwiseAnd
+                    | NodeKind::BitwiseShift
+                    | NodeKind::Sum
+                    | NodeKind::Term,
+                    [lhs, op, rhs],
+                ) => self.binary(lhs, token(op), rhs, node),
+                _ => malformed(node),
+            },
+        }
+    }
+
+    /* call '(' arguments? ')' */
+    fn call(&mut self, callee: &Node, rest: &[Node], node: &Node) -> Expr {
+        let mut args = vec![];
+        if let [arguments, _] = rest {
+            self.arguments(arguments, &mut args);
+        }
+        let callee = match callee.kind() {
+            NodeType::Atom(tok) if tok.kind == TokenKind::ID && callee.children().is_empty() => {
+                ident(tok)
+            }
+            _ => {
+                self.diagnostics.push(
+                    Diagnostic::error("only functions can be called", callee.span())
+                        .with_code("E0012")
+                        .with_label(callee.span(), "this is not the name of a function"),
+                );
+                Ident {
+                    name: String::new(),
+                    span: callee.span(),
+                }
+            }
+        };
+        Expr::Call {
+            callee,
+            args,
+            span: node.span(),
+        }
+    }
+
+    /* Flattens the right-nested arguments rule */
+    fn arguments(&mut self, node: &Node, args: &mut Vec<Expr>) {
+        match (cons(node), node.children()) {
+            (Some(NodeKind::Arguments), [first, _, rest]) => {
+                args.push(self.expr(first));
+                self.arguments(rest, args);
+            }
+            _ => args.push(self.expr(node)),
+        }
+    }
+
+    /* STRING_PART tokens alternating with interpolated expressions.
+     * Interpolations that did not parse were reported by the parser. */
+    fn template(&mut self, parts: &[Node], node: &Node) -> Expr {
+        let mut exprs = vec![];
+        for part in parts {
+            match (part.kind(), part.children()) {
+                (NodeType::Atom(tok), []) if tok.kind == TokenKind::STRING_PART => {
+                    let value = unescape(tok).0;
+                    if !value.is_empty() {
+                        exprs.push(Expr::Literal {
+                            value: Literal::Str(value),
+                            span: tok.span,
+                        });
+                    }
+                }
+                (NodeType::Cons(NodeKind::Error), _) => {}
+                _ => exprs.push(self.expr(part)),
+            }
+        }
+        Expr::Template {
+            parts: exprs,
+            span: node.span(),
+        }
+    }
+
+    /* Flattens the right-nested elements rule */
+    fn elements(&mut self, node: &Node, elements: &mut Vec<Expr>) {
+        match (cons(node), node.children()) {
+            (Some(NodeKind::Elements), [first, _, rest]) => {
+                elements.push(self.expr(first));
+                self.elements(rest, elements);
+            }
+            _ => elements.push(self.expr(node)),
+        }
+    }
+
+    fn unary(&mut self, op: &Token, operand: &Node, node: &Node) -> Expr {
+        Expr::Unary {
+            op: UnOp::from_token(op.kind).unwrap_or_else(|| malformed(node)),
+            op_span: op.span,
+            operand: Box::new(self.expr(operand)),
+            span: node.span(),
+        }
+    }
+
+    fn binary(&mut self, lhs: &Node, op: &Token, rhs: &Node, node: &Node) -> Expr {
+        Expr::Binary {
+            op: BinOp::from_token(op.kind).unwrap_or_else(|| malformed(node)),
+            op_span: op.span,
+            lhs: Box::new(self.expr(lhs)),
+            rhs: Box::new(self.expr(rhs)),
+            span: node.span(),
+        }
+    }
+
+    fn atom(&mut self, tok: &Token) -> Expr {
+        let lexeme = tok.lexeme.as_deref().unwrap_or_default();
+        let value = match tok.kind {
+            TokenKind::ID => return Expr::Ident(ident(tok)),
+            TokenKind::TRUE => Literal::Bool(true),
+            TokenKind::FALSE => Literal::Bool(false),
+            TokenKind::STRING => Literal::Str(unescape(tok).0),
+            TokenKind::NUMBER => match lexeme.parse::<i64>() {
+                Ok(value) => Literal::Int(value),
+                Err(_) => {
+                    self.diagnostics.push(
+                        Diagnostic::error("integer literal is too large", tok.span)
+                            .with_code("E0004")
+                            .with_label(tok.span, "does not fit into an `int`")
+                            .with_note(format!("the largest `int` is {}", i64::MAX)),
+                    );
+                    Literal::Int(0)
+                }
+            },
+            _ => panic!("unexpected {:?} token in expression", tok.kind),
+        };
+        Expr::Literal {
+            value,
+            span: tok.span,
+        }
+    }
+}
+
+/* INT | BOOL | STR | '[' datatype ']' */
+fn datatype_of(node: &Node) -> Type {
+    match (node.kind(), node.children()) {
+        (NodeType::Atom(tok), []) => Type::from_token(tok.kind).unwrap_or_else(|| malformed(node)),
+        (NodeType::Cons(NodeKind::DataType), [_, inner, _]) => {
+            Type::List(Box::new(datatype_of(inner)))
+        }
+        _ => malformed(node),
+    }
+}
+
+fn cons(node: &Node) -> Option<NodeKind> {
+    match node.kind() {
+        NodeType::Cons(kind) => Some(*kind),
+        NodeType::Atom(_) => None,
+    }
+}
+
+fn token(node: &Node) -> &Token {
+    match node.kind() {
+        NodeType::Atom(tok) => tok,
+        NodeType::Cons(_) => malformed(node),
+    }
+}
+
+fn is_token(node: &Node, kind: TokenKind) -> bool {
+    matches!(node.kind(), NodeType::Atom(tok) if tok.kind == kind)
+}
+
+fn ident(tok: &Token) -> Ident {
+    Ident {
+        name: tok.lexeme.clone().unwrap_or_default(),
+        span: tok.span,
+    }
+}
+
+/* The parser only produces the shapes handled above, anything else is a bug */
+fn malformed(node: &Node) -> ! {
+    panic!("malformed parse tree: {}", node)
+}
+
+#[cfg(test)]
+const SAMPLE: &str = "let a: int = -(1 + 2) * 3 % 4 << 1;
+    let b: bool = !(a >= 1) && a != 2 || true;
+    let s: str = \"abc\";
+    while a < 10 { a = a + 1; }
+    { let c: int = { a } ; }
+    let d: int = if b { 1 } else if !b { let e: int = 2; e } else { ~3 };
+    def f(x: int, y: str) -> int { return g(x) * -f(1, \"a\"); }
+    def g() {}
+    print(f(1, s) + 2);
+    for i in a - 1..f(2, s) * 2 { for c in s { print(c); } }
+    let xs: [[int]] = [[], [1, -a], f(1, s)[0]];
+    xs[0][1] = -xs[a][len(xs)] * 2;
+    print(\"\\\"{s[1..a + 1]}\\t{ {f(a, \"}\")} }\\u{e9}\" + \"\");";
+
+#[test]
+fn packrat_and_pratt_lower_to_the_same_ast() {
+    use crate::testing::pipeline::parse;
+
+    let program = lower(&parse(SAMPLE, false)).unwrap();
+    assert_eq!(program, lower(&parse(SAMPLE, true)).unwrap());
+    assert_eq!(program.stmts.len(), 11);
+}
+
+#[test]
+fn keeps_functions_apart_from_statements() {
+    let program = crate::testing::pipeline::program(SAMPLE);
+    assert_eq!(program.functions.len(), 2);
+    assert_eq!(program.functions[0].params.len(), 2);
+    assert_eq!(program.functions[1].ret, None);
+}
+
+#[test]
+fn nests_operators_by_precedence() {
+    let program = crate::testing::pipeline::program(SAMPLE);
+    let Stmt::Let { value, .. } = &program.stmts[0] else { panic!() };
+    let Expr::Binary { op: BinOp::Shl, lhs, .. } = value else { panic!() };
+    let Expr::Binary { op: BinOp::Mod, lhs, .. } = &**lhs else { panic!() };
+    let Expr::Binary { op: BinOp::Mul, lhs, .. } = &**lhs else { panic!() };
+    assert!(matches!(&**lhs, Expr::Unary { op: UnOp::Neg, .. }));
+}
+
+#[test]
+fn lowers_for_loops_over_ranges() {
+    let program = crate::testing::pipeline::program(SAMPLE);
+    let Stmt::For { iter, body, .. } = &program.stmts[7] else { panic!() };
+    let Expr::Range { start, end, .. } = iter else { panic!() };
+    assert!(matches!(**start, Expr::Binary { op: BinOp::Sub, .. }));
+    assert!(matches!(**end, Expr::Binary { op: BinOp::Mul, .. }));
+    assert!(matches!(body.stmts[..], [Stmt::For { iter: Expr::Ident(_), .. }]));
+}
+
+#[test]
+fn lowers_lists_and_index_assignment() {
+    let program = crate::testing::pipeline::program(SAMPLE);
+    let Stmt::Let { ty, value, .. } = &program.stmts[8] else { panic!() };
+    assert_eq!(ty.to_string(), "[[int]]");
+    assert!(matches!(value, Expr::List { elements, .. } if elements.len() == 3));
+    let Stmt::IndexAssign { list, value, .. } = &program.stmts[9] else { panic!() };
+    assert!(matches!(list, Expr::Index { .. }));
+    let Expr::Binary { lhs, .. } = value else { panic!() };
+    assert!(matches!(&**lhs, Expr::Unary { operand, .. } if matches!(**operand, Expr::Index { .. })));
+}
+
+#[test]
+fn lowers_templates_into_parts() {
+    let program = crate::testing::pipeline::program(SAMPLE);
+    let Stmt::Expr(Expr::Call { args, .. }) = &program.stmts[10] else { panic!() };
+    let Expr::Binary { lhs, rhs, .. } = &args[0] else { panic!() };
+    let Expr::Template { parts, .. } = &**lhs else { panic!() };
+    let text = |expr: &Expr| match expr {
+        Expr::Literal { value: Literal::Str(value), .. } => value.clone(),
+        _ => panic!(),
+    };
+    assert_eq!(parts.len(), 5);
+    assert_eq!(
+        (text(&parts[0]), text(&parts[2]), text(&parts[4])),
+        ("\"".into(), "\t".into(), "é".into())
+    );
+    assert!(matches!(&parts[1], Expr::Index { index, .. } if matches!(**index, Expr::Range { .. })));
+    assert!(matches!(&parts[3], Expr::Block(_)));
+    assert_eq!(text(rhs), "");
+}
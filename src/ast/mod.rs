@@ -0,0 +1,3 @@
+#[allow(clippy::module_inception, reason = "the tree types, next to their lowering from the parse tree")]
+pub mod ast;
+pub mod lower;
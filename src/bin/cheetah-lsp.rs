@@ -0,0 +1,44 @@
+use cheetah::interp::interp::on_large_stack;
+use cheetah::lsp::server::{self, Server};
+use std::{env, io, process};
+
+const USAGE: &str = "usage: cheetah-lsp [--stdio] [--parser packrat|pratt]";
+
+fn main() {
+    let mut pratt = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            /* Editors pass this to say how to talk, which is the only way */
+            "--stdio" => {}
+            "--parser" => match args.next().as_deref() {
+                Some("packrat") => pratt = false,
+                Some("pratt") => pratt = true,
+                _ => usage(),
+            },
+            _ => usage(),
+        }
+    }
+
+    /* Checking a document recurses as deep as it nests */
+    let code = on_large_stack(|| {
+        let mut server = Server::new(pratt);
+        server::run(
+            &mut server,
+            &mut io::stdin().lock(),
+            &mut io::stdout().lock(),
+        )
+    });
+    match code {
+        Ok(code) => process::exit(code),
+        Err(error) => {
+            eprintln!("cheetah-lsp: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,188 @@
+use crate::interp::value::Value;
+use crate::source::span::Span;
+
+/* The instructions of the stack machine. Each is a byte, followed by a u16
+ * operand for the ones that index something or count values, or by an i32
+ * offset for jumps, counted from the end of the jump. Operands are little
+ * endian. */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+#[allow(clippy::upper_case_acronyms, non_camel_case_types)]
+pub enum Op {
+    /* CONSTANT i pushes constant i of the chunk */
+    CONSTANT,
+    UNIT,
+    TRUE,
+    FALSE,
+    POP,
+    /* POP_N n pops n values */
+    POP_N,
+    /* SLIDE n keeps the top value and pops the n below it, which is how a
+     * block leaves its value once its locals go out of scope */
+    SLIDE,
+    /* Locals are slots counted from the bottom of the frame */
+    GET_LOCAL,
+    SET_LOCAL,
+    GET_GLOBAL,
+    SET_GLOBAL,
+
+    ADD,
+    SUB,
+    MUL,
+    DIV,
+    MOD,
+    BIT_AND,
+    BIT_OR,
+    BIT_XOR,
+    SHL,
+    SHR,
+    EQ,
+    NE,
+    LT,
+    LE,
+    GT,
+    GE,
+    NOT,
+    NEG,
+    BIT_NOT,
+
+    JUMP,
+    /* Pops the condition and jumps if it is false */
+    JUMP_IF_FALSE,
+    /* CALL f calls function f with its arguments on the stack */
+    CALL,
+    RETURN,
+    PRINT,
+    LEN,
+
+    /* LIST n pops n values into a list, TEMPLATE n into a string */
+    LIST,
+    TEMPLATE,
+    /* list index -> element */
+    INDEX,
+    /* list start end -> slice */
+    SLICE,
+    /* value list index -> */
+    SET_INDEX,
+    /* Replaces a string or list with a list of what a `for` loop visits */
+    ITER,
+}
+
+impl Op {
+    pub const ALL: &'static [Op] = &[
+        Op::CONSTANT,
+        Op::UNIT,
+        Op::TRUE,
+        Op::FALSE,
+        Op::POP,
+        Op::POP_N,
+        Op::SLIDE,
+        Op::GET_LOCAL,
+        Op::SET_LOCAL,
+        Op::GET_GLOBAL,
+        Op::SET_GLOBAL,
+        Op::ADD,
+        Op::SUB,
+        Op::MUL,
+        Op::DIV,
+        Op::MOD,
+        Op::BIT_AND,
+        Op::BIT_OR,
+        Op::BIT_XOR,
+        Op::SHL,
+        Op::SHR,
+        Op::EQ,
+        Op::NE,
+        Op::LT,
+        Op::LE,
+        Op::GT,
+        Op::GE,
+        Op::NOT,
+        Op::NEG,
+        Op::BIT_NOT,
+        Op::JUMP,
+        Op::JUMP_IF_FALSE,
+        Op::CALL,
+        Op::RETURN,
+        Op::PRINT,
+        Op::LEN,
+        Op::LIST,
+        Op::TEMPLATE,
+        Op::INDEX,
+        Op::SLICE,
+        Op::SET_INDEX,
+        Op::ITER,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<Op> {
+        Op::ALL.get(byte as usize).copied()
+    }
+
+    /* The number of bytes of operand after the opcode */
+    pub fn operand_size(self) -> usize {
+        match self {
+            Op::CONSTANT
+            | Op::POP_N
+            | Op::SLIDE
+            | Op::GET_LOCAL
+            | Op::SET_LOCAL
+            | Op::GET_GLOBAL
+            | Op::SET_GLOBAL
+            | Op::CALL
+            | Op::LIST
+            | Op::TEMPLATE => 2,
+            Op::JUMP | Op::JUMP_IF_FALSE => 4,
+            _ => 0,
+        }
+    }
+}
+
+/* The code of one function. Instructions that can fail have the spans to
+ * report the error at, the whole expression and the operand to blame,
+ * kept apart from the code in order of their offsets. */
+#[derive(Clone, Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    pub spans: Vec<(usize, Span, Span)>,
+}
+
+impl Chunk {
+    pub fn u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    pub fn i32(&self, offset: usize) -> i32 {
+        let bytes = self.code[offset..offset + 4]
+            .try_into()
+            .expect("four bytes");
+        i32::from_le_bytes(bytes)
+    }
+
+    /* The spans of the instruction at `offset` */
+    pub fn spans(&self, offset: usize) -> (Span, Span) {
+        match self.spans.binary_search_by_key(&offset, |(at, _, _)| *at) {
+            Ok(i) => (self.spans[i].1, self.spans[i].2),
+            Err(_) => (Span::default(), Span::default()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub chunk: Chunk,
+}
+
+/* A compiled program. Function 0 is the script itself, the statements
+ * outside of any function, and `globals` names the slots of its `let`s. */
+#[derive(Clone, Debug)]
+pub struct Module {
+    pub functions: Vec<Function>,
+    pub globals: Vec<String>,
+}
+
+impl Module {
+    pub const SCRIPT: usize = 0;
+}
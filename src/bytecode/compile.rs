@@ -0,0 +1,555 @@
+use super::bytecode::{Chunk, Function, Module, Op};
+use crate::ast::ast::{BinOp, Block, Expr, Literal, Program, Stmt, UnOp};
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::interp::value::Value;
+use crate::sema::builtins::Builtin;
+use crate::source::span::Span;
+use std::collections::HashMap;
+
+/* Compiles a checked program. Programs that have not been through the
+ * checker may make it panic. Fails only for functions too big for the
+ * operands of the instruction set. */
+pub fn compile(program: &Program) -> Result<Module, Vec<Diagnostic>> {
+    let mut compiler = Compiler {
+        functions: HashMap::new(),
+        arities: vec![0],
+        globals: vec![],
+        frame: Frame::new("<script>", program.span),
+        diagnostics: vec![],
+    };
+    for (i, function) in program.functions.iter().enumerate() {
+        compiler
+            .functions
+            .insert(function.name.name.clone(), Module::SCRIPT + 1 + i);
+        compiler.arities.push(function.params.len());
+    }
+
+    let mut functions = vec![compiler.script(program)];
+    for function in &program.functions {
+        compiler.frame = Frame::new(&function.name.name, function.span);
+        for param in &function.params {
+            compiler.frame.height += 1;
+            compiler.declare(&param.name.name);
+        }
+        compiler.block(&function.body);
+        compiler.emit(Op::RETURN);
+        functions.push(compiler.finish(function.params.len()));
+    }
+
+    match compiler.diagnostics.is_empty() {
+        true => Ok(Module {
+            functions,
+            globals: compiler.globals,
+        }),
+        false => Err(compiler.diagnostics),
+    }
+}
+
+/* A name on the stack, in the slot counted from the bottom of the frame */
+struct Local {
+    name: String,
+    slot: usize,
+    depth: usize,
+}
+
+/* The function being compiled. `height` is how many values its frame has
+ * on the stack at the current instruction, locals and temporaries alike,
+ * which is where the value of the next `let` will be. */
+struct Frame {
+    name: String,
+    span: Span,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    depth: usize,
+    height: usize,
+    too_large: bool,
+}
+
+impl Frame {
+    fn new(name: &str, span: Span) -> Frame {
+        Frame {
+            name: name.to_string(),
+            span,
+            chunk: Chunk::default(),
+            locals: vec![],
+            depth: 0,
+            height: 0,
+            too_large: false,
+        }
+    }
+}
+
+struct Compiler {
+    functions: HashMap<String, usize>,
+    arities: Vec<usize>,
+    globals: Vec<String>,
+    frame: Frame,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Compiler {
+    /* The statements outside of functions, whose value is the last one if
+     * it is an expression or a block */
+    fn script(&mut self, program: &Program) -> Function {
+        let mut value = false;
+        for (i, stmt) in program.stmts.iter().enumerate() {
+            match stmt {
+                Stmt::Expr(expr) if i + 1 == program.stmts.len() => self.expr(expr),
+                Stmt::Block(block) if i + 1 == program.stmts.len() => self.block(block),
+                stmt => {
+                    self.stmt(stmt);
+                    continue;
+                }
+            }
+            value = true;
+        }
+        if !value {
+            self.emit(Op::UNIT);
+        }
+        self.emit(Op::RETURN);
+        self.finish(0)
+    }
+
+    fn finish(&mut self, arity: usize) -> Function {
+        let frame = std::mem::replace(&mut self.frame, Frame::new("", Span::default()));
+        Function {
+            name: frame.name,
+            arity,
+            chunk: frame.chunk,
+        }
+    }
+
+    /* Statements leave the stack as they found it, apart from `let`, which
+     * leaves its value as a local */
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let { name, value, .. } => {
+                self.expr(value);
+                if self.frame.name == "<script>" && self.frame.depth == 0 {
+                    let slot = self.global(&name.name);
+                    self.emit_u16(Op::SET_GLOBAL, slot);
+                } else {
+                    self.declare(&name.name);
+                }
+            }
+            Stmt::Assign { target, value, .. } => {
+                self.expr(value);
+                match self.local(&target.name) {
+                    Some(slot) => self.emit_u16(Op::SET_LOCAL, slot),
+                    None => {
+                        let slot = self.global(&target.name);
+                        self.emit_u16(Op::SET_GLOBAL, slot);
+                    }
+                }
+            }
+            Stmt::IndexAssign {
+                list, index, value, ..
+            } => {
+                self.expr(value);
+                self.expr(list);
+                self.expr(index);
+                self.emit_spanned(Op::SET_INDEX, index.span(), index.span());
+            }
+            Stmt::While { cond, body, .. } => {
+                let top = self.frame.chunk.code.len();
+                self.expr(cond);
+                let exit = self.jump(Op::JUMP_IF_FALSE);
+                self.block(body);
+                self.emit(Op::POP);
+                self.jump_back(top);
+                self.patch(exit);
+            }
+            Stmt::For {
+                var, iter, body, ..
+            } => self.for_loop(&var.name, iter, body),
+            Stmt::Return { value, .. } => {
+                match value {
+                    Some(value) => self.expr(value),
+                    None => self.emit(Op::UNIT),
+                }
+                self.emit(Op::RETURN);
+            }
+            Stmt::Block(block) => {
+                self.block(block);
+                self.emit(Op::POP);
+            }
+            Stmt::Expr(expr) => {
+                self.expr(expr);
+                self.emit(Op::POP);
+            }
+        }
+    }
+
+    /* A range is counted through, anything else is turned into a list of
+     * its items first, and a hidden counter indexes into that. Both keep
+     * two hidden locals for as long as the loop runs. */
+    fn for_loop(&mut self, var: &str, iter: &Expr, body: &Block) {
+        self.frame.depth += 1;
+        let range = match iter {
+            Expr::Range { start, end, .. } => {
+                self.expr(start);
+                self.declare("");
+                self.expr(end);
+                self.declare("");
+                true
+            }
+            iter => {
+                self.expr(iter);
+                self.emit(Op::ITER);
+                self.declare("");
+                self.constant(Value::Int(0));
+                self.declare("");
+                false
+            }
+        };
+        let (first, second) = (self.frame.height - 2, self.frame.height - 1);
+        let counter = if range { first } else { second };
+
+        let top = self.frame.chunk.code.len();
+        if range {
+            self.emit_u16(Op::GET_LOCAL, first);
+            self.emit_u16(Op::GET_LOCAL, second);
+        } else {
+            self.emit_u16(Op::GET_LOCAL, second);
+            self.emit_u16(Op::GET_LOCAL, first);
+            self.emit(Op::LEN);
+        }
+        self.emit(Op::LT);
+        let exit = self.jump(Op::JUMP_IF_FALSE);
+
+        /* The variable is a fresh local in every iteration */
+        self.frame.depth += 1;
+        if range {
+            self.emit_u16(Op::GET_LOCAL, counter);
+        } else {
+            self.emit_u16(Op::GET_LOCAL, first);
+            self.emit_u16(Op::GET_LOCAL, second);
+            self.emit(Op::INDEX);
+        }
+        self.declare(var);
+        self.block(body);
+        self.emit(Op::POP);
+        self.end_scope(false);
+
+        self.emit_u16(Op::GET_LOCAL, counter);
+        self.constant(Value::Int(1));
+        self.emit(Op::ADD);
+        self.emit_u16(Op::SET_LOCAL, counter);
+        self.jump_back(top);
+        self.patch(exit);
+        self.end_scope(false);
+    }
+
+    /* Pushes the value of the block, dropping its locals from under it */
+    fn block(&mut self, block: &Block) {
+        self.frame.depth += 1;
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        match &block.tail {
+            Some(tail) => self.expr(tail),
+            None => self.emit(Op::UNIT),
+        }
+        self.end_scope(true);
+    }
+
+    fn end_scope(&mut self, keep_value: bool) {
+        self.frame.depth -= 1;
+        let depth = self.frame.depth;
+        let count = self
+            .frame
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth > depth)
+            .count();
+        self.frame.locals.truncate(self.frame.locals.len() - count);
+        match (count, keep_value) {
+            (0, _) => {}
+            (_, true) => self.emit_u16(Op::SLIDE, count),
+            (_, false) => self.emit_u16(Op::POP_N, count),
+        }
+    }
+
+    /* Pushes the value of an expression */
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal { value, .. } => match value {
+                Literal::Int(value) => self.constant(Value::Int(*value)),
+                Literal::Str(value) => self.constant(Value::Str(value.clone())),
+                Literal::Bool(true) => self.emit(Op::TRUE),
+                Literal::Bool(false) => self.emit(Op::FALSE),
+            },
+            Expr::Ident(ident) => match self.local(&ident.name) {
+                Some(slot) => self.emit_u16(Op::GET_LOCAL, slot),
+                None => {
+                    let slot = self.global(&ident.name);
+                    self.emit_u16(Op::GET_GLOBAL, slot);
+                }
+            },
+            Expr::Call { callee, args, span } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                match self.functions.get(&callee.name) {
+                    Some(function) => {
+                        let offset = self.frame.chunk.code.len();
+                        self.emit_u16(Op::CALL, *function);
+                        self.frame.chunk.spans.push((offset, *span, callee.span));
+                    }
+                    None => match Builtin::ALL
+                        .iter()
+                        .find(|builtin| builtin.name() == callee.name)
+                    {
+                        Some(Builtin::Print) => self.emit(Op::PRINT),
+                        Some(Builtin::Len) => self.emit(Op::LEN),
+                        None => panic!("unresolved function `{}`", callee.name),
+                    },
+                }
+            }
+            Expr::Unary {
+                op, operand, span, ..
+            } => {
+                self.expr(operand);
+                match op {
+                    UnOp::Not => self.emit(Op::NOT),
+                    UnOp::BitNot => self.emit(Op::BIT_NOT),
+                    UnOp::Neg => self.emit_spanned(Op::NEG, *span, *span),
+                    UnOp::Plus => {}
+                }
+            }
+            Expr::Binary {
+                op: op @ (BinOp::And | BinOp::Or),
+                lhs,
+                rhs,
+                ..
+            } => {
+                /* The left side decides, or leaves it to the right side */
+                self.expr(lhs);
+                let height = self.frame.height - 1;
+                let right = self.jump(Op::JUMP_IF_FALSE);
+                match op {
+                    BinOp::And => self.expr(rhs),
+                    _ => self.emit(Op::TRUE),
+                }
+                let end = self.jump(Op::JUMP);
+                self.patch(right);
+                self.frame.height = height;
+                match op {
+                    BinOp::And => self.emit(Op::FALSE),
+                    _ => self.expr(rhs),
+                }
+                self.patch(end);
+            }
+            Expr::Binary {
+                op, lhs, rhs, span, ..
+            } => {
+                self.expr(lhs);
+                self.expr(rhs);
+                self.emit_spanned(binary(*op), *span, rhs.span());
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(cond);
+                let height = self.frame.height - 1;
+                let otherwise = self.jump(Op::JUMP_IF_FALSE);
+                self.block(then_branch);
+                let end = self.jump(Op::JUMP);
+                self.patch(otherwise);
+                self.frame.height = height;
+                match else_branch {
+                    Some(else_branch) => self.expr(else_branch),
+                    None => self.emit(Op::UNIT),
+                }
+                self.patch(end);
+            }
+            Expr::Block(block) => self.block(block),
+            Expr::List { elements, .. } => {
+                for element in elements {
+                    self.expr(element);
+                }
+                self.emit_u16(Op::LIST, elements.len());
+            }
+            Expr::Index { list, index, .. } => {
+                self.expr(list);
+                match &**index {
+                    Expr::Range { start, end, span } => {
+                        self.expr(start);
+                        self.expr(end);
+                        self.emit_spanned(Op::SLICE, *span, *span);
+                    }
+                    index => {
+                        self.expr(index);
+                        self.emit_spanned(Op::INDEX, index.span(), index.span());
+                    }
+                }
+            }
+            Expr::Template { parts, .. } => {
+                for part in parts {
+                    self.expr(part);
+                }
+                self.emit_u16(Op::TEMPLATE, parts.len());
+            }
+            Expr::Range { .. } => unreachable!("ranges are only iterated over"),
+        }
+    }
+
+    fn declare(&mut self, name: &str) {
+        self.frame.locals.push(Local {
+            name: name.to_string(),
+            slot: self.frame.height - 1,
+            depth: self.frame.depth,
+        });
+    }
+
+    fn local(&self, name: &str) -> Option<usize> {
+        let local = self
+            .frame
+            .locals
+            .iter()
+            .rev()
+            .find(|local| local.name == name)?;
+        Some(local.slot)
+    }
+
+    /* The slot of a global, one per name, as a `let` at the top of the
+     * script replaces any earlier one */
+    fn global(&mut self, name: &str) -> usize {
+        match self.globals.iter().position(|global| global == name) {
+            Some(slot) => slot,
+            None => {
+                self.globals.push(name.to_string());
+                self.globals.len() - 1
+            }
+        }
+    }
+
+    fn constant(&mut self, value: Value) {
+        let constants = &mut self.frame.chunk.constants;
+        let index = match constants.iter().position(|constant| *constant == value) {
+            Some(index) => index,
+            None => {
+                constants.push(value);
+                constants.len() - 1
+            }
+        };
+        self.emit_u16(Op::CONSTANT, index);
+    }
+
+    fn emit(&mut self, op: Op) {
+        self.frame.chunk.code.push(op as u8);
+        self.frame.height = self.frame.height.wrapping_add_signed(self.effect(op, 0));
+    }
+
+    fn emit_u16(&mut self, op: Op, operand: usize) {
+        let operand = match u16::try_from(operand) {
+            Ok(operand) => operand,
+            Err(_) => {
+                if !self.frame.too_large {
+                    self.frame.too_large = true;
+                    let message = format!("`{}` is too large to compile", self.frame.name);
+                    self.diagnostics.push(Diagnostic::error(message, self.frame.span).with_note(
+                        "a function can only have 65536 constants, locals, globals or list elements",
+                    ));
+                }
+                u16::MAX
+            }
+        };
+        self.frame.chunk.code.push(op as u8);
+        self.frame.chunk.code.extend(operand.to_le_bytes());
+        self.frame.height = self
+            .frame
+            .height
+            .wrapping_add_signed(self.effect(op, operand as usize));
+    }
+
+    /* An instruction that can fail at runtime, with the expression it
+     * computes and the operand to blame */
+    fn emit_spanned(&mut self, op: Op, span: Span, blame: Span) {
+        let offset = self.frame.chunk.code.len();
+        self.emit(op);
+        self.frame.chunk.spans.push((offset, span, blame));
+    }
+
+    /* How an instruction changes the height of the stack */
+    fn effect(&self, op: Op, operand: usize) -> isize {
+        let operand = operand as isize;
+        match op {
+            Op::CONSTANT | Op::UNIT | Op::TRUE | Op::FALSE | Op::GET_LOCAL | Op::GET_GLOBAL => 1,
+            Op::POP | Op::SET_LOCAL | Op::SET_GLOBAL | Op::JUMP_IF_FALSE | Op::RETURN => -1,
+            Op::POP_N | Op::SLIDE => -operand,
+            Op::ADD
+            | Op::SUB
+            | Op::MUL
+            | Op::DIV
+            | Op::MOD
+            | Op::BIT_AND
+            | Op::BIT_OR
+            | Op::BIT_XOR
+            | Op::SHL
+            | Op::SHR
+            | Op::EQ
+            | Op::NE
+            | Op::LT
+            | Op::LE
+            | Op::GT
+            | Op::GE
+            | Op::INDEX => -1,
+            Op::NOT | Op::NEG | Op::BIT_NOT | Op::JUMP | Op::PRINT | Op::LEN | Op::ITER => 0,
+            Op::CALL => {
+                1 - self
+                    .arities
+                    .get(operand as usize)
+                    .map_or(0, |arity| *arity as isize)
+            }
+            Op::LIST | Op::TEMPLATE => 1 - operand,
+            Op::SLICE => -2,
+            Op::SET_INDEX => -3,
+        }
+    }
+
+    /* Emits a jump to be patched, and returns where its offset goes */
+    fn jump(&mut self, op: Op) -> usize {
+        self.emit(op);
+        self.frame.chunk.code.extend([0; 4]);
+        self.frame.chunk.code.len() - 4
+    }
+
+    /* Points the jump with its offset at `at` to the next instruction */
+    fn patch(&mut self, at: usize) {
+        let offset = (self.frame.chunk.code.len() - (at + 4)) as i32;
+        self.frame.chunk.code[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+    }
+
+    fn jump_back(&mut self, target: usize) {
+        let at = self.jump(Op::JUMP);
+        let offset = target as i64 - (at + 4) as i64;
+        self.frame.chunk.code[at..at + 4].copy_from_slice(&(offset as i32).to_le_bytes());
+    }
+}
+
+fn binary(op: BinOp) -> Op {
+    match op {
+        BinOp::Eq => Op::EQ,
+        BinOp::Ne => Op::NE,
+        BinOp::Lt => Op::LT,
+        BinOp::Le => Op::LE,
+        BinOp::Gt => Op::GT,
+        BinOp::Ge => Op::GE,
+        BinOp::BitOr => Op::BIT_OR,
+        BinOp::BitXor => Op::BIT_XOR,
+        BinOp::BitAnd => Op::BIT_AND,
+        BinOp::Shl => Op::SHL,
+        BinOp::Shr => Op::SHR,
+        BinOp::Add => Op::ADD,
+        BinOp::Sub => Op::SUB,
+        BinOp::Mul => Op::MUL,
+        BinOp::Div => Op::DIV,
+        BinOp::Mod => Op::MOD,
+        BinOp::And | BinOp::Or => unreachable!("short-circuiting operators are jumps"),
+    }
+}
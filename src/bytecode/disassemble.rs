@@ -0,0 +1,109 @@
+use super::bytecode::{Chunk, Module, Op};
+use crate::interp::value::Value;
+use std::fmt::Write;
+
+/* Lists the instructions of every function, the script first. Each line
+ * has the offset, the instruction and its operand, and after a `;` what
+ * the operand refers to: a constant, a global, a function or where a jump
+ * lands. */
+pub fn disassemble(module: &Module) -> String {
+    let mut out = String::new();
+    for (i, function) in module.functions.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let _ = writeln!(out, "{}/{}:", function.name, function.arity);
+        let chunk = &function.chunk;
+        let mut at = 0;
+        while at < chunk.code.len() {
+            out += &instruction(module, chunk, at);
+            out.push('\n');
+            at += 1 + Op::from_byte(chunk.code[at]).map_or(0, Op::operand_size);
+        }
+    }
+    out
+}
+
+fn instruction(module: &Module, chunk: &Chunk, at: usize) -> String {
+    let Some(op) = Op::from_byte(chunk.code[at]) else {
+        return format!("{:04}  <unknown opcode {}>", at, chunk.code[at]);
+    };
+    let name = format!("{:?}", op);
+    let (operand, note) = match op {
+        Op::CONSTANT => {
+            let index = chunk.u16(at + 1) as usize;
+            let note = match &chunk.constants[index] {
+                Value::Str(text) => format!("{:?}", text),
+                value => value.to_string(),
+            };
+            (index.to_string(), note)
+        }
+        Op::GET_GLOBAL | Op::SET_GLOBAL => {
+            let slot = chunk.u16(at + 1) as usize;
+            (slot.to_string(), module.globals[slot].clone())
+        }
+        Op::CALL => {
+            let function = chunk.u16(at + 1) as usize;
+            (
+                function.to_string(),
+                module.functions[function].name.clone(),
+            )
+        }
+        Op::JUMP | Op::JUMP_IF_FALSE => {
+            let offset = chunk.i32(at + 1);
+            let target = (at + 5).wrapping_add_signed(offset as isize);
+            (offset.to_string(), format!("-> {:04}", target))
+        }
+        op if op.operand_size() == 2 => (chunk.u16(at + 1).to_string(), String::new()),
+        _ => (String::new(), String::new()),
+    };
+    let line = format!("{:04}  {:<13} {:>5}", at, name, operand);
+    match note.is_empty() {
+        true => line.trim_end().to_string(),
+        false => format!("{}  ; {}", line, note),
+    }
+}
+
+#[test]
+fn lists_instructions() {
+    use super::compile::compile;
+    use crate::testing::pipeline::checked;
+
+    let input = "def twice(s: str) -> str { s + s }
+        let n: int = 3;
+        while n > 0 && true { n = n - 1; }
+        print(twice(\"ab\"));";
+    assert_eq!(
+        disassemble(&compile(&checked(input)).unwrap()),
+        "\
+<script>/0:
+0000  CONSTANT          0  ; 3
+0003  SET_GLOBAL        0  ; n
+0006  GET_GLOBAL        0  ; n
+0009  CONSTANT          1  ; 0
+0012  GT
+0013  JUMP_IF_FALSE     6  ; -> 0024
+0018  TRUE
+0019  JUMP              1  ; -> 0025
+0024  FALSE
+0025  JUMP_IF_FALSE    17  ; -> 0047
+0030  GET_GLOBAL        0  ; n
+0033  CONSTANT          2  ; 1
+0036  SUB
+0037  SET_GLOBAL        0  ; n
+0040  UNIT
+0041  POP
+0042  JUMP            -41  ; -> 0006
+0047  CONSTANT          3  ; \"ab\"
+0050  CALL              1  ; twice
+0053  PRINT
+0054  RETURN
+
+twice/1:
+0000  GET_LOCAL         0
+0003  GET_LOCAL         0
+0006  ADD
+0007  RETURN
+"
+    );
+}
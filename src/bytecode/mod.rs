@@ -0,0 +1,4 @@
+#[allow(clippy::module_inception, reason = "the instruction set, next to its compiler and disassembler")]
+pub mod bytecode;
+pub mod compile;
+pub mod disassemble;
@@ -0,0 +1,678 @@
+use crate::ast::ast::{BinOp, Block, Expr, Function, Ident, Literal, Program, Stmt, Type, UnOp};
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::interp::interp::MAX_CALL_DEPTH;
+use crate::sema::builtins::Builtin;
+use crate::sema::check::Checker;
+use crate::sema::types::Ty;
+use crate::source::map::SourceMap;
+use crate::source::span::Span;
+use std::fmt::Write;
+
+const RUNTIME: &str = include_str!("runtime.c");
+
+/* Translates a checked program to a single C99 file, the runtime first.
+ * The script becomes `main`. Every value is computed into a temporary
+ * before it is used, which keeps the order of evaluation of the program
+ * and turns blocks and `if` into plain statements. Names are prefixed,
+ * `f_` for functions, `v_` for variables and `t` for temporaries, so
+ * they clash neither with C nor with each other. Lists are reported as
+ * unsupported. */
+pub fn compile(
+    program: &Program,
+    checker: &Checker,
+    sources: &SourceMap,
+) -> Result<String, Vec<Diagnostic>> {
+    let mut generator = Generator {
+        checker,
+        sources,
+        functions: program
+            .functions
+            .iter()
+            .map(|function| function.name.name.clone())
+            .collect(),
+        names: 0,
+        locals: vec![],
+        depth: 0,
+        code: String::new(),
+        indent: 1,
+        diagnostics: vec![],
+    };
+
+    let mut out = format!("#define CHEETAH_MAX_CALL_DEPTH {}\n", MAX_CALL_DEPTH);
+    out += RUNTIME;
+    out.push('\n');
+    let signatures: Vec<String> = program
+        .functions
+        .iter()
+        .map(|function| generator.signature(function))
+        .collect();
+    for signature in &signatures {
+        let _ = writeln!(out, "{};", signature);
+    }
+    for (function, signature) in program.functions.iter().zip(&signatures) {
+        out.push('\n');
+        out += &generator.function(function, signature);
+    }
+    out.push('\n');
+    out += &generator.script(program);
+
+    match generator.diagnostics.is_empty() {
+        true => Ok(out),
+        false => Err(generator.diagnostics),
+    }
+}
+
+/* A variable in scope, and the name it has in C */
+struct Local {
+    name: String,
+    c_name: String,
+    depth: usize,
+}
+
+struct Generator<'a> {
+    checker: &'a Checker,
+    sources: &'a SourceMap,
+    functions: Vec<String>,
+    /* Numbers the C names, so each declaration gets one of its own */
+    names: usize,
+    locals: Vec<Local>,
+    depth: usize,
+    /* The body of the function being generated */
+    code: String,
+    indent: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Generator<'_> {
+    fn signature(&mut self, function: &Function) -> String {
+        let ret = match &function.ret {
+            Some(ret) => self.c_type(ret, function.ret_span),
+            None => "int",
+        };
+        let params: Vec<String> = function
+            .params
+            .iter()
+            .map(|param| {
+                let ty = self.c_type(&param.ty, param.ty_span);
+                format!("{} v_{}", ty, param.name.name)
+            })
+            .collect();
+        let params = match params.is_empty() {
+            true => "void".to_string(),
+            false => params.join(", "),
+        };
+        format!("{} f_{}({})", ret, function.name.name, params)
+    }
+
+    /* Parameters keep their name with the `v_` prefix, as they are the
+     * first declarations of the function */
+    fn function(&mut self, function: &Function, signature: &str) -> String {
+        self.locals = function
+            .params
+            .iter()
+            .map(|param| Local {
+                name: param.name.name.clone(),
+                c_name: format!("v_{}", param.name.name),
+                depth: 0,
+            })
+            .collect();
+        self.depth = 0;
+        self.code.clear();
+
+        let value = self.block(&function.body);
+        let returns = match &function.body.tail {
+            _ if function.ret.is_none() => Some("0".to_string()),
+            Some(tail) if self.has_value(tail) => Some(value),
+            _ => None,
+        };
+        if let Some(value) = returns {
+            self.line(format!("return {};", value));
+        }
+        format!("{} {{\n{}}}\n", signature, std::mem::take(&mut self.code))
+    }
+
+    /* The statements outside of functions, printing the value of the last
+     * one like `cheetah run` does */
+    fn script(&mut self, program: &Program) -> String {
+        self.locals.clear();
+        self.depth = 0;
+        self.code.clear();
+        for (i, stmt) in program.stmts.iter().enumerate() {
+            let (value, ty) = match stmt {
+                Stmt::Expr(expr) if i + 1 == program.stmts.len() => {
+                    (self.expr(expr), self.type_of(expr))
+                }
+                Stmt::Block(block) if i + 1 == program.stmts.len() => {
+                    let value = self.block(block);
+                    let tail = block.tail.as_deref();
+                    (value, tail.map_or(Ty::Unit, |tail| self.type_of(tail)))
+                }
+                stmt => {
+                    self.stmt(stmt);
+                    continue;
+                }
+            };
+            if matches!(ty, Ty::Int | Ty::Bool | Ty::Str) {
+                self.print(&ty, &value);
+            }
+        }
+        self.line("return 0;");
+        format!("int main(void) {{\n{}}}\n", std::mem::take(&mut self.code))
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let {
+                name,
+                ty,
+                ty_span,
+                value,
+                ..
+            } => {
+                let value = self.expr(value);
+                let ty = self.c_type(ty, *ty_span);
+                let c_name = self.declare(&name.name);
+                self.line(format!("{} {} = {};", ty, c_name, value));
+            }
+            Stmt::Assign { target, value, .. } => {
+                let value = self.expr(value);
+                let c_name = self.local(&target.name);
+                self.line(format!("{} = {};", c_name, value));
+            }
+            Stmt::IndexAssign { list, .. } => self.unsupported("lists", list.span()),
+            Stmt::While { cond, body, .. } => {
+                /* The condition may take statements to compute */
+                self.open("for (;;) {");
+                let cond = self.expr(cond);
+                self.open(format!("if (!{}) {{", cond));
+                self.line("break;");
+                self.close();
+                self.block(body);
+                self.close();
+            }
+            Stmt::For {
+                var, iter, body, ..
+            } => self.for_loop(var, iter, body),
+            Stmt::Return { value, .. } => {
+                /* Returning a value that never comes is left to the value */
+                let value = match value {
+                    Some(value) if self.type_of(value) == Ty::Never => {
+                        self.expr(value);
+                        return;
+                    }
+                    Some(value) if self.has_value(value) => self.expr(value),
+                    Some(value) => {
+                        self.expr(value);
+                        "0".to_string()
+                    }
+                    None => "0".to_string(),
+                };
+                self.line(format!("return {};", value));
+            }
+            Stmt::Block(block) => {
+                self.block(block);
+            }
+            Stmt::Expr(expr) => {
+                self.expr(expr);
+            }
+        }
+    }
+
+    /* Ranges are counted through and strings walked a character at a
+     * time, the variable being a fresh copy in every iteration */
+    fn for_loop(&mut self, var: &Ident, iter: &Expr, body: &Block) {
+        let (item, ty) = match iter {
+            Expr::Range { start, end, .. } => {
+                let (start, end) = (self.expr(start), self.expr(end));
+                let counter = self.temporary();
+                self.open(format!(
+                    "for (int64_t {0} = {1}; {0} < {2}; {0}++) {{",
+                    counter, start, end
+                ));
+                (counter, "int64_t")
+            }
+            iter if self.type_of(iter) == Ty::Str => {
+                let text = self.expr(iter);
+                let at = self.temporary();
+                self.open(format!(
+                    "for (int64_t {0} = 0; {0} < {1}.size;) {{",
+                    at, text
+                ));
+                (
+                    format!("cheetah_next_char({}, &{})", text, at),
+                    "cheetah_str",
+                )
+            }
+            iter => return self.unsupported("lists", iter.span()),
+        };
+        self.depth += 1;
+        let c_name = self.declare(&var.name);
+        self.line(format!("{} {} = {};", ty, c_name, item));
+        self.block(body);
+        self.end_scope();
+        self.close();
+    }
+
+    /* Runs the block in a C block of its own, and returns a temporary
+     * declared before it that holds its value */
+    fn block(&mut self, block: &Block) -> String {
+        let result = match &block.tail {
+            Some(tail) if self.has_value(tail) => Some(self.declare_temporary(tail)),
+            _ => None,
+        };
+        self.open("{");
+        self.depth += 1;
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            let value = self.expr(tail);
+            if let Some(result) = &result {
+                self.line(format!("{} = {};", result, value));
+            }
+        }
+        self.end_scope();
+        self.close();
+        result.unwrap_or_else(|| "0".to_string())
+    }
+
+    fn end_scope(&mut self) {
+        self.depth -= 1;
+        let depth = self.depth;
+        self.locals.retain(|local| local.depth <= depth);
+    }
+
+    /* Emits the statements computing an expression, and returns a C
+     * expression for its value that has no side effects. Values without
+     * a type of their own, `()` and blocks that return, are `0`. */
+    fn expr(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Literal { value, .. } => match value {
+                Literal::Int(value) => format!("INT64_C({})", value),
+                Literal::Bool(value) => value.to_string(),
+                Literal::Str(value) => {
+                    let temporary = self.temporary();
+                    self.line(format!(
+                        "cheetah_str {} = {{{}, {}}};",
+                        temporary,
+                        quote(value),
+                        value.len()
+                    ));
+                    temporary
+                }
+            },
+            Expr::Ident(ident) => {
+                let c_name = self.local(&ident.name);
+                self.computed(expr, c_name)
+            }
+            Expr::Call { callee, args, span } => {
+                let args: Vec<String> = args.iter().map(|arg| self.expr(arg)).collect();
+                if !self.functions.contains(&callee.name) {
+                    return self.builtin(expr, callee, &args, *span);
+                }
+                self.line(format!("cheetah_enter({});", self.location(*span)));
+                let call = format!("f_{}({})", callee.name, args.join(", "));
+                let value = self.computed(expr, call);
+                self.line("cheetah_depth--;");
+                value
+            }
+            Expr::Unary {
+                op, operand, span, ..
+            } => {
+                let operand = self.expr(operand);
+                let value = match op {
+                    UnOp::Not => format!("!{}", operand),
+                    UnOp::BitNot => format!("~{}", operand),
+                    UnOp::Neg => format!("cheetah_neg({}, {})", operand, self.location(*span)),
+                    UnOp::Plus => return operand,
+                };
+                self.computed(expr, value)
+            }
+            Expr::Binary {
+                op: op @ (BinOp::And | BinOp::Or),
+                lhs,
+                rhs,
+                ..
+            } => {
+                /* The left side decides, or leaves it to the right side */
+                let left = self.expr(lhs);
+                let result = self.computed(expr, left);
+                match op {
+                    BinOp::And => self.open(format!("if ({}) {{", result)),
+                    _ => self.open(format!("if (!{}) {{", result)),
+                }
+                let right = self.expr(rhs);
+                self.line(format!("{} = {};", result, right));
+                self.close();
+                result
+            }
+            Expr::Binary {
+                op, lhs, rhs, span, ..
+            } => {
+                let strings = self.type_of(lhs) == Ty::Str;
+                let (left, right) = (self.expr(lhs), self.expr(rhs));
+                let value = self.binary(*op, strings, &left, &right, *span);
+                self.computed(expr, value)
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let cond = self.expr(cond);
+                let result = self.has_value(expr).then(|| self.declare_temporary(expr));
+                self.open(format!("if ({}) {{", cond));
+                let value = self.block(then_branch);
+                self.assign_branch(&result, then_branch.tail.as_deref(), value);
+                if let Some(else_branch) = else_branch {
+                    self.close_open("} else {");
+                    let value = self.expr(else_branch);
+                    self.assign_branch(&result, Some(else_branch), value);
+                }
+                self.close();
+                result.unwrap_or_else(|| "0".to_string())
+            }
+            Expr::Block(block) => self.block(block),
+            Expr::Index { list, index, .. } if self.type_of(list) == Ty::Str => {
+                let text = self.expr(list);
+                let value = match &**index {
+                    Expr::Range { start, end, span } => {
+                        let (start, end) = (self.expr(start), self.expr(end));
+                        let location = self.location(*span);
+                        format!("cheetah_slice({}, {}, {}, {})", text, start, end, location)
+                    }
+                    index => {
+                        let i = self.expr(index);
+                        let location = self.location(index.span());
+                        format!("cheetah_index({}, {}, {})", text, i, location)
+                    }
+                };
+                self.computed(expr, value)
+            }
+            Expr::List { span, .. } | Expr::Index { span, .. } => {
+                self.unsupported("lists", *span);
+                "0".to_string()
+            }
+            Expr::Template { parts, .. } => {
+                let mut text = None;
+                for part in parts {
+                    let ty = self.type_of(part);
+                    let value = self.expr(part);
+                    let value = match ty {
+                        Ty::Int => format!("cheetah_int_str({})", value),
+                        Ty::Bool => format!("cheetah_bool_str({})", value),
+                        _ => value,
+                    };
+                    text = Some(match text {
+                        Some(text) => format!("cheetah_concat({}, {})", text, value),
+                        None => value,
+                    });
+                }
+                let text = text.unwrap_or_else(|| "cheetah_empty".to_string());
+                self.computed(expr, text)
+            }
+            Expr::Range { .. } => unreachable!("ranges are only iterated over"),
+        }
+    }
+
+    fn binary(&mut self, op: BinOp, strings: bool, left: &str, right: &str, span: Span) -> String {
+        let checked = |name: &str, location: String| {
+            format!("cheetah_{}({}, {}, {})", name, left, right, location)
+        };
+        let symbol = match op {
+            BinOp::Add if strings => return format!("cheetah_concat({}, {})", left, right),
+            BinOp::Eq if strings => return format!("cheetah_str_eq({}, {})", left, right),
+            BinOp::Ne if strings => return format!("!cheetah_str_eq({}, {})", left, right),
+            BinOp::Add => return checked("add", self.location(span)),
+            BinOp::Sub => return checked("sub", self.location(span)),
+            BinOp::Mul => return checked("mul", self.location(span)),
+            BinOp::Div => return checked("div", self.location(span)),
+            BinOp::Mod => return checked("rem", self.location(span)),
+            BinOp::Shl => return checked("shl", self.location(span)),
+            BinOp::Shr => return checked("shr", self.location(span)),
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::BitAnd => "&",
+            BinOp::And | BinOp::Or => unreachable!("short-circuiting operators are branches"),
+        };
+        match strings {
+            true => format!("cheetah_str_compare({}, {}) {} 0", left, right, symbol),
+            false => format!("{} {} {}", left, symbol, right),
+        }
+    }
+
+    fn builtin(&mut self, expr: &Expr, callee: &Ident, args: &[String], span: Span) -> String {
+        let Expr::Call { args: exprs, .. } = expr else {
+            unreachable!("builtins are called");
+        };
+        let ty = self.type_of(&exprs[0]);
+        match Builtin::ALL
+            .iter()
+            .find(|builtin| builtin.name() == callee.name)
+        {
+            Some(Builtin::Print) => {
+                self.print(&ty, &args[0]);
+                "0".to_string()
+            }
+            Some(Builtin::Len) if ty == Ty::Str => {
+                self.computed(expr, format!("cheetah_len({})", args[0]))
+            }
+            Some(Builtin::Len) => {
+                self.unsupported("lists", span);
+                "0".to_string()
+            }
+            None => panic!("unresolved function `{}`", callee.name),
+        }
+    }
+
+    fn print(&mut self, ty: &Ty, value: &str) {
+        let function = match ty {
+            Ty::Int => "int",
+            Ty::Bool => "bool",
+            Ty::Str => "str",
+            /* Already reported where the list was made */
+            Ty::List(_) => return,
+            _ => "unit",
+        };
+        self.line(format!("cheetah_print_{}({});", function, value));
+    }
+
+    /* Stores the value of the branch of an `if` in its result, unless the
+     * branch has no value to store */
+    fn assign_branch(&mut self, result: &Option<String>, branch: Option<&Expr>, value: String) {
+        if let (Some(result), Some(branch)) = (result, branch) {
+            if self.has_value(branch) {
+                self.line(format!("{} = {};", result, value));
+            }
+        }
+    }
+
+    /* A new temporary holding `value`, of the type of `expr` */
+    fn computed(&mut self, expr: &Expr, value: impl AsRef<str>) -> String {
+        let ty = self.c_ty(&self.type_of(expr));
+        let temporary = self.temporary();
+        self.line(format!("{} {} = {};", ty, temporary, value.as_ref()));
+        temporary
+    }
+
+    /* A new temporary of the type of `expr`, to be assigned later */
+    fn declare_temporary(&mut self, expr: &Expr) -> String {
+        let ty = self.c_ty(&self.type_of(expr));
+        let temporary = self.temporary();
+        self.line(format!("{} {};", ty, temporary));
+        temporary
+    }
+
+    fn temporary(&mut self) -> String {
+        self.names += 1;
+        format!("t{}", self.names)
+    }
+
+    fn declare(&mut self, name: &str) -> String {
+        self.names += 1;
+        let c_name = format!("v_{}_{}", name, self.names);
+        self.locals.push(Local {
+            name: name.to_string(),
+            c_name: c_name.clone(),
+            depth: self.depth,
+        });
+        c_name
+    }
+
+    fn local(&self, name: &str) -> String {
+        let local = self.locals.iter().rev().find(|local| local.name == name);
+        local
+            .unwrap_or_else(|| panic!("unresolved name `{}`", name))
+            .c_name
+            .clone()
+    }
+
+    fn type_of(&self, expr: &Expr) -> Ty {
+        self.checker.type_of(expr).cloned().unwrap_or(Ty::Error)
+    }
+
+    /* Whether the expression has a value to store, which `()` and
+     * expressions that never finish do not */
+    fn has_value(&self, expr: &Expr) -> bool {
+        !matches!(self.type_of(expr), Ty::Unit | Ty::Never | Ty::Error)
+    }
+
+    fn c_type(&mut self, ty: &Type, span: Span) -> &'static str {
+        match ty {
+            Type::Int => "int64_t",
+            Type::Bool => "bool",
+            Type::Str => "cheetah_str",
+            Type::List(_) => {
+                self.unsupported("lists", span);
+                "int"
+            }
+        }
+    }
+
+    fn c_ty(&self, ty: &Ty) -> &'static str {
+        match ty {
+            Ty::Int => "int64_t",
+            Ty::Bool => "bool",
+            Ty::Str => "cheetah_str",
+            _ => "int",
+        }
+    }
+
+    /* Where `span` starts, as a C string for runtime errors */
+    fn location(&self, span: Span) -> String {
+        let file = self.sources.file(span.file);
+        quote(&format!("{}:{}", file.name, file.location(span.start)))
+    }
+
+    /* Reported once for each kind of value, where it is first used */
+    fn unsupported(&mut self, what: &str, span: Span) {
+        let message = format!("{} cannot be compiled to C", what);
+        if self.diagnostics.iter().any(|seen| seen.message == message) {
+            return;
+        }
+        self.diagnostics.push(
+            Diagnostic::error(message, span)
+                .with_note("C code supports `int`, `bool` and `str` values"),
+        );
+    }
+
+    fn line(&mut self, line: impl AsRef<str>) {
+        let indent = "    ".repeat(self.indent);
+        let _ = writeln!(self.code, "{}{}", indent, line.as_ref());
+    }
+
+    /* A line ending in `{`, indenting the ones after it */
+    fn open(&mut self, line: impl AsRef<str>) {
+        self.line(line);
+        self.indent += 1;
+    }
+
+    fn close(&mut self) {
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn close_open(&mut self, line: &str) {
+        self.indent -= 1;
+        self.open(line);
+    }
+}
+
+/* A C string literal. Every byte that is not printable ASCII is an
+ * octal escape of three digits, so it cannot run into the next one. */
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' | b'\\' | b'?' => quoted += &format!("\\{}", byte as char),
+            b' '..=b'~' => quoted.push(byte as char),
+            byte => quoted += &format!("\\{:03o}", byte),
+        }
+    }
+    quoted + "\""
+}
+
+/* Compiles sample programs with `cc` as strict C99, runs them, and
+ * compares what they print and how they fail with the interpreter */
+#[cfg(test)]
+fn compile_source(input: &str) -> Result<String, Vec<Diagnostic>> {
+    use crate::testing::pipeline::check;
+
+    let mut sources = SourceMap::new();
+    sources.add_file("test.ch".to_string(), input.to_string());
+    let (program, checker) = check(input);
+    assert!(!checker.has_errors(), "{:?}", checker.diagnostics());
+    compile(&program, &checker, &sources)
+}
+
+#[test]
+fn rejects_lists_once() {
+    let errors = compile_source(
+        "def first(xs: [int]) -> int { xs[0] } let a: [int] = [1]; a[0] = 2; for x in a {}",
+    )
+    .unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(messages, ["lists cannot be compiled to C"]);
+}
+
+#[test]
+fn runs_like_the_interpreter() {
+    use super::native::scratch_dir;
+    use crate::testing::differential::{compare_outcomes, executable, SCALARS, STRINGS};
+    use std::process::Command;
+
+    if Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("skipping, there is no `cc` to compile with");
+        return;
+    }
+
+    let dir = scratch_dir().unwrap();
+    let programs = [SCALARS, STRINGS].concat();
+    compare_outcomes(&programs, |i, program, checker, sources| {
+        let (source, path) = (
+            dir.join(format!("program{}.c", i)),
+            dir.join(format!("program{}", i)),
+        );
+        std::fs::write(&source, compile(program, checker, sources).unwrap()).unwrap();
+        let cc = Command::new("cc")
+            .args(["-std=c99", "-pedantic-errors", "-o"])
+            .arg(&path)
+            .arg(&source)
+            .output()
+            .unwrap();
+        assert!(
+            cc.status.success(),
+            "{}",
+            String::from_utf8_lossy(&cc.stderr)
+        );
+        executable(&path)
+    });
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,4 @@
+pub mod c;
+pub mod native;
+pub mod wasm;
+pub mod x86_64;
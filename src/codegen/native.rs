@@ -0,0 +1,73 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/* Assembles the output of `x86_64::compile` with `as` and links it with
+ * `cc` into an executable at `output`. On failure the error says which
+ * tool failed and what it printed. */
+pub fn link(assembly: &str, output: &Path) -> Result<(), String> {
+    let scratch =
+        scratch_dir().map_err(|error| format!("could not create a directory: {}", error))?;
+    let (source, object) = (scratch.join("program.s"), scratch.join("program.o"));
+    let result = fs::write(&source, assembly)
+        .map_err(|error| format!("{}: {}", source.display(), error))
+        .and_then(|()| run(Command::new("as").arg("-o").arg(&object).arg(&source)))
+        .and_then(|()| run(Command::new("cc").arg("-o").arg(output).arg(&object)));
+    let _ = fs::remove_dir_all(&scratch);
+    result
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .map_err(|error| format!("could not run `{}`: {}", program, error))?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(format!(
+            "`{}` failed\n{}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )),
+    }
+}
+
+/* A fresh directory for the intermediate files, unique to the process
+ * and the call */
+pub fn scratch_dir() -> std::io::Result<PathBuf> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!(
+        "cheetah-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    );
+    let dir = std::env::temp_dir().join(name);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/* Compiles sample programs to executables, runs them, and compares what
+ * they print and how they fail with the interpreter */
+#[test]
+fn runs_like_the_interpreter() {
+    use super::x86_64::compile;
+    use crate::testing::differential::{compare_outcomes, executable, SCALARS};
+
+    if !cfg!(target_arch = "x86_64") {
+        eprintln!("skipping, x86-64 executables cannot run here");
+        return;
+    }
+    if Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("skipping, there is no `cc` to link with");
+        return;
+    }
+
+    let dir = scratch_dir().unwrap();
+    compare_outcomes(SCALARS, |i, program, checker, sources| {
+        let path = dir.join(format!("program{}", i));
+        link(&compile(program, checker, sources).unwrap(), &path).unwrap();
+        executable(&path)
+    });
+    let _ = fs::remove_dir_all(&dir);
+}
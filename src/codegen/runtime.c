@@ -0,0 +1,237 @@
+/* The runtime of programs compiled to C, pasted in front of them. Runtime
+ * errors are worded like the interpreter's and exit with code 3. */
+#include <inttypes.h>
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* Strings are UTF-8 and never change, so slices share the bytes of the
+ * string they come from. Nothing is ever freed. */
+typedef struct {
+    const char *bytes;
+    int64_t size;
+} cheetah_str;
+
+cheetah_str cheetah_empty = {"", 0};
+
+int64_t cheetah_depth = 0;
+
+void cheetah_fail(const char *where, const char *format, ...) {
+    va_list args;
+    fflush(stdout);
+    fputs("error: ", stderr);
+    va_start(args, format);
+    vfprintf(stderr, format, args);
+    va_end(args);
+    fprintf(stderr, "\n --> %s\n", where);
+    exit(3);
+}
+
+/* Counts a call about to be made, the caller uncounts it after */
+void cheetah_enter(const char *where) {
+    if (cheetah_depth >= CHEETAH_MAX_CALL_DEPTH) {
+        cheetah_fail(where, "stack overflow");
+    }
+    cheetah_depth++;
+}
+
+int64_t cheetah_add(int64_t a, int64_t b, const char *where) {
+    if ((b > 0 && a > INT64_MAX - b) || (b < 0 && a < INT64_MIN - b)) {
+        cheetah_fail(where, "attempt to add with overflow");
+    }
+    return a + b;
+}
+
+int64_t cheetah_sub(int64_t a, int64_t b, const char *where) {
+    if ((b < 0 && a > INT64_MAX + b) || (b > 0 && a < INT64_MIN + b)) {
+        cheetah_fail(where, "attempt to subtract with overflow");
+    }
+    return a - b;
+}
+
+int64_t cheetah_mul(int64_t a, int64_t b, const char *where) {
+    bool overflows = a > 0 ? (b > 0 ? a > INT64_MAX / b : b < INT64_MIN / a)
+                           : (b > 0 ? a < INT64_MIN / b : a != 0 && b < INT64_MAX / a);
+    if (overflows) {
+        cheetah_fail(where, "attempt to multiply with overflow");
+    }
+    return a * b;
+}
+
+int64_t cheetah_div(int64_t a, int64_t b, const char *where) {
+    if (b == 0) {
+        cheetah_fail(where, "attempt to divide by zero");
+    }
+    if (a == INT64_MIN && b == -1) {
+        cheetah_fail(where, "attempt to divide with overflow");
+    }
+    return a / b;
+}
+
+int64_t cheetah_rem(int64_t a, int64_t b, const char *where) {
+    if (b == 0) {
+        cheetah_fail(where, "attempt to calculate the remainder with a divisor of zero");
+    }
+    if (a == INT64_MIN && b == -1) {
+        cheetah_fail(where, "attempt to calculate the remainder with overflow");
+    }
+    return a % b;
+}
+
+int64_t cheetah_neg(int64_t a, const char *where) {
+    if (a == INT64_MIN) {
+        cheetah_fail(where, "attempt to negate with overflow");
+    }
+    return -a;
+}
+
+int64_t cheetah_shl(int64_t a, int64_t b, const char *where) {
+    if (b < 0 || b > 63) {
+        cheetah_fail(where, "attempt to shift left by `%" PRId64 "`, which would overflow", b);
+    }
+    return (int64_t)((uint64_t)a << b);
+}
+
+/* Shifting a negative number right is implementation defined in C, so
+ * the sign is shifted in by hand */
+int64_t cheetah_shr(int64_t a, int64_t b, const char *where) {
+    if (b < 0 || b > 63) {
+        cheetah_fail(where, "attempt to shift right by `%" PRId64 "`, which would overflow", b);
+    }
+    return a < 0 ? ~(~a >> b) : a >> b;
+}
+
+char *cheetah_alloc(int64_t size) {
+    char *bytes = malloc(size);
+    if (bytes == NULL) {
+        fputs("error: out of memory\n", stderr);
+        exit(3);
+    }
+    return bytes;
+}
+
+cheetah_str cheetah_concat(cheetah_str a, cheetah_str b) {
+    char *bytes = cheetah_alloc(a.size + b.size + 1);
+    cheetah_str joined;
+    memcpy(bytes, a.bytes, a.size);
+    memcpy(bytes + a.size, b.bytes, b.size);
+    joined.bytes = bytes;
+    joined.size = a.size + b.size;
+    return joined;
+}
+
+bool cheetah_str_eq(cheetah_str a, cheetah_str b) {
+    return a.size == b.size && memcmp(a.bytes, b.bytes, a.size) == 0;
+}
+
+/* Byte order of UTF-8 is the order of the characters */
+int cheetah_str_compare(cheetah_str a, cheetah_str b) {
+    int order = memcmp(a.bytes, b.bytes, a.size < b.size ? a.size : b.size);
+    if (order != 0) {
+        return order;
+    }
+    return (a.size > b.size) - (a.size < b.size);
+}
+
+/* Where the character starting at byte `at` ends */
+int64_t cheetah_char_end(cheetah_str s, int64_t at) {
+    at++;
+    while (at < s.size && (s.bytes[at] & 0xC0) == 0x80) {
+        at++;
+    }
+    return at;
+}
+
+/* Lengths and indices count characters, not bytes */
+int64_t cheetah_len(cheetah_str s) {
+    int64_t len = 0;
+    int64_t at;
+    for (at = 0; at < s.size; at = cheetah_char_end(s, at)) {
+        len++;
+    }
+    return len;
+}
+
+int64_t cheetah_offset(cheetah_str s, int64_t index) {
+    int64_t at = 0;
+    for (; index > 0; index--) {
+        at = cheetah_char_end(s, at);
+    }
+    return at;
+}
+
+cheetah_str cheetah_substr(cheetah_str s, int64_t from, int64_t to) {
+    cheetah_str sub;
+    sub.bytes = s.bytes + from;
+    sub.size = to - from;
+    return sub;
+}
+
+cheetah_str cheetah_index(cheetah_str s, int64_t index, const char *where) {
+    int64_t len = cheetah_len(s);
+    int64_t at;
+    if (index < 0 || index >= len) {
+        cheetah_fail(where,
+                     "index out of bounds: the length is %" PRId64 " but the index is %" PRId64,
+                     len, index);
+    }
+    at = cheetah_offset(s, index);
+    return cheetah_substr(s, at, cheetah_char_end(s, at));
+}
+
+cheetah_str cheetah_slice(cheetah_str s, int64_t start, int64_t end, const char *where) {
+    int64_t len = cheetah_len(s);
+    if (start < 0 || start > end || end > len) {
+        cheetah_fail(where, "slice `%" PRId64 "..%" PRId64 "` is out of bounds for length %" PRId64,
+                     start, end, len);
+    }
+    return cheetah_substr(s, cheetah_offset(s, start), cheetah_offset(s, end));
+}
+
+/* The character at byte `*at`, moving `*at` past it */
+cheetah_str cheetah_next_char(cheetah_str s, int64_t *at) {
+    int64_t start = *at;
+    *at = cheetah_char_end(s, start);
+    return cheetah_substr(s, start, *at);
+}
+
+cheetah_str cheetah_int_str(int64_t value) {
+    char *bytes = cheetah_alloc(24);
+    cheetah_str s;
+    s.bytes = bytes;
+    s.size = sprintf(bytes, "%" PRId64, value);
+    return s;
+}
+
+cheetah_str cheetah_bool_str(bool value) {
+    cheetah_str s;
+    s.bytes = value ? "true" : "false";
+    s.size = value ? 4 : 5;
+    return s;
+}
+
+/* `print` returns `()`, which is an int that is always 0 */
+int cheetah_print_int(int64_t value) {
+    printf("%" PRId64 "\n", value);
+    return 0;
+}
+
+int cheetah_print_bool(bool value) {
+    puts(value ? "true" : "false");
+    return 0;
+}
+
+int cheetah_print_str(cheetah_str s) {
+    fwrite(s.bytes, 1, s.size, stdout);
+    putchar('\n');
+    return 0;
+}
+
+int cheetah_print_unit(int unit) {
+    (void)unit;
+    puts("()");
+    return 0;
+}
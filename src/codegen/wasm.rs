@@ -0,0 +1,1242 @@
+use crate::ast::ast::{BinOp, Block, Expr, Function, Ident, Literal, Program, Stmt, Type, UnOp};
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::interp::interp::MAX_CALL_DEPTH;
+use crate::sema::builtins::Builtin;
+use crate::sema::check::Checker;
+use crate::sema::types::Ty;
+use crate::source::map::SourceMap;
+use crate::source::span::Span;
+use std::fmt::Write;
+
+/* The functions a module imports from its host, all from "cheetah". The
+ * `print_` ones print a value and a newline. `error` is given where its
+ * message and the location of the error start in memory, both ended by
+ * a NUL, and a value that stands for `{}` in the message. It must not
+ * return, the module traps if it does. */
+const IMPORTS: [(&str, &[ValType]); 4] = [
+    ("print_int", &[ValType::I64]),
+    ("print_bool", &[ValType::I32]),
+    ("print_unit", &[]),
+    ("error", &[ValType::I32, ValType::I32, ValType::I64]),
+];
+const PRINT_INT: u32 = 0;
+const PRINT_BOOL: u32 = 1;
+const PRINT_UNIT: u32 = 2;
+const ERROR: u32 = 3;
+/* The script, right after the imports */
+const MAIN: u32 = 4;
+/* The only global, how deeply calls are nested */
+const DEPTH: u32 = 0;
+
+/* Compiles a checked program to a WebAssembly module. `int` is `i64`,
+ * `bool` is `i32` and `()` is no value at all. The script becomes the
+ * exported `main`, which prints through the imports and reports runtime
+ * errors the way the interpreter words them. Only `int` and `bool`
+ * values are supported, anything that needs a string or a list is
+ * reported. */
+pub fn compile(
+    program: &Program,
+    checker: &Checker,
+    sources: &SourceMap,
+) -> Result<Module, Vec<Diagnostic>> {
+    let mut generator = Generator {
+        checker,
+        sources,
+        functions: vec![],
+        helpers: vec![],
+        strings: vec![],
+        frame: Frame::new(0),
+        module: Module {
+            types: vec![],
+            names: IMPORTS.iter().map(|(name, _)| name.to_string()).collect(),
+            functions: vec![],
+            data: vec![],
+        },
+        diagnostics: vec![],
+    };
+    for (_, params) in IMPORTS {
+        generator.module.type_index(params.to_vec(), vec![]);
+    }
+    for function in &program.functions {
+        let returns = match &function.ret {
+            Some(ret) => Kind::of_val_type(generator.val_type(ret, function.ret_span)),
+            None => Kind::Unit,
+        };
+        generator
+            .functions
+            .push((function.name.name.clone(), returns));
+    }
+    generator.script(program);
+    for function in &program.functions {
+        generator.function(function);
+    }
+    for helper in generator.helpers.clone() {
+        generator.helper_function(helper);
+    }
+    match generator.diagnostics.is_empty() {
+        true => Ok(generator.module),
+        false => Err(generator.diagnostics),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ValType {
+    I32,
+    I64,
+}
+
+impl ValType {
+    fn name(self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+        }
+    }
+
+    fn byte(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7f,
+            ValType::I64 => 0x7e,
+        }
+    }
+}
+
+/* What an expression leaves on the stack. After code that never goes on
+ * the stack can be anything, which is `Never`. */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+    Unit,
+    Int,
+    Bool,
+    Never,
+}
+
+impl Kind {
+    fn of(ty: Option<&Ty>) -> Kind {
+        match ty {
+            Some(Ty::Int) => Kind::Int,
+            Some(Ty::Bool) => Kind::Bool,
+            Some(Ty::Never) => Kind::Never,
+            _ => Kind::Unit,
+        }
+    }
+
+    fn of_val_type(ty: ValType) -> Kind {
+        match ty {
+            ValType::I32 => Kind::Bool,
+            ValType::I64 => Kind::Int,
+        }
+    }
+
+    fn val_type(self) -> Option<ValType> {
+        match self {
+            Kind::Int => Some(ValType::I64),
+            Kind::Bool => Some(ValType::I32),
+            Kind::Unit | Kind::Never => None,
+        }
+    }
+}
+
+/* The instructions the backend uses. Blocks and loops never have a
+ * result, only ifs do. */
+#[derive(Clone, Copy, Debug)]
+enum Instr {
+    Plain(&'static str, u8),
+    I32Const(i32),
+    I64Const(i64),
+    LocalGet(u32),
+    LocalSet(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    Call(u32),
+    Block,
+    Loop,
+    If(Option<ValType>),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+}
+
+const UNREACHABLE: Instr = Instr::Plain("unreachable", 0x00);
+const RETURN: Instr = Instr::Plain("return", 0x0f);
+const DROP: Instr = Instr::Plain("drop", 0x1a);
+const I32_EQZ: Instr = Instr::Plain("i32.eqz", 0x45);
+const I32_EQ: Instr = Instr::Plain("i32.eq", 0x46);
+const I32_NE: Instr = Instr::Plain("i32.ne", 0x47);
+const I32_GE_S: Instr = Instr::Plain("i32.ge_s", 0x4e);
+const I32_ADD: Instr = Instr::Plain("i32.add", 0x6a);
+const I32_SUB: Instr = Instr::Plain("i32.sub", 0x6b);
+const I32_AND: Instr = Instr::Plain("i32.and", 0x71);
+const I64_EQZ: Instr = Instr::Plain("i64.eqz", 0x50);
+const I64_EQ: Instr = Instr::Plain("i64.eq", 0x51);
+const I64_NE: Instr = Instr::Plain("i64.ne", 0x52);
+const I64_LT_S: Instr = Instr::Plain("i64.lt_s", 0x53);
+const I64_GT_S: Instr = Instr::Plain("i64.gt_s", 0x55);
+const I64_GT_U: Instr = Instr::Plain("i64.gt_u", 0x56);
+const I64_LE_S: Instr = Instr::Plain("i64.le_s", 0x57);
+const I64_GE_S: Instr = Instr::Plain("i64.ge_s", 0x59);
+const I64_ADD: Instr = Instr::Plain("i64.add", 0x7c);
+const I64_SUB: Instr = Instr::Plain("i64.sub", 0x7d);
+const I64_MUL: Instr = Instr::Plain("i64.mul", 0x7e);
+const I64_DIV_S: Instr = Instr::Plain("i64.div_s", 0x7f);
+const I64_REM_S: Instr = Instr::Plain("i64.rem_s", 0x81);
+const I64_AND: Instr = Instr::Plain("i64.and", 0x83);
+const I64_OR: Instr = Instr::Plain("i64.or", 0x84);
+const I64_XOR: Instr = Instr::Plain("i64.xor", 0x85);
+const I64_SHL: Instr = Instr::Plain("i64.shl", 0x86);
+const I64_SHR_S: Instr = Instr::Plain("i64.shr_s", 0x87);
+
+/* A compiled module, written out with `text` or `binary`. Both have the
+ * same sections in the same order, so assembling the text gives exactly
+ * the binary. */
+pub struct Module {
+    types: Vec<(Vec<ValType>, Vec<ValType>)>,
+    /* The names of every function, the imports first, for comments */
+    names: Vec<String>,
+    functions: Vec<Func>,
+    /* The strings runtime errors print, from address 0 */
+    data: Vec<u8>,
+}
+
+struct Func {
+    ty: u32,
+    locals: Vec<ValType>,
+    code: Vec<Instr>,
+}
+
+impl Module {
+    fn type_index(&mut self, params: Vec<ValType>, results: Vec<ValType>) -> u32 {
+        let ty = (params, results);
+        match self.types.iter().position(|found| *found == ty) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(ty);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+
+    fn pages(&self) -> usize {
+        self.data.len().div_ceil(65536).max(1)
+    }
+
+    /* The module in the text format, one instruction a line */
+    pub fn text(&self) -> String {
+        let mut out = String::from("(module\n");
+        for (i, (params, results)) in self.types.iter().enumerate() {
+            let _ = write!(out, "  (type (;{};) (func", i);
+            for (keyword, types) in [("param", params), ("result", results)] {
+                if !types.is_empty() {
+                    let names: Vec<_> = types.iter().map(|ty| ty.name()).collect();
+                    let _ = write!(out, " ({} {})", keyword, names.join(" "));
+                }
+            }
+            out += "))\n";
+        }
+        for (i, (name, _)) in IMPORTS.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "  (import \"cheetah\" \"{}\" (func (;{};) (type {})))",
+                name, i, i
+            );
+        }
+        for (i, function) in self.functions.iter().enumerate() {
+            let index = IMPORTS.len() + i;
+            let _ = writeln!(
+                out,
+                "  (func (;{};) (type {}) ;; {}",
+                index, function.ty, self.names[index]
+            );
+            if !function.locals.is_empty() {
+                let names: Vec<_> = function.locals.iter().map(|ty| ty.name()).collect();
+                let _ = writeln!(out, "    (local {})", names.join(" "));
+            }
+            let mut depth = 2;
+            for instr in &function.code {
+                if matches!(instr, Instr::Else | Instr::End) {
+                    depth -= 1;
+                }
+                let _ = writeln!(out, "{}{}", "  ".repeat(depth), self.instr_text(instr));
+                if matches!(
+                    instr,
+                    Instr::Block | Instr::Loop | Instr::If(_) | Instr::Else
+                ) {
+                    depth += 1;
+                }
+            }
+            out += "  )\n";
+        }
+        let _ = writeln!(out, "  (memory (;0;) {})", self.pages());
+        out += "  (global (;0;) (mut i32) (i32.const 0))\n";
+        let _ = writeln!(out, "  (export \"main\" (func {}))", MAIN);
+        out += "  (export \"memory\" (memory 0))\n";
+        if !self.data.is_empty() {
+            out += "  (data (;0;) (i32.const 0) \"";
+            for &byte in &self.data {
+                match byte {
+                    b'"' | b'\\' => out += &format!("\\{}", byte as char),
+                    b' '..=b'~' => out.push(byte as char),
+                    byte => out += &format!("\\{:02x}", byte),
+                }
+            }
+            out += "\")\n";
+        }
+        out + ")\n"
+    }
+
+    fn instr_text(&self, instr: &Instr) -> String {
+        match instr {
+            Instr::Plain(name, _) => name.to_string(),
+            Instr::I32Const(value) => format!("i32.const {}", value),
+            Instr::I64Const(value) => format!("i64.const {}", value),
+            Instr::LocalGet(index) => format!("local.get {}", index),
+            Instr::LocalSet(index) => format!("local.set {}", index),
+            Instr::GlobalGet(index) => format!("global.get {}", index),
+            Instr::GlobalSet(index) => format!("global.set {}", index),
+            Instr::Call(index) => format!("call {} ;; {}", index, self.names[*index as usize]),
+            Instr::Block => "block".to_string(),
+            Instr::Loop => "loop".to_string(),
+            Instr::If(None) => "if".to_string(),
+            Instr::If(Some(ty)) => format!("if (result {})", ty.name()),
+            Instr::Else => "else".to_string(),
+            Instr::End => "end".to_string(),
+            Instr::Br(depth) => format!("br {}", depth),
+            Instr::BrIf(depth) => format!("br_if {}", depth),
+        }
+    }
+
+    /* The module in the binary format */
+    pub fn binary(&self) -> Vec<u8> {
+        let mut out = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut types = vec![];
+        unsigned(&mut types, self.types.len() as u64);
+        for (params, results) in &self.types {
+            types.push(0x60);
+            for list in [params, results] {
+                unsigned(&mut types, list.len() as u64);
+                types.extend(list.iter().map(|ty| ty.byte()));
+            }
+        }
+        section(&mut out, 1, &types);
+
+        let mut imports = vec![];
+        unsigned(&mut imports, IMPORTS.len() as u64);
+        for (i, (name, _)) in IMPORTS.iter().enumerate() {
+            bytes(&mut imports, b"cheetah");
+            bytes(&mut imports, name.as_bytes());
+            imports.push(0x00);
+            unsigned(&mut imports, i as u64);
+        }
+        section(&mut out, 2, &imports);
+
+        let mut functions = vec![];
+        unsigned(&mut functions, self.functions.len() as u64);
+        for function in &self.functions {
+            unsigned(&mut functions, function.ty as u64);
+        }
+        section(&mut out, 3, &functions);
+
+        let mut memory = vec![1, 0x00];
+        unsigned(&mut memory, self.pages() as u64);
+        section(&mut out, 5, &memory);
+
+        /* A mutable i32 starting at `i32.const 0` */
+        section(&mut out, 6, &[1, 0x7f, 0x01, 0x41, 0x00, 0x0b]);
+
+        let mut exports = vec![2];
+        bytes(&mut exports, b"main");
+        exports.push(0x00);
+        unsigned(&mut exports, MAIN as u64);
+        bytes(&mut exports, b"memory");
+        exports.extend([0x02, 0x00]);
+        section(&mut out, 7, &exports);
+
+        let mut code = vec![];
+        unsigned(&mut code, self.functions.len() as u64);
+        for function in &self.functions {
+            let body = function_body(function);
+            unsigned(&mut code, body.len() as u64);
+            code.extend(body);
+        }
+        section(&mut out, 10, &code);
+
+        if !self.data.is_empty() {
+            let mut data = vec![1, 0x00, 0x41, 0x00, 0x0b];
+            bytes(&mut data, &self.data);
+            section(&mut out, 11, &data);
+        }
+        out
+    }
+}
+
+/* The locals, runs of the same type counted together, then the code */
+fn function_body(function: &Func) -> Vec<u8> {
+    let mut runs: Vec<(u32, ValType)> = vec![];
+    for &ty in &function.locals {
+        match runs.last_mut() {
+            Some((count, last)) if *last == ty => *count += 1,
+            _ => runs.push((1, ty)),
+        }
+    }
+    let mut out = vec![];
+    unsigned(&mut out, runs.len() as u64);
+    for (count, ty) in runs {
+        unsigned(&mut out, count as u64);
+        out.push(ty.byte());
+    }
+    for instr in &function.code {
+        match *instr {
+            Instr::Plain(_, opcode) => out.push(opcode),
+            Instr::I32Const(value) => {
+                out.push(0x41);
+                signed(&mut out, value as i64);
+            }
+            Instr::I64Const(value) => {
+                out.push(0x42);
+                signed(&mut out, value);
+            }
+            Instr::LocalGet(index) => operand(&mut out, 0x20, index),
+            Instr::LocalSet(index) => operand(&mut out, 0x21, index),
+            Instr::GlobalGet(index) => operand(&mut out, 0x23, index),
+            Instr::GlobalSet(index) => operand(&mut out, 0x24, index),
+            Instr::Call(index) => operand(&mut out, 0x10, index),
+            Instr::Block => out.extend([0x02, 0x40]),
+            Instr::Loop => out.extend([0x03, 0x40]),
+            Instr::If(ty) => out.extend([0x04, ty.map_or(0x40, ValType::byte)]),
+            Instr::Else => out.push(0x05),
+            Instr::End => out.push(0x0b),
+            Instr::Br(depth) => operand(&mut out, 0x0c, depth),
+            Instr::BrIf(depth) => operand(&mut out, 0x0d, depth),
+        }
+    }
+    out.push(0x0b);
+    out
+}
+
+fn section(out: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    out.push(id);
+    bytes(out, contents);
+}
+
+/* A vector of bytes, its length first */
+fn bytes(out: &mut Vec<u8>, contents: &[u8]) {
+    unsigned(out, contents.len() as u64);
+    out.extend(contents);
+}
+
+fn operand(out: &mut Vec<u8>, opcode: u8, index: u32) {
+    out.push(opcode);
+    unsigned(out, index as u64);
+}
+
+/* LEB128, seven bits a byte with the high bit set on all but the last */
+fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        match value {
+            0 => return out.push(byte),
+            _ => out.push(byte | 0x80),
+        }
+    }
+}
+
+/* Signed LEB128 stops once the rest is all sign bits, including the sign
+ * bit of the last byte */
+fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        match done {
+            true => return out.push(byte),
+            false => out.push(byte | 0x80),
+        }
+    }
+}
+
+/* The functions that check an operation before doing it, added to the
+ * module the first time they are called. All but `Enter` return the
+ * result, `Enter` counts a call about to be made. Each takes where the
+ * error would be, as its last parameter. */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Helper {
+    Enter,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Neg,
+    Shl,
+    Shr,
+}
+
+impl Helper {
+    fn name(self) -> &'static str {
+        match self {
+            Helper::Enter => "enter",
+            Helper::Add => "checked_add",
+            Helper::Sub => "checked_sub",
+            Helper::Mul => "checked_mul",
+            Helper::Div => "checked_div",
+            Helper::Rem => "checked_rem",
+            Helper::Neg => "checked_neg",
+            Helper::Shl => "checked_shl",
+            Helper::Shr => "checked_shr",
+        }
+    }
+}
+
+/* A name in scope and the local it is kept in */
+struct Local {
+    name: String,
+    index: u32,
+    kind: Kind,
+    depth: usize,
+}
+
+/* The function being generated. Parameters are the first locals. */
+struct Frame {
+    params: usize,
+    locals: Vec<Local>,
+    types: Vec<ValType>,
+    depth: usize,
+    code: Vec<Instr>,
+}
+
+impl Frame {
+    fn new(params: usize) -> Frame {
+        Frame {
+            params,
+            locals: vec![],
+            types: vec![],
+            depth: 0,
+            code: vec![],
+        }
+    }
+}
+
+struct Generator<'a> {
+    checker: &'a Checker,
+    sources: &'a SourceMap,
+    /* The functions of the program and what they return */
+    functions: Vec<(String, Kind)>,
+    helpers: Vec<Helper>,
+    /* Where each string put in the data starts */
+    strings: Vec<(String, i32)>,
+    frame: Frame,
+    module: Module,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Generator<'_> {
+    /* The statements outside of functions, printing the value of the last
+     * one like `cheetah run` does. Scripts cannot return, so their lets
+     * are locals of `main`. */
+    fn script(&mut self, program: &Program) {
+        self.frame = Frame::new(0);
+        for (i, stmt) in program.stmts.iter().enumerate() {
+            let kind = match stmt {
+                Stmt::Expr(expr) if i + 1 == program.stmts.len() => self.expr(expr),
+                Stmt::Block(block) if i + 1 == program.stmts.len() => self.block(block),
+                stmt => {
+                    self.stmt(stmt);
+                    continue;
+                }
+            };
+            match kind {
+                Kind::Int => self.emit(Instr::Call(PRINT_INT)),
+                Kind::Bool => self.emit(Instr::Call(PRINT_BOOL)),
+                Kind::Unit | Kind::Never => {}
+            }
+        }
+        self.finish("main", vec![], None);
+    }
+
+    fn function(&mut self, function: &Function) {
+        let params: Vec<ValType> = function
+            .params
+            .iter()
+            .map(|param| self.val_type(&param.ty, param.ty_span))
+            .collect();
+        self.frame = Frame::new(params.len());
+        for (i, (param, &ty)) in function.params.iter().zip(&params).enumerate() {
+            self.frame.locals.push(Local {
+                name: param.name.name.clone(),
+                index: i as u32,
+                kind: Kind::of_val_type(ty),
+                depth: 0,
+            });
+        }
+        let returns = self
+            .functions
+            .iter()
+            .find(|(name, _)| *name == function.name.name)
+            .map_or(Kind::Unit, |(_, returns)| *returns);
+        let kind = self.block(&function.body);
+        self.fit(kind, returns);
+        self.finish(&function.name.name, params, returns.val_type());
+    }
+
+    fn finish(&mut self, name: &str, params: Vec<ValType>, result: Option<ValType>) {
+        let frame = std::mem::replace(&mut self.frame, Frame::new(0));
+        let ty = self.module.type_index(params, result.into_iter().collect());
+        self.module.names.push(name.to_string());
+        self.module.functions.push(Func {
+            ty,
+            locals: frame.types,
+            code: frame.code,
+        });
+    }
+
+    /* Returns whether control never makes it past the statement. Values
+     * of expression statements are dropped. */
+    fn stmt(&mut self, stmt: &Stmt) -> bool {
+        match stmt {
+            Stmt::Let {
+                name,
+                ty,
+                ty_span,
+                value,
+                ..
+            } => {
+                let ty = self.val_type(ty, *ty_span);
+                let kind = self.expr(value);
+                let index = self.declare(&name.name, ty);
+                self.emit(Instr::LocalSet(index));
+                kind == Kind::Never
+            }
+            Stmt::Assign { target, value, .. } => {
+                let kind = self.expr(value);
+                let (index, _) = self.place(&target.name);
+                self.emit(Instr::LocalSet(index));
+                kind == Kind::Never
+            }
+            Stmt::IndexAssign { list, .. } => {
+                self.unsupported("lists", list.span());
+                false
+            }
+            Stmt::While { cond, body, .. } => {
+                self.emit(Instr::Block);
+                self.emit(Instr::Loop);
+                self.expr(cond);
+                self.emit(I32_EQZ);
+                self.emit(Instr::BrIf(1));
+                let kind = self.block(body);
+                self.fit(kind, Kind::Unit);
+                self.emit(Instr::Br(0));
+                self.emit(Instr::End);
+                self.emit(Instr::End);
+                false
+            }
+            Stmt::For {
+                var, iter, body, ..
+            } => {
+                self.for_loop(var, iter, body);
+                false
+            }
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                self.emit(RETURN);
+                true
+            }
+            Stmt::Block(block) => {
+                let kind = self.block(block);
+                self.fit(kind, Kind::Unit);
+                kind == Kind::Never
+            }
+            Stmt::Expr(expr) => {
+                let kind = self.expr(expr);
+                self.fit(kind, Kind::Unit);
+                kind == Kind::Never
+            }
+        }
+    }
+
+    /* Only ranges are counted through, in a hidden counter next to the
+     * hidden end, copied into the variable in every iteration */
+    fn for_loop(&mut self, var: &Ident, iter: &Expr, body: &Block) {
+        let Expr::Range { start, end, .. } = iter else {
+            self.unsupported(self.collection(iter), iter.span());
+            return;
+        };
+        self.frame.depth += 1;
+        self.expr(start);
+        let counter = self.declare("", ValType::I64);
+        self.emit(Instr::LocalSet(counter));
+        self.expr(end);
+        let end = self.declare("", ValType::I64);
+        self.emit(Instr::LocalSet(end));
+
+        self.emit(Instr::Block);
+        self.emit(Instr::Loop);
+        self.emit(Instr::LocalGet(counter));
+        self.emit(Instr::LocalGet(end));
+        self.emit(I64_GE_S);
+        self.emit(Instr::BrIf(1));
+        self.frame.depth += 1;
+        let var = self.declare(&var.name, ValType::I64);
+        self.emit(Instr::LocalGet(counter));
+        self.emit(Instr::LocalSet(var));
+        let kind = self.block(body);
+        self.fit(kind, Kind::Unit);
+        self.end_scope();
+        self.emit(Instr::LocalGet(counter));
+        self.emit(Instr::I64Const(1));
+        self.emit(I64_ADD);
+        self.emit(Instr::LocalSet(counter));
+        self.emit(Instr::Br(0));
+        self.emit(Instr::End);
+        self.emit(Instr::End);
+        self.end_scope();
+    }
+
+    /* Blocks are only scopes, their code goes inline */
+    fn block(&mut self, block: &Block) -> Kind {
+        self.frame.depth += 1;
+        let mut diverges = false;
+        for stmt in &block.stmts {
+            diverges |= self.stmt(stmt);
+        }
+        let kind = match &block.tail {
+            Some(tail) => self.expr(tail),
+            None if diverges => Kind::Never,
+            None => Kind::Unit,
+        };
+        self.end_scope();
+        kind
+    }
+
+    fn end_scope(&mut self) {
+        self.frame.depth -= 1;
+        let depth = self.frame.depth;
+        self.frame.locals.retain(|local| local.depth <= depth);
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Kind {
+        match expr {
+            Expr::Literal { value, span } => match value {
+                Literal::Int(value) => {
+                    self.emit(Instr::I64Const(*value));
+                    Kind::Int
+                }
+                Literal::Bool(value) => {
+                    self.emit(Instr::I32Const(*value as i32));
+                    Kind::Bool
+                }
+                Literal::Str(_) => self.unsupported("strings", *span),
+            },
+            Expr::Ident(ident) => {
+                let (index, kind) = self.place(&ident.name);
+                self.emit(Instr::LocalGet(index));
+                kind
+            }
+            Expr::Call { callee, args, span } => self.call(callee, args, *span),
+            Expr::Unary {
+                op, operand, span, ..
+            } => {
+                let kind = self.expr(operand);
+                match op {
+                    UnOp::Not => self.emit(I32_EQZ),
+                    UnOp::BitNot => {
+                        self.emit(Instr::I64Const(-1));
+                        self.emit(I64_XOR);
+                    }
+                    UnOp::Neg => {
+                        self.checked(Helper::Neg, *span);
+                    }
+                    UnOp::Plus => {}
+                }
+                kind
+            }
+            Expr::Binary {
+                op: op @ (BinOp::And | BinOp::Or),
+                lhs,
+                rhs,
+                ..
+            } => {
+                /* The left side decides, or leaves it to the right side */
+                self.expr(lhs);
+                self.emit(Instr::If(Some(ValType::I32)));
+                if *op == BinOp::Or {
+                    self.emit(Instr::I32Const(1));
+                    self.emit(Instr::Else);
+                }
+                let kind = self.expr(rhs);
+                self.fit(kind, Kind::Bool);
+                if *op == BinOp::And {
+                    self.emit(Instr::Else);
+                    self.emit(Instr::I32Const(0));
+                }
+                self.emit(Instr::End);
+                Kind::Bool
+            }
+            Expr::Binary {
+                op, lhs, rhs, span, ..
+            } => {
+                let left = self.expr(lhs);
+                let right = self.expr(rhs);
+                self.binary(*op, left == Kind::Bool || right == Kind::Bool, *span)
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let kind = Kind::of(self.checker.type_of(expr));
+                let wanted = match kind {
+                    Kind::Never => Kind::Unit,
+                    kind => kind,
+                };
+                self.expr(cond);
+                self.emit(Instr::If(wanted.val_type()));
+                let found = self.block(then_branch);
+                self.fit(found, wanted);
+                if let Some(else_branch) = else_branch {
+                    self.emit(Instr::Else);
+                    let found = self.expr(else_branch);
+                    self.fit(found, wanted);
+                }
+                self.emit(Instr::End);
+                /* Both branches return, but wasm does not know that */
+                if kind == Kind::Never {
+                    self.emit(UNREACHABLE);
+                }
+                kind
+            }
+            Expr::Block(block) => self.block(block),
+            Expr::List { span, .. } => self.unsupported("lists", *span),
+            Expr::Index { list, span, .. } => self.unsupported(self.collection(list), *span),
+            Expr::Template { span, .. } => self.unsupported("strings", *span),
+            Expr::Range { .. } => unreachable!("ranges are only iterated over"),
+        }
+    }
+
+    /* Applies an operator to the two operands on the stack, `bools` if
+     * they are compared as `bool` */
+    fn binary(&mut self, op: BinOp, bools: bool, span: Span) -> Kind {
+        let (instr, kind) = match op {
+            BinOp::Eq if bools => (I32_EQ, Kind::Bool),
+            BinOp::Ne if bools => (I32_NE, Kind::Bool),
+            BinOp::Eq => (I64_EQ, Kind::Bool),
+            BinOp::Ne => (I64_NE, Kind::Bool),
+            BinOp::Lt => (I64_LT_S, Kind::Bool),
+            BinOp::Le => (I64_LE_S, Kind::Bool),
+            BinOp::Gt => (I64_GT_S, Kind::Bool),
+            BinOp::Ge => (I64_GE_S, Kind::Bool),
+            BinOp::BitOr => (I64_OR, Kind::Int),
+            BinOp::BitXor => (I64_XOR, Kind::Int),
+            BinOp::BitAnd => (I64_AND, Kind::Int),
+            BinOp::Add => return self.checked(Helper::Add, span),
+            BinOp::Sub => return self.checked(Helper::Sub, span),
+            BinOp::Mul => return self.checked(Helper::Mul, span),
+            BinOp::Div => return self.checked(Helper::Div, span),
+            BinOp::Mod => return self.checked(Helper::Rem, span),
+            BinOp::Shl => return self.checked(Helper::Shl, span),
+            BinOp::Shr => return self.checked(Helper::Shr, span),
+            BinOp::And | BinOp::Or => unreachable!("short-circuiting operators are ifs"),
+        };
+        self.emit(instr);
+        kind
+    }
+
+    /* Calls the helper that checks an operation, blaming `span` */
+    fn checked(&mut self, helper: Helper, span: Span) -> Kind {
+        let location = self.location(span);
+        self.emit(Instr::I32Const(location));
+        let index = self.helper(helper);
+        self.emit(Instr::Call(index));
+        Kind::Int
+    }
+
+    /* Calls are counted by the caller, `Enter` checks there is room for
+     * one more before it is made */
+    fn call(&mut self, callee: &Ident, args: &[Expr], span: Span) -> Kind {
+        let Some(index) = self
+            .functions
+            .iter()
+            .position(|(name, _)| *name == callee.name)
+        else {
+            return self.builtin(callee, args, span);
+        };
+        for arg in args {
+            self.expr(arg);
+        }
+        self.checked(Helper::Enter, span);
+        self.emit(Instr::Call(MAIN + 1 + index as u32));
+        self.emit(Instr::GlobalGet(DEPTH));
+        self.emit(Instr::I32Const(1));
+        self.emit(I32_SUB);
+        self.emit(Instr::GlobalSet(DEPTH));
+        self.functions[index].1
+    }
+
+    fn builtin(&mut self, callee: &Ident, args: &[Expr], span: Span) -> Kind {
+        match Builtin::ALL
+            .iter()
+            .find(|builtin| builtin.name() == callee.name)
+        {
+            Some(Builtin::Print) => {
+                let print = match self.expr(&args[0]) {
+                    Kind::Int => PRINT_INT,
+                    Kind::Bool => PRINT_BOOL,
+                    Kind::Unit => PRINT_UNIT,
+                    Kind::Never => return Kind::Never,
+                };
+                self.emit(Instr::Call(print));
+                Kind::Unit
+            }
+            Some(Builtin::Len) => self.unsupported("`len`", span),
+            None => panic!("unresolved function `{}`", callee.name),
+        }
+    }
+
+    /* Makes what an expression left on the stack what is wanted there,
+     * which for a well typed program only ever drops a value */
+    fn fit(&mut self, found: Kind, wanted: Kind) {
+        if found != wanted && found != Kind::Never && wanted == Kind::Unit {
+            self.emit(DROP);
+        }
+    }
+
+    /* The index of a helper, adding it after the program's functions */
+    fn helper(&mut self, helper: Helper) -> u32 {
+        let position = match self.helpers.iter().position(|h| *h == helper) {
+            Some(position) => position,
+            None => {
+                self.helpers.push(helper);
+                self.helpers.len() - 1
+            }
+        };
+        MAIN + 1 + (self.functions.len() + position) as u32
+    }
+
+    /* The operands come first and where the error is last. Errors call
+     * the host's `error`, which does not return. */
+    fn helper_function(&mut self, helper: Helper) {
+        let (a, b) = (Instr::LocalGet(0), Instr::LocalGet(1));
+        let overflow = |operation: &str| format!("attempt to {} with overflow", operation);
+        let params = match helper {
+            Helper::Enter => vec![ValType::I32],
+            Helper::Neg => vec![ValType::I64, ValType::I32],
+            _ => vec![ValType::I64, ValType::I64, ValType::I32],
+        };
+        let at = params.len() as u32 - 1;
+        self.frame = Frame::new(params.len());
+        match helper {
+            Helper::Enter => {
+                self.emit(Instr::GlobalGet(DEPTH));
+                self.emit(Instr::I32Const(MAX_CALL_DEPTH as i32));
+                self.emit(I32_GE_S);
+                self.fail_if("stack overflow", at, None);
+                self.emit(Instr::GlobalGet(DEPTH));
+                self.emit(Instr::I32Const(1));
+                self.emit(I32_ADD);
+                self.emit(Instr::GlobalSet(DEPTH));
+            }
+            Helper::Add | Helper::Sub => {
+                /* Overflow flips the sign in a way the operands cannot */
+                let index = self.declare("", ValType::I64);
+                let result = Instr::LocalGet(index);
+                let (op, operation, (x, y)) = match helper {
+                    Helper::Add => (I64_ADD, "add", (b, result)),
+                    _ => (I64_SUB, "subtract", (a, b)),
+                };
+                self.emit_all(&[a, b, op, Instr::LocalSet(index)]);
+                self.emit_all(&[a, result, I64_XOR, x, y, I64_XOR]);
+                self.emit_all(&[I64_AND, Instr::I64Const(0), I64_LT_S]);
+                self.fail_if(&overflow(operation), at, None);
+                self.emit(result);
+            }
+            Helper::Mul => {
+                /* Dividing the product gives back the operand unless it
+                 * overflowed, where -1 times the smallest int cannot be
+                 * divided back */
+                let result = self.declare("", ValType::I64);
+                self.emit_all(&[a, b, I64_MUL, Instr::LocalSet(result)]);
+                self.emit_all(&[a, Instr::I64Const(-1), I64_EQ]);
+                self.emit(Instr::If(Some(ValType::I32)));
+                self.emit_all(&[b, Instr::I64Const(i64::MIN), I64_EQ]);
+                self.emit(Instr::Else);
+                self.emit_all(&[a, I64_EQZ]);
+                self.emit(Instr::If(Some(ValType::I32)));
+                self.emit(Instr::I32Const(0));
+                self.emit(Instr::Else);
+                self.emit_all(&[Instr::LocalGet(result), a, I64_DIV_S, b, I64_NE]);
+                self.emit(Instr::End);
+                self.emit(Instr::End);
+                self.fail_if(&overflow("multiply"), at, None);
+                self.emit(Instr::LocalGet(result));
+            }
+            Helper::Div | Helper::Rem => {
+                let (op, zero, operation) = match helper {
+                    Helper::Div => (I64_DIV_S, "attempt to divide by zero", "divide"),
+                    _ => (
+                        I64_REM_S,
+                        "attempt to calculate the remainder with a divisor of zero",
+                        "calculate the remainder",
+                    ),
+                };
+                self.emit_all(&[b, I64_EQZ]);
+                self.fail_if(zero, at, None);
+                self.emit_all(&[a, Instr::I64Const(i64::MIN), I64_EQ]);
+                self.emit_all(&[b, Instr::I64Const(-1), I64_EQ, I32_AND]);
+                self.fail_if(&overflow(operation), at, None);
+                self.emit_all(&[a, b, op]);
+            }
+            Helper::Neg => {
+                self.emit_all(&[a, Instr::I64Const(i64::MIN), I64_EQ]);
+                self.fail_if(&overflow("negate"), at, None);
+                self.emit_all(&[Instr::I64Const(0), a, I64_SUB]);
+            }
+            Helper::Shl | Helper::Shr => {
+                /* Negative amounts compare as huge unsigned numbers */
+                let (op, direction) = match helper {
+                    Helper::Shl => (I64_SHL, "left"),
+                    _ => (I64_SHR_S, "right"),
+                };
+                let message = format!(
+                    "attempt to shift {} by `{{}}`, which would overflow",
+                    direction
+                );
+                self.emit_all(&[b, Instr::I64Const(63), I64_GT_U]);
+                self.fail_if(&message, at, Some(b));
+                self.emit_all(&[a, b, op]);
+            }
+        }
+        let result = (helper != Helper::Enter).then_some(ValType::I64);
+        self.finish(helper.name(), params, result);
+    }
+
+    /* Reports an error with `message` if the condition on the stack holds,
+     * the location being in local `at` */
+    fn fail_if(&mut self, message: &str, at: u32, value: Option<Instr>) {
+        let message = self.string(message);
+        self.emit(Instr::If(None));
+        self.emit(Instr::I32Const(message));
+        self.emit(Instr::LocalGet(at));
+        self.emit(value.unwrap_or(Instr::I64Const(0)));
+        self.emit(Instr::Call(ERROR));
+        self.emit(UNREACHABLE);
+        self.emit(Instr::End);
+    }
+
+    /* Where `span` starts, as the address of a string in the data */
+    fn location(&mut self, span: Span) -> i32 {
+        let file = self.sources.file(span.file);
+        let location = format!("{}:{}", file.name, file.location(span.start));
+        self.string(&location)
+    }
+
+    fn string(&mut self, text: &str) -> i32 {
+        if let Some((_, at)) = self.strings.iter().find(|(found, _)| found == text) {
+            return *at;
+        }
+        let at = self.module.data.len() as i32;
+        self.module.data.extend(text.as_bytes());
+        self.module.data.push(0);
+        self.strings.push((text.to_string(), at));
+        at
+    }
+
+    /* Gives a name the next free local, and returns its index */
+    fn declare(&mut self, name: &str, ty: ValType) -> u32 {
+        let index = (self.frame.params + self.frame.types.len()) as u32;
+        self.frame.types.push(ty);
+        self.frame.locals.push(Local {
+            name: name.to_string(),
+            index,
+            kind: Kind::of_val_type(ty),
+            depth: self.frame.depth,
+        });
+        index
+    }
+
+    /* The local a name is kept in and what it holds */
+    fn place(&self, name: &str) -> (u32, Kind) {
+        let found = self.frame.locals.iter().rev().find(|l| l.name == name);
+        let found = found.expect("names are resolved");
+        (found.index, found.kind)
+    }
+
+    fn val_type(&mut self, ty: &Type, span: Span) -> ValType {
+        match ty {
+            Type::Int => ValType::I64,
+            Type::Bool => ValType::I32,
+            Type::Str => {
+                self.unsupported("strings", span);
+                ValType::I64
+            }
+            Type::List(_) => {
+                self.unsupported("lists", span);
+                ValType::I64
+            }
+        }
+    }
+
+    /* What to call a value that is indexed or iterated over */
+    fn collection(&self, expr: &Expr) -> &'static str {
+        match self.checker.type_of(expr) {
+            Some(Ty::Str) => "strings",
+            _ => "lists",
+        }
+    }
+
+    /* Reports what cannot be compiled, once for each kind of value where
+     * it is first used. The code generated for it is never used, so it is
+     * treated as never going on. */
+    fn unsupported(&mut self, what: &str, span: Span) -> Kind {
+        let message = format!("{} cannot be compiled to WebAssembly", what);
+        if self.diagnostics.iter().any(|seen| seen.message == message) {
+            return Kind::Never;
+        }
+        self.diagnostics.push(
+            Diagnostic::error(message, span)
+                .with_note("WebAssembly modules only support `int` and `bool` values"),
+        );
+        Kind::Never
+    }
+
+    fn emit(&mut self, instr: Instr) {
+        self.frame.code.push(instr);
+    }
+
+    fn emit_all(&mut self, instrs: &[Instr]) {
+        self.frame.code.extend_from_slice(instrs);
+    }
+}
+
+/* What compiling `input` reports, nothing when it compiles */
+#[cfg(test)]
+fn diagnostics(input: &str) -> Vec<Diagnostic> {
+    use crate::testing::pipeline::check;
+
+    let mut sources = SourceMap::new();
+    sources.add_file("test.ch".to_string(), input.to_string());
+    let (program, checker) = check(input);
+    assert!(!checker.has_errors(), "{:?}", checker.diagnostics());
+    compile(&program, &checker, &sources)
+        .err()
+        .unwrap_or_default()
+}
+
+/* Strings are indexed and iterated over like lists, but are strings */
+#[test]
+fn names_strings_used_like_lists() {
+    for input in [
+        "def first(s: str) -> str { s[0] }",
+        "def each(s: str) { for c in s {} }",
+    ] {
+        let errors = diagnostics(input);
+        let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, ["strings cannot be compiled to WebAssembly"]);
+    }
+}
+
+#[test]
+fn rejects_strings_and_lists_once() {
+    let errors = diagnostics("def twice(s: str) -> str { s + s } let a: [int] = [1]; a[0];");
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "strings cannot be compiled to WebAssembly",
+            "lists cannot be compiled to WebAssembly",
+        ]
+    );
+}
+
+/* Runs sample programs in wasmi and compares what they print and how
+ * they fail with the interpreter. The text is assembled too, which has to
+ * give the same binary. */
+#[test]
+fn runs_like_the_interpreter() {
+    use crate::testing::differential::{compare_outcomes, SCALARS};
+    use wasmi::core::Trap;
+    use wasmi::{Caller, Config, Engine, Extern, Linker, StackLimits, Store};
+
+    #[derive(Default)]
+    struct Host {
+        output: String,
+        error: Option<String>,
+    }
+    fn string(memory: &[u8], at: i32) -> String {
+        let bytes = &memory[at as usize..];
+        let end = bytes.iter().position(|&byte| byte == 0).unwrap();
+        String::from_utf8(bytes[..end].to_vec()).unwrap()
+    }
+
+    compare_outcomes(SCALARS, |i, program, checker, sources| {
+        let module = compile(program, checker, sources).unwrap();
+        let binary = module.binary();
+        assert_eq!(
+            wat::parse_str(module.text()).unwrap(),
+            binary,
+            "{}",
+            SCALARS[i]
+        );
+
+        let mut config = Config::default();
+        config.set_stack_limits(StackLimits::new(1024, 1 << 24, 4 * MAX_CALL_DEPTH).unwrap());
+        let engine = Engine::new(&config);
+        let mut store = Store::new(&engine, Host::default());
+        let mut linker = Linker::<Host>::new(&engine);
+        let print = |caller: &mut Caller<'_, Host>, text: String| {
+            caller.data_mut().output += &format!("{}\n", text);
+        };
+        linker
+            .func_wrap(
+                "cheetah",
+                "print_int",
+                move |mut caller: Caller<'_, Host>, value: i64| {
+                    print(&mut caller, value.to_string())
+                },
+            )
+            .unwrap()
+            .func_wrap(
+                "cheetah",
+                "print_bool",
+                move |mut caller: Caller<'_, Host>, value: i32| {
+                    print(&mut caller, (value != 0).to_string())
+                },
+            )
+            .unwrap()
+            .func_wrap(
+                "cheetah",
+                "print_unit",
+                move |mut caller: Caller<'_, Host>| print(&mut caller, "()".to_string()),
+            )
+            .unwrap()
+            .func_wrap(
+                "cheetah",
+                "error",
+                |mut caller: Caller<'_, Host>, message: i32, location: i32, value: i64| {
+                    let memory = caller.get_export("memory").and_then(Extern::into_memory);
+                    let memory = memory.unwrap().data(&caller);
+                    let message = string(memory, message).replace("{}", &value.to_string());
+                    let location = string(memory, location);
+                    caller.data_mut().error =
+                        Some(format!("error: {}\n --> {}\n", message, location));
+                    Err::<(), _>(Trap::new("the program stopped with an error"))
+                },
+            )
+            .unwrap();
+        let module = wasmi::Module::new(&engine, &binary[..]).unwrap();
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        let main = instance.get_typed_func::<(), ()>(&store, "main").unwrap();
+        let result = main.call(&mut store, ());
+        let Host { output, error } = store.into_data();
+        assert_eq!(result.is_err(), error.is_some(), "{}", SCALARS[i]);
+        (output, error)
+    });
+}
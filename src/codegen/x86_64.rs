@@ -0,0 +1,728 @@
+use crate::ast::ast::{BinOp, Block, Expr, Function, Ident, Literal, Program, Stmt, Type, UnOp};
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::interp::interp::MAX_CALL_DEPTH;
+use crate::sema::builtins::Builtin;
+use crate::sema::check::Checker;
+use crate::sema::types::Ty;
+use crate::source::map::SourceMap;
+use crate::source::span::Span;
+use std::fmt::Write;
+
+/* Where the System V calling convention passes the first six arguments,
+ * the rest go on the stack */
+const ARGUMENTS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+
+/* Compiles a checked program to assembly for x86-64 Linux, in GNU as'
+ * Intel syntax, to be linked against the C library. The script becomes
+ * `main` and every function follows the System V calling convention.
+ * Only `int` and `bool` values are supported, anything that needs a string
+ * or a list is reported. Runtime errors are printed to stderr the way the
+ * interpreter words them, and exit with code 3. */
+pub fn compile(
+    program: &Program,
+    checker: &Checker,
+    sources: &SourceMap,
+) -> Result<String, Vec<Diagnostic>> {
+    let mut generator = Generator {
+        checker,
+        sources,
+        functions: program
+            .functions
+            .iter()
+            .map(|function| function.name.name.clone())
+            .collect(),
+        globals: vec![],
+        messages: vec![],
+        labels: 0,
+        frame: Frame::new(true, String::new()),
+        text: String::new(),
+        diagnostics: vec![],
+    };
+    generator.script(program);
+    for function in &program.functions {
+        generator.function(function);
+    }
+    match generator.diagnostics.is_empty() {
+        true => Ok(generator.assembly()),
+        false => Err(generator.diagnostics),
+    }
+}
+
+/* A name in the stack frame, `slot` counted down from the frame pointer */
+struct Local {
+    name: String,
+    slot: usize,
+    depth: usize,
+}
+
+/* The function being generated. `pushed` is how many temporaries are on
+ * the stack at the current instruction, which decides whether a call has
+ * to pad the stack to keep it 16 byte aligned. */
+struct Frame {
+    script: bool,
+    code: String,
+    locals: Vec<Local>,
+    depth: usize,
+    slots: usize,
+    pushed: usize,
+    /* Where `return` jumps to */
+    end: String,
+    /* The labels checks jump to when they fail, with their message */
+    failures: Vec<(String, usize)>,
+}
+
+impl Frame {
+    fn new(script: bool, end: String) -> Frame {
+        Frame {
+            script,
+            code: String::new(),
+            locals: vec![],
+            depth: 0,
+            slots: 0,
+            pushed: 0,
+            end,
+            failures: vec![],
+        }
+    }
+}
+
+struct Generator<'a> {
+    checker: &'a Checker,
+    sources: &'a SourceMap,
+    functions: Vec<String>,
+    globals: Vec<String>,
+    /* printf formats of the runtime errors, found by their index */
+    messages: Vec<String>,
+    labels: usize,
+    frame: Frame,
+    /* The functions generated so far */
+    text: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Generator<'_> {
+    /* The statements outside of functions, printing the value of the last
+     * one like `cheetah run` does */
+    fn script(&mut self, program: &Program) {
+        self.frame = Frame::new(true, self.label());
+        for (i, stmt) in program.stmts.iter().enumerate() {
+            let ty = match stmt {
+                Stmt::Expr(expr) if i + 1 == program.stmts.len() => {
+                    self.expr(expr);
+                    self.checker.type_of(expr).cloned()
+                }
+                Stmt::Block(block) if i + 1 == program.stmts.len() => {
+                    self.block(block);
+                    let tail = block.tail.as_ref();
+                    tail.and_then(|tail| self.checker.type_of(tail).cloned())
+                }
+                stmt => {
+                    self.stmt(stmt);
+                    continue;
+                }
+            };
+            if let Some(ty @ (Ty::Int | Ty::Bool)) = ty {
+                self.print(&ty);
+            }
+        }
+        self.emit("xor eax, eax");
+        self.finish("main");
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.frame = Frame::new(false, self.label());
+        if let Some(ret) = &function.ret {
+            self.supported(ret, function.ret_span);
+        }
+        for (i, param) in function.params.iter().enumerate() {
+            self.supported(&param.ty, param.ty_span);
+            let slot = self.declare(&param.name.name);
+            match ARGUMENTS.get(i) {
+                Some(register) => self.emit(format!("mov {}, {}", slot, register)),
+                None => {
+                    let offset = 16 + 8 * (i - ARGUMENTS.len());
+                    self.emit(format!("mov rax, qword ptr [rbp + {}]", offset));
+                    self.emit(format!("mov {}, rax", slot));
+                }
+            }
+        }
+        self.block(&function.body);
+        self.finish(&symbol(&function.name.name));
+    }
+
+    /* Wraps the code of the frame in a prologue and an epilogue, followed
+     * by the failed checks. Functions count how deeply calls are nested,
+     * which callers check before calling. */
+    fn finish(&mut self, name: &str) {
+        let frame = std::mem::replace(&mut self.frame, Frame::new(true, String::new()));
+        let size = 8 * (frame.slots + frame.slots % 2);
+        let _ = writeln!(self.text, "{}:", name);
+        let _ = writeln!(self.text, "    push rbp\n    mov rbp, rsp");
+        if size > 0 {
+            let _ = writeln!(self.text, "    sub rsp, {}", size);
+        }
+        if !frame.script {
+            let _ = writeln!(self.text, "    inc qword ptr [rip + cheetah_depth]");
+        }
+        self.text += &frame.code;
+        let _ = writeln!(self.text, "{}:", frame.end);
+        if !frame.script {
+            let _ = writeln!(self.text, "    dec qword ptr [rip + cheetah_depth]");
+        }
+        let _ = writeln!(self.text, "    leave\n    ret");
+        for (label, message) in frame.failures {
+            let _ = writeln!(self.text, "{}:", label);
+            let _ = writeln!(self.text, "    lea rdi, [rip + .Lmessage{}]", message);
+            let _ = writeln!(self.text, "    mov rsi, rcx\n    jmp cheetah_fail");
+        }
+        self.text.push('\n');
+    }
+
+    /* The whole file: the functions, a routine that reports runtime
+     * errors, and the data they use. What was printed is flushed before
+     * the error is written, which goes straight to stderr. */
+    fn assembly(&self) -> String {
+        let mut out = String::new();
+        out += "    .intel_syntax noprefix\n    .text\n    .globl main\n\n";
+        out += &self.text;
+        out += "\
+cheetah_fail:
+    and rsp, -16
+    mov r12, rdi
+    mov r13, rsi
+    xor edi, edi
+    call fflush@PLT
+    mov rdx, r13
+    mov rsi, r12
+    mov edi, 2
+    xor eax, eax
+    call dprintf@PLT
+    mov edi, 3
+    call exit@PLT
+
+    .section .rodata
+cheetah_int:
+    .asciz \"%ld\\n\"
+cheetah_true:
+    .asciz \"true\"
+cheetah_false:
+    .asciz \"false\"
+cheetah_unit:
+    .asciz \"()\"
+";
+        for (i, message) in self.messages.iter().enumerate() {
+            let _ = writeln!(out, ".Lmessage{}:\n    .asciz {}", i, quote(message));
+        }
+        out += "\n    .bss\n    .p2align 3\ncheetah_depth:\n    .zero 8\n";
+        for global in &self.globals {
+            let _ = writeln!(out, "{}:\n    .zero 8", global_symbol(global));
+        }
+        out += "\n    .section .note.GNU-stack,\"\",@progbits\n";
+        out
+    }
+
+    /* Statements leave their value, if any, in rax */
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let {
+                name,
+                ty,
+                ty_span,
+                value,
+                ..
+            } => {
+                self.supported(ty, *ty_span);
+                self.expr(value);
+                let place = match self.frame.script && self.frame.depth == 0 {
+                    true => self.global(&name.name),
+                    false => self.declare(&name.name),
+                };
+                self.emit(format!("mov {}, rax", place));
+            }
+            Stmt::Assign { target, value, .. } => {
+                self.expr(value);
+                let place = self.place(&target.name);
+                self.emit(format!("mov {}, rax", place));
+            }
+            Stmt::IndexAssign { list, .. } => self.unsupported("lists", list.span()),
+            Stmt::While { cond, body, .. } => {
+                let (top, exit) = (self.label(), self.label());
+                self.place_label(&top);
+                self.expr(cond);
+                self.emit("test rax, rax");
+                self.emit(format!("jz {}", exit));
+                self.block(body);
+                self.emit(format!("jmp {}", top));
+                self.place_label(&exit);
+            }
+            Stmt::For {
+                var, iter, body, ..
+            } => self.for_loop(var, iter, body),
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                let end = self.frame.end.clone();
+                self.emit(format!("jmp {}", end));
+            }
+            Stmt::Block(block) => self.block(block),
+            Stmt::Expr(expr) => self.expr(expr),
+        }
+    }
+
+    /* Only ranges are counted through, in a hidden counter next to the
+     * hidden end, copied into the variable in every iteration */
+    fn for_loop(&mut self, var: &Ident, iter: &Expr, body: &Block) {
+        let Expr::Range { start, end, .. } = iter else {
+            return self.unsupported(self.collection(iter), iter.span());
+        };
+        self.frame.depth += 1;
+        self.expr(start);
+        let counter = self.declare("");
+        self.emit(format!("mov {}, rax", counter));
+        self.expr(end);
+        let end = self.declare("");
+        self.emit(format!("mov {}, rax", end));
+
+        let (top, exit) = (self.label(), self.label());
+        self.place_label(&top);
+        self.emit(format!("mov rax, {}", counter));
+        self.emit(format!("cmp rax, {}", end));
+        self.emit(format!("jge {}", exit));
+        self.frame.depth += 1;
+        let var = self.declare(&var.name);
+        self.emit(format!("mov {}, rax", var));
+        self.block(body);
+        self.end_scope();
+        self.emit(format!("inc {}", counter));
+        self.emit(format!("jmp {}", top));
+        self.place_label(&exit);
+        self.end_scope();
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.frame.depth += 1;
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            self.expr(tail);
+        }
+        self.end_scope();
+    }
+
+    fn end_scope(&mut self) {
+        self.frame.depth -= 1;
+        let depth = self.frame.depth;
+        self.frame.locals.retain(|local| local.depth <= depth);
+    }
+
+    /* Leaves the value of an expression in rax, `()` being any value */
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal { value, span } => match value {
+                Literal::Int(value) => self.emit(format!("mov rax, {}", value)),
+                Literal::Bool(value) => self.emit(format!("mov eax, {}", *value as u8)),
+                Literal::Str(_) => self.unsupported("strings", *span),
+            },
+            Expr::Ident(ident) => {
+                let place = self.place(&ident.name);
+                self.emit(format!("mov rax, {}", place));
+            }
+            Expr::Call { callee, args, span } => self.call(callee, args, *span),
+            Expr::Unary {
+                op, operand, span, ..
+            } => {
+                self.expr(operand);
+                match op {
+                    UnOp::Not => self.emit("xor eax, 1"),
+                    UnOp::BitNot => self.emit("not rax"),
+                    UnOp::Neg => {
+                        self.emit("neg rax");
+                        let fail = self.fail(&overflow("negate"), *span);
+                        self.emit(format!("jo {}", fail));
+                    }
+                    UnOp::Plus => {}
+                }
+            }
+            Expr::Binary {
+                op: op @ (BinOp::And | BinOp::Or),
+                lhs,
+                rhs,
+                ..
+            } => {
+                /* The left side decides, or leaves it to the right side */
+                let (decided, end) = (self.label(), self.label());
+                self.expr(lhs);
+                self.emit("test rax, rax");
+                match op {
+                    BinOp::And => self.emit(format!("jz {}", decided)),
+                    _ => self.emit(format!("jnz {}", decided)),
+                }
+                self.expr(rhs);
+                self.emit(format!("jmp {}", end));
+                self.place_label(&decided);
+                match op {
+                    BinOp::And => self.emit("xor eax, eax"),
+                    _ => self.emit("mov eax, 1"),
+                }
+                self.place_label(&end);
+            }
+            Expr::Binary {
+                op, lhs, rhs, span, ..
+            } => {
+                self.expr(lhs);
+                self.emit("push rax");
+                self.frame.pushed += 1;
+                self.expr(rhs);
+                self.emit("mov rcx, rax");
+                self.emit("pop rax");
+                self.frame.pushed -= 1;
+                self.binary(*op, *span);
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let (otherwise, end) = (self.label(), self.label());
+                self.expr(cond);
+                self.emit("test rax, rax");
+                self.emit(format!("jz {}", otherwise));
+                self.block(then_branch);
+                self.emit(format!("jmp {}", end));
+                self.place_label(&otherwise);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+                self.place_label(&end);
+            }
+            Expr::Block(block) => self.block(block),
+            Expr::List { span, .. } => self.unsupported("lists", *span),
+            Expr::Index { list, span, .. } => self.unsupported(self.collection(list), *span),
+            Expr::Template { span, .. } => self.unsupported("strings", *span),
+            Expr::Range { .. } => unreachable!("ranges are only iterated over"),
+        }
+    }
+
+    /* Applies an operator to the left operand in rax and the right one in
+     * rcx, checking for the errors the interpreter reports */
+    fn binary(&mut self, op: BinOp, span: Span) {
+        let compare =
+            |condition: &str| format!("cmp rax, rcx\n    set{} al\n    movzx eax, al", condition);
+        match op {
+            BinOp::Eq => self.emit(compare("e")),
+            BinOp::Ne => self.emit(compare("ne")),
+            BinOp::Lt => self.emit(compare("l")),
+            BinOp::Le => self.emit(compare("le")),
+            BinOp::Gt => self.emit(compare("g")),
+            BinOp::Ge => self.emit(compare("ge")),
+            BinOp::BitOr => self.emit("or rax, rcx"),
+            BinOp::BitXor => self.emit("xor rax, rcx"),
+            BinOp::BitAnd => self.emit("and rax, rcx"),
+            BinOp::Add | BinOp::Sub | BinOp::Mul => {
+                let (instruction, operation) = match op {
+                    BinOp::Add => ("add", "add"),
+                    BinOp::Sub => ("sub", "subtract"),
+                    _ => ("imul", "multiply"),
+                };
+                self.emit(format!("{} rax, rcx", instruction));
+                let fail = self.fail(&overflow(operation), span);
+                self.emit(format!("jo {}", fail));
+            }
+            BinOp::Div | BinOp::Mod => {
+                let (zero, operation) = match op {
+                    BinOp::Div => ("attempt to divide by zero", "divide"),
+                    _ => (
+                        "attempt to calculate the remainder with a divisor of zero",
+                        "calculate the remainder",
+                    ),
+                };
+                let fail = self.fail(zero, span);
+                self.emit("test rcx, rcx");
+                self.emit(format!("jz {}", fail));
+                /* The smallest int divided by -1 does not fit */
+                let (fail, fits) = (self.fail(&overflow(operation), span), self.label());
+                self.emit("cmp rcx, -1");
+                self.emit(format!("jne {}", fits));
+                self.emit(format!("mov rdx, {}", i64::MIN));
+                self.emit("cmp rax, rdx");
+                self.emit(format!("je {}", fail));
+                self.place_label(&fits);
+                self.emit("cqo\n    idiv rcx");
+                if op == BinOp::Mod {
+                    self.emit("mov rax, rdx");
+                }
+            }
+            BinOp::Shl | BinOp::Shr => {
+                let (direction, instruction) = match op {
+                    BinOp::Shl => ("left", "shl"),
+                    _ => ("right", "sar"),
+                };
+                /* Negative amounts compare as huge unsigned numbers */
+                let message = format!(
+                    "attempt to shift {} by `%ld`, which would overflow",
+                    direction
+                );
+                let fail = self.fail(&message, span);
+                self.emit("cmp rcx, 63");
+                self.emit(format!("ja {}", fail));
+                self.emit(format!("{} rax, cl", instruction));
+            }
+            BinOp::And | BinOp::Or => unreachable!("short-circuiting operators are jumps"),
+        }
+    }
+
+    /* Pushes the arguments in order, then moves the first six into their
+     * registers and copies the rest below them, the seventh on top */
+    fn call(&mut self, callee: &Ident, args: &[Expr], span: Span) {
+        if !self.functions.contains(&callee.name) {
+            return self.builtin(callee, args, span);
+        }
+        for arg in args {
+            self.expr(arg);
+            self.emit("push rax");
+            self.frame.pushed += 1;
+        }
+        let count = args.len();
+        let stack = count.saturating_sub(ARGUMENTS.len());
+        let below = 8 * (stack + (self.frame.pushed + stack) % 2);
+        if below > 0 {
+            self.emit(format!("sub rsp, {}", below));
+        }
+        let argument = |i: usize| format!("qword ptr [rsp + {}]", below + 8 * (count - 1 - i));
+        for i in 0..stack {
+            self.emit(format!("mov rax, {}", argument(ARGUMENTS.len() + i)));
+            self.emit(format!("mov qword ptr [rsp + {}], rax", 8 * i));
+        }
+        for (i, register) in ARGUMENTS.iter().take(count).enumerate() {
+            self.emit(format!("mov {}, {}", register, argument(i)));
+        }
+
+        let fail = self.fail("stack overflow", span);
+        self.emit(format!(
+            "cmp qword ptr [rip + cheetah_depth], {}",
+            MAX_CALL_DEPTH
+        ));
+        self.emit(format!("jge {}", fail));
+        self.emit(format!("call {}", symbol(&callee.name)));
+        if below + 8 * count > 0 {
+            self.emit(format!("add rsp, {}", below + 8 * count));
+        }
+        self.frame.pushed -= count;
+    }
+
+    fn builtin(&mut self, callee: &Ident, args: &[Expr], span: Span) {
+        match Builtin::ALL
+            .iter()
+            .find(|builtin| builtin.name() == callee.name)
+        {
+            Some(Builtin::Print) => {
+                self.expr(&args[0]);
+                let ty = self.checker.type_of(&args[0]).cloned();
+                self.print(&ty.unwrap_or(Ty::Unit));
+            }
+            Some(Builtin::Len) => self.unsupported("`len`", span),
+            None => panic!("unresolved function `{}`", callee.name),
+        }
+    }
+
+    /* Prints the value in rax followed by a newline */
+    fn print(&mut self, ty: &Ty) {
+        match ty {
+            Ty::Int => {
+                self.emit("mov rsi, rax\n    lea rdi, [rip + cheetah_int]\n    xor eax, eax");
+                self.call_c("printf");
+            }
+            Ty::Bool => {
+                self.emit("lea rdi, [rip + cheetah_false]\n    lea rcx, [rip + cheetah_true]");
+                self.emit("test rax, rax\n    cmovnz rdi, rcx");
+                self.call_c("puts");
+            }
+            _ => {
+                self.emit("lea rdi, [rip + cheetah_unit]");
+                self.call_c("puts");
+            }
+        }
+    }
+
+    /* Calls into the C library with the stack aligned */
+    fn call_c(&mut self, function: &str) {
+        match self.frame.pushed % 2 {
+            0 => self.emit(format!("call {}@PLT", function)),
+            _ => self.emit(format!(
+                "sub rsp, 8\n    call {}@PLT\n    add rsp, 8",
+                function
+            )),
+        }
+    }
+
+    /* Gives a name the next free slot of the frame, and returns it */
+    fn declare(&mut self, name: &str) -> String {
+        let slot = self.frame.locals.len();
+        self.frame.locals.push(Local {
+            name: name.to_string(),
+            slot,
+            depth: self.frame.depth,
+        });
+        self.frame.slots = self.frame.slots.max(slot + 1);
+        local(slot)
+    }
+
+    /* Where the value of a name is kept, a local or else a global */
+    fn place(&mut self, name: &str) -> String {
+        let found = self.frame.locals.iter().rev().find(|l| l.name == name);
+        match found {
+            Some(found) => local(found.slot),
+            None => self.global(name),
+        }
+    }
+
+    fn global(&mut self, name: &str) -> String {
+        if !self.globals.iter().any(|global| global == name) {
+            self.globals.push(name.to_string());
+        }
+        format!("qword ptr [rip + {}]", global_symbol(name))
+    }
+
+    /* A label to jump to when a check fails, which prints the message with
+     * where `span` starts. The message is a printf format, given the value
+     * in rcx. */
+    fn fail(&mut self, message: &str, span: Span) -> String {
+        let file = self.sources.file(span.file);
+        let message = format!(
+            "error: {}\n --> {}:{}\n",
+            message,
+            file.name.replace('%', "%%"),
+            file.location(span.start)
+        );
+        let index = match self.messages.iter().position(|m| *m == message) {
+            Some(index) => index,
+            None => {
+                self.messages.push(message);
+                self.messages.len() - 1
+            }
+        };
+        let label = self.label();
+        self.frame.failures.push((label.clone(), index));
+        label
+    }
+
+    fn supported(&mut self, ty: &Type, span: Span) {
+        match ty {
+            Type::Int | Type::Bool => {}
+            Type::Str => self.unsupported("strings", span),
+            Type::List(_) => self.unsupported("lists", span),
+        }
+    }
+
+    /* What to call a value that is indexed or iterated over */
+    fn collection(&self, expr: &Expr) -> &'static str {
+        match self.checker.type_of(expr) {
+            Some(Ty::Str) => "strings",
+            _ => "lists",
+        }
+    }
+
+    /* Reported once for each kind of value, where it is first used */
+    fn unsupported(&mut self, what: &str, span: Span) {
+        let message = format!("{} cannot be compiled to x86-64", what);
+        if self.diagnostics.iter().any(|seen| seen.message == message) {
+            return;
+        }
+        self.diagnostics.push(
+            Diagnostic::error(message, span)
+                .with_note("native code only supports `int` and `bool` values"),
+        );
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    fn place_label(&mut self, label: &str) {
+        let _ = writeln!(self.frame.code, "{}:", label);
+    }
+
+    fn emit(&mut self, instruction: impl AsRef<str>) {
+        let _ = writeln!(self.frame.code, "    {}", instruction.as_ref());
+    }
+}
+
+fn overflow(operation: &str) -> String {
+    format!("attempt to {} with overflow", operation)
+}
+
+fn local(slot: usize) -> String {
+    format!("qword ptr [rbp - {}]", 8 * (slot + 1))
+}
+
+/* Names of the program are prefixed so they cannot clash with the C
+ * library or with each other */
+fn symbol(function: &str) -> String {
+    format!("cheetah_fn_{}", function)
+}
+
+fn global_symbol(name: &str) -> String {
+    format!("cheetah_global_{}", name)
+}
+
+/* A string literal for as */
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' | b'\\' => quoted += &format!("\\{}", byte as char),
+            b' '..=b'~' => quoted.push(byte as char),
+            byte => quoted += &format!("\\{:03o}", byte),
+        }
+    }
+    quoted + "\""
+}
+
+#[cfg(test)]
+fn compile_source(input: &str) -> Result<String, Vec<Diagnostic>> {
+    use crate::testing::pipeline::check;
+
+    let mut sources = SourceMap::new();
+    sources.add_file("test.ch".to_string(), input.to_string());
+    let (program, checker) = check(input);
+    assert!(!checker.has_errors(), "{:?}", checker.diagnostics());
+    compile(&program, &checker, &sources)
+}
+
+#[test]
+fn compiles_functions_and_calls() {
+    let assembly = compile_source("def add(a: int, b: int) -> int { a + b } print(add(1, 2));");
+    let assembly = assembly.unwrap();
+    assert!(assembly.contains("main:\n") && assembly.contains("call cheetah_fn_add\n"));
+}
+
+#[test]
+fn rejects_strings_and_lists() {
+    let errors =
+        compile_source("def twice(s: str) -> str { s + s } let a: [int] = [1]; a[0];").unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "lists cannot be compiled to x86-64",
+            "strings cannot be compiled to x86-64",
+        ]
+    );
+}
+
+/* Strings are indexed and iterated over like lists, but are strings */
+#[test]
+fn names_strings_used_like_lists() {
+    for input in [
+        "def first(s: str) -> str { s[0] }",
+        "def each(s: str) { for c in s {} }",
+    ] {
+        let errors = compile_source(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, ["strings cannot be compiled to x86-64"]);
+    }
+}
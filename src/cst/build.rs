@@ -0,0 +1,132 @@
+use super::cst::{GreenElement, GreenNode, GreenToken, SyntaxNode};
+use crate::lexer::lex::split_trivia;
+use crate::lexer::tokens::{Token, TokenKind};
+use crate::parser::node::{Node, NodeKind, NodeType};
+use std::rc::Rc;
+
+/* Builds the lossless tree for a parse tree. Every token of the source,
+ * EOF included, is somewhere in the parse tree, so together with their
+ * trivia the tree covers every byte. The parser has already collapsed
+ * nodes with a single child, and those stay collapsed. */
+pub fn build(tree: &Node) -> SyntaxNode {
+    let mut elements = vec![];
+    green(tree, &mut elements);
+    let root = match elements.as_slice() {
+        [GreenElement::Node(node)] => node.clone(),
+        _ => Rc::new(GreenNode::new(NodeKind::Prog, elements)),
+    };
+    SyntaxNode::new_root(root, tree.span().file)
+}
+
+fn green(node: &Node, elements: &mut Vec<GreenElement>) {
+    match node.kind() {
+        NodeType::Atom(token) if node.children().is_empty() => tokens(token, elements),
+        NodeType::Cons(kind) => {
+            let children: Vec<&Node> = node.children().iter().collect();
+            elements.push(green_node(*kind, &children));
+        }
+        /* The Pratt parser keeps an operator in an atom with the operands
+         * as children, which go either side of it */
+        NodeType::Atom(token) => {
+            let operator = Node::new(NodeType::Atom(token.clone()), None);
+            let kind = operator_kind(token.kind, node.children().len());
+            let children = match node.children() {
+                [lhs, rhs] => vec![lhs, &operator, rhs],
+                [operand] => vec![&operator, operand],
+                _ => unreachable!("operators have one or two operands"),
+            };
+            elements.push(green_node(kind, &children));
+        }
+    }
+}
+
+fn green_node(kind: NodeKind, children: &[&Node]) -> GreenElement {
+    let mut elements = vec![];
+    for child in children {
+        green(child, &mut elements);
+    }
+    GreenElement::Node(Rc::new(GreenNode::new(kind, elements)))
+}
+
+/* The token's trivia followed by the token itself */
+fn tokens(token: &Token, elements: &mut Vec<GreenElement>) {
+    for piece in split_trivia(token) {
+        let text = piece.lexeme.as_deref().unwrap_or_default();
+        elements.push(GreenElement::Token(Rc::new(GreenToken::new(
+            piece.kind, text,
+        ))));
+    }
+    let text = token.lexeme.as_deref().unwrap_or_default();
+    elements.push(GreenElement::Token(Rc::new(GreenToken::new(
+        token.kind, text,
+    ))));
+}
+
+/* The rule the packrat parser would have built a node with */
+fn operator_kind(operator: TokenKind, operands: usize) -> NodeKind {
+    match (operator, operands) {
+        (TokenKind::BOOL_NOT, 1) => NodeKind::LogicNot,
+        (_, 1) => NodeKind::Factor,
+        (TokenKind::BOOL_OR, _) => NodeKind::LogicOr,
+        (TokenKind::BOOL_AND, _) => NodeKind::LogicAnd,
+        (TokenKind::BIT_OR, _) => NodeKind::BitwiseOr,
+        (TokenKind::BIT_XOR, _) => NodeKind::BitwiseXor,
+        (TokenKind::BIT_AND, _) => NodeKind::BitwiseAnd,
+        (TokenKind::BIT_LEFT | TokenKind::BIT_RIGHT, _) => NodeKind::BitwiseShift,
+        (TokenKind::PLUS | TokenKind::MINUS, _) => NodeKind::Sum,
+        (TokenKind::MULTIPLY | TokenKind::DIVIDE | TokenKind::MODULUS, _) => NodeKind::Term,
+        _ => NodeKind::Comparison,
+    }
+}
+
+/* Builds the tree of `input` with either parser, checks that it gives
+ * back the source, and returns the tree to compare the parsers with */
+#[cfg(test)]
+fn round_trip(input: &str, pratt: bool) -> String {
+    use crate::lexer::lex::Lexer;
+    use crate::parser::parser::Parser;
+
+    let mut parser = Parser::new(Lexer::new(input.to_string()));
+    let tree = parser
+        .parse(pratt)
+        .expect("recovery always produces a tree");
+    let cst = build(&tree);
+    assert_eq!(cst.to_string(), input, "pratt: {}", pratt);
+    assert_eq!(cst.span().len(), input.len());
+    for token in cst.tokens() {
+        assert_eq!(&input[token.span().start..token.span().end], token.text());
+    }
+    format!("{:?}", cst)
+}
+
+#[test]
+fn round_trips_comments_and_whitespace() {
+    let inputs = [
+        "# squares\ndef square(x: int) -> int {\n    return x * x; // no overflow check\n}\n\nlet a: int = square(/* two */ 2) + -1;\nprint(\"a is { a } and {  square(a)\t}\");\n",
+        "let xs: [int] = [1, 2,3 ];\tfor x in xs[0 .. 2] { print(x); }\n/* trailing /* nested */ */  ",
+        "print(\"{}\"); print(\"{ a // }\"); print(\"{ /* }\");",
+    ];
+    for input in inputs {
+        assert_eq!(
+            round_trip(input, false),
+            round_trip(input, true),
+            "in {:?}",
+            input
+        );
+    }
+}
+
+#[test]
+fn round_trips_syntax_errors() {
+    let input = "let a: int = (1 + ;\nwhile a { let b = 2; }\n$ let s: str = \"{ 1 + }\";";
+    for pratt in [false, true] {
+        round_trip(input, pratt);
+    }
+}
+
+#[test]
+fn round_trips_empty_sources() {
+    for input in ["", "   // just a comment"] {
+        assert_eq!(round_trip(input, false), round_trip(input, true));
+    }
+}
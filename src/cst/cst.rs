@@ -0,0 +1,260 @@
+use crate::lexer::tokens::TokenKind;
+use crate::parser::node::NodeKind;
+use crate::source::span::{FileId, Span};
+use std::fmt;
+use std::rc::Rc;
+
+/* The green tree holds the text and nothing else. A node only knows its
+ * kind, its children and how many bytes they cover, so it can be shared
+ * between trees and an edit only has to rebuild the path to the root. */
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct GreenNode {
+    kind: NodeKind,
+    width: usize,
+    children: Vec<GreenElement>,
+}
+
+/* A token with its exact text. Whitespace and comments are tokens of
+ * their own, kinds WHITESPACE and COMMENT. */
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct GreenToken {
+    kind: TokenKind,
+    text: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenNode {
+    pub fn new(kind: NodeKind, children: Vec<GreenElement>) -> GreenNode {
+        let width = children.iter().map(GreenElement::width).sum();
+        GreenNode {
+            kind,
+            width,
+            children,
+        }
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+}
+
+impl GreenToken {
+    pub fn new(kind: TokenKind, text: &str) -> GreenToken {
+        GreenToken {
+            kind,
+            text: text.to_string(),
+        }
+    }
+
+    pub fn kind(&self) -> TokenKind {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl GreenElement {
+    pub fn width(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.width,
+            GreenElement::Token(token) => token.text.len(),
+        }
+    }
+}
+
+impl fmt::Display for GreenNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => write!(f, "{}", node)?,
+                GreenElement::Token(token) => write!(f, "{}", token.text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/* The red tree is a view of the green tree made on demand, which adds
+ * parents and the position of every node in the file */
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    parent: Option<SyntaxNode>,
+    file: FileId,
+    offset: usize,
+}
+
+#[derive(Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    parent: SyntaxNode,
+    offset: usize,
+}
+
+#[derive(Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Rc<GreenNode>, file: FileId) -> SyntaxNode {
+        SyntaxNode(Rc::new(NodeData {
+            green,
+            parent: None,
+            file,
+            offset: 0,
+        }))
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.0.green
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.0.green.kind
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(
+            self.0.file,
+            self.0.offset,
+            self.0.offset + self.0.green.width,
+        )
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        self.0.parent.clone()
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        let mut children = vec![];
+        for child in &self.0.green.children {
+            children.push(match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: green.clone(),
+                    parent: Some(self.clone()),
+                    file: self.0.file,
+                    offset,
+                }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: green.clone(),
+                    parent: self.clone(),
+                    offset,
+                }),
+            });
+            offset += child.width();
+        }
+        children
+    }
+
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        let children = self.children_with_tokens().into_iter();
+        children
+            .filter_map(|child| match child {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect()
+    }
+
+    /* Every token under the node in source order, trivia included */
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = vec![];
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> TokenKind {
+        self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn span(&self) -> Span {
+        let file = self.parent.0.file;
+        Span::new(file, self.offset, self.offset + self.green.text.len())
+    }
+
+    pub fn parent(&self) -> SyntaxNode {
+        self.parent.clone()
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.kind(), TokenKind::WHITESPACE | TokenKind::COMMENT)
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.green)
+    }
+}
+
+/* One line per node and token, indented by depth, such as
+ * `Declaration@0..15` and `ID@4..5 "a"` */
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let depth = std::iter::successors(self.parent(), SyntaxNode::parent).count();
+        let span = self.span();
+        writeln!(
+            f,
+            "{:indent$}{:?}@{}..{}",
+            "",
+            self.kind(),
+            span.start,
+            span.end,
+            indent = depth * 2
+        )?;
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => write!(f, "{:?}", node)?,
+                SyntaxElement::Token(token) => {
+                    writeln!(f, "{:indent$}{:?}", "", token, indent = depth * 2 + 2)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let span = self.span();
+        write!(
+            f,
+            "{:?}@{}..{} {:?}",
+            self.kind(),
+            span.start,
+            span.end,
+            self.text()
+        )
+    }
+}
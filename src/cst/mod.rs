@@ -0,0 +1,3 @@
+pub mod build;
+#[allow(clippy::module_inception, reason = "the lossless tree, next to the code building it")]
+pub mod cst;
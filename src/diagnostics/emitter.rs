@@ -0,0 +1,155 @@
+use super::diagnostic::Diagnostic;
+use crate::source::{map::SourceMap, span::Span};
+use std::io::{self, Write};
+
+const TAB_WIDTH: usize = 4;
+
+/* Renders diagnostics the way rustc does:
+ *
+ *   error[E0003]: expected `;`, found `let`
+ *    --> example.ch:2:1
+ *     |
+ *   2 | let b: int = 2;
+ *     | ^^^ expected `;`
+ *     |
+ *     = help: ...
+ */
+pub struct Emitter<'a> {
+    sources: &'a SourceMap,
+}
+
+struct Annotation {
+    line: usize,
+    column: usize,
+    width: usize,
+    message: String,
+    primary: bool,
+}
+
+impl<'a> Emitter<'a> {
+    pub fn new(sources: &'a SourceMap) -> Emitter<'a> {
+        Emitter { sources }
+    }
+
+    pub fn emit<W: Write>(&self, out: &mut W, diagnostics: &[Diagnostic]) -> io::Result<()> {
+        for diagnostic in diagnostics {
+            writeln!(out, "{}", self.render(diagnostic))?;
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let file = self.sources.file(diagnostic.primary_span.file);
+        let start = file.location(diagnostic.primary_span.start);
+
+        let mut annotations = vec![self.annotate(diagnostic.primary_span, String::new(), true)];
+        for label in &diagnostic.labels {
+            if label.span.file != diagnostic.primary_span.file {
+                continue;
+            }
+            if label.span == diagnostic.primary_span && annotations[0].message.is_empty() {
+                annotations[0].message = label.message.clone();
+            } else {
+                annotations.push(self.annotate(label.span, label.message.clone(), false));
+            }
+        }
+        annotations.sort_by_key(|a| (a.line, a.column));
+
+        let last_line = annotations.iter().map(|a| a.line).max().unwrap_or(0);
+        let gutter = (last_line + 1).to_string().len();
+        let blank = format!("{} |", " ".repeat(gutter));
+
+        let mut out = format!("{}\n", diagnostic);
+        out += &format!("{}--> {}:{}\n", " ".repeat(gutter), file.name, start);
+        out += &format!("{}\n", blank);
+
+        let mut previous: Option<usize> = None;
+        for annotation in &annotations {
+            if previous != Some(annotation.line) {
+                if previous.is_some_and(|line| line + 1 < annotation.line) {
+                    out += "...\n";
+                }
+                let text = expand_tabs(file.line(annotation.line));
+                out += &format!("{:>gutter$} | {}\n", annotation.line + 1, text.trim_end());
+                previous = Some(annotation.line);
+            }
+
+            let mark = if annotation.primary { "^" } else { "-" };
+            let underline = format!(
+                "{}{} {}",
+                " ".repeat(annotation.column),
+                mark.repeat(annotation.width),
+                annotation.message
+            );
+            out += &format!("{} {}\n", blank, underline.trim_end());
+        }
+
+        if !diagnostic.notes.is_empty() || diagnostic.help.is_some() {
+            out += &format!("{}\n", blank);
+        }
+        for note in &diagnostic.notes {
+            out += &format!("{} = note: {}\n", " ".repeat(gutter), note);
+        }
+        if let Some(help) = &diagnostic.help {
+            out += &format!("{} = help: {}\n", " ".repeat(gutter), help);
+        }
+        out
+    }
+
+    /* Spans covering several lines are only underlined on their first line */
+    fn annotate(&self, span: Span, message: String, primary: bool) -> Annotation {
+        let file = self.sources.file(span.file);
+        let start = file.location(span.start);
+        let end = file.location(span.end);
+
+        let line = file.line(start.line);
+        let before = &line[..start.column_utf8.min(line.len())];
+        let marked = if end.line == start.line {
+            &line[before.len()..end.column_utf8.min(line.len())]
+        } else {
+            &line[before.len()..]
+        };
+
+        Annotation {
+            line: start.line,
+            column: display_width(before),
+            width: display_width(marked).max(1),
+            message,
+            primary,
+        }
+    }
+}
+
+fn display_width(s: &str) -> usize {
+    s.chars().map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum()
+}
+
+fn expand_tabs(s: &str) -> String {
+    s.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+#[test]
+fn renders_snippet_with_carets() {
+    use crate::source::map::SourceMap;
+
+    let mut sources = SourceMap::new();
+    let file = sources.add_file("example.ch".into(), "let a: int = 1\nlet b: int = 2;".into());
+    let diagnostic = Diagnostic::error("expected `;`, found `let`", Span::new(file, 15, 18))
+        .with_code("E0003")
+        .with_label(Span::new(file, 15, 18), "unexpected token")
+        .with_label(Span::new(file, 14, 14), "expected `;` here")
+        .with_help("add `;` here");
+
+    let expected = "\
+error[E0003]: expected `;`, found `let`
+ --> example.ch:2:1
+  |
+1 | let a: int = 1
+  |               - expected `;` here
+2 | let b: int = 2;
+  | ^^^ unexpected token
+  |
+  = help: add `;` here
+";
+    assert_eq!(Emitter::new(&sources).render(&diagnostic), expected);
+}
@@ -0,0 +1,30 @@
+/* Error codes
+ * -----------
+ * E0001  unknown start of token
+ * E0002  invalid string literal
+ * E0003  unexpected token
+ * E0004  integer literal too large
+ * E0005  cannot find value
+ * E0006  mismatched types
+ * E0007  binary operator cannot be applied to operand types
+ * E0008  unary operator cannot be applied to operand type
+ * E0009  `if` and `else` have incompatible types
+ * E0010  use of a value before its declaration
+ * E0011  assignment to an undeclared variable
+ * E0012  call of something that is not a function name
+ * E0013  name defined multiple times
+ * E0014  variable used inside a function it is declared outside of
+ * E0015  function used as a value or value called as a function
+ * E0016  wrong number of arguments
+ * E0017  `return` outside of a function
+ * E0018  `for` loop over a value that cannot be iterated
+ * E0019  assignment to something other than a variable or list element
+ * E0020  indexing into a value that cannot be indexed
+ * E0021  invalid escape sequence in a string literal
+ * E0022  interpolation of a value that has no text form
+ * E0023  block comment that is never closed
+ *
+ * W0001  declaration shadows an earlier one
+ */
+pub mod diagnostic;
+pub mod emitter;
@@ -0,0 +1,287 @@
+pub const USAGE: &str = "\
+usage: cheetah <command> [options] [FILE]
+
+commands:
+  lex      print the tokens of a file
+  parse    print the parse tree of a file
+  check    parse and type check a file
+  run      check and run a file
+  build    check a file and write out the stages chosen with --emit, or
+           compile it for the --target
+  fmt      format files in place
+  repl     evaluate entries as they are typed
+  help     print this message
+
+`cheetah FILE` is short for `cheetah run FILE`. Without a FILE, or with
+`-`, the source is read from stdin.
+
+options:
+  --parser packrat|pratt   the expression parser to use (default packrat)
+  --format sexpr|json|dot  how lex, parse and --emit print trees and tokens
+                           (default sexpr)
+  --emit STAGE[,STAGE]     what build writes: tokens, parse, ast, ir,
+                           bytecode, asm or wat (default ast)
+  --target x86-64|c|wasm   build links an executable, named after FILE
+                           or a.out, with `as` and `cc`, writes the
+                           program as a single C99 file, or writes a
+                           WebAssembly module named after FILE
+  -o, --output PATH        where build writes to instead of stdout
+  --vm                     run compiles to bytecode and runs that instead
+                           of walking the tree
+  --check                  fmt only reports files that are not formatted
+  --width N                the line width fmt wraps at (default 100)
+
+exit codes:
+  0  success
+  1  the program has errors, or fmt --check found unformatted files
+  2  the command line is wrong, a file could not be read or written, or
+     an executable could not be linked
+  3  the program stopped with an error while running
+";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Command {
+    Lex,
+    Parse,
+    Check,
+    Run,
+    Build,
+    Fmt,
+    Repl,
+    Help,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Sexpr,
+    Json,
+    Dot,
+}
+
+/* The stages of compilation that build can write out, in pipeline order */
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+pub enum Stage {
+    Tokens,
+    Parse,
+    Ast,
+    Ir,
+    Bytecode,
+    Asm,
+    Wat,
+}
+
+/* What build compiles the program to instead of writing out stages */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Target {
+    X86_64,
+    C,
+    Wasm,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    /* Paths, where `-` is stdin. Only fmt takes more than one. */
+    pub inputs: Vec<String>,
+    pub pratt: bool,
+    pub format: Format,
+    /* Empty unless --emit was given, build then writes the ast */
+    pub emit: Vec<Stage>,
+    pub target: Option<Target>,
+    pub output: Option<String>,
+    pub vm: bool,
+    pub check: bool,
+    pub width: usize,
+}
+
+impl Options {
+    pub fn new(command: Command) -> Options {
+        Options {
+            command,
+            inputs: vec![],
+            pratt: false,
+            format: Format::Sexpr,
+            emit: vec![],
+            target: None,
+            output: None,
+            vm: false,
+            check: false,
+            width: crate::format::format::DEFAULT_WIDTH,
+        }
+    }
+
+    /* Reads the arguments after the program name */
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut args = args.iter().peekable();
+        let command = match args.peek().map(|arg| arg.as_str()) {
+            None | Some("help" | "--help" | "-h") => return Ok(Options::new(Command::Help)),
+            Some("lex") => Some(Command::Lex),
+            Some("parse") => Some(Command::Parse),
+            Some("check") => Some(Command::Check),
+            Some("run") => Some(Command::Run),
+            Some("build") => Some(Command::Build),
+            Some("fmt") => Some(Command::Fmt),
+            Some("repl") => Some(Command::Repl),
+            /* `cheetah [options] FILE` is short for run */
+            Some(arg) if arg.starts_with('-') || arg.ends_with(".ch") => None,
+            Some(arg) if std::path::Path::new(arg).exists() => None,
+            Some(arg) => return Err(format!("unknown command `{}`", arg)),
+        };
+        if command.is_some() {
+            args.next();
+        }
+        let mut options = Options::new(command.unwrap_or(Command::Run));
+
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = |name: &str| match inline.clone().or_else(|| args.next().cloned()) {
+                Some(value) => Ok(value),
+                None => Err(format!("`{}` needs a value", name)),
+            };
+            match flag {
+                "--parser" => {
+                    options.pratt = match value(flag)?.as_str() {
+                        "packrat" => false,
+                        "pratt" => true,
+                        other => {
+                            return Err(format!(
+                                "unknown parser `{}`, expected packrat or pratt",
+                                other
+                            ))
+                        }
+                    }
+                }
+                "--format" => {
+                    options.format = match value(flag)?.as_str() {
+                        "sexpr" => Format::Sexpr,
+                        "json" => Format::Json,
+                        "dot" => Format::Dot,
+                        other => {
+                            return Err(format!(
+                                "unknown format `{}`, expected sexpr, json or dot",
+                                other
+                            ))
+                        }
+                    }
+                }
+                "--emit" => {
+                    let mut emit = vec![];
+                    for stage in value(flag)?.split(',') {
+                        emit.push(match stage {
+                            "tokens" => Stage::Tokens,
+                            "parse" => Stage::Parse,
+                            "ast" => Stage::Ast,
+                            "ir" => Stage::Ir,
+                            "bytecode" => Stage::Bytecode,
+                            "asm" => Stage::Asm,
+                            "wat" => Stage::Wat,
+                            other => {
+                                return Err(format!(
+                                "unknown stage `{}`, expected tokens, parse, ast, ir, bytecode, asm or wat",
+                                other
+                            ))
+                            }
+                        });
+                    }
+                    emit.sort();
+                    emit.dedup();
+                    options.emit = emit;
+                }
+                "--target" => {
+                    options.target = match value(flag)?.as_str() {
+                        "x86-64" | "x86_64" => Some(Target::X86_64),
+                        "c" => Some(Target::C),
+                        "wasm" => Some(Target::Wasm),
+                        other => {
+                            return Err(format!(
+                                "unknown target `{}`, expected x86-64, c or wasm",
+                                other
+                            ))
+                        }
+                    }
+                }
+                "-o" | "--output" => options.output = Some(value(flag)?),
+                "--vm" => options.vm = true,
+                "--check" => options.check = true,
+                "--width" => {
+                    options.width = value(flag)?
+                        .parse()
+                        .map_err(|_| "`--width` needs a number of columns".to_string())?
+                }
+                "-" => options.inputs.push(arg.clone()),
+                flag if flag.starts_with('-') => return Err(format!("unknown option `{}`", flag)),
+                _ => options.inputs.push(arg.clone()),
+            }
+        }
+
+        if options.command == Command::Repl && !options.inputs.is_empty() {
+            return Err("repl reads from stdin and takes no files".to_string());
+        }
+        if options.command != Command::Fmt && options.inputs.len() > 1 {
+            return Err("only fmt takes more than one file".to_string());
+        }
+        if options.output.is_some() && options.emit.len() > 1 {
+            return Err("`--output` takes a single stage to emit".to_string());
+        }
+        if options.target.is_some() && !options.emit.is_empty() {
+            return Err("build writes either the stages to --emit or the --target".to_string());
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+}
+
+#[test]
+fn parses_options_and_inputs() {
+    let options = Options::parse(&args("parse --parser pratt --format=json a.ch")).unwrap();
+    assert_eq!(options.command, Command::Parse);
+    assert!(options.pratt);
+    assert_eq!(options.format, Format::Json);
+    assert_eq!(options.inputs, ["a.ch"]);
+
+    let options = Options::parse(&args("build --emit bytecode,ir,ast,tokens -")).unwrap();
+    assert_eq!(
+        options.emit,
+        [Stage::Tokens, Stage::Ast, Stage::Ir, Stage::Bytecode]
+    );
+    assert_eq!(options.inputs, ["-"]);
+
+    let options = Options::parse(&args("build --target x86-64 -o prog a.ch")).unwrap();
+    assert_eq!(
+        (options.target, options.output.as_deref()),
+        (Some(Target::X86_64), Some("prog"))
+    );
+    let options = Options::parse(&args("build --target c a.ch")).unwrap();
+    assert_eq!(options.target, Some(Target::C));
+    let options = Options::parse(&args("build --emit=wat,asm a.ch")).unwrap();
+    assert_eq!(options.emit, [Stage::Asm, Stage::Wat]);
+}
+
+#[test]
+fn runs_a_lone_file_and_helps_without_arguments() {
+    let options = Options::parse(&args("script.ch")).unwrap();
+    assert_eq!(
+        (options.command, options.inputs),
+        (Command::Run, vec!["script.ch".to_string()])
+    );
+    assert_eq!(Options::parse(&[]).unwrap().command, Command::Help);
+}
+
+#[test]
+fn rejects_bad_command_lines() {
+    assert!(Options::parse(&args("compile a.ch")).is_err());
+    assert!(Options::parse(&args("run --parser lalr a.ch")).is_err());
+    assert!(Options::parse(&args("run a.ch b.ch")).is_err());
+    assert!(Options::parse(&args("build --emit tokens,ast -o out a.ch")).is_err());
+    assert!(Options::parse(&args("lex --width")).is_err());
+    assert!(Options::parse(&args("build --target arm a.ch")).is_err());
+    assert!(Options::parse(&args("build --target x86-64 --emit asm a.ch")).is_err());
+    assert!(Options::parse(&args("build --target wasm --emit wat a.ch")).is_err());
+}
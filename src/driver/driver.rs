@@ -0,0 +1,570 @@
+use super::cli::{Command, Format, Options, Stage, Target, USAGE};
+use crate::ast::ast::Program;
+use crate::ast::lower::lower;
+use crate::bytecode::bytecode::Module;
+use crate::bytecode::compile::compile;
+use crate::bytecode::disassemble::disassemble;
+use crate::codegen::{c, native, wasm, x86_64};
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::diagnostics::emitter::Emitter;
+use crate::dump::{dot, json, sexpr};
+use crate::format::format::format;
+use crate::interp::interp::{self, on_large_stack, Interpreter};
+use crate::interp::value::Value;
+use crate::ir::{build, print, verify};
+use crate::lexer::lex::{error_diagnostic, Lexer};
+use crate::lexer::tokens::{Token, TokenKind};
+use crate::parser::node::Node;
+use crate::parser::parser::Parser;
+use crate::repl::repl::{self, Repl};
+use crate::sema::check::Checker;
+use crate::source::map::SourceMap;
+use crate::source::span::FileId;
+use crate::vm::vm::Vm;
+use std::fs;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+
+pub const SUCCESS: i32 = 0;
+/* The program has errors, or fmt --check found unformatted files */
+pub const FAILURE: i32 = 1;
+/* Bad command line, or a file that could not be read or written */
+pub const USAGE_ERROR: i32 = 2;
+/* The program stopped with an error while it was running */
+pub const RUNTIME_ERROR: i32 = 3;
+
+/* The name stdin goes by in diagnostics */
+const STDIN: &str = "<stdin>";
+
+/* Runs the command line `args`, without the program name, and returns
+ * the exit code. Everything is read and written through the given
+ * streams, apart from named files. */
+pub fn main(
+    args: &[String],
+    stdin: &mut dyn Read,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> i32 {
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            let _ = writeln!(err, "error: {}\n\n{}", message, USAGE);
+            return USAGE_ERROR;
+        }
+    };
+    let mut driver = Driver {
+        options,
+        sources: SourceMap::new(),
+        stdin,
+        out,
+        err,
+    };
+    let code = match driver.options.command {
+        Command::Help => {
+            let _ = write!(driver.out, "{}", USAGE);
+            SUCCESS
+        }
+        Command::Fmt => driver.fmt(),
+        Command::Repl => driver.repl(),
+        command => driver.compile(command),
+    };
+    let _ = driver.out.flush();
+    code
+}
+
+struct Driver<'a> {
+    options: Options,
+    sources: SourceMap,
+    stdin: &'a mut dyn Read,
+    out: &'a mut dyn Write,
+    err: &'a mut dyn Write,
+}
+
+/* Why a command stopped early, turned into an exit code at the top */
+type Stop = i32;
+
+/* What check leaves for the commands after it */
+type Checked = (Vec<Token>, Node, Program, Checker);
+
+impl Driver<'_> {
+    fn compile(&mut self, command: Command) -> i32 {
+        let input = self
+            .options
+            .inputs
+            .first()
+            .cloned()
+            .unwrap_or_else(|| "-".to_string());
+        let result = self.read(&input).and_then(|(file, source)| match command {
+            Command::Lex => self.lex(file, source),
+            Command::Parse => self.parse_command(file, source),
+            Command::Check => self.check(file, source).map(|_| ()),
+            Command::Run => self.run(file, source),
+            Command::Build => self.build(file, source),
+            Command::Fmt | Command::Repl | Command::Help => unreachable!("handled by main"),
+        });
+        match result {
+            Ok(()) => SUCCESS,
+            Err(code) => code,
+        }
+    }
+
+    fn lex(&mut self, file: FileId, source: String) -> Result<(), Stop> {
+        let tokens = Lexer::with_file(source, file).tokens();
+        let text = match self.options.format {
+            Format::Sexpr => sexpr::tokens(&tokens),
+            Format::Json => json::document("tokens", json::tokens(&tokens)).pretty() + "\n",
+            Format::Dot => {
+                return Err(self.usage("tokens cannot be printed as dot, use sexpr or json"))
+            }
+        };
+        self.write_out(&text)?;
+        let errors: Vec<Diagnostic> = tokens
+            .iter()
+            .filter(|token| token.kind == TokenKind::ERROR)
+            .map(error_diagnostic)
+            .collect();
+        self.report(&errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(FAILURE)
+        }
+    }
+
+    /* Prints the tree even when it has errors, as recovery keeps the parts
+     * that did parse */
+    fn parse_command(&mut self, file: FileId, source: String) -> Result<(), Stop> {
+        let mut parser = Parser::new(Lexer::with_file(source, file));
+        let tree = parser.parse(self.options.pratt);
+        if let Some(tree) = &tree {
+            let text = self.dump(tree);
+            self.write_out(&text)?;
+        }
+        self.report(parser.diagnostics());
+        if tree.is_none() || parser.has_errors() {
+            return Err(FAILURE);
+        }
+        Ok(())
+    }
+
+    /* Everything up to and including the checker */
+    fn check(&mut self, file: FileId, source: String) -> Result<Checked, Stop> {
+        let tokens = match self.options.emit.contains(&Stage::Tokens) {
+            true => Lexer::with_file(source.clone(), file).tokens(),
+            false => vec![],
+        };
+        let mut parser = Parser::new(Lexer::with_file(source, file));
+        let tree = parser.parse(self.options.pratt);
+        self.report(parser.diagnostics());
+        let (Some(tree), false) = (tree, parser.has_errors()) else {
+            return Err(FAILURE);
+        };
+
+        let program = match lower(&tree) {
+            Ok(program) => program,
+            Err(diagnostics) => {
+                self.report(&diagnostics);
+                return Err(FAILURE);
+            }
+        };
+
+        let mut checker = Checker::new();
+        checker.check_program(&program);
+        self.report(checker.diagnostics());
+        if checker.has_errors() {
+            return Err(FAILURE);
+        }
+        Ok((tokens, tree, program, checker))
+    }
+
+    fn run(&mut self, file: FileId, source: String) -> Result<(), Stop> {
+        let (_, _, program, _) = self.check(file, source)?;
+        let (output, result) = if self.options.vm {
+            let module = self.bytecode(&program)?;
+            let mut vm = Vm::capturing();
+            let result = vm
+                .run(&module)
+                .map(|value| (value != Value::Unit).then(|| value.to_string()));
+            (vm.take_output(), result)
+        } else {
+            self.interpret(&program)
+        };
+        self.write_out(&output)?;
+        match result {
+            Ok(None) => Ok(()),
+            Ok(Some(value)) => self.write_out(&format!("{}\n", value)),
+            Err(error) => {
+                self.report(&[*error]);
+                Err(RUNTIME_ERROR)
+            }
+        }
+    }
+
+    /* Runs a program with the tree walking interpreter, returning what it
+     * printed and its value if it is not `()` */
+    fn interpret(&mut self, program: &Program) -> (String, interp::Result<Option<String>>) {
+        /* Values can hold lists shared through an Rc, so only the output
+         * and the printed value leave the interpreter's thread */
+        on_large_stack(|| {
+            let mut interpreter = Interpreter::capturing();
+            let result = interpreter
+                .run(program)
+                .map(|value| (value != Value::Unit).then(|| value.to_string()));
+            (interpreter.take_output(), result)
+        })
+    }
+
+    fn bytecode(&mut self, program: &Program) -> Result<Module, Stop> {
+        compile(program).map_err(|diagnostics| {
+            self.report(&diagnostics);
+            FAILURE
+        })
+    }
+
+    fn assembly(&mut self, program: &Program, checker: &Checker) -> Result<String, Stop> {
+        x86_64::compile(program, checker, &self.sources).map_err(|diagnostics| {
+            self.report(&diagnostics);
+            FAILURE
+        })
+    }
+
+    /* Writes each stage chosen with --emit, one after the other, or the
+     * one stage to --output */
+    fn build(&mut self, file: FileId, source: String) -> Result<(), Stop> {
+        if self.options.emit.is_empty() && self.options.target.is_none() {
+            self.options.emit.push(Stage::Ast);
+        }
+        let emit = self.options.emit.clone();
+        if emit.contains(&Stage::Ast) && self.options.format != Format::Sexpr {
+            return Err(self.usage("the ast can only be printed as sexpr"));
+        }
+        if emit.contains(&Stage::Tokens) && self.options.format == Format::Dot {
+            return Err(self.usage("tokens cannot be printed as dot, use sexpr or json"));
+        }
+        let listings = [Stage::Ir, Stage::Bytecode, Stage::Asm, Stage::Wat];
+        if listings.iter().any(|stage| emit.contains(stage)) && self.options.format != Format::Sexpr
+        {
+            return Err(
+                self.usage("ir, bytecode, asm and wat are printed as listings, leave out --format")
+            );
+        }
+        let (tokens, tree, program, checker) = self.check(file, source)?;
+        if let Some(target) = self.options.target {
+            return self.target(target, &program, &checker);
+        }
+        let ir = match emit.contains(&Stage::Ir) {
+            true => Some(build::build(&program, &checker)),
+            false => None,
+        };
+        if let Some(Err(errors)) = ir.as_ref().map(verify::verify) {
+            panic!("invalid ir:\n{}", errors.join("\n"));
+        }
+        let bytecode = match emit.contains(&Stage::Bytecode) {
+            true => Some(self.bytecode(&program)?),
+            false => None,
+        };
+        let assembly = match emit.contains(&Stage::Asm) {
+            true => Some(self.assembly(&program, &checker)?),
+            false => None,
+        };
+        let module = match emit.contains(&Stage::Wat) {
+            true => Some(self.wasm(&program, &checker)?),
+            false => None,
+        };
+        let mut text = String::new();
+        for stage in emit {
+            text += &match (stage, self.options.format) {
+                (Stage::Tokens, Format::Json) => {
+                    json::document("tokens", json::tokens(&tokens)).pretty() + "\n"
+                }
+                (Stage::Tokens, _) => sexpr::tokens(&tokens),
+                (Stage::Parse, _) => self.dump(&tree),
+                (Stage::Ast, _) => sexpr::program(&program),
+                (Stage::Ir, _) => print::print(ir.as_ref().expect("built above")),
+                (Stage::Bytecode, _) => disassemble(bytecode.as_ref().expect("compiled above")),
+                (Stage::Asm, _) => assembly.clone().expect("compiled above"),
+                (Stage::Wat, _) => module.as_ref().expect("compiled above").text(),
+            };
+        }
+        match self.options.output.clone() {
+            Some(path) => fs::write(&path, text).map_err(|error| self.io_error(&path, error)),
+            None => self.write_out(&text),
+        }
+    }
+
+    fn wasm(&mut self, program: &Program, checker: &Checker) -> Result<wasm::Module, Stop> {
+        wasm::compile(program, checker, &self.sources).map_err(|diagnostics| {
+            self.report(&diagnostics);
+            FAILURE
+        })
+    }
+
+    /* Compiles for the --target. Executables are written to --output or
+     * else next to the input without its extension, and modules next to
+     * it with a .wasm one. C is written like the stages. */
+    fn target(&mut self, target: Target, program: &Program, checker: &Checker) -> Result<(), Stop> {
+        if target == Target::C {
+            let text = c::compile(program, checker, &self.sources).map_err(|diagnostics| {
+                self.report(&diagnostics);
+                FAILURE
+            })?;
+            return match self.options.output.clone() {
+                Some(path) => fs::write(&path, text).map_err(|error| self.io_error(&path, error)),
+                None => self.write_out(&text),
+            };
+        }
+        let extension = match target {
+            Target::Wasm => "wasm",
+            _ => "",
+        };
+        let output = self.options.output.clone().unwrap_or_else(|| {
+            let input = self
+                .options
+                .inputs
+                .first()
+                .map_or("-", |input| input.as_str());
+            let path = Path::new(input);
+            match (input, path.extension(), target) {
+                ("-", _, Target::Wasm) => "a.wasm".to_string(),
+                ("-", _, _) | (_, None, Target::X86_64) => "a.out".to_string(),
+                _ => path
+                    .with_extension(extension)
+                    .to_string_lossy()
+                    .into_owned(),
+            }
+        });
+        if target == Target::Wasm {
+            let binary = self.wasm(program, checker)?.binary();
+            return fs::write(&output, binary).map_err(|error| self.io_error(&output, error));
+        }
+        let assembly = self.assembly(program, checker)?;
+        native::link(&assembly, Path::new(&output)).map_err(|message| self.usage(&message))
+    }
+
+    /* `cheetah fmt [--check] [--width N] FILE...` formats files in place,
+     * or with --check only lists the ones that are not formatted. Source
+     * from stdin is written formatted to stdout. */
+    fn fmt(&mut self) -> i32 {
+        let mut inputs = self.options.inputs.clone();
+        if inputs.is_empty() {
+            inputs.push("-".to_string());
+        }
+        let mut code = SUCCESS;
+        for input in inputs {
+            let Ok((file, source)) = self.read(&input) else {
+                code = code.max(USAGE_ERROR);
+                continue;
+            };
+            let mut parser = Parser::new(Lexer::with_file(source.clone(), file));
+            let tree = parser.parse(false);
+            self.report(parser.diagnostics());
+            let (Some(tree), false) = (tree, parser.has_errors()) else {
+                code = code.max(FAILURE);
+                continue;
+            };
+
+            let formatted = format(&tree, self.options.width);
+            let result = if self.options.check {
+                if formatted != source {
+                    let line = source
+                        .lines()
+                        .zip(formatted.lines())
+                        .take_while(|(old, new)| old == new)
+                        .count();
+                    code = code.max(FAILURE);
+                    self.write_out(&format!(
+                        "{}:{}: not formatted\n",
+                        self.name(&input),
+                        line + 1
+                    ))
+                } else {
+                    Ok(())
+                }
+            } else if input == "-" {
+                self.write_out(&formatted)
+            } else if formatted != source {
+                fs::write(&input, formatted).map_err(|error| self.io_error(&input, error))
+            } else {
+                Ok(())
+            };
+            if let Err(stop) = result {
+                code = code.max(stop);
+            }
+        }
+        code
+    }
+
+    fn repl(&mut self) -> i32 {
+        let mut repl = Repl::new(self.options.pratt);
+        let mut input = BufReader::new(&mut *self.stdin);
+        match repl::run(&mut repl, &mut input, self.out) {
+            Ok(()) => SUCCESS,
+            Err(error) => {
+                let _ = writeln!(self.err, "error: {}", error);
+                USAGE_ERROR
+            }
+        }
+    }
+
+    /* Reads a file, or stdin for `-`, and adds it to the source map */
+    fn read(&mut self, input: &str) -> Result<(FileId, String), Stop> {
+        let mut source = String::new();
+        let result = match input {
+            "-" => self.stdin.read_to_string(&mut source).map(|_| ()),
+            path => fs::read_to_string(path).map(|text| source = text),
+        };
+        if let Err(error) = result {
+            return Err(self.io_error(input, error));
+        }
+        let file = self
+            .sources
+            .add_file(self.name(input).to_string(), source.clone());
+        Ok((file, source))
+    }
+
+    fn dump(&self, tree: &Node) -> String {
+        match self.options.format {
+            Format::Sexpr => format!("{}\n", tree),
+            Format::Json => json::document("tree", json::node(tree)).pretty() + "\n",
+            Format::Dot => dot::node(tree),
+        }
+    }
+
+    fn name<'b>(&self, input: &'b str) -> &'b str {
+        if input == "-" {
+            STDIN
+        } else {
+            input
+        }
+    }
+
+    fn write_out(&mut self, text: &str) -> Result<(), Stop> {
+        self.out.write_all(text.as_bytes()).map_err(|error| {
+            let _ = writeln!(self.err, "error: could not write output: {}", error);
+            USAGE_ERROR
+        })
+    }
+
+    fn report(&mut self, diagnostics: &[Diagnostic]) {
+        let _ = Emitter::new(&self.sources).emit(&mut self.err, diagnostics);
+    }
+
+    fn usage(&mut self, message: &str) -> Stop {
+        let _ = writeln!(self.err, "error: {}", message);
+        USAGE_ERROR
+    }
+
+    fn io_error(&mut self, input: &str, error: std::io::Error) -> Stop {
+        let _ = writeln!(self.err, "error: {}: {}", self.name(input), error);
+        USAGE_ERROR
+    }
+}
+
+/* Runs the driver on a command line and standard input, in memory */
+#[cfg(test)]
+fn cheetah(line: &str, stdin: &str) -> (i32, String, String) {
+    let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+    let (mut out, mut err) = (vec![], vec![]);
+    let code = main(&args, &mut stdin.as_bytes(), &mut out, &mut err);
+    (
+        code,
+        String::from_utf8(out).unwrap(),
+        String::from_utf8(err).unwrap(),
+    )
+}
+
+#[test]
+fn runs_programs() {
+    let (code, out, _) = cheetah("run", "let a: int = 2; print(a); a * 21;");
+    assert_eq!((code, out.as_str()), (SUCCESS, "2\n42\n"));
+    assert_eq!(cheetah("run", ""), (SUCCESS, String::new(), String::new()));
+
+    let (code, out, _) = cheetah("run --vm", "let a: int = 2; print(a); a * 21;");
+    assert_eq!((code, out.as_str()), (SUCCESS, "2\n42\n"));
+}
+
+#[test]
+fn dumps_tokens_and_trees() {
+    let (code, out, _) = cheetah("lex -", "a + 1");
+    assert_eq!(code, SUCCESS);
+    assert_eq!(
+        out,
+        "ID \"a\" 0..1\nPLUS \"+\" 2..3\nNUMBER \"1\" 4..5\nEOF 5..5\n"
+    );
+
+    let (code, out, _) = cheetah("parse --parser pratt --format dot", "1 + 2;");
+    assert_eq!(code, SUCCESS);
+    assert!(out.starts_with("digraph tree {") && out.contains("PLUS\\n+"));
+
+    let (code, out, _) = cheetah("parse --format=json", "1 + 2;");
+    assert_eq!(code, SUCCESS);
+    assert!(out.starts_with("{\n  \"version\": 1,\n  \"tree\": {\n    \"kind\": \"Prog\""));
+
+    let (code, out, _) = cheetah("build --emit tokens,parse", "1;");
+    assert_eq!(code, SUCCESS);
+    assert!(out.starts_with("NUMBER \"1\" 0..1\n") && out.contains("(Prog"));
+
+    let (code, out, _) = cheetah("build", "1;");
+    assert_eq!(code, SUCCESS);
+    assert_eq!(out, "(Program 0..2\n  (Expr 0..1\n    (Int 1 0..1)))\n");
+
+    let (code, out, _) = cheetah("build --emit ir", "print(1 + 2);");
+    assert_eq!(code, SUCCESS);
+    assert!(out.starts_with("fn <script>() -> () {\nbb0:\n    %0: int = const 1\n"));
+    let (code, out, _) = cheetah("build --emit bytecode", "print(1 + 2);");
+    assert_eq!(code, SUCCESS);
+    assert!(out.starts_with("<script>/0:\n0000  CONSTANT          0  ; 1\n"));
+
+    let (code, out, _) = cheetah("build --emit asm", "print(1 + 2);");
+    assert_eq!(code, SUCCESS);
+    assert!(out.starts_with("    .intel_syntax noprefix\n") && out.contains("main:\n"));
+    let (code, out, _) = cheetah("build --emit wat", "print(1 < 2);");
+    assert_eq!(code, SUCCESS);
+    assert!(out.starts_with("(module\n") && out.contains("    call 1 ;; print_bool\n"));
+    let (code, out, _) = cheetah("build --target c", "print(\"hi\");");
+    assert_eq!(code, SUCCESS);
+    assert!(out.contains("int main(void) {\n") && out.contains("cheetah_print_str(t1);"));
+}
+
+#[test]
+fn program_errors_exit_with_failure() {
+    let (code, out, err) = cheetah("check", "let a: int = true;");
+    assert_eq!((code, out.as_str()), (FAILURE, ""));
+    assert!(err.contains("<stdin>:1:"));
+
+    let (code, _, err) = cheetah("build --emit asm", "print(\"hi\");");
+    assert_eq!(code, FAILURE);
+    assert!(err.contains("strings cannot be compiled to x86-64"));
+    let (code, _, err) = cheetah("build --emit wat", "print([1]);");
+    assert_eq!(code, FAILURE);
+    assert!(err.contains("lists cannot be compiled to WebAssembly"));
+}
+
+#[test]
+fn runtime_errors_exit_with_their_own_code() {
+    let (code, _, err) = cheetah("run", "[1][2];");
+    assert_eq!(code, RUNTIME_ERROR);
+    assert!(err.contains("error"));
+    assert_eq!(cheetah("run --vm", "[1][2];").0, RUNTIME_ERROR);
+}
+
+#[test]
+fn formats_and_checks_formatting() {
+    assert_eq!(cheetah("fmt", "a+1;").1, "a + 1;\n");
+    assert_eq!(cheetah("fmt", "# only a comment\n").1, "# only a comment\n");
+    assert_eq!(cheetah("fmt --check", "a+1;").0, FAILURE);
+}
+
+#[test]
+fn usage_errors_exit_with_their_own_code() {
+    assert_eq!(cheetah("run --parser", "").0, USAGE_ERROR);
+    assert_eq!(cheetah("run missing.ch", "").0, USAGE_ERROR);
+    assert_eq!(cheetah("lex --format dot", "").0, USAGE_ERROR);
+    assert_eq!(cheetah("help", "").0, SUCCESS);
+}
+
+#[test]
+fn runs_the_repl_on_stdin() {
+    let (code, out, _) = cheetah("repl", "let a: int = 2;\n{\na }\n");
+    assert_eq!((code, out.as_str()), (SUCCESS, ">> >> .. 2: int\n>> \n"));
+}
@@ -0,0 +1,3 @@
+pub mod cli;
+#[allow(clippy::module_inception, reason = "the commands, next to the command line they are parsed from")]
+pub mod driver;
@@ -0,0 +1,83 @@
+use crate::parser::node::{Node, NodeType};
+use std::fmt::Write;
+
+/* A Graphviz digraph of the tree, rule nodes as boxes and tokens as
+ * ellipses labelled with their text. Nodes are numbered depth first. */
+pub fn node(root: &Node) -> String {
+    let mut out = String::from("digraph tree {\n    node [shape=box, fontname=\"monospace\"];\n");
+    let mut next = 0;
+    write_node(&mut out, root, &mut next);
+    out.push_str("}\n");
+    out
+}
+
+fn write_node(out: &mut String, node: &Node, next: &mut usize) -> usize {
+    let id = *next;
+    *next += 1;
+    match node.kind() {
+        NodeType::Cons(kind) => {
+            writeln!(
+                out,
+                "    n{} [label={}];",
+                id,
+                quote(&format!("{:?}", kind))
+            )
+            .unwrap();
+        }
+        NodeType::Atom(token) => {
+            let label = match token.lexeme.as_deref() {
+                Some(text) => format!("{:?}\n{}", token.kind, text),
+                None => format!("{:?}", token.kind),
+            };
+            writeln!(out, "    n{} [label={}, shape=ellipse];", id, quote(&label)).unwrap();
+        }
+    }
+    for child in node.children() {
+        let child = write_node(out, child, next);
+        writeln!(out, "    n{} -> n{};", id, child).unwrap();
+    }
+    id
+}
+
+fn quote(label: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in label.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[test]
+fn draws_trees() {
+    let tree = crate::testing::pipeline::parse("f(\"a\");", true);
+    assert_eq!(
+        node(&tree),
+        r#"digraph tree {
+    node [shape=box, fontname="monospace"];
+    n0 [label="Prog"];
+    n1 [label="Statement"];
+    n2 [label="Call"];
+    n3 [label="ID\nf", shape=ellipse];
+    n2 -> n3;
+    n4 [label="LPAREN\n(", shape=ellipse];
+    n2 -> n4;
+    n5 [label="STRING\n\"a\"", shape=ellipse];
+    n2 -> n5;
+    n6 [label="RPAREN\n)", shape=ellipse];
+    n2 -> n6;
+    n1 -> n2;
+    n7 [label="SEMICOLON\n;", shape=ellipse];
+    n1 -> n7;
+    n0 -> n1;
+    n8 [label="EOF", shape=ellipse];
+    n0 -> n8;
+}
+"#
+    );
+}
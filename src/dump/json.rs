@@ -0,0 +1,547 @@
+use crate::lexer::tokens::Token;
+use crate::parser::node::{Node, NodeType};
+use crate::source::span::Span;
+use std::fmt::Write;
+
+/* Just enough JSON to write out trees and tokens, and to read them back.
+ * Objects keep their keys in the order they were given, so the output is
+ * stable. Numbers keep the text they were read from, so any JSON number
+ * can be read and written back as it was. */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/* Bumped whenever a dump changes shape, so tools reading them can tell */
+pub const VERSION: i64 = 1;
+
+impl Json {
+    pub fn object<const N: usize>(fields: [(&str, Json); N]) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn number(value: i64) -> Json {
+        Json::Number(value.to_string())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /* Also for numbers written with a fraction or exponent that are
+     * whole, like `2.0` or `1e3` */
+    pub fn as_i64(&self) -> Option<i64> {
+        let Json::Number(text) = self else {
+            return None;
+        };
+        if let Ok(value) = text.parse() {
+            return Some(value);
+        }
+        let value = self.as_f64()?;
+        let whole = value.fract() == 0.0 && value.abs() < 9.2e18;
+        whole.then_some(value as i64)
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(text) => text.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /* Two space indentation. Arrays of scalars and objects whose fields
+     * are all scalars or such arrays stay on one line, so a token is one
+     * line of a dump. */
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, Some(0));
+        out
+    }
+
+    /* Everything on one line */
+    pub fn compact(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, None);
+        out
+    }
+
+    /* Pretty at `depth`, or compact without one */
+    fn write(&self, out: &mut String, depth: Option<usize>) {
+        let depth = depth.filter(|_| !self.is_flat());
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(value) => write!(out, "{}", value).unwrap(),
+            Json::Number(text) => out.push_str(text),
+            Json::String(value) => write_string(out, value),
+            Json::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    separate(out, i, depth);
+                    item.write(out, depth.map(|depth| depth + 1));
+                }
+                close(out, items.is_empty(), depth);
+                out.push(']');
+            }
+            Json::Object(fields) => {
+                out.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    separate(out, i, depth);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, depth.map(|depth| depth + 1));
+                }
+                close(out, fields.is_empty(), depth);
+                out.push('}');
+            }
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Json::Array(_) | Json::Object(_))
+    }
+
+    fn is_flat(&self) -> bool {
+        let scalars = |items: &[Json]| items.iter().all(Json::is_scalar);
+        match self {
+            Json::Array(items) => scalars(items),
+            Json::Object(fields) => fields.iter().all(|(_, value)| match value {
+                Json::Array(items) => scalars(items),
+                value => value.is_scalar(),
+            }),
+            _ => true,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut reader = Reader { text, position: 0 };
+        let value = reader.value()?;
+        reader.whitespace();
+        match reader.peek() {
+            None => Ok(value),
+            Some(_) => Err(reader.error("expected the end of the input")),
+        }
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.compact())
+    }
+}
+
+/* Before the item at index `i` of an array or object */
+fn separate(out: &mut String, i: usize, depth: Option<usize>) {
+    match depth {
+        Some(depth) => {
+            out.push_str(if i > 0 { ",\n" } else { "\n" });
+            indent(out, depth + 1);
+        }
+        None if i > 0 => out.push_str(", "),
+        None => {}
+    }
+}
+
+fn close(out: &mut String, empty: bool, depth: Option<usize>) {
+    if let (Some(depth), false) = (depth, empty) {
+        out.push('\n');
+        indent(out, depth);
+    }
+}
+
+fn indent(out: &mut String, depth: usize) {
+    out.extend(std::iter::repeat_n("  ", depth));
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/* Reads JSON text one value at a time */
+struct Reader<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn whitespace(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        {
+            self.position += 1;
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{} at byte {}", message, self.position)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.whitespace();
+        match self.bump() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected `{}`", expected))),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Result<Json, String> {
+        match self.text[self.position..].starts_with(keyword) {
+            true => {
+                self.position += keyword.len();
+                Ok(value)
+            }
+            false => Err(self.error("expected a value")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.whitespace();
+        match self.peek() {
+            Some('n') => self.keyword("null", Json::Null),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('-' | '0'..='9') => self.number(),
+            Some('[') => {
+                self.position += 1;
+                let mut items = vec![];
+                self.whitespace();
+                if self.peek() == Some(']') {
+                    self.position += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.whitespace();
+                    match self.bump() {
+                        Some(',') => continue,
+                        Some(']') => return Ok(Json::Array(items)),
+                        _ => return Err(self.error("expected `,` or `]`")),
+                    }
+                }
+            }
+            Some('{') => {
+                self.position += 1;
+                let mut fields = vec![];
+                self.whitespace();
+                if self.peek() == Some('}') {
+                    self.position += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    self.whitespace();
+                    match self.bump() {
+                        Some(',') => continue,
+                        Some('}') => return Ok(Json::Object(fields)),
+                        _ => return Err(self.error("expected `,` or `}`")),
+                    }
+                }
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    /* `-? (0 | [1-9][0-9]*) (. [0-9]+)? ([eE] [+-]? [0-9]+)?` */
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.position;
+        self.eat(|c| c == '-');
+        match self.peek() {
+            Some('0') => self.position += 1,
+            Some('1'..='9') => self.digits(),
+            _ => return Err(self.error("expected a digit")),
+        }
+        if self.eat(|c| c == '.') {
+            self.required_digits()?;
+        }
+        if self.eat(|c| matches!(c, 'e' | 'E')) {
+            self.eat(|c| matches!(c, '+' | '-'));
+            self.required_digits()?;
+        }
+        Ok(Json::Number(self.text[start..self.position].to_string()))
+    }
+
+    fn eat(&mut self, expected: impl Fn(char) -> bool) -> bool {
+        let found = self.peek().is_some_and(expected);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn digits(&mut self) {
+        while self.eat(|c| c.is_ascii_digit()) {}
+    }
+
+    fn required_digits(&mut self) -> Result<(), String> {
+        if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            return Err(self.error("expected a digit"));
+        }
+        self.digits();
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.bump() != Some('"') {
+            return Err(self.error("expected a string"));
+        }
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some('"') => return Ok(value),
+                Some('\\') => {
+                    let escaped = match self.bump() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    value.push(escaped);
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    /* After `\\u`, which takes a second escape for the low half of a
+     * surrogate pair */
+    fn unicode(&mut self) -> Result<char, String> {
+        let high = self.hex()?;
+        let code = match high {
+            0xD800..=0xDBFF if self.text[self.position..].starts_with("\\u") => {
+                self.position += 2;
+                match self.hex()? {
+                    low @ 0xDC00..=0xDFFF => 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00),
+                    _ => return Err(self.error("unpaired surrogate")),
+                }
+            }
+            code => code,
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.position..self.position + 4);
+        match digits.filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit())) {
+            Some(digits) => {
+                self.position += 4;
+                Ok(u32::from_str_radix(digits, 16).expect("checked to be hex"))
+            }
+            None => Err(self.error("expected four hex digits")),
+        }
+    }
+}
+
+fn span(span: Span) -> Json {
+    Json::Array(vec![
+        Json::number(span.start as i64),
+        Json::number(span.end as i64),
+    ])
+}
+
+/* The schema of the dumps, at VERSION. Spans are `[start, end]` byte
+ * offsets into the file, and a token's text is empty only for EOF.
+ *
+ *   document  {"version": 1, "tokens": [TOKEN...]} or {"version": 1, "tree": NODE}
+ *   TOKEN     {"token": KIND, "text": TEXT, "span": SPAN}
+ *   NODE      {"kind": KIND, "span": SPAN, "children": [NODE...]}, a rule
+ *             or a TOKEN, with "children" after its span if it is an
+ *             operator from the Pratt parser and its operands */
+pub fn document(key: &str, value: Json) -> Json {
+    Json::object([("version", Json::number(VERSION)), (key, value)])
+}
+
+pub fn token(token: &Token) -> Json {
+    Json::object([
+        ("token", Json::String(format!("{:?}", token.kind))),
+        (
+            "text",
+            Json::String(token.lexeme.clone().unwrap_or_default()),
+        ),
+        ("span", span(token.span)),
+    ])
+}
+
+pub fn tokens(tokens: &[Token]) -> Json {
+    Json::Array(tokens.iter().map(token).collect())
+}
+
+pub fn node(node: &Node) -> Json {
+    let children = || Json::Array(node.children().iter().map(self::node).collect());
+    match node.kind() {
+        NodeType::Cons(kind) => Json::object([
+            ("kind", Json::String(format!("{:?}", kind))),
+            ("span", span(node.span())),
+            ("children", children()),
+        ]),
+        NodeType::Atom(leaf) if node.children().is_empty() => token(leaf),
+        NodeType::Atom(operator) => {
+            let Json::Object(mut fields) = token(operator) else {
+                unreachable!("tokens are objects")
+            };
+            fields.push(("children".to_string(), children()));
+            Json::Object(fields)
+        }
+    }
+}
+
+#[test]
+fn dumps_a_stable_schema() {
+    let tree = crate::testing::pipeline::parse("-a * 2;", true);
+    let dump = document("tree", node(&tree)).pretty();
+    assert_eq!(
+        dump,
+        r#"{
+  "version": 1,
+  "tree": {
+    "kind": "Prog",
+    "span": [0, 7],
+    "children": [
+      {
+        "kind": "Statement",
+        "span": [0, 7],
+        "children": [
+          {
+            "token": "MULTIPLY",
+            "text": "*",
+            "span": [3, 4],
+            "children": [
+              {
+                "token": "MINUS",
+                "text": "-",
+                "span": [0, 1],
+                "children": [
+                  {"token": "ID", "text": "a", "span": [1, 2]}
+                ]
+              },
+              {"token": "NUMBER", "text": "2", "span": [5, 6]}
+            ]
+          },
+          {"token": "SEMICOLON", "text": ";", "span": [6, 7]}
+        ]
+      },
+      {"token": "EOF", "text": "", "span": [7, 7]}
+    ]
+  }
+}"#
+    );
+    assert_eq!(Json::parse(&dump), Ok(document("tree", node(&tree))));
+}
+
+#[test]
+fn escapes_and_unescapes_strings() {
+    let text = Json::String("\"tab\t\u{1}é😀\"".to_string());
+    assert_eq!(text.compact(), r#""\"tab\t\u0001é😀\"""#);
+    assert_eq!(Json::parse(&text.compact()), Ok(text));
+    assert_eq!(
+        Json::parse(r#""\ud83d\ude00\u00e9""#),
+        Ok(Json::String("😀é".to_string()))
+    );
+}
+
+#[test]
+fn parses_nested_values() {
+    assert_eq!(
+        Json::parse(r#" {"a": [1, -2, true, null], "b": {}} "#)
+            .unwrap()
+            .compact(),
+        r#"{"a": [1, -2, true, null], "b": {}}"#
+    );
+}
+
+#[test]
+fn rejects_malformed_json() {
+    for bad in [
+        "",
+        "[1,]",
+        "{\"a\" 1}",
+        "01",
+        "1.",
+        ".5",
+        "-",
+        "1e",
+        "+1",
+        "\"\\ud800\"",
+        "[1] 2",
+        "tru",
+    ] {
+        assert!(Json::parse(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn reads_every_json_number() {
+    let numbers = Json::parse("[0, -1.5, 2.0, 1e3, 6.02E+23, -0, 3.25e-1]").unwrap();
+    assert_eq!(
+        numbers.compact(),
+        "[0, -1.5, 2.0, 1e3, 6.02E+23, -0, 3.25e-1]"
+    );
+    let numbers = numbers.as_array().unwrap();
+    assert_eq!(
+        numbers.iter().map(Json::as_i64).collect::<Vec<_>>(),
+        [Some(0), None, Some(2), Some(1000), None, Some(0), None]
+    );
+    assert_eq!(numbers[6].as_f64(), Some(0.325));
+}
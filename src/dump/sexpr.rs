@@ -0,0 +1,220 @@
+use crate::ast::ast::{Block, Expr, Function, Ident, Literal, Program, Stmt};
+use crate::lexer::tokens::Token;
+use crate::source::span::Span;
+
+/* `KIND "text" start..end`, the token's text quoted the way Rust would */
+pub fn token(token: &Token) -> String {
+    match &token.lexeme {
+        Some(text) => format!(
+            "{:?} {:?} {}..{}",
+            token.kind, text, token.span.start, token.span.end
+        ),
+        None => format!("{:?} {}..{}", token.kind, token.span.start, token.span.end),
+    }
+}
+
+/* One token per line */
+pub fn tokens(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(|token| self::token(token) + "\n")
+        .collect()
+}
+
+/* The AST of a program, one node per line with its children indented
+ * below it. Each node is `(Kind details start..end children)`. */
+pub fn program(program: &Program) -> String {
+    let mut children: Vec<Sexpr> = program.functions.iter().map(function).collect();
+    children.extend(program.stmts.iter().map(statement));
+    Sexpr::new("Program", vec![], program.span, children).render()
+}
+
+/* The AST of a single statement, in the same layout */
+pub fn stmt(stmt: &Stmt) -> String {
+    statement(stmt).render()
+}
+
+struct Sexpr {
+    head: String,
+    children: Vec<Sexpr>,
+}
+
+impl Sexpr {
+    fn new(kind: &str, details: Vec<String>, span: Span, children: Vec<Sexpr>) -> Sexpr {
+        let mut head = vec![kind.to_string()];
+        head.extend(details);
+        head.push(format!("{}..{}", span.start, span.end));
+        Sexpr {
+            head: head.join(" "),
+            children,
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        self.write(0, &mut out);
+        out + "\n"
+    }
+
+    fn write(&self, depth: usize, out: &mut String) {
+        out.push('(');
+        out.push_str(&self.head);
+        for child in &self.children {
+            out.push('\n');
+            out.push_str(&"  ".repeat(depth + 1));
+            child.write(depth + 1, out);
+        }
+        out.push(')');
+    }
+}
+
+fn name(ident: &Ident) -> String {
+    format!("{:?}", ident.name)
+}
+
+fn function(function: &Function) -> Sexpr {
+    let mut details = vec![name(&function.name)];
+    if let Some(ret) = &function.ret {
+        details.extend(["->".to_string(), ret.to_string()]);
+    }
+    let mut children: Vec<Sexpr> = function
+        .params
+        .iter()
+        .map(|param| {
+            let details = vec![name(&param.name), param.ty.to_string()];
+            Sexpr::new("Param", details, param.name.span, vec![])
+        })
+        .collect();
+    children.push(block(&function.body));
+    Sexpr::new("Function", details, function.span, children)
+}
+
+/* The tail is the last child, statements that are expressions being
+ * wrapped in an `Expr` */
+fn block(block: &Block) -> Sexpr {
+    let mut children: Vec<Sexpr> = block.stmts.iter().map(statement).collect();
+    children.extend(block.tail.as_deref().map(expr));
+    Sexpr::new("Block", vec![], block.span, children)
+}
+
+fn statement(stmt: &Stmt) -> Sexpr {
+    let span = stmt.span();
+    match stmt {
+        Stmt::Let {
+            name: ident,
+            ty,
+            value,
+            ..
+        } => Sexpr::new(
+            "Let",
+            vec![name(ident), ty.to_string()],
+            span,
+            vec![expr(value)],
+        ),
+        Stmt::Assign { target, value, .. } => {
+            Sexpr::new("Assign", vec![name(target)], span, vec![expr(value)])
+        }
+        Stmt::IndexAssign {
+            list, index, value, ..
+        } => Sexpr::new(
+            "IndexAssign",
+            vec![],
+            span,
+            vec![expr(list), expr(index), expr(value)],
+        ),
+        Stmt::While { cond, body, .. } => {
+            Sexpr::new("While", vec![], span, vec![expr(cond), block(body)])
+        }
+        Stmt::For {
+            var, iter, body, ..
+        } => Sexpr::new("For", vec![name(var)], span, vec![expr(iter), block(body)]),
+        Stmt::Return { value, .. } => {
+            Sexpr::new("Return", vec![], span, value.iter().map(expr).collect())
+        }
+        Stmt::Block(inner) => self::block(inner),
+        Stmt::Expr(value) => Sexpr::new("Expr", vec![], span, vec![expr(value)]),
+    }
+}
+
+fn expr(expr: &Expr) -> Sexpr {
+    let span = expr.span();
+    let all = |exprs: &[Expr]| exprs.iter().map(self::expr).collect();
+    match expr {
+        Expr::Binary { op, lhs, rhs, .. } => Sexpr::new(
+            "Binary",
+            vec![op.to_string()],
+            span,
+            vec![self::expr(lhs), self::expr(rhs)],
+        ),
+        Expr::Unary { op, operand, .. } => Sexpr::new(
+            "Unary",
+            vec![op.to_string()],
+            span,
+            vec![self::expr(operand)],
+        ),
+        Expr::If {
+            cond,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            let mut children = vec![self::expr(cond), block(then_branch)];
+            children.extend(else_branch.as_deref().map(self::expr));
+            Sexpr::new("If", vec![], span, children)
+        }
+        Expr::Call { callee, args, .. } => Sexpr::new("Call", vec![name(callee)], span, all(args)),
+        Expr::Index { list, index, .. } => Sexpr::new(
+            "Index",
+            vec![],
+            span,
+            vec![self::expr(list), self::expr(index)],
+        ),
+        Expr::List { elements, .. } => Sexpr::new("List", vec![], span, all(elements)),
+        Expr::Template { parts, .. } => Sexpr::new("Template", vec![], span, all(parts)),
+        Expr::Range { start, end, .. } => Sexpr::new(
+            "Range",
+            vec![],
+            span,
+            vec![self::expr(start), self::expr(end)],
+        ),
+        Expr::Block(inner) => block(inner),
+        Expr::Literal { value, .. } => match value {
+            Literal::Int(n) => Sexpr::new("Int", vec![n.to_string()], span, vec![]),
+            Literal::Bool(b) => Sexpr::new("Bool", vec![b.to_string()], span, vec![]),
+            Literal::Str(text) => Sexpr::new("Str", vec![format!("{:?}", text)], span, vec![]),
+        },
+        Expr::Ident(ident) => Sexpr::new("Ident", vec![name(ident)], span, vec![]),
+    }
+}
+
+#[test]
+fn dumps_the_ast() {
+    let input = "def f(n: int) -> int { n }\nlet s: str = \"a{f(1)}\";\nwhile -x < 2 { x = x + 1; }\nprint(s);";
+    assert_eq!(
+        program(&crate::testing::pipeline::program(input)),
+        r#"(Program 0..88
+  (Function "f" -> int 0..26
+    (Param "n" int 6..7)
+    (Block 21..26
+      (Ident "n" 23..24)))
+  (Let "s" str 27..50
+    (Template 40..49
+      (Str "a" 40..43)
+      (Call "f" 43..47
+        (Int 1 45..46))))
+  (While 51..78
+    (Binary < 57..63
+      (Unary - 57..59
+        (Ident "x" 58..59))
+      (Int 2 62..63))
+    (Block 64..78
+      (Assign "x" 66..76
+        (Binary + 70..75
+          (Ident "x" 70..71)
+          (Int 1 74..75)))))
+  (Expr 79..87
+    (Call "print" 79..87
+      (Ident "s" 85..86))))
+"#
+    );
+}
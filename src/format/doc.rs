@@ -0,0 +1,127 @@
+/* A document in the style of Wadler's "A prettier printer": text and
+ * line breaks, where a group either fits on the rest of the line with
+ * all of its breaks flat, or has all of them broken */
+#[derive(Clone, Debug)]
+pub enum Doc {
+    Text(String),
+    /* A space, or a newline when the enclosing group is broken */
+    Line,
+    /* Nothing, or a newline when the enclosing group is broken */
+    SoftLine,
+    /* Always a newline, which breaks every group around it */
+    HardLine,
+    /* Text held back until the end of the line, for trailing comments.
+     * It also breaks the groups around it, as nothing may follow it. */
+    LineSuffix(String),
+    Indent(Box<Doc>),
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+const INDENT: usize = 4;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+type Item<'a> = (usize, Mode, &'a Doc);
+
+pub fn text(text: impl Into<String>) -> Doc {
+    Doc::Text(text.into())
+}
+
+pub fn concat(docs: Vec<Doc>) -> Doc {
+    Doc::Concat(docs)
+}
+
+pub fn group(doc: Doc) -> Doc {
+    Doc::Group(Box::new(doc))
+}
+
+pub fn indent(doc: Doc) -> Doc {
+    Doc::Indent(Box::new(doc))
+}
+
+impl Doc {
+    /* Lays the document out in lines of at most `width` columns where it
+     * can, without trailing whitespace */
+    pub fn render(&self, width: usize) -> String {
+        let mut out = String::new();
+        let mut column = 0;
+        let mut suffixes: Vec<&str> = vec![];
+        let mut stack: Vec<Item> = vec![(0, Mode::Break, self)];
+        while let Some((indentation, mode, doc)) = stack.pop() {
+            match doc {
+                Doc::Text(text) => {
+                    out.push_str(text);
+                    column += text.chars().count();
+                }
+                Doc::Line if mode == Mode::Flat => {
+                    out.push(' ');
+                    column += 1;
+                }
+                Doc::SoftLine if mode == Mode::Flat => {}
+                Doc::Line | Doc::SoftLine | Doc::HardLine => {
+                    out.extend(suffixes.drain(..));
+                    newline(&mut out, indentation);
+                    column = indentation;
+                }
+                Doc::LineSuffix(text) => suffixes.push(text),
+                Doc::Indent(doc) => stack.push((indentation + INDENT, mode, doc)),
+                Doc::Group(doc) => {
+                    let remaining = width as isize - column as isize;
+                    let flat = mode == Mode::Flat
+                        || fits(remaining, (indentation, Mode::Flat, doc), &stack);
+                    let mode = if flat { Mode::Flat } else { Mode::Break };
+                    stack.push((indentation, mode, doc));
+                }
+                Doc::Concat(docs) => {
+                    stack.extend(docs.iter().rev().map(|doc| (indentation, mode, doc)));
+                }
+            }
+        }
+        out.extend(suffixes.drain(..));
+        out
+    }
+}
+
+fn newline(out: &mut String, indentation: usize) {
+    let trimmed = out.trim_end_matches(' ').len();
+    out.truncate(trimmed);
+    out.push('\n');
+    out.extend(std::iter::repeat_n(' ', indentation));
+}
+
+/* If everything up to the next newline fits in `width` columns, with
+ * `first` laid out flat and the rest of the stack as it will be printed */
+fn fits(mut width: isize, first: Item, rest: &[Item]) -> bool {
+    let mut stack = vec![first];
+    let mut rest = rest.iter().rev();
+    loop {
+        let (indentation, mode, doc) = match stack.pop() {
+            Some(item) => item,
+            None => match rest.next() {
+                Some(item) => *item,
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(text) => width -= text.chars().count() as isize,
+            Doc::Line | Doc::SoftLine if mode == Mode::Break => return true,
+            Doc::Line => width -= 1,
+            Doc::SoftLine => {}
+            Doc::HardLine => return mode == Mode::Break,
+            Doc::LineSuffix(_) if mode == Mode::Flat => return false,
+            Doc::LineSuffix(_) => {}
+            Doc::Indent(doc) | Doc::Group(doc) => stack.push((indentation, mode, doc)),
+            Doc::Concat(docs) => {
+                stack.extend(docs.iter().rev().map(|doc| (indentation, mode, doc)));
+            }
+        }
+        if width < 0 {
+            return false;
+        }
+    }
+}
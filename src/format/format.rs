@@ -0,0 +1,912 @@
+use super::doc::{concat, group, indent, text, Doc};
+use crate::cst::build::build;
+use crate::cst::cst::{SyntaxElement, SyntaxNode, SyntaxToken};
+use crate::lexer::tokens::TokenKind;
+use crate::parser::node::{Node, NodeKind};
+use crate::parser::pratt::{infix_bp, prefix_bp};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+pub const DEFAULT_WIDTH: usize = 100;
+
+/* Formats a parse tree from either parser into the canonical layout.
+ * Comments are kept: one on a line of its own stays on a line of its
+ * own, one after code stays at the end of that line. */
+pub fn format(tree: &Node, width: usize) -> String {
+    let cst = build(tree);
+    let formatter = Formatter::new(&cst);
+    let mut formatted = formatter.node(&cst).render(width);
+    if !formatted.is_empty() {
+        formatted.push('\n');
+    }
+    formatted
+}
+
+/* The comments around a token, sorted by where they end up */
+#[derive(Default)]
+struct Comments {
+    /* On lines of their own before the token, each with whether there is
+     * a blank line before it */
+    leading: Vec<(String, bool)>,
+    /* Block comments between the previous token and this one, all on one
+     * line */
+    inline: Vec<String>,
+    /* At the end of the line the token ends */
+    trailing: Vec<String>,
+    /* If there is a blank line right before the token */
+    blank: bool,
+}
+
+impl Comments {
+    fn any(&self) -> bool {
+        !self.leading.is_empty() || !self.inline.is_empty() || !self.trailing.is_empty()
+    }
+}
+
+/* Where an expression is, which decides if it needs parentheses */
+#[derive(Clone, Copy)]
+enum Context {
+    /* A whole expression, such as a value or an argument */
+    Value,
+    /* A whole expression that a statement starts with or a block follows,
+     * where an `if` or a block would be read as a statement */
+    Head,
+    /* The left operand of a binary operator with this left binding power */
+    Left(u8, NodeKind),
+    /* The right operand of a binary operator with this right binding power */
+    Right(u8, NodeKind),
+    /* The operand of a prefix operator with this binding power */
+    Prefix(u8, NodeKind),
+    /* What is called or indexed */
+    Postfix,
+}
+
+struct Formatter {
+    comments: HashMap<usize, Comments>,
+    /* Tokens whose leading comments have already been laid out, by a
+     * statement list putting them between its statements */
+    placed: RefCell<HashSet<usize>>,
+}
+
+impl Formatter {
+    fn new(root: &SyntaxNode) -> Formatter {
+        Formatter {
+            comments: comments(root),
+            placed: RefCell::new(HashSet::new()),
+        }
+    }
+
+    fn node(&self, node: &SyntaxNode) -> Doc {
+        let parts = parts(node);
+        match node.kind() {
+            NodeKind::Prog => self.program(&parts),
+            NodeKind::Items | NodeKind::Statements => self.statements(&flatten(node, node.kind())),
+            NodeKind::FunctionDef => self.function(&parts),
+            NodeKind::Parameter => concat(vec![
+                self.element(&parts[0]),
+                self.element(&parts[1]),
+                text(" "),
+                self.element(&parts[2]),
+            ]),
+            NodeKind::Block | NodeKind::BlockExpr => group(self.block(node, &parts)),
+            NodeKind::Statement => concat(vec![
+                self.expression(&parts[0], Context::Head),
+                self.element(&parts[1]),
+            ]),
+            NodeKind::Declaration => concat(vec![
+                self.element(&parts[0]),
+                text(" "),
+                self.element(&parts[1]),
+                self.element(&parts[2]),
+                text(" "),
+                self.element(&parts[3]),
+                text(" "),
+                self.element(&parts[4]),
+                text(" "),
+                self.expression(&parts[5], Context::Value),
+            ]),
+            NodeKind::Assignment => concat(vec![
+                self.expression(&parts[0], Context::Value),
+                text(" "),
+                self.element(&parts[1]),
+                text(" "),
+                self.expression(&parts[2], Context::Value),
+            ]),
+            /* The blocks of an if and its elses are all on one line or none */
+            NodeKind::IfStmt => group(self.if_chain(&parts)),
+            NodeKind::WhileStmt => concat(vec![
+                self.element(&parts[0]),
+                text(" "),
+                self.expression(&parts[1], Context::Head),
+                text(" "),
+                self.element(&parts[2]),
+            ]),
+            NodeKind::ForStmt => concat(vec![
+                self.element(&parts[0]),
+                text(" "),
+                self.element(&parts[1]),
+                text(" "),
+                self.element(&parts[2]),
+                text(" "),
+                self.expression(&parts[3], Context::Head),
+                text(" "),
+                self.element(&parts[4]),
+            ]),
+            NodeKind::Range => concat(vec![
+                self.expression(&parts[0], Context::Head),
+                self.element(&parts[1]),
+                self.expression(&parts[2], Context::Head),
+            ]),
+            NodeKind::ReturnStmt => match parts.as_slice() {
+                [ret, value, semicolon] => concat(vec![
+                    self.element(ret),
+                    text(" "),
+                    self.expression(value, Context::Value),
+                    self.element(semicolon),
+                ]),
+                _ => concat(parts.iter().map(|part| self.element(part)).collect()),
+            },
+            NodeKind::LogicOr
+            | NodeKind::LogicAnd
+            | NodeKind::Comparison
+            | NodeKind::BitwiseOr
+            | NodeKind::BitwiseXor
+            | NodeKind::BitwiseAnd
+            | NodeKind::BitwiseShift
+            | NodeKind::Sum
+            | NodeKind::Term => self.binary(node),
+            NodeKind::LogicNot | NodeKind::Factor => {
+                let operator = token_kind(&parts[0]).expect("prefix operators are tokens");
+                let ((), bp) = prefix_bp(operator).expect("a prefix operator");
+                /* `- -a` rather than `--a` */
+                let repeated = match self.strip(&parts[1]) {
+                    SyntaxElement::Node(operand) if operand.kind() == node.kind() => {
+                        let inner = token_kind(&self::parts(&operand)[0]);
+                        inner == Some(operator)
+                            && matches!(operator, TokenKind::MINUS | TokenKind::PLUS)
+                    }
+                    _ => false,
+                };
+                concat(vec![
+                    self.element(&parts[0]),
+                    text(if repeated { " " } else { "" }),
+                    self.expression(&parts[1], Context::Prefix(bp, node.kind())),
+                ])
+            }
+            NodeKind::Index => concat(vec![
+                self.expression(&parts[0], Context::Postfix),
+                self.element(&parts[1]),
+                self.expression(&parts[2], Context::Value),
+                self.element(&parts[3]),
+            ]),
+            NodeKind::Call => {
+                let (close, inner) = parts[1..].split_last().expect("a call has parentheses");
+                concat(vec![
+                    self.expression(&parts[0], Context::Postfix),
+                    self.delimited(&inner[0], &items(&inner[1..], NodeKind::Arguments), close),
+                ])
+            }
+            NodeKind::List => {
+                let (close, inner) = parts.split_last().expect("a list has brackets");
+                self.delimited(&inner[0], &items(&inner[1..], NodeKind::Elements), close)
+            }
+            NodeKind::Template => concat(
+                parts
+                    .iter()
+                    .map(|part| match part {
+                        SyntaxElement::Token(_) => self.element(part),
+                        SyntaxElement::Node(_) => text(
+                            self.expression(part, Context::Value)
+                                .render(isize::MAX as usize),
+                        ),
+                    })
+                    .collect(),
+            ),
+            NodeKind::Error => self.verbatim(node),
+            /* Parentheses that had to stay, and anything left */
+            _ => concat(parts.iter().map(|part| self.element(part)).collect()),
+        }
+    }
+
+    fn element(&self, element: &SyntaxElement) -> Doc {
+        match element {
+            SyntaxElement::Node(node) => self.node(node),
+            SyntaxElement::Token(token) => self.token(token),
+        }
+    }
+
+    fn token(&self, token: &SyntaxToken) -> Doc {
+        let Some(comments) = self.comments.get(&token.span().start) else {
+            return text(token.text());
+        };
+        let mut docs = vec![];
+        if !comments.leading.is_empty() && !self.placed.borrow().contains(&token.span().start) {
+            docs.push(Doc::HardLine);
+            docs.extend(self.leading(token));
+        }
+        docs.extend(
+            comments
+                .inline
+                .iter()
+                .map(|comment| text(format!("{} ", comment))),
+        );
+        docs.push(self.bare(token));
+        concat(docs)
+    }
+
+    /* The token and the comments at the end of its line, without those
+     * before it */
+    fn bare(&self, token: &SyntaxToken) -> Doc {
+        let Some(comments) = self.comments.get(&token.span().start) else {
+            return text(token.text());
+        };
+        let mut docs = vec![text(token.text())];
+        docs.extend(
+            comments
+                .trailing
+                .iter()
+                .map(|comment| Doc::LineSuffix(format!(" {}", comment))),
+        );
+        concat(docs)
+    }
+
+    /* The leading comments of a token, each followed by a newline, which
+     * then belong to whoever calls this. Only the first caller gets them. */
+    fn leading(&self, token: &SyntaxToken) -> Vec<Doc> {
+        let start = token.span().start;
+        if !self.placed.borrow_mut().insert(start) {
+            return vec![];
+        }
+        let Some(comments) = self.comments.get(&start) else {
+            return vec![];
+        };
+        let mut docs = vec![];
+        for (i, (comment, blank)) in comments.leading.iter().enumerate() {
+            if i > 0 && *blank {
+                docs.push(Doc::HardLine);
+            }
+            docs.extend([text(comment), Doc::HardLine]);
+        }
+        if !comments.leading.is_empty() && comments.blank {
+            docs.push(Doc::HardLine);
+        }
+        docs
+    }
+
+    /* If there was a blank line before the token or its leading comments */
+    fn blank_before(&self, token: &SyntaxToken) -> bool {
+        self.comments
+            .get(&token.span().start)
+            .is_some_and(|comments| {
+                comments
+                    .leading
+                    .first()
+                    .map_or(comments.blank, |(_, blank)| *blank)
+            })
+    }
+
+    /* Items or statements one per line, keeping single blank lines */
+    fn statements(&self, statements: &[SyntaxElement]) -> Doc {
+        let mut docs = vec![];
+        for (i, statement) in statements.iter().enumerate() {
+            let first = first_token(statement);
+            if i > 0 {
+                docs.push(Doc::HardLine);
+                if self.blank_before(&first) {
+                    docs.push(Doc::HardLine);
+                }
+            }
+            docs.extend(self.leading(&first));
+            docs.push(self.element(statement));
+        }
+        concat(docs)
+    }
+
+    /* Comments before the token on their own lines, for those that end a
+     * block or the file. Block comments right before it get lines of
+     * their own too, so the token is left to be laid out bare. */
+    fn closing_comments(&self, token: &SyntaxToken, after_statements: bool) -> Vec<Doc> {
+        let mut docs = vec![];
+        if let Some(comments) = self.comments.get(&token.span().start) {
+            for (i, (comment, blank)) in comments.leading.iter().enumerate() {
+                if i > 0 || after_statements {
+                    docs.push(Doc::HardLine);
+                    if *blank {
+                        docs.push(Doc::HardLine);
+                    }
+                }
+                docs.push(text(comment));
+            }
+            for comment in &comments.inline {
+                if !docs.is_empty() || after_statements {
+                    docs.push(Doc::HardLine);
+                }
+                docs.push(text(comment));
+            }
+        }
+        self.placed.borrow_mut().insert(token.span().start);
+        docs
+    }
+
+    fn program(&self, parts: &[SyntaxElement]) -> Doc {
+        let (eof, items) = parts.split_last().expect("a program ends with EOF");
+        let items: Vec<SyntaxElement> = items
+            .iter()
+            .flat_map(|item| match item {
+                SyntaxElement::Node(node) if node.kind() == NodeKind::Items => {
+                    flatten(node, NodeKind::Items)
+                }
+                _ => vec![item.clone()],
+            })
+            .collect();
+        let SyntaxElement::Token(eof) = eof else {
+            unreachable!("a program ends with EOF")
+        };
+        let mut docs = vec![self.statements(&items)];
+        docs.extend(self.closing_comments(eof, !items.is_empty()));
+        docs.push(self.token(eof));
+        concat(docs)
+    }
+
+    fn if_chain(&self, parts: &[SyntaxElement]) -> Doc {
+        let block = |element: &SyntaxElement| match element {
+            SyntaxElement::Node(block) => self.block(block, &self::parts(block)),
+            SyntaxElement::Token(_) => unreachable!("blocks are nodes"),
+        };
+        let mut docs = vec![
+            self.element(&parts[0]),
+            text(" "),
+            self.expression(&parts[1], Context::Head),
+            text(" "),
+            block(&parts[2]),
+        ];
+        if let Some(SyntaxElement::Node(else_stmt)) = parts.get(3) {
+            let else_parts = self::parts(else_stmt);
+            docs.extend([text(" "), self.element(&else_parts[0]), text(" ")]);
+            docs.push(match &else_parts[1] {
+                SyntaxElement::Node(if_stmt) if if_stmt.kind() == NodeKind::IfStmt => {
+                    self.if_chain(&self::parts(if_stmt))
+                }
+                other => block(other),
+            });
+        }
+        concat(docs)
+    }
+
+    fn function(&self, parts: &[SyntaxElement]) -> Doc {
+        let mut docs = vec![self.element(&parts[0]), text(" "), self.element(&parts[1])];
+        let close = parts
+            .iter()
+            .position(|part| token_kind(part) == Some(TokenKind::RPAREN));
+        let close = close.expect("a function has a `)`");
+        let parameters = items(&parts[3..close], NodeKind::Parameters);
+        docs.push(self.delimited(&parts[2], &parameters, &parts[close]));
+        for part in &parts[close + 1..] {
+            docs.extend([text(" "), self.element(part)]);
+        }
+        concat(docs)
+    }
+
+    /* A comma separated list in brackets, on one line if it fits and one
+     * item per line if not */
+    fn delimited(
+        &self,
+        open: &SyntaxElement,
+        items: &[SyntaxElement],
+        close: &SyntaxElement,
+    ) -> Doc {
+        let open = self.element(open);
+        if items.is_empty() {
+            return concat(vec![open, self.element(close)]);
+        }
+        let mut inner = vec![Doc::SoftLine];
+        for item in items {
+            match token_kind(item) {
+                Some(TokenKind::COMMA) => inner.extend([self.element(item), Doc::Line]),
+                _ => inner.push(self.broken(item, Context::Value)),
+            }
+        }
+        group(concat(vec![
+            open,
+            indent(concat(inner)),
+            Doc::SoftLine,
+            self.element(close),
+        ]))
+    }
+
+    fn block(&self, node: &SyntaxNode, parts: &[SyntaxElement]) -> Doc {
+        let (open, rest) = parts
+            .split_first()
+            .expect("a block starts with an opening brace");
+        let (close, inner) = rest
+            .split_last()
+            .expect("a block ends with a closing brace");
+        let SyntaxElement::Token(closing) = close else {
+            unreachable!("a block ends with a closing brace")
+        };
+        let mut items: Vec<SyntaxElement> = vec![];
+        for part in inner {
+            match part {
+                SyntaxElement::Node(statements) if statements.kind() == NodeKind::Statements => {
+                    items.extend(flatten(statements, NodeKind::Statements))
+                }
+                _ => items.push(part.clone()),
+            }
+        }
+        let commented = self
+            .comments
+            .get(&closing.span().start)
+            .is_some_and(|c| !c.leading.is_empty() || !c.inline.is_empty());
+        if items.is_empty() && !commented {
+            return concat(vec![self.element(open), self.element(close)]);
+        }
+
+        /* The expression a block ends with can be the only thing in it */
+        let tail = match node.kind() {
+            NodeKind::BlockExpr => items.pop(),
+            _ => None,
+        };
+        let tail_commented = tail.as_ref().is_some_and(|tail| {
+            self.comments
+                .get(&first_token(tail).span().start)
+                .is_some_and(|c| !c.leading.is_empty())
+        });
+        if let (Some(tail), true, false) = (&tail, items.is_empty(), commented || tail_commented) {
+            return concat(vec![
+                self.element(open),
+                indent(concat(vec![
+                    Doc::Line,
+                    self.expression(tail, Context::Value),
+                ])),
+                Doc::Line,
+                self.element(close),
+            ]);
+        }
+
+        let mut body = vec![Doc::HardLine, self.statements(&items)];
+        if let Some(tail) = &tail {
+            let first = first_token(tail);
+            if !items.is_empty() {
+                body.push(Doc::HardLine);
+                if self.blank_before(&first) {
+                    body.push(Doc::HardLine);
+                }
+            }
+            body.extend(self.leading(&first));
+            body.push(self.expression(tail, Context::Value));
+        }
+        let mut closing_comments =
+            self.closing_comments(closing, !items.is_empty() || tail.is_some());
+        body.append(&mut closing_comments);
+        concat(vec![
+            self.element(open),
+            indent(concat(body)),
+            Doc::HardLine,
+            self.bare(closing),
+        ])
+    }
+
+    /* A binary operation, with a run of the same operator on one line if
+     * it fits and broken after each operator if not */
+    fn binary(&self, node: &SyntaxNode) -> Doc {
+        let kind = node.kind();
+        let mut operations: Vec<(SyntaxElement, SyntaxElement)> = vec![];
+        let mut first = SyntaxElement::Node(node.clone());
+        /* Comparisons do not chain */
+        loop {
+            match &first {
+                SyntaxElement::Node(inner)
+                    if inner.kind() == kind
+                        && (kind != NodeKind::Comparison || operations.is_empty()) =>
+                {
+                    let parts = parts(inner);
+                    operations.push((parts[1].clone(), parts[2].clone()));
+                    first = parts[0].clone();
+                }
+                _ => break,
+            }
+        }
+        operations.reverse();
+
+        let operator = |element: &SyntaxElement| {
+            let kind = token_kind(element).expect("binary operators are tokens");
+            infix_bp(kind).expect("a binary operator")
+        };
+        let (l_bp, _) = operator(&operations[0].0);
+        let mut rest = vec![];
+        for (op, rhs) in &operations {
+            let (_, r_bp) = operator(op);
+            /* Comments before an operator go after it, as lines break
+             * there */
+            let SyntaxElement::Token(op) = op else {
+                unreachable!("binary operators are tokens")
+            };
+            let moved = concat(self.leading(op));
+            rest.extend([
+                text(" "),
+                self.token(op),
+                Doc::Line,
+                moved,
+                self.broken(rhs, Context::Right(r_bp, kind)),
+            ]);
+        }
+        group(concat(vec![
+            self.expression(&first, Context::Left(l_bp, kind)),
+            indent(concat(rest)),
+        ]))
+    }
+
+    /* An expression after a line that may break, with its leading
+     * comments on lines of their own before it. Left to the token, they
+     * would come with a line of their own and break every group around
+     * the expression. */
+    fn broken(&self, element: &SyntaxElement, context: Context) -> Doc {
+        let comments = self.leading(&first_token(element));
+        concat(vec![concat(comments), self.expression(element, context)])
+    }
+
+    /* An expression with the parentheses it needs where it is, and no
+     * others. Parentheses with comments on them are left alone. */
+    fn expression(&self, element: &SyntaxElement, context: Context) -> Doc {
+        let inner = self.strip(element);
+        if is_parenthesized(&inner) || !self.needs_parens(&inner, context) {
+            return self.element(&inner);
+        }
+        concat(vec![text("("), self.element(&inner), text(")")])
+    }
+
+    fn strip(&self, element: &SyntaxElement) -> SyntaxElement {
+        let mut element = element.clone();
+        while is_parenthesized(&element) {
+            let SyntaxElement::Node(node) = &element else {
+                break;
+            };
+            let parts = parts(node);
+            let commented = [&parts[0], &parts[2]].iter().any(|paren| {
+                let SyntaxElement::Token(paren) = paren else {
+                    return true;
+                };
+                self.comments
+                    .get(&paren.span().start)
+                    .is_some_and(Comments::any)
+            });
+            if commented {
+                break;
+            }
+            element = parts[1].clone();
+        }
+        element
+    }
+
+    fn needs_parens(&self, element: &SyntaxElement, context: Context) -> bool {
+        let SyntaxElement::Node(node) = element else {
+            return false;
+        };
+        let kind = node.kind();
+        let logic = |parent: NodeKind| {
+            matches!(
+                parent,
+                NodeKind::LogicOr | NodeKind::LogicAnd | NodeKind::LogicNot
+            )
+        };
+        match kind {
+            /* The packrat parser only allows `if` as a whole expression, and
+             * both would take an `if` or a block as a statement */
+            NodeKind::IfStmt | NodeKind::Block | NodeKind::BlockExpr => {
+                !matches!(context, Context::Value)
+            }
+            _ if is_binary(kind) => match context {
+                Context::Value | Context::Head => false,
+                Context::Left(l_bp, parent) => {
+                    l_bp >= self.right_exposure(node)
+                        || (parent == NodeKind::Comparison && kind == parent)
+                }
+                Context::Right(r_bp, parent) => {
+                    self.left_exposure(node) < r_bp
+                        || (parent == NodeKind::Comparison && kind == parent)
+                }
+                Context::Prefix(bp, _) => self.left_exposure(node) < bp,
+                Context::Postfix => true,
+            },
+            /* The packrat parser only allows `!` below other logic operators */
+            NodeKind::LogicNot | NodeKind::Factor => match context {
+                Context::Value | Context::Head => false,
+                Context::Left(l_bp, parent) => {
+                    l_bp >= self.right_exposure(node)
+                        || (kind == NodeKind::LogicNot && !logic(parent))
+                }
+                Context::Right(_, parent) | Context::Prefix(_, parent) => {
+                    kind == NodeKind::LogicNot && !logic(parent)
+                }
+                Context::Postfix => true,
+            },
+            _ => false,
+        }
+    }
+
+    /* The lowest binding power of an operator along the right edge of an
+     * expression, which an operator after it would compete with */
+    fn right_exposure(&self, node: &SyntaxNode) -> u8 {
+        let parts = parts(node);
+        let (bp, last, context) = match node.kind() {
+            kind if is_binary(kind) => {
+                let (_, r_bp) = infix_bp(token_kind(&parts[1]).expect("an operator"))
+                    .expect("a binary operator");
+                (r_bp, &parts[2], Context::Right(r_bp, kind))
+            }
+            NodeKind::LogicNot | NodeKind::Factor => {
+                let ((), bp) = prefix_bp(token_kind(&parts[0]).expect("an operator"))
+                    .expect("a prefix operator");
+                (bp, &parts[1], Context::Prefix(bp, node.kind()))
+            }
+            _ => return u8::MAX,
+        };
+        match self.strip(last) {
+            SyntaxElement::Node(last)
+                if !self.needs_parens(&SyntaxElement::Node(last.clone()), context) =>
+            {
+                bp.min(self.right_exposure(&last))
+            }
+            _ => bp,
+        }
+    }
+
+    /* The lowest binding power of an operator along the left edge */
+    fn left_exposure(&self, node: &SyntaxNode) -> u8 {
+        let kind = node.kind();
+        if !is_binary(kind) {
+            return u8::MAX;
+        }
+        let parts = parts(node);
+        let (l_bp, _) =
+            infix_bp(token_kind(&parts[1]).expect("an operator")).expect("a binary operator");
+        match self.strip(&parts[0]) {
+            SyntaxElement::Node(first)
+                if !self.needs_parens(
+                    &SyntaxElement::Node(first.clone()),
+                    Context::Left(l_bp, kind),
+                ) =>
+            {
+                l_bp.min(self.left_exposure(&first))
+            }
+            _ => l_bp,
+        }
+    }
+
+    /* Code that did not parse, as it was written */
+    fn verbatim(&self, node: &SyntaxNode) -> Doc {
+        let tokens = node.tokens();
+        let start = tokens
+            .iter()
+            .position(|token| !token.is_trivia())
+            .unwrap_or(tokens.len());
+        text(
+            tokens[start..]
+                .iter()
+                .map(SyntaxToken::text)
+                .collect::<String>(),
+        )
+    }
+}
+
+/* Sorts the comments of the tree onto the tokens they belong to */
+fn comments(root: &SyntaxNode) -> HashMap<usize, Comments> {
+    let mut comments: HashMap<usize, Comments> = HashMap::new();
+    let mut previous: Option<usize> = None;
+    let mut trivia: Vec<SyntaxToken> = vec![];
+    for token in root.tokens() {
+        if token.is_trivia() {
+            trivia.push(token);
+            continue;
+        }
+
+        let mut current = Comments::default();
+        let mut newlines = 0;
+        let mut line_ended = false;
+        for (i, piece) in trivia.iter().enumerate() {
+            if piece.kind() == TokenKind::WHITESPACE {
+                newlines += piece.text().matches('\n').count();
+                line_ended |= newlines > 0;
+                continue;
+            }
+            let comment = piece.text().to_string();
+            let line_ends = comment.starts_with('#')
+                || comment.starts_with("//")
+                || trivia[i + 1..]
+                    .iter()
+                    .any(|piece| piece.text().contains('\n'))
+                || token.kind() == TokenKind::EOF;
+            match (previous, line_ended, line_ends) {
+                (Some(previous), false, true) => {
+                    comments.entry(previous).or_default().trailing.push(comment)
+                }
+                /* A block comment with code after it on the same line */
+                (_, _, false) => {
+                    current.inline.push(comment);
+                    continue;
+                }
+                _ => current.leading.push((comment, newlines >= 2)),
+            }
+            newlines = 0;
+        }
+        current.blank = newlines >= 2;
+        trivia.clear();
+
+        let start = token.span().start;
+        if !current.leading.is_empty() || !current.inline.is_empty() || current.blank {
+            comments.insert(start, current);
+        }
+        previous = Some(start);
+    }
+    comments
+}
+
+/* The children of a node other than trivia */
+fn parts(node: &SyntaxNode) -> Vec<SyntaxElement> {
+    node.children_with_tokens()
+        .into_iter()
+        .filter(|child| !matches!(child, SyntaxElement::Token(token) if token.is_trivia()))
+        .collect()
+}
+
+/* The parts of a list nested to the right, such as `a , (b , c)` */
+fn flatten(node: &SyntaxNode, kind: NodeKind) -> Vec<SyntaxElement> {
+    let mut elements = vec![];
+    for part in parts(node) {
+        match &part {
+            SyntaxElement::Node(inner) if inner.kind() == kind => {
+                elements.extend(flatten(inner, kind))
+            }
+            _ => elements.push(part),
+        }
+    }
+    elements
+}
+
+/* The items of a list from the parts between its brackets */
+fn items(parts: &[SyntaxElement], kind: NodeKind) -> Vec<SyntaxElement> {
+    let mut items = vec![];
+    for part in parts {
+        match part {
+            SyntaxElement::Node(node) if node.kind() == kind => items.extend(flatten(node, kind)),
+            _ => items.push(part.clone()),
+        }
+    }
+    items
+}
+
+fn first_token(element: &SyntaxElement) -> SyntaxToken {
+    match element {
+        SyntaxElement::Token(token) => token.clone(),
+        SyntaxElement::Node(node) => node
+            .tokens()
+            .into_iter()
+            .find(|token| !token.is_trivia())
+            .expect("nodes hold at least one token"),
+    }
+}
+
+fn token_kind(element: &SyntaxElement) -> Option<TokenKind> {
+    match element {
+        SyntaxElement::Token(token) => Some(token.kind()),
+        SyntaxElement::Node(_) => None,
+    }
+}
+
+fn is_parenthesized(element: &SyntaxElement) -> bool {
+    let SyntaxElement::Node(node) = element else {
+        return false;
+    };
+    node.kind() == NodeKind::Primary
+        && parts(node).first().and_then(token_kind) == Some(TokenKind::LPAREN)
+}
+
+fn is_binary(kind: NodeKind) -> bool {
+    matches!(
+        kind,
+        NodeKind::LogicOr
+            | NodeKind::LogicAnd
+            | NodeKind::Comparison
+            | NodeKind::BitwiseOr
+            | NodeKind::BitwiseXor
+            | NodeKind::BitwiseAnd
+            | NodeKind::BitwiseShift
+            | NodeKind::Sum
+            | NodeKind::Term
+    )
+}
+
+#[cfg(test)]
+const SAMPLE: &str = "# header\n\ndef   square(x:int)->int{return x*x;}   // squares\n\n\n\
+    let a:int=square(/* two */ 2)+(-1);\n\
+    let b: bool = !(a == 3) && ((a < 2) || a > 5);\n\
+    let c: int = (a - (a - 1)) * (a + 1) - (a * 2);\n\
+    if (b) { print(\"yes {  a+1 }\"); } else if a > 1 { print(\"no\"); } else {}\n\
+    let long: int = square(1000000000) + square(2000000000) + square(3000000000);\n\
+    let v: int = if b { 1 } else { 2 };\n\
+    while (a < 10) {\n  a = a + 1;\n\n\n  // bump\n  xs[0] = -xs[0];\n}\n\
+    ({ 1 }) + (a);\n// the end";
+
+/* Formats `program` with both parsers and checks that formatting again
+ * changes nothing and that only spans differ in the AST */
+#[cfg(test)]
+fn assert_formats_stably(program: &str, width: usize) -> String {
+    use crate::testing::pipeline::{parse, program as lower};
+
+    /* Spans move when formatting, so only the rest of the AST is compared */
+    let spans = regex::Regex::new(r"Span \{[^}]*\}").unwrap();
+    let ast = |input: &str| {
+        spans
+            .replace_all(&format!("{:?}", lower(input)), "")
+            .to_string()
+    };
+
+    let formatted = format(&parse(program, false), width);
+    assert_eq!(format(&parse(program, true), width), formatted);
+    assert_eq!(format(&parse(&formatted, true), width), formatted);
+    assert_eq!(ast(&formatted), ast(program), "\n{}", formatted);
+    formatted
+}
+
+#[test]
+fn formats_canonically() {
+    let expected = "# header\n\ndef square(x: int) -> int {\n    return x * x;\n} // squares\n\n\
+        let a: int = square(/* two */ 2) + -1;\n\
+        let b: bool = !a == 3 && (a < 2 || a > 5);\n\
+        let c: int = (a - (a - 1)) * (a + 1) - a * 2;\n\
+        if b {\n    print(\"yes {a + 1}\");\n} else if a > 1 {\n    print(\"no\");\n} else {}\n\
+        let long: int = square(1000000000) +\n    square(2000000000) +\n    square(3000000000);\n\
+        let v: int = if b { 1 } else { 2 };\n\
+        while a < 10 {\n    a = a + 1;\n\n    // bump\n    xs[0] = -xs[0];\n}\n\
+        ({ 1 }) + a;\n// the end\n";
+    assert_eq!(assert_formats_stably(SAMPLE, 60), expected);
+}
+
+#[test]
+fn keeps_meaning_at_every_width() {
+    let programs = [
+        SAMPLE,
+        "let a: int = -(1 + 2) * 3 % 4 << 1; let b: bool = !(a >= 1) && a != 2 || true;
+        let d: int = if b { 1 } else if !b { let e: int = 2; e } else { ~3 };
+        def f(x: int, y: str) -> int { return g(x) * -f(1, \"a\"); } def g() {}
+        for i in a - 1..f(2, s) * 2 { for c in s { print(c); } }
+        let xs: [[int]] = [[], [1, -a], f(1, s)[0]]; xs[0][1] = -xs[a][len(xs)] * 2;
+        print(\"\\\"{s[1..a + 1]}\\t{ {f(a, \"}\")} }\" + \"\");
+        let e: bool = (!a) == (b == c) || !(a && b) || -(-a) - (-a) == (a << 1) >> 2;
+        let f: int = (a + b)[0] + (-a)[1] + ((1)) - (a - b) - (a - b - c) + (a * b) * c;",
+    ];
+    for program in programs {
+        for width in [100, 40, 10] {
+            assert_formats_stably(program, width);
+        }
+    }
+}
+
+#[test]
+fn keeps_comments_inside_expressions_on_their_own_lines() {
+    let commented = "let a: int = { // c\n x + 1 };\n\
+        let d: int = {\n// d\n x + 1 };\n\
+        let b: int = x + // after\n// between\n 1 * 2;\n\
+        let c: int = x\n# before\n + 1;\n\
+        print(f(\n# first\na + 1, b));";
+    let expected = "let a: int = { // c\n    x + 1\n};\n\
+        let d: int = {\n    // d\n    x + 1\n};\n\
+        let b: int = x + // after\n    // between\n    1 * 2;\n\
+        let c: int = x +\n    # before\n    1;\n\
+        print(\n    f(\n        # first\n        a + 1,\n        b\n    )\n);\n";
+    assert_eq!(assert_formats_stably(commented, 100), expected);
+    for width in [40, 10] {
+        assert_formats_stably(commented, width);
+    }
+}
+
+#[test]
+fn keeps_comments_before_a_closing_brace_inside_the_block() {
+    let commented = "def f() { x; /* inner */ }\n\
+        let a: int = { 1 /* tail */ };\n\
+        def g() { /* empty */ }";
+    let expected = "def f() {\n    x;\n    /* inner */\n}\n\
+        let a: int = {\n    1\n    /* tail */\n};\n\
+        def g() {\n    /* empty */\n}\n";
+    assert_eq!(assert_formats_stably(commented, 100), expected);
+}
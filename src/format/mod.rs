@@ -0,0 +1,3 @@
+pub mod doc;
+#[allow(clippy::module_inception, reason = "the formatter, next to the documents it lays out")]
+pub mod format;
@@ -0,0 +1,662 @@
+use super::value::Value;
+use crate::ast::ast::{BinOp, Block, Expr, Function, Ident, Literal, Program, Stmt, UnOp};
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::sema::builtins::Builtin;
+use crate::source::span::Span;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+/* Runtime errors are boxed to keep the happy path small */
+pub type Result<T> = std::result::Result<T, Box<Diagnostic>>;
+
+/* Every call nests a handful of Rust frames, so deep recursion needs a
+ * bigger stack than a thread gets by default. See `on_large_stack`. */
+pub const MAX_CALL_DEPTH: usize = 10_000;
+const STACK_SIZE: usize = 256 << 20;
+
+/* Runs `f` on a thread with a stack big enough for MAX_CALL_DEPTH calls */
+pub fn on_large_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("could not spawn the interpreter thread")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/* Why evaluation stopped before reaching the end of an expression */
+enum Unwind {
+    Return(Value),
+    Error(Box<Diagnostic>),
+}
+
+impl From<Box<Diagnostic>> for Unwind {
+    fn from(error: Box<Diagnostic>) -> Unwind {
+        Unwind::Error(error)
+    }
+}
+
+type Flow<T> = std::result::Result<T, Unwind>;
+
+/* Where `print` writes to */
+enum Output {
+    Stdout,
+    Captured(String),
+}
+
+/* The globals and functions defined at some point, see `save` */
+pub struct Snapshot {
+    globals: HashMap<String, Value>,
+    functions: HashMap<String, Rc<Function>>,
+}
+
+/* Evaluates a checked program by walking its AST. Programs that have not
+ * been through the checker may make it panic. Globals and functions
+ * outlive `run`, so an interpreter can be fed a program piece by piece. */
+pub struct Interpreter {
+    scopes: Vec<HashMap<String, Value>>,
+    functions: HashMap<String, Rc<Function>>,
+    depth: usize,
+    output: Output,
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter {
+            scopes: vec![HashMap::new()],
+            functions: HashMap::new(),
+            depth: 0,
+            output: Output::Stdout,
+        }
+    }
+
+    /* An interpreter that keeps what the program prints, see `take_output` */
+    pub fn capturing() -> Interpreter {
+        Interpreter {
+            output: Output::Captured(String::new()),
+            ..Interpreter::new()
+        }
+    }
+
+    pub fn take_output(&mut self) -> String {
+        match &mut self.output {
+            Output::Captured(output) => std::mem::take(output),
+            Output::Stdout => String::new(),
+        }
+    }
+
+    /* Runs every statement, returning the value of the last one if it is
+     * an expression or a block */
+    pub fn run(&mut self, program: &Program) -> Result<Value> {
+        for function in &program.functions {
+            self.functions
+                .insert(function.name.name.clone(), Rc::new(function.clone()));
+        }
+        let mut value = Value::Unit;
+        for stmt in &program.stmts {
+            value = match self.exec(stmt) {
+                Ok(value) => value,
+                Err(Unwind::Error(error)) => return Err(error),
+                Err(Unwind::Return(_)) => unreachable!("`return` outside of a function"),
+            };
+        }
+        Ok(value)
+    }
+
+    /* Takes note of what is defined, so that a run that stopped halfway
+     * can be undone with `restore`. Lists are shared rather than copied,
+     * so changes to their elements are not undone. */
+    pub fn save(&self) -> Snapshot {
+        Snapshot {
+            globals: self.scopes[0].clone(),
+            functions: self.functions.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.scopes = vec![snapshot.globals];
+        self.functions = snapshot.functions;
+        self.depth = 0;
+    }
+
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.scopes[0].get(name)
+    }
+
+    fn exec(&mut self, stmt: &Stmt) -> Flow<Value> {
+        match stmt {
+            Stmt::Let { name, value, .. } => {
+                let value = self.eval_expr(value)?;
+                self.scopes
+                    .last_mut()
+                    .expect("there is always a global scope")
+                    .insert(name.name.clone(), value);
+            }
+            Stmt::Assign { target, value, .. } => {
+                let value = self.eval_expr(value)?;
+                *self.lookup(&target.name) = value;
+            }
+            Stmt::IndexAssign {
+                list, index, value, ..
+            } => {
+                let value = self.eval_expr(value)?;
+                let Value::List(elements) = self.eval_expr(list)? else {
+                    panic!("only lists can be assigned to by index")
+                };
+                let i = self.eval_expr(index)?.as_int();
+                let len = elements.borrow().len();
+                let slot = position(i, len, index.span())?;
+                elements.borrow_mut()[slot] = value;
+            }
+            Stmt::While { cond, body, .. } => {
+                while self.eval_expr(cond)?.as_bool() {
+                    self.block(body)?;
+                }
+            }
+            Stmt::For {
+                var, iter, body, ..
+            } => match iter {
+                /* Ranges are counted through rather than collected */
+                Expr::Range { start, end, .. } => {
+                    let start = self.eval_expr(start)?.as_int();
+                    let end = self.eval_expr(end)?.as_int();
+                    for i in start..end {
+                        self.iteration(var, Value::Int(i), body)?;
+                    }
+                }
+                iter => {
+                    for item in self.eval_expr(iter)?.items() {
+                        self.iteration(var, item, body)?;
+                    }
+                }
+            },
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.eval_expr(value)?,
+                    None => Value::Unit,
+                };
+                return Err(Unwind::Return(value));
+            }
+            Stmt::Block(block) => return self.block(block),
+            Stmt::Expr(expr) => return self.eval_expr(expr),
+        }
+        Ok(Value::Unit)
+    }
+
+    pub fn eval(&mut self, expr: &Expr) -> Result<Value> {
+        match self.eval_expr(expr) {
+            Ok(value) => Ok(value),
+            Err(Unwind::Error(error)) => Err(error),
+            Err(Unwind::Return(_)) => unreachable!("`return` outside of a function"),
+        }
+    }
+
+    fn eval_expr(&mut self, expr: &Expr) -> Flow<Value> {
+        match expr {
+            Expr::Literal { value, .. } => Ok(match value {
+                Literal::Int(value) => Value::Int(*value),
+                Literal::Bool(value) => Value::Bool(*value),
+                Literal::Str(value) => Value::Str(value.clone()),
+            }),
+            Expr::Ident(ident) => Ok(self.lookup(&ident.name).clone()),
+            Expr::Call {
+                callee, args, span, ..
+            } => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(self.eval_expr(arg)?);
+                }
+                self.call(callee, values, *span)
+            }
+            Expr::Unary {
+                op, operand, span, ..
+            } => {
+                let operand = self.eval_expr(operand)?;
+                match op {
+                    UnOp::Not => Ok(Value::Bool(!operand.as_bool())),
+                    UnOp::BitNot => Ok(Value::Int(!operand.as_int())),
+                    UnOp::Plus => Ok(operand),
+                    UnOp::Neg => match operand.as_int().checked_neg() {
+                        Some(value) => Ok(Value::Int(value)),
+                        None => Err(overflow("negate", *span).into()),
+                    },
+                }
+            }
+            Expr::Binary {
+                op: BinOp::And,
+                lhs,
+                rhs,
+                ..
+            } => Ok(Value::Bool(
+                self.eval_expr(lhs)?.as_bool() && self.eval_expr(rhs)?.as_bool(),
+            )),
+            Expr::Binary {
+                op: BinOp::Or,
+                lhs,
+                rhs,
+                ..
+            } => Ok(Value::Bool(
+                self.eval_expr(lhs)?.as_bool() || self.eval_expr(rhs)?.as_bool(),
+            )),
+            Expr::Binary {
+                op, lhs, rhs, span, ..
+            } => {
+                let left = self.eval_expr(lhs)?;
+                let right = self.eval_expr(rhs)?;
+                Ok(binary(*op, left, right, rhs.span(), *span)?)
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                if self.eval_expr(cond)?.as_bool() {
+                    self.block(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.eval_expr(else_branch)
+                } else {
+                    Ok(Value::Unit)
+                }
+            }
+            Expr::Block(block) => self.block(block),
+            Expr::List { elements, .. } => {
+                let mut values = vec![];
+                for element in elements {
+                    values.push(self.eval_expr(element)?);
+                }
+                Ok(Value::list(values))
+            }
+            Expr::Index { list, index, .. } => {
+                let list = self.eval_expr(list)?;
+                if let Expr::Range { start, end, span } = &**index {
+                    let start = self.eval_expr(start)?.as_int();
+                    let end = self.eval_expr(end)?.as_int();
+                    return Ok(slice(list, start, end, *span)?);
+                }
+                let i = self.eval_expr(index)?.as_int();
+                match list {
+                    Value::List(elements) => {
+                        let elements = elements.borrow();
+                        Ok(elements[position(i, elements.len(), index.span())?].clone())
+                    }
+                    Value::Str(value) => {
+                        let len = value.chars().count();
+                        let c = value.chars().nth(position(i, len, index.span())?);
+                        Ok(Value::Str(c.expect("checked by position").to_string()))
+                    }
+                    value => panic!("cannot index into {:?}", value),
+                }
+            }
+            Expr::Template { parts, .. } => {
+                let mut text = String::new();
+                for part in parts {
+                    text += &self.eval_expr(part)?.to_string();
+                }
+                Ok(Value::Str(text))
+            }
+            Expr::Range { .. } => unreachable!("ranges are only iterated over"),
+        }
+    }
+
+    fn call(&mut self, callee: &Ident, args: Vec<Value>, span: Span) -> Flow<Value> {
+        let Some(function) = self.functions.get(&callee.name).cloned() else {
+            return self.call_builtin(&callee.name, args);
+        };
+        if self.depth == MAX_CALL_DEPTH {
+            let error = Diagnostic::error("stack overflow", span)
+                .with_label(span, format!("`{}` was called here", callee.name))
+                .with_note(format!("calls may only be nested {} deep", MAX_CALL_DEPTH));
+            return Err(Unwind::Error(Box::new(error)));
+        }
+
+        /* Functions only see their parameters */
+        let params = function
+            .params
+            .iter()
+            .map(|param| param.name.name.clone())
+            .zip(args)
+            .collect();
+        let caller = std::mem::replace(&mut self.scopes, vec![params]);
+        self.depth += 1;
+        let value = self.block(&function.body);
+        self.depth -= 1;
+        self.scopes = caller;
+
+        match value {
+            Err(Unwind::Return(value)) => Ok(value),
+            value => value,
+        }
+    }
+
+    fn call_builtin(&mut self, name: &str, args: Vec<Value>) -> Flow<Value> {
+        match Builtin::ALL.iter().find(|builtin| builtin.name() == name) {
+            Some(Builtin::Print) => {
+                let line = format!("{}\n", args[0]);
+                match &mut self.output {
+                    Output::Stdout => {
+                        let mut stdout = std::io::stdout();
+                        stdout
+                            .write_all(line.as_bytes())
+                            .expect("could not write to stdout");
+                    }
+                    Output::Captured(output) => output.push_str(&line),
+                }
+                Ok(Value::Unit)
+            }
+            Some(Builtin::Len) => match &args[0] {
+                Value::Str(value) => Ok(Value::Int(value.chars().count() as i64)),
+                Value::List(elements) => Ok(Value::Int(elements.borrow().len() as i64)),
+                value => panic!("cannot take the length of {:?}", value),
+            },
+            None => panic!("unresolved function `{}`", name),
+        }
+    }
+
+    fn block(&mut self, block: &Block) -> Flow<Value> {
+        self.scopes.push(HashMap::new());
+        let value = self.block_body(block);
+        self.scopes.pop();
+        value
+    }
+
+    /* Runs a loop body with the loop variable in a scope of its own */
+    fn iteration(&mut self, var: &Ident, item: Value, body: &Block) -> Flow<Value> {
+        self.scopes.push(HashMap::from([(var.name.clone(), item)]));
+        let value = self.block(body);
+        self.scopes.pop();
+        value
+    }
+
+    fn block_body(&mut self, block: &Block) -> Flow<Value> {
+        for stmt in &block.stmts {
+            self.exec(stmt)?;
+        }
+        match &block.tail {
+            Some(tail) => self.eval_expr(tail),
+            None => Ok(Value::Unit),
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> &mut Value {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+            .unwrap_or_else(|| panic!("unresolved name `{}`", name))
+    }
+}
+
+/* Turns an index into a position in a sequence of `len` elements */
+pub fn position(index: i64, len: usize, span: Span) -> Result<usize> {
+    match usize::try_from(index) {
+        Ok(position) if position < len => Ok(position),
+        _ => Err(Box::new(
+            Diagnostic::error(
+                format!(
+                    "index out of bounds: the length is {} but the index is {}",
+                    len, index
+                ),
+                span,
+            )
+            .with_label(span, format!("this evaluated to {}", index)),
+        )),
+    }
+}
+
+/* The characters or elements from `start` up to but not including `end` */
+pub fn slice(value: Value, start: i64, end: i64, span: Span) -> Result<Value> {
+    let len = match &value {
+        Value::Str(value) => value.chars().count(),
+        Value::List(elements) => elements.borrow().len(),
+        value => panic!("cannot slice {:?}", value),
+    };
+    let bounds = usize::try_from(start).ok().zip(usize::try_from(end).ok());
+    let Some((from, to)) = bounds.filter(|(from, to)| from <= to && *to <= len) else {
+        return Err(Box::new(
+            Diagnostic::error(
+                format!(
+                    "slice `{}..{}` is out of bounds for length {}",
+                    start, end, len
+                ),
+                span,
+            )
+            .with_label(span, "this range"),
+        ));
+    };
+    Ok(match value {
+        Value::List(elements) => Value::list(elements.borrow()[from..to].to_vec()),
+        value => Value::Str(
+            value
+                .to_string()
+                .chars()
+                .skip(from)
+                .take(to - from)
+                .collect(),
+        ),
+    })
+}
+
+pub fn binary(op: BinOp, left: Value, right: Value, rhs: Span, span: Span) -> Result<Value> {
+    if let (Value::Str(left), Value::Str(right)) = (&left, &right) {
+        match op {
+            BinOp::Add => return Ok(Value::Str(format!("{}{}", left, right))),
+            BinOp::Lt => return Ok(Value::Bool(left < right)),
+            BinOp::Le => return Ok(Value::Bool(left <= right)),
+            BinOp::Gt => return Ok(Value::Bool(left > right)),
+            BinOp::Ge => return Ok(Value::Bool(left >= right)),
+            _ => {}
+        }
+    }
+    let value = match op {
+        BinOp::Eq => return Ok(Value::Bool(left == right)),
+        BinOp::Ne => return Ok(Value::Bool(left != right)),
+        BinOp::And | BinOp::Or => unreachable!("short-circuiting operators are evaluated lazily"),
+        _ => {
+            let (left, right) = (left.as_int(), right.as_int());
+            match op {
+                BinOp::Lt => return Ok(Value::Bool(left < right)),
+                BinOp::Le => return Ok(Value::Bool(left <= right)),
+                BinOp::Gt => return Ok(Value::Bool(left > right)),
+                BinOp::Ge => return Ok(Value::Bool(left >= right)),
+                BinOp::BitOr => left | right,
+                BinOp::BitXor => left ^ right,
+                BinOp::BitAnd => left & right,
+                BinOp::Add => left
+                    .checked_add(right)
+                    .ok_or_else(|| overflow("add", span))?,
+                BinOp::Sub => left
+                    .checked_sub(right)
+                    .ok_or_else(|| overflow("subtract", span))?,
+                BinOp::Mul => left
+                    .checked_mul(right)
+                    .ok_or_else(|| overflow("multiply", span))?,
+                BinOp::Div | BinOp::Mod if right == 0 => {
+                    let message = match op {
+                        BinOp::Div => "attempt to divide by zero",
+                        _ => "attempt to calculate the remainder with a divisor of zero",
+                    };
+                    return Err(Box::new(
+                        Diagnostic::error(message, span).with_label(rhs, "this evaluated to zero"),
+                    ));
+                }
+                BinOp::Div => left
+                    .checked_div(right)
+                    .ok_or_else(|| overflow("divide", span))?,
+                BinOp::Mod => left
+                    .checked_rem(right)
+                    .ok_or_else(|| overflow("calculate the remainder", span))?,
+                BinOp::Shl | BinOp::Shr => shift(op, left, right, rhs, span)?,
+                _ => unreachable!(),
+            }
+        }
+    };
+    Ok(Value::Int(value))
+}
+
+fn shift(op: BinOp, left: i64, right: i64, rhs: Span, span: Span) -> Result<i64> {
+    let direction = if op == BinOp::Shl { "left" } else { "right" };
+    let shifted = u32::try_from(right).ok().and_then(|amount| match op {
+        BinOp::Shl => left.checked_shl(amount),
+        _ => left.checked_shr(amount),
+    });
+    shifted.ok_or_else(|| {
+        Box::new(
+            Diagnostic::error(
+                format!(
+                    "attempt to shift {} by `{}`, which would overflow",
+                    direction, right
+                ),
+                span,
+            )
+            .with_label(rhs, format!("this evaluated to {}", right))
+            .with_note(format!(
+                "an `int` can only be shifted by 0 to {}",
+                i64::BITS - 1
+            )),
+        )
+    })
+}
+
+pub fn overflow(operation: &str, span: Span) -> Box<Diagnostic> {
+    Box::new(
+        Diagnostic::error(format!("attempt to {} with overflow", operation), span).with_note(
+            format!("an `int` holds values from {} to {}", i64::MIN, i64::MAX),
+        ),
+    )
+}
+
+#[cfg(test)]
+fn run(input: &str) -> (Interpreter, Result<Value>) {
+    let program = crate::testing::pipeline::checked(input);
+    let mut interpreter = Interpreter::new();
+    let value = interpreter.run(&program);
+    (interpreter, value)
+}
+
+#[test]
+fn runs_statements_and_keeps_globals() {
+    let (interpreter, value) = run("let n: int = 10;
+        let a: int = 0;
+        let b: int = 1;
+        while n > 0 { let c: int = a + b; a = b; b = c; n = n - 1; }
+        let big: bool = { let limit: int = 50; if a > limit { true } else { false } };
+        let s: str = if big && a % 5 == 0 { \"big\" } else { \"small\" };
+        { a * 2 }");
+    assert_eq!(value, Ok(Value::Int(110)));
+    assert_eq!(interpreter.global("a"), Some(&Value::Int(55)));
+    assert_eq!(interpreter.global("big"), Some(&Value::Bool(true)));
+    assert_eq!(interpreter.global("s"), Some(&Value::Str("big".into())));
+    assert_eq!(interpreter.global("c"), None);
+}
+
+#[test]
+fn for_iterates_ranges_and_strings() {
+    let (interpreter, value) = run("def find(s: str, c: str) -> int {
+            let i: int = 0;
+            for x in s { if x == c { return i; } i = i + 1; }
+            0 - 1
+        }
+        let sum: int = 0;
+        for i in 1..find(\"cheetah\", \"t\") + 1 { for j in 0..i { sum = sum + j; } }
+        { sum }");
+    assert_eq!(value, Ok(Value::Int(10)));
+    assert_eq!(interpreter.global("i"), None);
+}
+
+#[test]
+fn lists_are_shared_between_variables() {
+    let (interpreter, value) = run("let xs: [[int]] = [[1, 2], [3]];
+        let ys: [[int]] = xs;
+        ys[1] = [4, 5];
+        xs[0][1] = len(ys[1]) * 10;
+        { xs[0][1] + xs[1][0] }");
+    assert_eq!(value, Ok(Value::Int(24)));
+    assert_eq!(
+        interpreter.global("ys").unwrap().to_string(),
+        "[[1, 20], [4, 5]]"
+    );
+}
+
+#[test]
+fn index_out_of_bounds_is_a_runtime_error() {
+    let input = "let xs: [int] = [1]; let i: int = 0 - 1; xs[i] = 2;";
+    let error = run(input).1.unwrap_err();
+    assert_eq!(
+        error.message,
+        "index out of bounds: the length is 1 but the index is -1"
+    );
+}
+
+#[test]
+fn strings_interpolate_slice_and_compare() {
+    let (interpreter, _) = run("let name: str = \"wörld\";
+        let xs: [str] = [name[1..3], name[4..5]];
+        let s: str = \"{name[0]}{\"}\"} {xs} {len(name) > 4}\\n\" + \"ok\";
+        let b: bool = \"abc\" < \"abd\" && \"b\" >= \"ab\";");
+    assert_eq!(
+        interpreter.global("s"),
+        Some(&Value::Str("w} [\"ör\", \"d\"] true\nok".into()))
+    );
+    assert_eq!(interpreter.global("b"), Some(&Value::Bool(true)));
+}
+
+#[test]
+fn slice_out_of_bounds_is_a_runtime_error() {
+    let error = run("let s: str = \"abc\"; let t: str = s[2..4];")
+        .1
+        .unwrap_err();
+    assert_eq!(error.message, "slice `2..4` is out of bounds for length 3");
+}
+
+#[test]
+fn division_by_zero_is_a_runtime_error() {
+    let input = "let a: int = 1; let b: int = a - 1; let c: int = a / b;";
+    let error = run(input).1.unwrap_err();
+    assert_eq!(error.message, "attempt to divide by zero");
+    assert_eq!(
+        &input[error.primary_span.start..error.primary_span.end],
+        "a / b"
+    );
+}
+
+#[test]
+fn shift_overflow_is_a_runtime_error() {
+    let input = "let a: int = 1 << 64;";
+    let error = run(input).1.unwrap_err();
+    assert_eq!(
+        error.message,
+        "attempt to shift left by `64`, which would overflow"
+    );
+    assert_eq!(
+        &input[error.labels[0].span.start..error.labels[0].span.end],
+        "64"
+    );
+}
+
+#[test]
+fn calls_recurse_until_the_stack_overflows() {
+    let program = crate::testing::pipeline::checked(
+        "def fact(n: int) -> int { if n < 2 { return 1; } n * fact(n - 1) }
+        def down(n: int) -> int { if n == 0 { 0 } else { down(n - 1) } }
+        print(fact(20));
+        print(down(200));
+        down(20000);",
+    );
+    let (output, error) = on_large_stack(|| {
+        let mut interpreter = Interpreter::capturing();
+        let error = interpreter.run(&program).unwrap_err();
+        (interpreter.take_output(), error)
+    });
+    assert_eq!(output, "2432902008176640000\n0\n");
+    assert_eq!(error.message, "stack overflow");
+}
@@ -0,0 +1,3 @@
+#[allow(clippy::module_inception, reason = "the interpreter, next to its runtime values")]
+pub mod interp;
+pub mod value;
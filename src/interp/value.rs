@@ -0,0 +1,70 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/* A runtime value. The checker has made sure operands have the right
+ * types, so the interpreter never has to convert between them. Lists are
+ * shared, assigning one to another variable does not copy it. */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    Str(String),
+    List(Rc<RefCell<Vec<Value>>>),
+    Unit,
+}
+
+impl Value {
+    pub fn as_int(&self) -> i64 {
+        match self {
+            Value::Int(value) => *value,
+            _ => panic!("expected an int, found {:?}", self),
+        }
+    }
+
+    pub fn as_bool(&self) -> bool {
+        match self {
+            Value::Bool(value) => *value,
+            _ => panic!("expected a bool, found {:?}", self),
+        }
+    }
+
+    pub fn list(elements: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+
+    /* What a `for` loop over this value iterates over, a string yields
+     * each of its characters as a string */
+    pub fn items(&self) -> Vec<Value> {
+        match self {
+            Value::Str(value) => value.chars().map(|c| Value::Str(c.to_string())).collect(),
+            Value::List(elements) => elements.borrow().clone(),
+            _ => panic!("cannot iterate over {:?}", self),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Str(value) => write!(f, "{}", value),
+            /* Strings are quoted inside a list, so `["a, b"]` and `["a", "b"]` differ */
+            Value::List(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match element {
+                        Value::Str(value) => write!(f, "{:?}", value)?,
+                        element => write!(f, "{}", element)?,
+                    }
+                }
+                write!(f, "]")
+            }
+            Value::Unit => write!(f, "()"),
+        }
+    }
+}
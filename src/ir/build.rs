@@ -0,0 +1,729 @@
+use super::ir::{BasicBlock, BlockId, Const, Function, Inst, Module, Phi, Terminator, Value};
+use crate::ast::ast::{BinOp, Block, Expr, Ident, Literal, Param, Program, Stmt, UnOp};
+use crate::sema::check::Checker;
+use crate::sema::resolve::SymbolId;
+use crate::sema::types::Ty;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/* Lowers a checked program to SSA form. A variable is whatever value was
+ * last assigned to it, and where control flow meets, after the branches
+ * of an `if` or `&&` and at the top of a loop, phis pick between the
+ * values it had on the way in. Programs that have not been through the
+ * checker may make it panic. */
+pub fn build(program: &Program, checker: &Checker) -> Module {
+    let mut functions = vec![script(program, checker)];
+    for function in &program.functions {
+        let mut builder = Builder::new(checker);
+        let params = builder.params(&function.params);
+        let value = builder.block(&function.body);
+        let ret = function.ret.as_ref().map_or(Ty::Unit, Ty::from);
+        builder.terminate(Terminator::Return(value));
+        functions.push(builder.finish(&function.name.name, params, ret));
+    }
+    Module { functions }
+}
+
+/* The statements outside of functions, returning the value of the last
+ * one like `cheetah run` prints it */
+fn script(program: &Program, checker: &Checker) -> Function {
+    let mut builder = Builder::new(checker);
+    let mut value = None;
+    for (i, stmt) in program.stmts.iter().enumerate() {
+        value = match stmt {
+            Stmt::Expr(expr) if i + 1 == program.stmts.len() => Some(builder.expr(expr)),
+            Stmt::Block(block) if i + 1 == program.stmts.len() => Some(builder.block(block)),
+            stmt => {
+                builder.stmt(stmt);
+                None
+            }
+        };
+    }
+    let value = value.unwrap_or_else(|| builder.unit());
+    let ret = builder.types[value.0].clone();
+    builder.terminate(Terminator::Return(value));
+    builder.finish(Module::SCRIPT, vec![], ret)
+}
+
+/* A block being built, which gets its terminator last */
+struct Draft {
+    phis: Vec<Phi>,
+    insts: Vec<(Value, Inst)>,
+    term: Option<Terminator>,
+}
+
+/* Builds one function. Code after a `return` goes on in a block nothing
+ * goes to, and `finish` throws those blocks away along with what only
+ * they define. */
+struct Builder<'a> {
+    checker: &'a Checker,
+    types: Vec<Ty>,
+    blocks: Vec<Draft>,
+    current: BlockId,
+    /* The value of each variable at the current instruction */
+    vars: BTreeMap<SymbolId, Value>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(checker: &'a Checker) -> Builder<'a> {
+        let mut builder = Builder {
+            checker,
+            types: vec![],
+            blocks: vec![],
+            current: BlockId(0),
+            vars: BTreeMap::new(),
+        };
+        builder.current = builder.new_block();
+        builder
+    }
+
+    fn params(&mut self, params: &[Param]) -> Vec<Value> {
+        let mut values = vec![];
+        for param in params {
+            let value = self.value(Ty::from(&param.ty));
+            let symbol = self.symbol(&param.name);
+            self.vars.insert(symbol, value);
+            values.push(value);
+        }
+        values
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let { name, value, .. } => {
+                let value = self.expr(value);
+                let symbol = self.symbol(name);
+                self.vars.insert(symbol, value);
+            }
+            Stmt::Assign { target, value, .. } => {
+                let value = self.expr(value);
+                let symbol = self.symbol(target);
+                self.vars.insert(symbol, value);
+            }
+            Stmt::IndexAssign {
+                list, index, value, ..
+            } => {
+                let value = self.expr(value);
+                let list = self.expr(list);
+                let index = self.expr(index);
+                self.emit(Inst::SetIndex(list, index, value), Ty::Unit);
+            }
+            Stmt::While { cond, body, .. } => {
+                let (header, phis) = self.loop_header();
+                let cond = self.expr(cond);
+                let exit = self.loop_exit(cond);
+                self.block(body);
+                self.loop_back(header, &phis);
+                self.switch(exit.0);
+                self.vars = exit.1;
+            }
+            Stmt::For {
+                var, iter, body, ..
+            } => self.for_loop(var, iter, body),
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.expr(value),
+                    None => self.unit(),
+                };
+                self.terminate(Terminator::Return(value));
+                let unreachable = self.new_block();
+                self.switch(unreachable);
+            }
+            Stmt::Block(block) => {
+                self.block(block);
+            }
+            Stmt::Expr(expr) => {
+                self.expr(expr);
+            }
+        }
+    }
+
+    /* Counts through a range, or through the indices of a list or string
+     * up to the length it had before the loop */
+    fn for_loop(&mut self, var: &Ident, iter: &Expr, body: &Block) {
+        let (start, end, items) = match iter {
+            Expr::Range { start, end, .. } => (self.expr(start), self.expr(end), None),
+            iter => {
+                let items = self.expr(iter);
+                let len = self.emit(Inst::Call("len".to_string(), vec![items]), Ty::Int);
+                (self.constant(Const::Int(0)), len, Some(items))
+            }
+        };
+        let entry = self.current;
+        let (header, phis) = self.loop_header();
+        let counter = self.phi(Ty::Int, vec![(entry, start)]);
+        let cond = self.emit(Inst::Binary(BinOp::Lt, counter, end), Ty::Bool);
+        let exit = self.loop_exit(cond);
+
+        let item = match items {
+            Some(items) => {
+                let ty = self.ty(iter).item().expect("checked to be iterable");
+                self.emit(Inst::Index(items, counter), ty)
+            }
+            None => counter,
+        };
+        let symbol = self.symbol(var);
+        self.vars.insert(symbol, item);
+        self.block(body);
+        let one = self.constant(Const::Int(1));
+        let next = self.emit(Inst::Binary(BinOp::Add, counter, one), Ty::Int);
+        let latch = self.current;
+        self.loop_back(header, &phis);
+        self.incoming(header, counter, latch, next);
+        self.switch(exit.0);
+        self.vars = exit.1;
+    }
+
+    /* Starts a loop at a new block, with a phi for every variable as it
+     * could have been changed by the last iteration */
+    fn loop_header(&mut self) -> (BlockId, Vec<(SymbolId, Value)>) {
+        let (entry, header) = (self.current, self.new_block());
+        self.terminate(Terminator::Jump(header));
+        self.switch(header);
+        let mut phis = vec![];
+        for (symbol, value) in self.vars.clone() {
+            let ty = self.var_type(symbol, value);
+            let phi = self.phi(ty, vec![(entry, value)]);
+            self.vars.insert(symbol, phi);
+            phis.push((symbol, phi));
+        }
+        (header, phis)
+    }
+
+    /* Branches into the body of a loop, returning the block after the loop
+     * and the variables there */
+    fn loop_exit(&mut self, cond: Value) -> (BlockId, BTreeMap<SymbolId, Value>) {
+        let (body, exit) = (self.new_block(), self.new_block());
+        self.terminate(Terminator::Branch(cond, body, exit));
+        self.switch(body);
+        (exit, self.vars.clone())
+    }
+
+    fn loop_back(&mut self, header: BlockId, phis: &[(SymbolId, Value)]) {
+        let latch = self.current;
+        self.terminate(Terminator::Jump(header));
+        for &(symbol, phi) in phis {
+            let value = self.vars[&symbol];
+            self.incoming(header, phi, latch, value);
+        }
+    }
+
+    fn block(&mut self, block: &Block) -> Value {
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        match &block.tail {
+            Some(tail) => self.expr(tail),
+            None => self.unit(),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Value {
+        match expr {
+            Expr::Literal { value, .. } => self.constant(match value {
+                Literal::Int(value) => Const::Int(*value),
+                Literal::Bool(value) => Const::Bool(*value),
+                Literal::Str(value) => Const::Str(value.clone()),
+            }),
+            Expr::Ident(ident) => {
+                let symbol = self.symbol(ident);
+                *self
+                    .vars
+                    .get(&symbol)
+                    .expect("variables are defined before use")
+            }
+            Expr::Call { callee, args, .. } => {
+                let args = args.iter().map(|arg| self.expr(arg)).collect();
+                let ty = self.ty(expr);
+                self.emit(Inst::Call(callee.name.clone(), args), ty)
+            }
+            Expr::Unary {
+                op: UnOp::Plus,
+                operand,
+                ..
+            } => self.expr(operand),
+            Expr::Unary { op, operand, .. } => {
+                let operand = self.expr(operand);
+                let ty = self.ty(expr);
+                self.emit(Inst::Unary(*op, operand), ty)
+            }
+            Expr::Binary {
+                op: op @ (BinOp::And | BinOp::Or),
+                lhs,
+                rhs,
+                ..
+            } => self.short_circuit(*op, lhs, rhs),
+            Expr::Binary { op, lhs, rhs, .. } => {
+                let lhs = self.expr(lhs);
+                let rhs = self.expr(rhs);
+                let ty = self.ty(expr);
+                self.emit(Inst::Binary(*op, lhs, rhs), ty)
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => self.if_expr(cond, then_branch, else_branch.as_deref(), self.ty(expr)),
+            Expr::Block(block) => self.block(block),
+            Expr::List { elements, .. } => {
+                let elements = elements.iter().map(|element| self.expr(element)).collect();
+                let ty = self.ty(expr);
+                self.emit(Inst::List(elements), ty)
+            }
+            Expr::Index { list, index, .. } => {
+                let list = self.expr(list);
+                let ty = self.ty(expr);
+                if let Expr::Range { start, end, .. } = &**index {
+                    let start = self.expr(start);
+                    let end = self.expr(end);
+                    return self.emit(Inst::Slice(list, start, end), ty);
+                }
+                let index = self.expr(index);
+                self.emit(Inst::Index(list, index), ty)
+            }
+            Expr::Template { parts, .. } => {
+                let parts = parts.iter().map(|part| self.expr(part)).collect();
+                self.emit(Inst::Template(parts), Ty::Str)
+            }
+            Expr::Range { .. } => unreachable!("ranges are only iterated over"),
+        }
+    }
+
+    /* The right side only runs if the left one did not decide, and the
+     * left one is what it decided */
+    fn short_circuit(&mut self, op: BinOp, lhs: &Expr, rhs: &Expr) -> Value {
+        let lhs = self.expr(lhs);
+        let (from, before) = (self.current, self.vars.clone());
+        let (right, join) = (self.new_block(), self.new_block());
+        self.terminate(match op {
+            BinOp::And => Terminator::Branch(lhs, right, join),
+            _ => Terminator::Branch(lhs, join, right),
+        });
+        self.switch(right);
+        let rhs = self.expr(rhs);
+        let arm = (self.current, self.vars.clone());
+        self.terminate(Terminator::Jump(join));
+        let arms = [(from, before.clone()), arm.clone()];
+        self.join(join, &arms, &before);
+        self.phi(Ty::Bool, vec![(from, lhs), (arm.0, rhs)])
+    }
+
+    fn if_expr(
+        &mut self,
+        cond: &Expr,
+        then_branch: &Block,
+        else_branch: Option<&Expr>,
+        ty: Ty,
+    ) -> Value {
+        let cond = self.expr(cond);
+        let (from, before) = (self.current, self.vars.clone());
+        let (then, join) = (self.new_block(), self.new_block());
+        let otherwise = match else_branch {
+            Some(_) => self.new_block(),
+            None => join,
+        };
+        self.terminate(Terminator::Branch(cond, then, otherwise));
+
+        self.switch(then);
+        let then_value = self.block(then_branch);
+        let then_arm = (self.current, self.vars.clone());
+        self.terminate(Terminator::Jump(join));
+        self.vars = before.clone();
+        let Some(else_branch) = else_branch else {
+            self.join(join, &[then_arm, (from, before.clone())], &before);
+            return self.unit();
+        };
+        self.switch(otherwise);
+        let else_value = self.expr(else_branch);
+        let else_arm = (self.current, self.vars.clone());
+        self.terminate(Terminator::Jump(join));
+
+        let incoming = vec![(then_arm.0, then_value), (else_arm.0, else_value)];
+        self.join(join, &[then_arm, else_arm], &before);
+        match ty {
+            Ty::Unit | Ty::Never => self.unit(),
+            ty => self.phi(ty, incoming),
+        }
+    }
+
+    /* Moves to `target`, where the blocks of `arms` meet, giving each
+     * variable defined before they split a phi if they disagree on it */
+    fn join(
+        &mut self,
+        target: BlockId,
+        arms: &[(BlockId, BTreeMap<SymbolId, Value>)],
+        before: &BTreeMap<SymbolId, Value>,
+    ) {
+        self.switch(target);
+        self.vars = BTreeMap::new();
+        for (&symbol, &value) in before {
+            let incoming: Vec<_> = arms
+                .iter()
+                .map(|(block, vars)| (*block, vars[&symbol]))
+                .collect();
+            let value = match incoming.iter().all(|(_, found)| *found == incoming[0].1) {
+                true => incoming[0].1,
+                false => {
+                    let ty = self.var_type(symbol, value);
+                    self.phi(ty, incoming)
+                }
+            };
+            self.vars.insert(symbol, value);
+        }
+    }
+
+    fn symbol(&self, ident: &Ident) -> SymbolId {
+        let symbols = self.checker.symbols();
+        symbols.resolution(ident.span).expect("names are resolved")
+    }
+
+    /* The declared type of a variable, or for loop variables the type of
+     * the value it has */
+    fn var_type(&self, symbol: SymbolId, value: Value) -> Ty {
+        match self.checker.symbols().symbol(symbol).ty() {
+            Some(ty) => Ty::from(ty),
+            None => self.types[value.0].clone(),
+        }
+    }
+
+    fn ty(&self, expr: &Expr) -> Ty {
+        self.checker
+            .type_of(expr)
+            .cloned()
+            .expect("expressions are checked")
+    }
+
+    fn value(&mut self, ty: Ty) -> Value {
+        self.types.push(ty);
+        Value(self.types.len() - 1)
+    }
+
+    fn emit(&mut self, inst: Inst, ty: Ty) -> Value {
+        let value = self.value(ty);
+        self.blocks[self.current.0].insts.push((value, inst));
+        value
+    }
+
+    fn constant(&mut self, value: Const) -> Value {
+        let ty = match value {
+            Const::Int(_) => Ty::Int,
+            Const::Bool(_) => Ty::Bool,
+            Const::Str(_) => Ty::Str,
+            Const::Unit => Ty::Unit,
+        };
+        self.emit(Inst::Const(value), ty)
+    }
+
+    fn unit(&mut self) -> Value {
+        self.constant(Const::Unit)
+    }
+
+    fn phi(&mut self, ty: Ty, incoming: Vec<(BlockId, Value)>) -> Value {
+        let value = self.value(ty);
+        self.blocks[self.current.0]
+            .phis
+            .push(Phi { value, incoming });
+        value
+    }
+
+    fn incoming(&mut self, block: BlockId, phi: Value, from: BlockId, value: Value) {
+        let phis = &mut self.blocks[block.0].phis;
+        let phi = phis.iter_mut().find(|found| found.value == phi);
+        phi.expect("the phi is in the block")
+            .incoming
+            .push((from, value));
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(Draft {
+            phis: vec![],
+            insts: vec![],
+            term: None,
+        });
+        BlockId(self.blocks.len() - 1)
+    }
+
+    fn switch(&mut self, block: BlockId) {
+        self.current = block;
+    }
+
+    fn terminate(&mut self, term: Terminator) {
+        self.blocks[self.current.0].term = Some(term);
+    }
+
+    /* Keeps the blocks control can reach, in reverse postorder, drops phis
+     * that only ever pick one value and constants nothing uses, then
+     * numbers the blocks and values in the order they are printed */
+    fn finish(&mut self, name: &str, params: Vec<Value>, ret: Ty) -> Function {
+        let drafts = std::mem::take(&mut self.blocks);
+        let order = reverse_postorder(&drafts);
+        let mut numbers = vec![None; drafts.len()];
+        for (i, &block) in order.iter().enumerate() {
+            numbers[block] = Some(BlockId(i));
+        }
+        let rename = |block: BlockId| numbers[block.0].expect("successors are reachable");
+        let mut drafts: Vec<Option<Draft>> = drafts.into_iter().map(Some).collect();
+        let mut blocks: Vec<BasicBlock> = vec![];
+        for &block in &order {
+            let draft = drafts[block].take().expect("blocks are visited once");
+            let phis = draft.phis.into_iter().map(|phi| Phi {
+                value: phi.value,
+                incoming: phi
+                    .incoming
+                    .into_iter()
+                    .filter_map(|(from, value)| Some((numbers[from.0]?, value)))
+                    .collect(),
+            });
+            let term = match draft.term.expect("every block is terminated") {
+                Terminator::Jump(target) => Terminator::Jump(rename(target)),
+                Terminator::Branch(cond, then, otherwise) => {
+                    Terminator::Branch(cond, rename(then), rename(otherwise))
+                }
+                Terminator::Return(value) => Terminator::Return(value),
+            };
+            blocks.push(BasicBlock {
+                phis: phis.collect(),
+                insts: draft.insts,
+                term,
+            });
+        }
+
+        let replaced = remove_trivial_phis(&mut blocks);
+        substitute(&mut blocks, |value| resolve(&replaced, value));
+        remove_unused_constants(&mut blocks);
+
+        let mut numbers = HashMap::new();
+        let mut types = vec![];
+        let mut number = |value: Value| {
+            numbers.insert(value, Value(types.len()));
+            types.push(self.types[value.0].clone());
+            Value(types.len() - 1)
+        };
+        let params = params.into_iter().map(&mut number).collect();
+        for block in &mut blocks {
+            for phi in &mut block.phis {
+                phi.value = number(phi.value);
+            }
+            for (value, _) in &mut block.insts {
+                *value = number(*value);
+            }
+        }
+        substitute(&mut blocks, |value| numbers[&value]);
+        Function {
+            name: name.to_string(),
+            params,
+            ret,
+            types,
+            blocks,
+        }
+    }
+}
+
+/* The blocks reachable from the entry, each before the blocks it goes
+ * to unless it is a loop going back. Successors are visited last to
+ * first so the first one comes first. */
+fn reverse_postorder(drafts: &[Draft]) -> Vec<usize> {
+    let (mut seen, mut order) = (vec![false; drafts.len()], vec![]);
+    let mut stack = vec![(0, false)];
+    while let Some((block, done)) = stack.pop() {
+        if done {
+            order.push(block);
+            continue;
+        }
+        if seen[block] {
+            continue;
+        }
+        seen[block] = true;
+        stack.push((block, true));
+        let term = drafts[block].term.as_ref();
+        for successor in term.map_or(vec![], Terminator::successors) {
+            if !seen[successor.0] {
+                stack.push((successor.0, false));
+            }
+        }
+    }
+    order.reverse();
+    order
+}
+
+/* A phi is trivial when every value it picks is the same one, or itself.
+ * Removing one can make others trivial, so this goes on until none are
+ * left. Returns what each removed phi stands for. */
+fn remove_trivial_phis(blocks: &mut [BasicBlock]) -> HashMap<Value, Value> {
+    let mut replaced = HashMap::new();
+    loop {
+        let mut changed = false;
+        for block in blocks.iter_mut() {
+            block.phis.retain(|phi| {
+                let mut picked = phi
+                    .incoming
+                    .iter()
+                    .map(|(_, value)| resolve(&replaced, *value))
+                    .filter(|value| *value != phi.value);
+                let Some(first) = picked.next() else {
+                    return true;
+                };
+                if picked.all(|value| value == first) {
+                    replaced.insert(phi.value, first);
+                    changed = true;
+                    return false;
+                }
+                true
+            });
+        }
+        if !changed {
+            return replaced;
+        }
+    }
+}
+
+fn resolve(replaced: &HashMap<Value, Value>, mut value: Value) -> Value {
+    while let Some(&next) = replaced.get(&value) {
+        value = next;
+    }
+    value
+}
+
+fn remove_unused_constants(blocks: &mut [BasicBlock]) {
+    let mut used = HashSet::new();
+    for block in blocks.iter() {
+        for phi in &block.phis {
+            used.extend(phi.incoming.iter().map(|(_, value)| *value));
+        }
+        for (_, inst) in &block.insts {
+            used.extend(inst.operands());
+        }
+        used.extend(block.term.operands());
+    }
+    for block in blocks {
+        block
+            .insts
+            .retain(|(value, inst)| !matches!(inst, Inst::Const(_)) || used.contains(value));
+    }
+}
+
+/* Replaces every use of a value with what `f` gives for it */
+fn substitute(blocks: &mut [BasicBlock], mut f: impl FnMut(Value) -> Value) {
+    for block in blocks {
+        for phi in &mut block.phis {
+            for (_, value) in &mut phi.incoming {
+                *value = f(*value);
+            }
+        }
+        for (_, inst) in &mut block.insts {
+            inst.map_operands(&mut f);
+        }
+        block.term = match block.term.clone() {
+            Terminator::Branch(cond, then, otherwise) => {
+                Terminator::Branch(f(cond), then, otherwise)
+            }
+            Terminator::Return(value) => Terminator::Return(f(value)),
+            term => term,
+        };
+    }
+}
+
+#[test]
+fn lowers_to_ssa() {
+    use super::print::print;
+    use crate::testing::pipeline::check;
+
+    let input = "def sum(xs: [int]) -> int {
+            let total: int = 0;
+            for x in xs { if x > 0 && x < 10 { total = total + x; } }
+            total
+        }
+        let n: int = 3;
+        while n > 0 || false { n = n - 1; }
+        print(sum([1, n, 20]));";
+    let (program, checker) = check(input);
+    assert_eq!(
+        print(&build(&program, &checker)),
+        "\
+fn <script>() -> () {
+bb0:
+    %0: int = const 3
+    jump bb1
+bb1:
+    %1: int = phi [bb0: %0], [bb4: %7]
+    %2: int = const 0
+    %3: bool = gt %1, %2
+    branch %3, bb3, bb2
+bb2:
+    %4: bool = const false
+    jump bb3
+bb3:
+    %5: bool = phi [bb1: %3], [bb2: %4]
+    branch %5, bb4, bb5
+bb4:
+    %6: int = const 1
+    %7: int = sub %1, %6
+    jump bb1
+bb5:
+    %8: int = const 1
+    %9: int = const 20
+    %10: [int] = list [%8, %1, %9]
+    %11: int = call sum(%10)
+    %12: () = call print(%11)
+    return %12
+}
+
+fn sum(%0: [int]) -> int {
+bb0:
+    %1: int = const 0
+    %2: int = call len(%0)
+    %3: int = const 0
+    jump bb1
+bb1:
+    %4: int = phi [bb0: %1], [bb6: %14]
+    %5: int = phi [bb0: %3], [bb6: %16]
+    %6: bool = lt %5, %2
+    branch %6, bb2, bb7
+bb2:
+    %7: int = index %0, %5
+    %8: int = const 0
+    %9: bool = gt %7, %8
+    branch %9, bb3, bb4
+bb3:
+    %10: int = const 10
+    %11: bool = lt %7, %10
+    jump bb4
+bb4:
+    %12: bool = phi [bb2: %9], [bb3: %11]
+    branch %12, bb5, bb6
+bb5:
+    %13: int = add %4, %7
+    jump bb6
+bb6:
+    %14: int = phi [bb5: %13], [bb4: %4]
+    %15: int = const 1
+    %16: int = add %5, %15
+    jump bb1
+bb7:
+    return %4
+}
+"
+    );
+}
+
+#[test]
+fn builds_modules_that_verify() {
+    use super::print::print;
+    use super::verify::verify;
+    use crate::testing::pipeline::check;
+
+    let programs = [
+        "def fib(n: int) -> int { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } } fib(10);",
+        "def find(n: int) -> int { let i: int = 0; while true { if i * i >= n { return i; } i = i + 1; } 0 }",
+        "let s: str = \"\"; for c in \"abc\" { s = c + s; } let t: str = \"{s}!{1 + 2}\"; t[0..2];",
+        "let xs: [int] = [0, 0]; for i in 0..2 { xs[i] = i; } xs = []; xs[1] = xs[0]; let a: bool = !(xs[1] == 0);",
+        "let n: int = 0; while n < 5 { let m: int = n; while m > 0 { m = m - 2; } n = n + m + 2; } n;",
+        "def f(x: int) -> int { return x; x + 1 } let b: bool = 1 < 2 && (2 > 3 || -f(1) == ~0); b;",
+    ];
+    for input in programs {
+        let (program, checker) = check(input);
+        assert!(!checker.has_errors(), "{}", input);
+        let module = build(&program, &checker);
+        assert_eq!(verify(&module), Ok(()), "{}", print(&module));
+    }
+}
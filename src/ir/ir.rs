@@ -0,0 +1,158 @@
+use crate::ast::ast::{BinOp, UnOp};
+use crate::sema::types::Ty;
+
+/* A value in SSA form, defined exactly once by a parameter, a phi or an
+ * instruction. Values are numbered within their function. */
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct Value(pub usize);
+
+/* A basic block, numbered within its function. The entry is block 0. */
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Const {
+    Int(i64),
+    Bool(bool),
+    Str(String),
+    Unit,
+}
+
+/* The instructions, each defining a value. Instructions that only have an
+ * effect define a `()`. There are no `&&` and `||`, they are branches. */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Inst {
+    Const(Const),
+    Unary(UnOp, Value),
+    Binary(BinOp, Value, Value),
+    /* Calls a function of the module or a builtin by name */
+    Call(String, Vec<Value>),
+    List(Vec<Value>),
+    /* list index -> element, the character for strings */
+    Index(Value, Value),
+    /* list start end -> slice */
+    Slice(Value, Value, Value),
+    /* list index value -> () */
+    SetIndex(Value, Value, Value),
+    /* Joins the values, as printed, into a string */
+    Template(Vec<Value>),
+}
+
+impl Inst {
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            Inst::Const(_) => vec![],
+            Inst::Unary(_, value) => vec![*value],
+            Inst::Binary(_, lhs, rhs) | Inst::Index(lhs, rhs) => vec![*lhs, *rhs],
+            Inst::Slice(a, b, c) | Inst::SetIndex(a, b, c) => vec![*a, *b, *c],
+            Inst::Call(_, values) | Inst::List(values) | Inst::Template(values) => values.clone(),
+        }
+    }
+
+    /* Replaces every operand with what `f` gives for it */
+    pub fn map_operands(&mut self, mut f: impl FnMut(Value) -> Value) {
+        match self {
+            Inst::Const(_) => {}
+            Inst::Unary(_, value) => *value = f(*value),
+            Inst::Binary(_, lhs, rhs) | Inst::Index(lhs, rhs) => {
+                *lhs = f(*lhs);
+                *rhs = f(*rhs);
+            }
+            Inst::Slice(a, b, c) | Inst::SetIndex(a, b, c) => {
+                *a = f(*a);
+                *b = f(*b);
+                *c = f(*c);
+            }
+            Inst::Call(_, values) | Inst::List(values) | Inst::Template(values) => {
+                for value in values {
+                    *value = f(*value);
+                }
+            }
+        }
+    }
+}
+
+/* Picks the value that came from the block control came from */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Phi {
+    pub value: Value,
+    pub incoming: Vec<(BlockId, Value)>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /* Goes to the first block if the condition is true */
+    Branch(Value, BlockId, BlockId),
+    Return(Value),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch(_, then, otherwise) => vec![*then, *otherwise],
+            Terminator::Return(_) => vec![],
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            Terminator::Jump(_) => vec![],
+            Terminator::Branch(cond, ..) | Terminator::Return(cond) => vec![*cond],
+        }
+    }
+}
+
+/* Phis come first, then the instructions in order, then the terminator */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BasicBlock {
+    pub phis: Vec<Phi>,
+    pub insts: Vec<(Value, Inst)>,
+    pub term: Terminator,
+}
+
+/* `types` has the type of every value, indexed by its number */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Value>,
+    pub ret: Ty,
+    pub types: Vec<Ty>,
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl Function {
+    pub fn ty(&self, value: Value) -> &Ty {
+        &self.types[value.0]
+    }
+
+    /* The blocks that go to each block, in order and without repeats */
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![vec![]; self.blocks.len()];
+        for (i, block) in self.blocks.iter().enumerate() {
+            for successor in block.term.successors() {
+                let list: &mut Vec<BlockId> = &mut predecessors[successor.0];
+                if !list.contains(&BlockId(i)) {
+                    list.push(BlockId(i));
+                }
+            }
+        }
+        predecessors
+    }
+}
+
+/* The script comes first, named `<script>`, returning the value of its
+ * last statement, then the functions in the order they are defined */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Module {
+    pub functions: Vec<Function>,
+}
+
+impl Module {
+    pub const SCRIPT: &'static str = "<script>";
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+}
@@ -0,0 +1,5 @@
+pub mod build;
+#[allow(clippy::module_inception, reason = "the IR types, between lowering, printing and verifying")]
+pub mod ir;
+pub mod print;
+pub mod verify;
@@ -0,0 +1,119 @@
+use super::ir::{Const, Function, Inst, Module, Terminator, Value};
+use crate::ast::ast::{BinOp, UnOp};
+use std::fmt::Write;
+
+/* Lists every function, the script first. Values are `%n` and blocks
+ * `bbn`, each definition is followed by the type of the value. */
+pub fn print(module: &Module) -> String {
+    let mut out = String::new();
+    for (i, function) in module.functions.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        out += &print_function(function);
+    }
+    out
+}
+
+pub fn print_function(function: &Function) -> String {
+    let params: Vec<_> = function
+        .params
+        .iter()
+        .map(|&param| format!("{}: {}", value(param), function.ty(param)))
+        .collect();
+    let mut out = format!(
+        "fn {}({}) -> {} {{\n",
+        function.name,
+        params.join(", "),
+        function.ret
+    );
+    for (i, block) in function.blocks.iter().enumerate() {
+        let _ = writeln!(out, "bb{}:", i);
+        for phi in &block.phis {
+            let incoming: Vec<_> = phi
+                .incoming
+                .iter()
+                .map(|(from, picked)| format!("[bb{}: {}]", from.0, value(*picked)))
+                .collect();
+            let _ = writeln!(
+                out,
+                "    {}: {} = phi {}",
+                value(phi.value),
+                function.ty(phi.value),
+                incoming.join(", ")
+            );
+        }
+        for (defined, inst) in &block.insts {
+            let _ = writeln!(
+                out,
+                "    {}: {} = {}",
+                value(*defined),
+                function.ty(*defined),
+                instruction(inst)
+            );
+        }
+        let _ = match &block.term {
+            Terminator::Jump(target) => writeln!(out, "    jump bb{}", target.0),
+            Terminator::Branch(cond, then, otherwise) => writeln!(
+                out,
+                "    branch {}, bb{}, bb{}",
+                value(*cond),
+                then.0,
+                otherwise.0
+            ),
+            Terminator::Return(returned) => writeln!(out, "    return {}", value(*returned)),
+        };
+    }
+    out + "}\n"
+}
+
+fn instruction(inst: &Inst) -> String {
+    let name = match inst {
+        Inst::Const(Const::Int(n)) => return format!("const {}", n),
+        Inst::Const(Const::Bool(b)) => return format!("const {}", b),
+        Inst::Const(Const::Str(text)) => return format!("const {:?}", text),
+        Inst::Const(Const::Unit) => return "const ()".to_string(),
+        Inst::Call(callee, args) => return format!("call {}({})", callee, values(args)),
+        Inst::List(elements) => return format!("list [{}]", values(elements)),
+        Inst::Unary(op, _) => match op {
+            UnOp::Not => "not",
+            UnOp::BitNot => "bitnot",
+            UnOp::Neg => "neg",
+            UnOp::Plus => "plus",
+        },
+        Inst::Binary(op, ..) => match op {
+            BinOp::Or => "logical_or",
+            BinOp::And => "logical_and",
+            BinOp::Eq => "eq",
+            BinOp::Ne => "ne",
+            BinOp::Lt => "lt",
+            BinOp::Le => "le",
+            BinOp::Gt => "gt",
+            BinOp::Ge => "ge",
+            BinOp::BitOr => "or",
+            BinOp::BitXor => "xor",
+            BinOp::BitAnd => "and",
+            BinOp::Shl => "shl",
+            BinOp::Shr => "shr",
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            BinOp::Div => "div",
+            BinOp::Mod => "rem",
+        },
+        Inst::Index(..) => "index",
+        Inst::Slice(..) => "slice",
+        Inst::SetIndex(..) => "set_index",
+        Inst::Template(_) => "template",
+    };
+    format!("{} {}", name, values(&inst.operands()))
+}
+
+fn value(value: Value) -> String {
+    format!("%{}", value.0)
+}
+
+fn values(list: &[Value]) -> String {
+    let list: Vec<_> = list.iter().map(|&v| value(v)).collect();
+    list.join(", ")
+}
@@ -0,0 +1,496 @@
+use super::ir::{BlockId, Const, Function, Inst, Module, Terminator, Value};
+use crate::ast::ast::{BinOp, UnOp};
+use crate::sema::types::Ty;
+use std::collections::HashMap;
+
+/* Checks what every function of a module has to keep to:
+ * - each value is defined once, by a parameter, a phi or an instruction,
+ *   and only used where its definition dominates the use. A phi uses its
+ *   values at the end of the blocks they come from.
+ * - each block can be reached from the entry, which has no phis, and
+ *   phis have exactly one value for each block that goes to theirs.
+ * - operands have the types their instructions take, and values have the
+ *   types their instructions give.
+ * Returns a line for everything that does not. */
+pub fn verify(module: &Module) -> Result<(), Vec<String>> {
+    let mut errors = vec![];
+    for function in &module.functions {
+        let mut verifier = Verifier {
+            module,
+            function,
+            defined: HashMap::new(),
+            idom: vec![],
+            order: vec![],
+            errors: vec![],
+        };
+        verifier.function();
+        errors.extend(
+            verifier
+                .errors
+                .into_iter()
+                .map(|error| format!("{}: {}", function.name, error)),
+        );
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+struct Verifier<'a> {
+    module: &'a Module,
+    function: &'a Function,
+    /* The block defining each value and where in it, parameters and phis
+     * at 0 and instruction `i` at `i + 1` */
+    defined: HashMap<Value, (usize, usize)>,
+    /* The immediate dominator of each reachable block */
+    idom: Vec<Option<usize>>,
+    /* Where each block is in reverse postorder, if it is reachable */
+    order: Vec<Option<usize>>,
+    errors: Vec<String>,
+}
+
+impl Verifier<'_> {
+    fn function(&mut self) {
+        let function = self.function;
+        let count = function.blocks.len();
+        if count == 0 {
+            return self.errors.push("there is no entry block".to_string());
+        }
+        for (i, block) in function.blocks.iter().enumerate() {
+            for successor in block.term.successors() {
+                if successor.0 >= count {
+                    self.errors.push(format!(
+                        "bb{} goes to bb{}, which does not exist",
+                        i, successor.0
+                    ));
+                }
+            }
+        }
+        if !self.errors.is_empty() {
+            return;
+        }
+        self.dominators();
+        if !function.blocks[0].phis.is_empty() {
+            self.errors.push("the entry block has phis".to_string());
+        }
+        for (i, found) in self.order.iter().enumerate() {
+            if found.is_none() {
+                self.errors.push(format!("bb{} cannot be reached", i));
+            }
+        }
+
+        for &param in &function.params {
+            self.define(param, 0, 0);
+        }
+        for (i, block) in function.blocks.iter().enumerate() {
+            for phi in &block.phis {
+                self.define(phi.value, i, 0);
+            }
+            for (at, (value, _)) in block.insts.iter().enumerate() {
+                self.define(*value, i, at + 1);
+            }
+        }
+        if !self.errors.is_empty() {
+            return;
+        }
+
+        let predecessors = function.predecessors();
+        for (i, block) in function.blocks.iter().enumerate() {
+            for phi in &block.phis {
+                self.phi(i, phi, &predecessors[i]);
+            }
+            for (at, (value, inst)) in block.insts.iter().enumerate() {
+                for operand in inst.operands() {
+                    self.used(operand, i, at + 1);
+                }
+                match self.result(inst) {
+                    Ok(found) if function.ty(*value).accepts(&found) => {}
+                    Ok(found) => self.errors.push(format!(
+                        "{} in bb{} has type `{}` but its instruction gives `{}`",
+                        show(*value),
+                        i,
+                        function.ty(*value),
+                        found
+                    )),
+                    Err(problem) => {
+                        self.errors
+                            .push(format!("{} in bb{}: {}", show(*value), i, problem))
+                    }
+                }
+            }
+            for operand in block.term.operands() {
+                self.used(operand, i, block.insts.len() + 1);
+            }
+            match &block.term {
+                Terminator::Branch(cond, ..) if *function.ty(*cond) != Ty::Bool => {
+                    self.errors.push(format!(
+                        "bb{} branches on {} of type `{}`",
+                        i,
+                        show(*cond),
+                        function.ty(*cond)
+                    ))
+                }
+                Terminator::Return(value) if !function.ret.accepts(function.ty(*value)) => {
+                    self.errors.push(format!(
+                        "bb{} returns {} of type `{}` from a function returning `{}`",
+                        i,
+                        show(*value),
+                        function.ty(*value),
+                        function.ret
+                    ))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn define(&mut self, value: Value, block: usize, at: usize) {
+        if value.0 >= self.function.types.len() {
+            self.errors.push(format!("{} has no type", show(value)));
+        }
+        if self.defined.insert(value, (block, at)).is_some() {
+            self.errors
+                .push(format!("{} is defined more than once", show(value)));
+        }
+    }
+
+    /* Checks that the definition of `value` comes before `at` in `block`,
+     * or in a block dominating it */
+    fn used(&mut self, value: Value, block: usize, at: usize) {
+        let Some(&(defined, position)) = self.defined.get(&value) else {
+            return self.errors.push(format!(
+                "{} is used in bb{} but never defined",
+                show(value),
+                block
+            ));
+        };
+        let dominated = match defined == block {
+            true => position < at,
+            false => self.dominates(defined, block),
+        };
+        if !dominated {
+            self.errors.push(format!(
+                "{} is used in bb{} where its definition in bb{} does not dominate",
+                show(value),
+                block,
+                defined
+            ));
+        }
+    }
+
+    fn phi(&mut self, block: usize, phi: &super::ir::Phi, predecessors: &[BlockId]) {
+        let ty = self.function.ty(phi.value);
+        for (i, &(from, value)) in phi.incoming.iter().enumerate() {
+            if !predecessors.contains(&from) {
+                self.errors.push(format!(
+                    "the phi {} in bb{} has a value for bb{}, which does not go there",
+                    show(phi.value),
+                    block,
+                    from.0
+                ));
+                continue;
+            }
+            if phi.incoming[..i].iter().any(|(seen, _)| *seen == from) {
+                self.errors.push(format!(
+                    "the phi {} in bb{} has more than one value for bb{}",
+                    show(phi.value),
+                    block,
+                    from.0
+                ));
+            }
+            self.used(value, from.0, usize::MAX);
+            let found = self.function.ty(value);
+            if !ty.accepts(found) {
+                self.errors.push(format!(
+                    "the phi {} in bb{} has type `{}` but picks {} of type `{}`",
+                    show(phi.value),
+                    block,
+                    ty,
+                    show(value),
+                    found
+                ));
+            }
+        }
+        for from in predecessors {
+            if !phi.incoming.iter().any(|(seen, _)| seen == from) {
+                self.errors.push(format!(
+                    "the phi {} in bb{} has no value for bb{}",
+                    show(phi.value),
+                    block,
+                    from.0
+                ));
+            }
+        }
+    }
+
+    /* The type an instruction gives for the types of its operands, or why
+     * it cannot take them */
+    fn result(&self, inst: &Inst) -> Result<Ty, String> {
+        let ty = |value: &Value| self.function.ty(*value).clone();
+        let operands: Vec<Ty> = inst.operands().iter().map(ty).collect();
+        let wrong = || {
+            let types: Vec<_> = operands.iter().map(|ty| format!("`{}`", ty)).collect();
+            Err(format!("the operands cannot be {}", types.join(", ")))
+        };
+        match inst {
+            Inst::Const(value) => Ok(match value {
+                Const::Int(_) => Ty::Int,
+                Const::Bool(_) => Ty::Bool,
+                Const::Str(_) => Ty::Str,
+                Const::Unit => Ty::Unit,
+            }),
+            Inst::Unary(op, _) => {
+                let expected = match op {
+                    UnOp::Not => Ty::Bool,
+                    UnOp::BitNot | UnOp::Neg | UnOp::Plus => Ty::Int,
+                };
+                match operands[0] == expected {
+                    true => Ok(expected),
+                    false => wrong(),
+                }
+            }
+            Inst::Binary(op, ..) => {
+                let (left, right) = (&operands[0], &operands[1]);
+                let ints = *left == Ty::Int && *right == Ty::Int;
+                match op {
+                    BinOp::And | BinOp::Or => Err(format!("`{}` has to be a branch", op)),
+                    BinOp::Add if *left == Ty::Str && *right == Ty::Str => Ok(Ty::Str),
+                    BinOp::Eq | BinOp::Ne if left.join(right).is_some() && *left != Ty::Unit => {
+                        Ok(Ty::Bool)
+                    }
+                    BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge
+                        if ints || (*left == Ty::Str && *right == Ty::Str) =>
+                    {
+                        Ok(Ty::Bool)
+                    }
+                    BinOp::Add
+                    | BinOp::Sub
+                    | BinOp::Mul
+                    | BinOp::Div
+                    | BinOp::Mod
+                    | BinOp::BitOr
+                    | BinOp::BitXor
+                    | BinOp::BitAnd
+                    | BinOp::Shl
+                    | BinOp::Shr
+                        if ints =>
+                    {
+                        Ok(Ty::Int)
+                    }
+                    _ => wrong(),
+                }
+            }
+            Inst::Call(callee, _) => self.call(callee, &operands),
+            Inst::List(_) => {
+                let mut element = Ty::Never;
+                for found in &operands {
+                    match element.join(found) {
+                        Some(joined) => element = joined,
+                        None => return wrong(),
+                    }
+                }
+                Ok(Ty::List(Box::new(element)))
+            }
+            Inst::Index(..) => match (&operands[0], &operands[1]) {
+                (Ty::List(element), Ty::Int) => Ok((**element).clone()),
+                (Ty::Str, Ty::Int) => Ok(Ty::Str),
+                _ => wrong(),
+            },
+            Inst::Slice(..) => match (&operands[0], &operands[1], &operands[2]) {
+                (list @ (Ty::List(_) | Ty::Str), Ty::Int, Ty::Int) => Ok(list.clone()),
+                _ => wrong(),
+            },
+            /* Values keep the type of what they were made from, so a `[int]`
+             * variable can be a `[!]`. Those are empty, storing into them
+             * fails when the program runs. */
+            Inst::SetIndex(..) => match (&operands[0], &operands[1], &operands[2]) {
+                (Ty::List(element), Ty::Int, value)
+                    if element.accepts(value) || **element == Ty::Never =>
+                {
+                    Ok(Ty::Unit)
+                }
+                _ => wrong(),
+            },
+            Inst::Template(_) => match operands.contains(&Ty::Unit) {
+                true => wrong(),
+                false => Ok(Ty::Str),
+            },
+        }
+    }
+
+    fn call(&self, callee: &str, args: &[Ty]) -> Result<Ty, String> {
+        let callable = match self.module.function(callee) {
+            Some(function) if function.name != Module::SCRIPT => function,
+            _ => {
+                return match (callee, args) {
+                    ("print", [_]) => Ok(Ty::Unit),
+                    ("len", [Ty::Str | Ty::List(_)]) => Ok(Ty::Int),
+                    ("print" | "len", _) => {
+                        Err(format!("`{}` cannot take these arguments", callee))
+                    }
+                    _ => Err(format!("`{}` is not a function", callee)),
+                };
+            }
+        };
+        let params = callable.params.iter().map(|&param| callable.ty(param));
+        let fits =
+            params.len() == args.len() && params.zip(args).all(|(param, arg)| param.accepts(arg));
+        match fits {
+            true => Ok(callable.ret.clone()),
+            false => Err(format!("`{}` cannot take these arguments", callee)),
+        }
+    }
+
+    /* Cooper, Harvey and Kennedy's iterative algorithm, over the blocks in
+     * reverse postorder */
+    fn dominators(&mut self) {
+        let blocks = &self.function.blocks;
+        let (mut seen, mut postorder) = (vec![false; blocks.len()], vec![]);
+        let mut stack = vec![(0, false)];
+        while let Some((block, done)) = stack.pop() {
+            if done {
+                postorder.push(block);
+                continue;
+            }
+            if seen[block] {
+                continue;
+            }
+            seen[block] = true;
+            stack.push((block, true));
+            for successor in blocks[block].term.successors().into_iter().rev() {
+                if !seen[successor.0] {
+                    stack.push((successor.0, false));
+                }
+            }
+        }
+        let rpo: Vec<usize> = postorder.into_iter().rev().collect();
+        self.order = vec![None; blocks.len()];
+        for (i, &block) in rpo.iter().enumerate() {
+            self.order[block] = Some(i);
+        }
+
+        let predecessors = self.function.predecessors();
+        self.idom = vec![None; blocks.len()];
+        self.idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &rpo[1..] {
+                let mut found: Option<usize> = None;
+                for from in &predecessors[block] {
+                    if self.idom[from.0].is_none() {
+                        continue;
+                    }
+                    found = Some(match found {
+                        None => from.0,
+                        Some(other) => self.intersect(from.0, other),
+                    });
+                }
+                if found.is_some() && self.idom[block] != found {
+                    self.idom[block] = found;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    fn intersect(&self, mut a: usize, mut b: usize) -> usize {
+        let order = |block: usize| self.order[block].expect("reachable");
+        while a != b {
+            while order(a) > order(b) {
+                a = self.idom[a].expect("processed");
+            }
+            while order(b) > order(a) {
+                b = self.idom[b].expect("processed");
+            }
+        }
+        a
+    }
+
+    /* Whether every path from the entry to `block` goes through `by`.
+     * Unreachable blocks are already reported, so they count as
+     * dominated. */
+    fn dominates(&self, by: usize, mut block: usize) -> bool {
+        loop {
+            if block == by {
+                return true;
+            }
+            match self.idom[block] {
+                None => return true,
+                Some(0) if block == 0 => return false,
+                Some(idom) => block = idom,
+            }
+        }
+    }
+}
+
+fn show(value: Value) -> String {
+    format!("%{}", value.0)
+}
+
+#[cfg(test)]
+fn broken() -> Module {
+    use super::ir::{BasicBlock, Phi};
+
+    /* bb0 branches on an int to bb1 and bb2, which both go to bb3. %2 is
+     * defined in bb1 but used in bb3, and the phi has no value for bb2. */
+    let block = |phis, insts, term| BasicBlock { phis, insts, term };
+    let function = Function {
+        name: "f".to_string(),
+        params: vec![Value(0)],
+        ret: Ty::Int,
+        types: vec![Ty::Int, Ty::Int, Ty::Int, Ty::Bool, Ty::Int],
+        blocks: vec![
+            block(
+                vec![],
+                vec![],
+                Terminator::Branch(Value(0), BlockId(1), BlockId(2)),
+            ),
+            block(
+                vec![],
+                vec![(Value(2), Inst::Const(Const::Int(1)))],
+                Terminator::Jump(BlockId(3)),
+            ),
+            block(vec![], vec![], Terminator::Jump(BlockId(3))),
+            block(
+                vec![Phi {
+                    value: Value(1),
+                    incoming: vec![(BlockId(1), Value(2))],
+                }],
+                vec![
+                    (Value(3), Inst::Binary(BinOp::Lt, Value(1), Value(2))),
+                    (Value(4), Inst::Binary(BinOp::And, Value(3), Value(3))),
+                ],
+                Terminator::Return(Value(3)),
+            ),
+        ],
+    };
+    Module {
+        functions: vec![function],
+    }
+}
+
+#[test]
+fn finds_broken_invariants() {
+    assert_eq!(
+        verify(&broken()),
+        Err(vec![
+            "f: bb0 branches on %0 of type `int`".to_string(),
+            "f: the phi %1 in bb3 has no value for bb2".to_string(),
+            "f: %2 is used in bb3 where its definition in bb1 does not dominate".to_string(),
+            "f: %4 in bb3: `&&` has to be a branch".to_string(),
+            "f: bb3 returns %3 of type `bool` from a function returning `int`".to_string(),
+        ])
+    );
+}
+
+#[test]
+fn accepts_dominated_uses() {
+    let mut module = broken();
+    let function = &mut module.functions[0];
+    function.blocks.truncate(2);
+    function.blocks[0].term = Terminator::Jump(BlockId(1));
+    function.blocks[1].term = Terminator::Return(Value(2));
+    assert_eq!(verify(&module), Ok(()));
+}
@@ -1,22 +1,45 @@
+use crate::diagnostics::diagnostic::Diagnostic;
 use crate::lexer::tokens::{Token, TokenKind};
-use regex::Regex;
+use crate::source::span::{FileId, Span};
 
 pub struct Lexer {
     input: String,
+    file: FileId,
+    /* Where `input` starts in the file, for lexing a piece of a file */
+    offset: usize,
     position: usize,
     lookahead: usize,
+    /* One past the last byte any token was scanned from, or the length
+     * of the input plus one once EOF has been seen. Incremental parsing
+     * uses it to tell which results depend on an edited part. */
+    furthest: usize,
 }
 
 impl Lexer {
     pub fn new(s: String) -> Lexer {
-        //        let vec_char: Vec<char> = s.chars().collect();
+        Lexer::with_file(s, FileId::default())
+    }
+
+    pub fn with_file(s: String, file: FileId) -> Lexer {
+        Lexer::with_offset(s, file, 0)
+    }
+
+    /* Lexes `s` as if it started `offset` bytes into `file` */
+    pub fn with_offset(s: String, file: FileId, offset: usize) -> Lexer {
         Lexer {
             input: s,
+            file,
+            offset,
             position: 0,
             lookahead: 0,
+            furthest: 0,
         }
     }
 
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
     pub fn mark(&self) -> (usize, usize) {
         (self.position, self.lookahead)
     }
@@ -25,39 +48,49 @@ impl Lexer {
         (self.position, self.lookahead) = location;
     }
 
-    pub fn next(&mut self) -> Token {
-        let mut token_buffer = TokenKind::ERROR;
-        let mut substring_buffer = "";
+    pub fn furthest(&self) -> usize {
+        self.furthest
+    }
 
-        if self.lookahead >= self.input.len() {
-            return Token::new(TokenKind::EOF, None, self.mark());
-        }
+    pub fn set_furthest(&mut self, furthest: usize) {
+        self.furthest = furthest;
+    }
+
+    /* Replaces `range`, in the offsets spans use, with `text` and goes
+     * back to the start. Returns the range as offsets into the input. */
+    pub fn edit(&mut self, range: std::ops::Range<usize>, text: &str) -> std::ops::Range<usize> {
+        let range = range.start - self.offset..range.end - self.offset;
+        self.input.replace_range(range.clone(), text);
+        (self.position, self.lookahead, self.furthest) = (0, 0, 0);
+        range
+    }
 
+    /* Whitespace and comments are not returned; they are attached as
+     * trivia to the token that follows them */
+    #[allow(
+        clippy::should_implement_trait,
+        reason = "keeps returning EOF at the end instead of stopping"
+    )]
+    pub fn next(&mut self) -> Token {
+        let trivia = self.position;
         loop {
             if self.lookahead >= self.input.len() {
-                return Token::new(token_buffer, Some(substring_buffer), self.mark());
+                self.furthest = self.furthest.max(self.input.len() + 1);
+                let end = self.offset + self.input.len();
+                return Token::new(TokenKind::EOF, None, Span::point(self.file, end))
+                    .with_trivia(&self.input[trivia..]);
             }
 
-            let substring = &self.input[self.position..=self.lookahead];
-            let token = match_string(substring);
-
-            if token == TokenKind::ERROR {
-                if token_buffer == TokenKind::ERROR {
-                    let t = Token::new(token, Some(substring), self.mark());
-                    self.lookahead += 1;
-                    self.position = self.lookahead;
-                    return t
-                } else if token_buffer == TokenKind::WHITESPACE {
-                    self.position = self.lookahead;
-                } else {
-                    let t = Token::new(token_buffer, Some(substring_buffer), self.mark());
-                    self.position = self.lookahead;
-                    return t
-                }
-            } else {
-                self.lookahead += 1;
-                token_buffer = token;
-                substring_buffer = substring;
+            let (kind, end, examined) = self.scan();
+            self.furthest = self.furthest.max(examined);
+            self.lookahead = end;
+            let span = Span::new(self.file, self.offset + self.position, self.offset + end);
+            let token = Token::new(kind, Some(&self.input[self.position..end]), span)
+                .with_trivia(&self.input[trivia..self.position]);
+            self.position = end;
+
+            if !matches!(kind, TokenKind::WHITESPACE | TokenKind::COMMENT) {
+                return token;
             }
         }
     }
@@ -68,46 +101,292 @@ impl Lexer {
         self.reset(pos);
         token
     }
+
+    /* Every token that is left, up to and including EOF */
+    pub fn tokens(mut self) -> Vec<Token> {
+        let mut tokens = vec![];
+        loop {
+            let token = self.next();
+            let eof = token.kind == TokenKind::EOF;
+            tokens.push(token);
+            if eof {
+                return tokens;
+            }
+        }
+    }
+
+    /* Runs the DFA from the current position and returns the longest match,
+     * along with one past the last byte it looked at. If no prefix is
+     * accepted, a single character is returned as an ERROR. */
+    fn scan(&self) -> (TokenKind, usize, usize) {
+        let bytes = self.input.as_bytes();
+        let start = self.lookahead;
+        if bytes[start] == b'"' {
+            /* An unterminated string swallows the rest of the input */
+            return match string_end(bytes, start) {
+                Some(end) => (TokenKind::STRING, end, end),
+                None => (TokenKind::ERROR, bytes.len(), bytes.len() + 1),
+            };
+        }
+        if bytes[start..].starts_with(b"/*") {
+            /* Block comments nest, which the DFA cannot count */
+            return match block_comment_end(bytes, start) {
+                Some(end) => (TokenKind::COMMENT, end, end),
+                None => (TokenKind::ERROR, bytes.len(), bytes.len() + 1),
+            };
+        }
+
+        let mut state = State::Start;
+        let mut accepted: Option<(TokenKind, usize)> = None;
+        let mut cursor = start;
+
+        while cursor < bytes.len() {
+            state = match transition(state, bytes[cursor]) {
+                Some(next) => next,
+                None => break,
+            };
+            cursor += 1;
+            if let Some(kind) = accepting(state) {
+                accepted = Some((kind, cursor));
+            }
+        }
+
+        /* The DFA stopped on the byte at `cursor`, or at the end */
+        let examined = cursor + 1;
+        match accepted {
+            Some((TokenKind::ID, end)) => (keyword(&self.input[start..end]), end, examined),
+            Some((kind, end)) => (kind, end, examined),
+            None => {
+                let width = self.input[start..].chars().next().map_or(1, char::len_utf8);
+                (TokenKind::ERROR, start + width, examined.max(start + width))
+            }
+        }
+    }
+}
+
+/* Splits the trivia of a token into WHITESPACE and COMMENT tokens */
+pub fn split_trivia(token: &Token) -> Vec<Token> {
+    let start = token.span.start - token.trivia.len();
+    let mut lexer = Lexer::with_offset(token.trivia.clone(), token.span.file, start);
+    let mut pieces = vec![];
+    while lexer.lookahead < lexer.input.len() {
+        let (kind, end, _) = lexer.scan();
+        let span = Span::new(lexer.file, start + lexer.position, start + end);
+        pieces.push(Token::new(kind, Some(&lexer.input[lexer.position..end]), span));
+        (lexer.position, lexer.lookahead) = (end, end);
+    }
+    pieces
 }
 
-fn match_string(sub: &str) -> TokenKind {
-    return match sub {
-        r"=" => TokenKind::ASSIGN,
-
-        r"!" => TokenKind::BOOL_NOT,
-        r"&&" => TokenKind::BOOL_AND,
-        r"||" => TokenKind::BOOL_OR,
-
-        r"~" => TokenKind::BIT_NOT,
-        r"&" => TokenKind::BIT_AND,
-        r"|" => TokenKind::BIT_OR,
-        r"^" => TokenKind::BIT_XOR,
-        r"<<" => TokenKind::BIT_LEFT,
-        r">>" => TokenKind::BIT_RIGHT,
-
-        r"+" => TokenKind::PLUS,
-        r"-" => TokenKind::MINUS,
-        r"*" => TokenKind::MULTIPLY,
-        r"/" => TokenKind::DIVIDE,
-        r"%" => TokenKind::MODULUS,
-
-        r"==" => TokenKind::EQ,
-        r"!=" => TokenKind::NE,
-        r">" => TokenKind::GT,
-        r">=" => TokenKind::GE,
-        r"<" => TokenKind::LT,
-        r"<=" => TokenKind::LE,
-
-        r"(" => TokenKind::LPAREN,
-        r")" => TokenKind::RPAREN,
-        r"{" => TokenKind::LCURLY,
-        r"}" => TokenKind::RCURLY,
-        r"[" => TokenKind::LBRACE,
-        r"]" => TokenKind::RBRACE,
-        r":" => TokenKind::COLON,
-        r";" => TokenKind::SEMICOLON,
-        r"," => TokenKind::COMMA,
+/* Strings and block comments that are never closed run to the end of
+ * the input, unlike other ERROR tokens */
+pub fn is_unterminated(token: &Token) -> bool {
+    token.kind == TokenKind::ERROR
+        && token.lexeme.as_deref().is_some_and(|lexeme| lexeme.starts_with('"') || lexeme.starts_with("/*"))
+}
+
+/* Explains why the lexer produced an ERROR token */
+pub fn error_diagnostic(token: &Token) -> Diagnostic {
+    match token.lexeme.as_deref() {
+        Some(lexeme) if lexeme.starts_with('"') => {
+            let quote = Span::new(token.span.file, token.span.start, token.span.start + 1);
+            Diagnostic::error("unterminated string literal", quote)
+                .with_code("E0002")
+                .with_label(quote, "string literal starts here")
+                .with_note("no closing `\"` was found before the end of the file")
+        }
+        Some(lexeme) if lexeme.starts_with("/*") => {
+            let open = Span::new(token.span.file, token.span.start, token.span.start + 2);
+            Diagnostic::error("unterminated block comment", open)
+                .with_code("E0023")
+                .with_label(open, "comment starts here")
+                .with_note("block comments nest, so every `/*` needs its own `*/`")
+        }
+        lexeme => Diagnostic::error(
+            format!("unknown start of token: {}", lexeme.unwrap_or_default()),
+            token.span,
+        )
+        .with_code("E0001"),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    Start,
+    Whitespace,
+    Identifier,
+    Number,
+
+    Assign,   // =
+    Bang,     // !
+    Amp,      // &
+    Pipe,     // |
+    Less,     // <
+    Greater,  // >
+    Minus,    // -
+    Dot,      // .
+    Slash,    // /
+    Comment,  // # or // up to the end of the line
+    Operator(TokenKind), // tokens that cannot be extended any further
+}
+
+fn transition(state: State, byte: u8) -> Option<State> {
+    let next = match (state, byte) {
+        (State::Start, b) if b.is_ascii_whitespace() => State::Whitespace,
+        (State::Start, b) if b.is_ascii_alphabetic() || b == b'_' => State::Identifier,
+        (State::Start, b) if b.is_ascii_digit() => State::Number,
+
+        (State::Start, b'=') => State::Assign,
+        (State::Start, b'!') => State::Bang,
+        (State::Start, b'&') => State::Amp,
+        (State::Start, b'|') => State::Pipe,
+        (State::Start, b'<') => State::Less,
+        (State::Start, b'>') => State::Greater,
+        (State::Start, b'.') => State::Dot,
+
+        (State::Start, b'~') => State::Operator(TokenKind::BIT_NOT),
+        (State::Start, b'^') => State::Operator(TokenKind::BIT_XOR),
+        (State::Start, b'+') => State::Operator(TokenKind::PLUS),
+        (State::Start, b'-') => State::Minus,
+        (State::Start, b'*') => State::Operator(TokenKind::MULTIPLY),
+        (State::Start, b'/') => State::Slash,
+        (State::Start, b'#') => State::Comment,
+        (State::Start, b'%') => State::Operator(TokenKind::MODULUS),
+        (State::Start, b'(') => State::Operator(TokenKind::LPAREN),
+        (State::Start, b')') => State::Operator(TokenKind::RPAREN),
+        (State::Start, b'{') => State::Operator(TokenKind::LCURLY),
+        (State::Start, b'}') => State::Operator(TokenKind::RCURLY),
+        (State::Start, b'[') => State::Operator(TokenKind::LBRACE),
+        (State::Start, b']') => State::Operator(TokenKind::RBRACE),
+        (State::Start, b':') => State::Operator(TokenKind::COLON),
+        (State::Start, b';') => State::Operator(TokenKind::SEMICOLON),
+        (State::Start, b',') => State::Operator(TokenKind::COMMA),
 
+        (State::Whitespace, b) if b.is_ascii_whitespace() => State::Whitespace,
+        (State::Identifier, b) if b.is_ascii_alphabetic() || b == b'_' => State::Identifier,
+        (State::Number, b) if b.is_ascii_digit() => State::Number,
+        (State::Comment, b) if b != b'\n' => State::Comment,
+
+        (State::Assign, b'=') => State::Operator(TokenKind::EQ),
+        (State::Bang, b'=') => State::Operator(TokenKind::NE),
+        (State::Amp, b'&') => State::Operator(TokenKind::BOOL_AND),
+        (State::Pipe, b'|') => State::Operator(TokenKind::BOOL_OR),
+        (State::Less, b'<') => State::Operator(TokenKind::BIT_LEFT),
+        (State::Less, b'=') => State::Operator(TokenKind::LE),
+        (State::Greater, b'>') => State::Operator(TokenKind::BIT_RIGHT),
+        (State::Greater, b'=') => State::Operator(TokenKind::GE),
+        (State::Minus, b'>') => State::Operator(TokenKind::ARROW),
+        (State::Dot, b'.') => State::Operator(TokenKind::DOTDOT),
+        (State::Slash, b'/') => State::Comment,
+
+        _ => return None,
+    };
+    Some(next)
+}
+
+fn accepting(state: State) -> Option<TokenKind> {
+    match state {
+        State::Start | State::Dot => None,
+        State::Whitespace => Some(TokenKind::WHITESPACE),
+        State::Identifier => Some(TokenKind::ID),
+        State::Number => Some(TokenKind::NUMBER),
+
+        State::Assign => Some(TokenKind::ASSIGN),
+        State::Bang => Some(TokenKind::BOOL_NOT),
+        State::Amp => Some(TokenKind::BIT_AND),
+        State::Pipe => Some(TokenKind::BIT_OR),
+        State::Less => Some(TokenKind::LT),
+        State::Greater => Some(TokenKind::GT),
+        State::Minus => Some(TokenKind::MINUS),
+        State::Slash => Some(TokenKind::DIVIDE),
+        State::Comment => Some(TokenKind::COMMENT),
+        State::Operator(kind) => Some(kind),
+    }
+}
+
+/* String literals are scanned by hand rather than by the DFA, as an
+ * interpolation can hold any expression, strings and braces included.
+ * Returns the position after the closing quote of the literal opening at
+ * `start`, or None if it is never closed. */
+fn string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut cursor = start + 1;
+    while cursor < bytes.len() {
+        match bytes[cursor] {
+            b'"' => return Some(cursor + 1),
+            b'\\' => cursor = escape_end(bytes, cursor),
+            b'{' => cursor = interpolation_end(bytes, cursor)?,
+            _ => cursor += 1,
+        }
+    }
+    None
+}
+
+/* The position after the escape sequence starting with the '\\' at
+ * `start`. The braces of `\u{...}` do not start an interpolation. */
+pub fn escape_end(bytes: &[u8], start: usize) -> usize {
+    let mut cursor = start + 2;
+    if bytes.get(start + 1) == Some(&b'u') && bytes.get(cursor) == Some(&b'{') {
+        cursor += 1;
+        while bytes.get(cursor).is_some_and(u8::is_ascii_hexdigit) {
+            cursor += 1;
+        }
+        if bytes.get(cursor) == Some(&b'}') {
+            cursor += 1;
+        }
+    }
+    cursor
+}
+
+/* The position after the '}' closing the interpolation opening at `start` */
+pub fn interpolation_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut cursor = start;
+    while cursor < bytes.len() {
+        match bytes[cursor] {
+            b'"' => {
+                cursor = string_end(bytes, cursor)?;
+                continue;
+            }
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(cursor + 1);
+                }
+            }
+            _ => {}
+        }
+        cursor += 1;
+    }
+    None
+}
+
+/* The position after the end of the block comment opening at `start`,
+ * counting any comments nested inside it */
+fn block_comment_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut cursor = start;
+    while cursor + 1 < bytes.len() {
+        match (bytes[cursor], bytes[cursor + 1]) {
+            (b'/', b'*') => {
+                depth += 1;
+                cursor += 2;
+            }
+            (b'*', b'/') => {
+                depth -= 1;
+                cursor += 2;
+                if depth == 0 {
+                    return Some(cursor);
+                }
+            }
+            _ => cursor += 1,
+        }
+    }
+    None
+}
+
+fn keyword(sub: &str) -> TokenKind {
+    match sub {
         r"int" => TokenKind::INT,
         r"bool" => TokenKind::BOOL,
         r"str" => TokenKind::STR,
@@ -118,30 +397,73 @@ fn match_string(sub: &str) -> TokenKind {
         r"for" => TokenKind::FOR,
         r"def" => TokenKind::DEF,
         r"let" => TokenKind::LET,
+        r"return" => TokenKind::RETURN,
+        r"in" => TokenKind::IN,
 
         r"true" => TokenKind::TRUE,
         r"false" => TokenKind::FALSE,
 
-        //        r"return" => TokenKind::RETURN,
-        _ => regex_match(sub),
-    };
+        _ => TokenKind::ID,
+    }
 }
 
-fn regex_match(sub: &str) -> TokenKind {
-    let re_whitespace = Regex::new(r"^\s+$").unwrap();
-    let re_identifier = Regex::new(r"^[a-zA-Z_]+$").unwrap();
-    let re_number = Regex::new(r"^\d+$").unwrap();
-    let re_string = Regex::new(r#"^"[a-zA-Z\d]+"$"#).unwrap();
-
-    if re_whitespace.is_match(sub) {
-        TokenKind::WHITESPACE
-    } else if re_identifier.is_match(sub) {
-        TokenKind::ID
-    } else if re_number.is_match(sub) {
-        TokenKind::NUMBER
-    } else if re_string.is_match(sub) {
-        TokenKind::STRING
-    } else {
-        TokenKind::ERROR
+#[test]
+fn keeps_comments_as_trivia() {
+    let input = "# header\nlet a: int = 8 // eight\n  / /* a /* nested */ comment */ 2;\n/* end */";
+    let mut lexer = Lexer::new(input.to_string());
+    let mut tokens = vec![];
+    loop {
+        let token = lexer.next();
+        tokens.push(token.clone());
+        if token.kind == TokenKind::EOF {
+            break;
+        }
+    }
+    let text: String = tokens.iter().map(Token::text).collect();
+    assert_eq!(text, input);
+    assert_eq!(tokens[6].kind, TokenKind::DIVIDE);
+
+    let comments: Vec<String> = tokens
+        .iter()
+        .flat_map(split_trivia)
+        .filter(|piece| piece.kind == TokenKind::COMMENT)
+        .map(|piece| {
+            assert_eq!(&input[piece.span.start..piece.span.end], piece.lexeme.as_deref().unwrap());
+            piece.lexeme.unwrap()
+        })
+        .collect();
+    assert_eq!(
+        comments,
+        ["# header", "// eight", "/* a /* nested */ comment */", "/* end */"]
+    );
+
+    let mut lexer = Lexer::new("1 /* /* */".to_string());
+    lexer.next();
+    let error = lexer.next();
+    assert_eq!(error.kind, TokenKind::ERROR);
+    assert_eq!(error_diagnostic(&error).code, Some("E0023"));
+}
+
+#[test]
+fn same_tokens_as_regex_lexer() {
+    use crate::lexer::reference::RegexLexer;
+
+    let inputs = [
+        "let a: bool = !((~(1 + 1) ^ ((1 * 1 + 1 / 1 ) >> 3)) == ((8 & 4 / (16 | 16)) & 255)) && ~((8*8)>>8) > 256 * ((8 + 8)>>12) + 64;",
+        "while a <= 10 { a = a << 1; b = a >= b || c != d; }",
+        "let integer: int = 1; if x {} else {} def for_each",
+        "x = 1$2 ab1; [a, b]",
+    ];
+
+    for input in inputs {
+        let mut new = Lexer::new(input.to_string());
+        let mut old = RegexLexer::new(input.to_string());
+        loop {
+            let (n, o) = (new.next(), old.next());
+            assert_eq!((n.kind, n.lexeme), (o.kind, o.lexeme), "in {:?}", input);
+            if o.kind == TokenKind::EOF {
+                break;
+            }
+        }
     }
 }
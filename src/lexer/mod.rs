@@ -1,2 +1,5 @@
 pub mod lex;
+pub mod strings;
 pub mod tokens;
+#[cfg(test)]
+pub mod reference;
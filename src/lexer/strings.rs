@@ -0,0 +1,177 @@
+use super::lex::{escape_end, interpolation_end};
+use super::tokens::{Token, TokenKind};
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::source::span::Span;
+
+/* A piece of a string literal, either text or the source of an
+ * interpolated expression */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Segment {
+    /* A STRING_PART token running from the opening '"' or the '}' ending
+     * the previous interpolation up to the next '{' or the closing '"' */
+    Text(Token),
+    Code { source: String, offset: usize },
+}
+
+/* Splits a STRING token at its interpolations. Text and code alternate,
+ * starting and ending with text, so `"{a}{b}"` has an empty text between
+ * the two. A literal without interpolations is returned as is. */
+pub fn segments(token: &Token) -> Vec<Segment> {
+    let lexeme = token.lexeme.as_deref().unwrap_or_default();
+    let bytes = lexeme.as_bytes();
+    let mut segments = vec![];
+    let mut text = 0;
+    let mut cursor = 1;
+    while cursor < bytes.len() - 1 {
+        match bytes[cursor] {
+            b'\\' => cursor = escape_end(bytes, cursor),
+            b'{' => {
+                let end = interpolation_end(bytes, cursor)
+                    .expect("the lexer only produces terminated strings");
+                segments.push(Segment::Text(part(token, text, cursor + 1)));
+                segments.push(Segment::Code {
+                    source: lexeme[cursor + 1..end - 1].to_string(),
+                    offset: token.span.start + cursor + 1,
+                });
+                text = end - 1;
+                cursor = end;
+            }
+            _ => cursor += 1,
+        }
+    }
+    if segments.is_empty() {
+        return vec![Segment::Text(token.clone())];
+    }
+    segments.push(Segment::Text(part(token, text, bytes.len())));
+    segments
+}
+
+fn part(token: &Token, start: usize, end: usize) -> Token {
+    let lexeme = token.lexeme.as_deref().unwrap_or_default();
+    let span = Span::new(
+        token.span.file,
+        token.span.start + start,
+        token.span.start + end,
+    );
+    let part = Token::new(TokenKind::STRING_PART, Some(&lexeme[start..end]), span);
+    /* The first part stands in for the whole literal, so it keeps its trivia */
+    match start {
+        0 => part.with_trivia(&token.trivia),
+        _ => part,
+    }
+}
+
+/* The text of a STRING or STRING_PART token without its delimiters and
+ * with its escape sequences replaced. Unknown escapes are reported and
+ * left out. */
+pub fn unescape(token: &Token) -> (String, Vec<Diagnostic>) {
+    let lexeme = token.lexeme.as_deref().unwrap_or_default();
+    let body = &lexeme[1..lexeme.len() - 1];
+    let mut value = String::new();
+    let mut diagnostics = vec![];
+    let mut chars = body.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        let start = token.span.start + 1 + i;
+        let escaped = match chars.next() {
+            Some((_, 'n')) => Some('\n'),
+            Some((_, 't')) => Some('\t'),
+            Some((_, 'r')) => Some('\r'),
+            Some((_, '0')) => Some('\0'),
+            Some((_, c @ ('\\' | '"' | '{' | '}'))) => Some(c),
+            Some((_, 'u')) => {
+                let mut digits = String::new();
+                let mut closed = false;
+                if chars.next_if(|(_, c)| *c == '{').is_some() {
+                    while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_hexdigit()) {
+                        digits.push(c);
+                    }
+                    closed = chars.next_if(|(_, c)| *c == '}').is_some();
+                }
+                let code = u32::from_str_radix(&digits, 16).ok();
+                match code
+                    .filter(|_| closed && digits.len() <= 6)
+                    .and_then(char::from_u32)
+                {
+                    Some(c) => Some(c),
+                    None => {
+                        let end = chars.peek().map_or(body.len(), |(j, _)| *j);
+                        let span = Span::new(token.span.file, start, token.span.start + 1 + end);
+                        diagnostics.push(
+                            Diagnostic::error("invalid unicode escape", span)
+                                .with_code("E0021")
+                                .with_label(span, "not a valid `\\u{...}` escape")
+                                .with_help("write the code point in hex, such as `\\u{1F600}`"),
+                        );
+                        None
+                    }
+                }
+            }
+            other => {
+                let c = other.map_or(' ', |(_, c)| c);
+                let span = Span::new(token.span.file, start, start + 1 + c.len_utf8());
+                diagnostics.push(
+                    Diagnostic::error(format!("unknown character escape: `{}`", c), span)
+                        .with_code("E0021")
+                        .with_label(span, "unknown escape")
+                        .with_help(
+                            "the escapes are `\\n`, `\\t`, `\\r`, `\\0`, `\\\\`, `\\\"`, `\\{`, `\\}` and `\\u{...}`",
+                        ),
+                );
+                None
+            }
+        };
+        value.extend(escaped);
+    }
+    (value, diagnostics)
+}
+
+#[test]
+fn splits_and_unescapes_strings() {
+    use super::lex::Lexer;
+
+    let input = r#"let s: str = "a\t{x + "}"}b\u{e9}\{{ {1} }\q";"#;
+    let mut lexer = Lexer::new(input.to_string());
+    let token = loop {
+        let token = lexer.next();
+        if token.kind == TokenKind::STRING {
+            break token;
+        }
+    };
+    assert_eq!(lexer.next().kind, TokenKind::SEMICOLON);
+
+    let segments = segments(&token);
+    let text = |segment: &Segment| match segment {
+        Segment::Text(token) => token.lexeme.clone().unwrap(),
+        Segment::Code { source, offset } => {
+            assert_eq!(&input[*offset..*offset + source.len()], source);
+            source.clone()
+        }
+    };
+    let pieces: Vec<String> = segments.iter().map(text).collect();
+    assert_eq!(
+        pieces,
+        [
+            r#""a\t{"#,
+            r#"x + "}""#,
+            r#"}b\u{e9}\{{"#,
+            " {1} ",
+            r#"}\q""#
+        ]
+    );
+
+    let Segment::Text(middle) = &segments[2] else {
+        panic!()
+    };
+    assert_eq!(unescape(middle), ("bé{".to_string(), vec![]));
+    let Segment::Text(last) = &segments[4] else {
+        panic!()
+    };
+    let (value, diagnostics) = unescape(last);
+    assert_eq!(value, "");
+    let span = diagnostics[0].primary_span;
+    assert_eq!(&input[span.start..span.end], r"\q");
+}
@@ -1,7 +1,8 @@
+use crate::source::span::Span;
 use std::fmt;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
-#[allow(non_camel_case_types)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 pub enum TokenKind {
     ASSIGN, // =
 
@@ -43,6 +44,8 @@ pub enum TokenKind {
     COLON,     // :
     SEMICOLON, // ;
     COMMA,     // ,
+    ARROW,     // ->
+    DOTDOT,    // ..
 
     // KEYWORDS
     // TYPES
@@ -57,39 +60,124 @@ pub enum TokenKind {
     FOR,
     DEF,
     LET,
+    RETURN,
+    IN,
 
     // BOOL
     TRUE,
     FALSE,
-    //    RETURN,
 
     // STUFF
     WHITESPACE,
+    COMMENT,
     ID,
     NUMBER,
     STRING,
+    STRING_PART, // the text around interpolations, split out of a STRING by the parser
 
     // ERROR and EOF
     ERROR,
     EOF,
 }
 
+impl TokenKind {
+    /* How the token is referred to in error messages */
+    pub fn describe(&self) -> &'static str {
+        match self {
+            TokenKind::ASSIGN => "`=`",
+            TokenKind::BOOL_NOT => "`!`",
+            TokenKind::BOOL_AND => "`&&`",
+            TokenKind::BOOL_OR => "`||`",
+            TokenKind::BIT_NOT => "`~`",
+            TokenKind::BIT_AND => "`&`",
+            TokenKind::BIT_OR => "`|`",
+            TokenKind::BIT_XOR => "`^`",
+            TokenKind::BIT_LEFT => "`<<`",
+            TokenKind::BIT_RIGHT => "`>>`",
+            TokenKind::PLUS => "`+`",
+            TokenKind::MINUS => "`-`",
+            TokenKind::MULTIPLY => "`*`",
+            TokenKind::DIVIDE => "`/`",
+            TokenKind::MODULUS => "`%`",
+            TokenKind::EQ => "`==`",
+            TokenKind::NE => "`!=`",
+            TokenKind::GT => "`>`",
+            TokenKind::GE => "`>=`",
+            TokenKind::LT => "`<`",
+            TokenKind::LE => "`<=`",
+            TokenKind::LPAREN => "`(`",
+            TokenKind::RPAREN => "`)`",
+            TokenKind::LCURLY => "`{`",
+            TokenKind::RCURLY => "`}`",
+            TokenKind::LBRACE => "`[`",
+            TokenKind::RBRACE => "`]`",
+            TokenKind::COLON => "`:`",
+            TokenKind::SEMICOLON => "`;`",
+            TokenKind::COMMA => "`,`",
+            TokenKind::ARROW => "`->`",
+            TokenKind::DOTDOT => "`..`",
+            TokenKind::INT => "`int`",
+            TokenKind::BOOL => "`bool`",
+            TokenKind::STR => "`str`",
+            TokenKind::IF => "`if`",
+            TokenKind::ELSE => "`else`",
+            TokenKind::WHILE => "`while`",
+            TokenKind::FOR => "`for`",
+            TokenKind::DEF => "`def`",
+            TokenKind::LET => "`let`",
+            TokenKind::RETURN => "`return`",
+            TokenKind::IN => "`in`",
+            TokenKind::TRUE => "`true`",
+            TokenKind::FALSE => "`false`",
+            TokenKind::WHITESPACE => "whitespace",
+            TokenKind::COMMENT => "comment",
+            TokenKind::ID => "identifier",
+            TokenKind::NUMBER => "number",
+            TokenKind::STRING | TokenKind::STRING_PART => "string",
+            TokenKind::ERROR => "unknown token",
+            TokenKind::EOF => "end of file",
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct Token {
     pub kind: TokenKind,
     pub lexeme: Option<String>,
-    pub position: (usize, usize),
+    pub span: Span,
+    /* The whitespace and comments between the previous token and this one,
+     * exactly as written. EOF holds whatever ends the file. */
+    pub trivia: String,
 }
 
 impl Token {
-    pub fn new(kind: TokenKind, lexeme: Option<&str>, position: (usize, usize)) -> Token {
+    pub fn new(kind: TokenKind, lexeme: Option<&str>, span: Span) -> Token {
         Token {
             kind,
-            lexeme: match lexeme {
-                Some(str) => Some(str.into()),
-                None => None,
-            },
-            position,
+            lexeme: lexeme.map(String::from),
+            span,
+            trivia: String::new(),
+        }
+    }
+
+    pub fn with_trivia(mut self, trivia: &str) -> Token {
+        self.trivia = trivia.to_string();
+        self
+    }
+
+    /* The token as it appeared in the source, trivia included */
+    pub fn text(&self) -> String {
+        format!("{}{}", self.trivia, self.lexeme.as_deref().unwrap_or_default())
+    }
+
+    /* How the token is referred to in error messages */
+    pub fn describe(&self) -> String {
+        match (&self.kind, &self.lexeme) {
+            (TokenKind::ID | TokenKind::NUMBER | TokenKind::STRING, Some(lexeme)) => {
+                format!("{} `{}`", self.kind.describe(), lexeme)
+            }
+            (TokenKind::ERROR, Some(lexeme)) => format!("`{}`", lexeme),
+            _ => self.kind.describe().into(),
         }
     }
 }
@@ -0,0 +1,130 @@
+pub mod ast;
+pub mod bytecode;
+pub mod codegen;
+pub mod cst;
+pub mod diagnostics;
+pub mod driver;
+pub mod dump;
+pub mod format;
+pub mod interp;
+pub mod ir;
+pub mod lexer;
+pub mod lsp;
+pub mod parser;
+pub mod repl;
+pub mod sema;
+pub mod source;
+#[cfg(test)]
+pub mod testing;
+pub mod vm;
+
+#[test]
+#[ignore = "long running, use `cargo test --release -- --ignored --nocapture`"]
+fn benchmark() {
+    use lexer::{lex::Lexer, reference::RegexLexer, tokens::TokenKind};
+    use parser::parser::Parser;
+    use std::time::{Duration, Instant};
+    let mut n = 1;
+    println!("Regex Lexer vs DFA Lexer\nNumber of Lines,Regex Time,DFA Time");
+    loop {
+        let input = "let a: bool = !((~(1 + 1) ^ ((1 * 1 + 1 / 1 ) >> 3)) == ((8 & 4 / (16 | 16)) & 255)) && ~((8*8)>>8) > 256 * ((8 + 8)>>12) + 64;";
+        let input = input.to_string().repeat(n);
+
+        let mut lex = RegexLexer::new(input.clone());
+        let now = Instant::now();
+        while lex.next().kind != TokenKind::EOF {}
+        let regex_time = Instant::now()-now;
+
+        let mut lex = Lexer::new(input);
+        let now = Instant::now();
+        while lex.next().kind != TokenKind::EOF {}
+        let dfa_time = Instant::now()-now;
+
+        println!("{},{:?},{:?}", n, regex_time, dfa_time);
+        if regex_time > Duration::new(60, 0) {
+            break
+        }
+        n *= 2;
+    }
+
+    n = 1;
+    println!("Pure Packrat\nNumber of Lines,Time to Parse");
+    loop {
+        let input = "let a: bool = !((~(1 + 1) ^ ((1 * 1 + 1 / 1 ) >> 3)) == ((8 & 4 / (16 | 16)) & 255)) && ~((8*8)>>8) > 256 * ((8 + 8)>>12) + 64;";
+        let lex = Lexer::new(input.to_string().repeat(n));
+        let mut parser = Parser::new(lex);
+        let now = Instant::now();
+        let tree = parser.parse(false);
+        let t = Instant::now()-now;
+        println!("{},{:?}", n, t);
+        assert!(tree.is_some());
+        if t > Duration::new(600, 0) {
+            break
+        }
+        n *= 2;
+    }
+
+    n = 1;
+    println!("Pratt\nNumber of Lines,Time to Parse");
+    loop {
+        let input = "let a: bool = !((~(1 + 1) ^ ((1 * 1 + 1 / 1 ) >> 3)) == ((8 & 4 / (16 | 16)) & 255)) && ~((8*8)>>8) > 256 * ((8 + 8)>>12) + 64;";
+        let lex = Lexer::new(input.to_string().repeat(n));
+        let mut parser = Parser::new(lex);
+        let now = Instant::now();
+        let tree = parser.parse(true);
+        let t = Instant::now()-now;
+        println!("{},{:?}", n, t);
+        assert!(tree.is_some());
+        if t > Duration::new(600, 0) {
+            break
+        }
+        n *= 2;
+    }
+
+    n = 1;
+    println!("Incremental Packrat\nNumber of Lines,Time to Reparse an Edit");
+    loop {
+        let line = "let a: bool = !((~(1 + 1) ^ ((1 * 1 + 1 / 1 ) >> 3)) == ((8 & 4 / (16 | 16)) & 255)) && ~((8*8)>>8) > 256 * ((8 + 8)>>12) + 64;";
+        let lex = Lexer::new(line.to_string().repeat(n));
+        let mut parser = Parser::new(lex);
+        parser.parse(false);
+        /* The first `1` of the middle line becomes a `2` */
+        let middle = line.len() * (n / 2) + 19;
+        let now = Instant::now();
+        let tree = parser.edit(middle..middle + 1, "2");
+        let t = Instant::now()-now;
+        println!("{},{:?}", n, t);
+        assert!(tree.is_some());
+        if t > Duration::new(1, 0) || n >= 1 << 14 {
+            break
+        }
+        n *= 2;
+    }
+
+    n = 1;
+    println!("Tree Walker vs VM\nLoop Iterations,Tree Walker Time,VM Time");
+    loop {
+        let input = format!("let i: int = 0; let s: int = 0; while i < {} {{ s = s + i % 7; i = i + 1; }}", n);
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = ast::lower::lower(&parser.parse(false).unwrap()).unwrap();
+
+        let mut interpreter = interp::interp::Interpreter::new();
+        let now = Instant::now();
+        interpreter.run(&program).unwrap();
+        let tree_time = Instant::now()-now;
+
+        let module = bytecode::compile::compile(&program).unwrap();
+        let mut vm = vm::vm::Vm::new();
+        let now = Instant::now();
+        vm.run(&module).unwrap();
+        let vm_time = Instant::now()-now;
+
+        println!("{},{:?},{:?}", n, tree_time, vm_time);
+        if tree_time > Duration::new(10, 0) || n >= 1 << 24 {
+            break
+        }
+        n *= 2;
+    }
+}
+
+
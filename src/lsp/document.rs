@@ -0,0 +1,571 @@
+use crate::ast::ast::Program;
+use crate::ast::lower::lower;
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::format::format::format;
+use crate::lexer::lex::{self, Lexer};
+use crate::lexer::strings::{self, Segment};
+use crate::lexer::tokens::{Token, TokenKind};
+use crate::parser::node::{Node, NodeKind, NodeType};
+use crate::parser::parser::Parser;
+use crate::sema::check::Checker;
+use crate::sema::resolve::{Symbol, SymbolKind};
+use crate::source::map::SourceFile;
+use crate::source::span::Span;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/* The semantic token types and modifiers the server reports, in the order
+ * of the legend it hands the client. Tokens refer to them by index. */
+pub const TOKEN_TYPES: &[&str] = &[
+    "keyword",
+    "type",
+    "function",
+    "parameter",
+    "variable",
+    "number",
+    "string",
+    "comment",
+    "operator",
+];
+pub const TOKEN_MODIFIERS: &[&str] = &["declaration"];
+
+const KEYWORD: u32 = 0;
+const TYPE: u32 = 1;
+const FUNCTION: u32 = 2;
+const PARAMETER: u32 = 3;
+const VARIABLE: u32 = 4;
+const NUMBER: u32 = 5;
+const STRING: u32 = 6;
+const COMMENT: u32 = 7;
+const OPERATOR: u32 = 8;
+const DECLARATION: u32 = 1;
+
+/* An entry of the outline of a document, a function with the variables
+ * declared in it or a global variable */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Outline {
+    pub name: String,
+    /* The type of a variable or the signature of a function */
+    pub detail: String,
+    pub function: bool,
+    pub span: Span,
+    /* The name, within `span` */
+    pub selection: Span,
+    pub children: Vec<Outline>,
+}
+
+/* An open file and what is known about it. Edits reparse it incrementally
+ * with the same parser, then the whole of it is lowered and checked again.
+ * A document with syntax errors is checked as far as the parser recovered
+ * it, leaving out the parts that did not parse. */
+pub struct Document {
+    pub version: i64,
+    file: SourceFile,
+    pratt: bool,
+    parser: Parser,
+    tree: Option<Node>,
+    /* Every token, with interpolated strings split into their text and
+     * the tokens of the code in between */
+    tokens: Vec<Token>,
+    program: Option<Program>,
+    checker: Checker,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    pub fn new(name: &str, text: String, version: i64, pratt: bool) -> Document {
+        let mut parser = Parser::new(Lexer::new(text.clone()));
+        let tree = parser.parse(pratt);
+        let mut document = Document {
+            version,
+            file: SourceFile::new(name.to_string(), text),
+            pratt,
+            parser,
+            tree,
+            tokens: vec![],
+            program: None,
+            checker: Checker::new(),
+            diagnostics: vec![],
+        };
+        document.analyze();
+        document
+    }
+
+    pub fn file(&self) -> &SourceFile {
+        &self.file
+    }
+
+    pub fn text(&self) -> &str {
+        &self.file.source
+    }
+
+    /* Replaces `range` of the text, in byte offsets, or all of it */
+    pub fn edit(&mut self, range: Option<Range<usize>>, text: &str) {
+        let source = match range {
+            Some(range) => {
+                let mut source = self.file.source.clone();
+                source.replace_range(range.clone(), text);
+                self.tree = self.parser.edit(range, text);
+                source
+            }
+            None => {
+                self.parser = Parser::new(Lexer::new(text.to_string()));
+                self.tree = self.parser.parse(self.pratt);
+                text.to_string()
+            }
+        };
+        self.file = SourceFile::new(self.file.name.clone(), source);
+        self.analyze();
+    }
+
+    fn analyze(&mut self) {
+        self.tokens = vec![];
+        self.diagnostics = self.parser.diagnostics().to_vec();
+        self.program = None;
+        self.checker = Checker::new();
+        let Some(tree) = &self.tree else {
+            return;
+        };
+        leaves(tree, &mut self.tokens);
+        match lower(tree) {
+            Ok(program) => {
+                self.checker.check_program(&program);
+                self.diagnostics.extend(self.checker.take_diagnostics());
+                self.program = Some(program);
+            }
+            Err(diagnostics) => self.diagnostics.extend(diagnostics),
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /* The tokens worth highlighting, encoded the way the protocol wants
+     * them: five numbers each, the line and start column relative to the
+     * token before, the length, the type and the modifiers. Tokens that
+     * span lines are split into one per line. */
+    pub fn semantic_tokens(&self) -> Vec<u32> {
+        let mut highlights = vec![];
+        for token in &self.tokens {
+            for piece in lex::split_trivia(token) {
+                if piece.kind == TokenKind::COMMENT {
+                    highlights.push((piece.span, COMMENT, 0));
+                }
+            }
+            if let Some(highlight) = self.highlight(token) {
+                highlights.push(highlight);
+            }
+        }
+
+        let mut data = vec![];
+        let (mut line, mut column) = (0, 0);
+        for (span, kind, modifiers) in highlights {
+            for piece in self.lines(span) {
+                let start = self.file.location(piece.start);
+                let length = self.file.source[piece].encode_utf16().count();
+                if length == 0 {
+                    continue;
+                }
+                let delta = match start.line == line {
+                    true => start.column_utf16 - column,
+                    false => start.column_utf16,
+                };
+                data.extend([
+                    (start.line - line) as u32,
+                    delta as u32,
+                    length as u32,
+                    kind,
+                    modifiers,
+                ]);
+                (line, column) = (start.line, start.column_utf16);
+            }
+        }
+        data
+    }
+
+    fn highlight(&self, token: &Token) -> Option<(Span, u32, u32)> {
+        let kind = match token.kind {
+            TokenKind::INT | TokenKind::BOOL | TokenKind::STR => TYPE,
+            TokenKind::IF
+            | TokenKind::ELSE
+            | TokenKind::WHILE
+            | TokenKind::FOR
+            | TokenKind::DEF
+            | TokenKind::LET
+            | TokenKind::RETURN
+            | TokenKind::IN
+            | TokenKind::TRUE
+            | TokenKind::FALSE => KEYWORD,
+            TokenKind::NUMBER => NUMBER,
+            TokenKind::STRING | TokenKind::STRING_PART => STRING,
+            TokenKind::ASSIGN
+            | TokenKind::BOOL_NOT
+            | TokenKind::BOOL_AND
+            | TokenKind::BOOL_OR
+            | TokenKind::BIT_NOT
+            | TokenKind::BIT_AND
+            | TokenKind::BIT_OR
+            | TokenKind::BIT_XOR
+            | TokenKind::BIT_LEFT
+            | TokenKind::BIT_RIGHT
+            | TokenKind::PLUS
+            | TokenKind::MINUS
+            | TokenKind::MULTIPLY
+            | TokenKind::DIVIDE
+            | TokenKind::MODULUS
+            | TokenKind::EQ
+            | TokenKind::NE
+            | TokenKind::GT
+            | TokenKind::GE
+            | TokenKind::LT
+            | TokenKind::LE
+            | TokenKind::ARROW
+            | TokenKind::DOTDOT => OPERATOR,
+            TokenKind::ID => {
+                /* Names that do not resolve, as in a document that does not
+                 * parse, are taken for variables */
+                let symbols = self.checker.symbols();
+                let Some(id) = symbols.resolution(token.span) else {
+                    return Some((token.span, VARIABLE, 0));
+                };
+                let symbol = symbols.symbol(id);
+                let kind = match symbol.kind {
+                    SymbolKind::Function { .. } | SymbolKind::Builtin(_) => FUNCTION,
+                    SymbolKind::Parameter(_) => PARAMETER,
+                    SymbolKind::Variable(_) | SymbolKind::LoopVariable => VARIABLE,
+                };
+                let modifiers = match symbol.span == token.span {
+                    true => DECLARATION,
+                    false => 0,
+                };
+                return Some((token.span, kind, modifiers));
+            }
+            _ => return None,
+        };
+        Some((token.span, kind, 0))
+    }
+
+    /* `span` cut at its line breaks, which are left out */
+    fn lines(&self, span: Span) -> Vec<Range<usize>> {
+        let mut lines = vec![];
+        let mut start = span.start;
+        for (i, _) in self.file.source[span.start..span.end].match_indices('\n') {
+            let end = span.start + i;
+            let end = match self.file.source[..end].ends_with('\r') {
+                true => end - 1,
+                false => end,
+            };
+            lines.push(start..end);
+            start = span.start + i + 1;
+        }
+        lines.push(start..span.end);
+        lines
+    }
+
+    /* What is under `offset`: the declaration of a name, or the type of the
+     * smallest expression there, along with the span it is about */
+    pub fn hover(&self, offset: usize) -> Option<(Span, String)> {
+        if let Some((span, symbol)) = self.symbol_at(offset) {
+            return Some((span, self.describe(span, symbol)));
+        }
+        let (span, ty) = self
+            .checker
+            .types()
+            .filter(|(span, _)| span.contains(offset))
+            .min_by_key(|(span, _)| span.len())?;
+        Some((span, ty.to_string()))
+    }
+
+    fn describe(&self, span: Span, symbol: &Symbol) -> String {
+        match &symbol.kind {
+            SymbolKind::Variable(ty) => format!("let {}: {}", symbol.name, ty),
+            SymbolKind::Parameter(ty) => format!("{}: {}", symbol.name, ty),
+            SymbolKind::LoopVariable => {
+                /* Only its uses have a type, which is the same for all */
+                let symbols = self.checker.symbols();
+                let id = symbols.resolution(span).expect("`span` names the symbol");
+                let uses = symbols.references(id);
+                let ty = self
+                    .checker
+                    .types()
+                    .find_map(|(span, ty)| uses.contains(&span).then_some(ty));
+                match ty {
+                    Some(ty) => format!("{}: {}", symbol.name, ty),
+                    None => symbol.name.clone(),
+                }
+            }
+            SymbolKind::Function { params, ret } => {
+                let params: Vec<String> = params.iter().map(ToString::to_string).collect();
+                let ret = ret
+                    .as_ref()
+                    .map_or(String::new(), |ret| format!(" -> {}", ret));
+                format!("def {}({}){}", symbol.name, params.join(", "), ret)
+            }
+            SymbolKind::Builtin(builtin) => format!("builtin {}", builtin),
+        }
+    }
+
+    /* Where the name under `offset` is declared */
+    pub fn definition(&self, offset: usize) -> Option<Span> {
+        let (_, symbol) = self.symbol_at(offset)?;
+        Some(symbol.span).filter(|span| !span.is_empty())
+    }
+
+    /* The name under `offset`, or right before it, and what it refers to */
+    fn symbol_at(&self, offset: usize) -> Option<(Span, &Symbol)> {
+        let names = self
+            .tokens
+            .iter()
+            .filter(|token| token.kind == TokenKind::ID);
+        let name = names
+            .clone()
+            .find(|token| token.span.contains(offset))
+            .or_else(|| names.clone().find(|token| token.span.end == offset))?;
+        let symbols = self.checker.symbols();
+        let id = symbols.resolution(name.span)?;
+        Some((name.span, symbols.symbol(id)))
+    }
+
+    /* The functions and variables of the document in source order, with
+     * each function holding the variables declared in it */
+    pub fn outline(&self) -> Vec<Outline> {
+        let Some(program) = &self.program else {
+            return vec![];
+        };
+        let mut outline: Vec<Outline> = program
+            .functions
+            .iter()
+            .map(|function| {
+                let params: Vec<String> = function
+                    .params
+                    .iter()
+                    .map(|param| param.ty.to_string())
+                    .collect();
+                let ret = function
+                    .ret
+                    .as_ref()
+                    .map_or(String::new(), |ret| format!(" -> {}", ret));
+                Outline {
+                    name: function.name.name.clone(),
+                    detail: format!("({}){}", params.join(", "), ret),
+                    function: true,
+                    span: function.span,
+                    selection: function.name.span,
+                    children: vec![],
+                }
+            })
+            .collect();
+
+        let mut declarations = HashMap::new();
+        if let Some(tree) = &self.tree {
+            self::declarations(tree, &mut declarations);
+        }
+        for (_, symbol) in self.checker.symbols().symbols() {
+            let SymbolKind::Variable(ty) = &symbol.kind else {
+                continue;
+            };
+            let variable = Outline {
+                name: symbol.name.clone(),
+                detail: ty.to_string(),
+                function: false,
+                span: declarations
+                    .get(&symbol.span)
+                    .copied()
+                    .unwrap_or(symbol.span),
+                selection: symbol.span,
+                children: vec![],
+            };
+            match outline
+                .iter_mut()
+                .find(|function| function.function && function.span.contains(symbol.span.start))
+            {
+                Some(function) => function.children.push(variable),
+                None => outline.push(variable),
+            }
+        }
+        outline.sort_by_key(|entry| entry.span.start);
+        outline
+    }
+
+    /* The text formatted, unless it has syntax errors */
+    pub fn format(&self, width: usize) -> Option<String> {
+        let tree = self.tree.as_ref().filter(|_| !self.parser.has_errors())?;
+        Some(format(tree, width))
+    }
+}
+
+/* The tokens of the tree in source order, which are all of them. The
+ * Pratt parser keeps an operator in an atom with the operands as
+ * children, so it goes between them. */
+fn leaves(node: &Node, tokens: &mut Vec<Token>) {
+    match (node.kind(), node.children()) {
+        (NodeType::Atom(operator), [lhs, rhs]) => {
+            leaves(lhs, tokens);
+            flatten(operator.clone(), tokens);
+            leaves(rhs, tokens);
+        }
+        (NodeType::Atom(token), children) => {
+            flatten(token.clone(), tokens);
+            for child in children {
+                leaves(child, tokens);
+            }
+        }
+        (NodeType::Cons(_), children) => {
+            for child in children {
+                leaves(child, tokens);
+            }
+        }
+    }
+}
+
+/* The span of every `let` statement in the tree, by the span of its name */
+fn declarations(node: &Node, found: &mut HashMap<Span, Span>) {
+    if let (NodeType::Cons(NodeKind::Statement), [declaration, ..]) = (node.kind(), node.children())
+    {
+        if let (NodeType::Cons(NodeKind::Declaration), [_, name, ..]) =
+            (declaration.kind(), declaration.children())
+        {
+            found.insert(name.span(), node.span());
+        }
+    }
+    for child in node.children() {
+        declarations(child, found);
+    }
+}
+
+/* Pushes `token`, or the pieces of an interpolated string */
+fn flatten(token: Token, tokens: &mut Vec<Token>) {
+    if token.kind != TokenKind::STRING {
+        tokens.push(token);
+        return;
+    }
+    for segment in strings::segments(&token) {
+        match segment {
+            Segment::Text(part) => tokens.push(part),
+            Segment::Code { source, offset } => {
+                for token in Lexer::with_offset(source, token.span.file, offset).tokens() {
+                    flatten(token, tokens);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+const HALF: &str = "def half(n: int) -> int {\n    n / 2\n}\n/* twice\n */ let a: int = half(8);\n";
+
+#[cfg(test)]
+fn offset(document: &Document, needle: &str) -> usize {
+    document.text().find(needle).unwrap()
+}
+
+#[test]
+fn highlights_tokens_by_what_they_name() {
+    let document = Document::new("a.ch", HALF.to_string(), 1, false);
+    assert!(document.diagnostics().is_empty());
+
+    let tokens: Vec<[u32; 5]> = document
+        .semantic_tokens()
+        .chunks(5)
+        .map(|chunk| chunk.try_into().unwrap())
+        .collect();
+    #[rustfmt::skip]
+    assert_eq!(tokens, [
+        [0, 0, 3, KEYWORD, 0], [0, 4, 4, FUNCTION, DECLARATION], [0, 5, 1, PARAMETER, DECLARATION],
+        [0, 3, 3, TYPE, 0], [0, 5, 2, OPERATOR, 0], [0, 3, 3, TYPE, 0],
+        [1, 4, 1, PARAMETER, 0], [0, 2, 1, OPERATOR, 0], [0, 2, 1, NUMBER, 0],
+        [2, 0, 8, COMMENT, 0], [1, 0, 3, COMMENT, 0], [0, 4, 3, KEYWORD, 0],
+        [0, 4, 1, VARIABLE, DECLARATION], [0, 3, 3, TYPE, 0], [0, 4, 1, OPERATOR, 0],
+        [0, 2, 4, FUNCTION, 0], [0, 5, 1, NUMBER, 0],
+    ]);
+
+    /* The Pratt parser keeps operators apart from their operands */
+    let pratt = Document::new("a.ch", HALF.to_string(), 1, true);
+    assert_eq!(pratt.semantic_tokens(), document.semantic_tokens());
+}
+
+#[test]
+fn hovers_and_finds_definitions() {
+    let document = Document::new("a.ch", HALF.to_string(), 1, false);
+    let call = offset(&document, "half(8)");
+    assert_eq!(document.hover(call).unwrap().1, "def half(int) -> int");
+    assert_eq!(document.hover(call + 5).unwrap().1, "int");
+    assert_eq!(
+        document.definition(call),
+        Some(Span::new(Default::default(), 4, 8))
+    );
+}
+
+#[test]
+fn outlines_declarations() {
+    let document = Document::new("a.ch", HALF.to_string(), 1, false);
+    let outline = document.outline();
+    assert_eq!(
+        outline.iter().map(|entry| &entry.name).collect::<Vec<_>>(),
+        ["half", "a"]
+    );
+    /* A variable spans its whole declaration, with the name selected */
+    let declaration = offset(&document, "let a");
+    assert_eq!(
+        (outline[1].span.start, outline[1].span.end),
+        (declaration, document.text().len() - 1)
+    );
+    assert_eq!(
+        outline[1].selection,
+        Span::new(Default::default(), declaration + 4, declaration + 5)
+    );
+}
+
+#[test]
+fn checks_what_is_left_of_broken_documents() {
+    let text = "let a: int = 1;\nlet b: int = ;\nprint(a + 1);\n";
+    let document = Document::new("a.ch", text.to_string(), 1, false);
+    assert!(document
+        .diagnostics()
+        .iter()
+        .all(|diagnostic| diagnostic.code == Some("E0003")));
+    let use_of_a = offset(&document, "a + 1");
+    assert_eq!(document.hover(use_of_a).unwrap().1, "let a: int");
+    assert_eq!(document.hover(use_of_a + 2).unwrap().1, "int");
+    assert_eq!(
+        document.definition(use_of_a),
+        Some(Span::new(Default::default(), 4, 5))
+    );
+}
+
+#[test]
+fn reanalyzes_edits() {
+    let mut document = Document::new("a.ch", HALF.to_string(), 1, false);
+    let call = offset(&document, "half(8)");
+
+    /* Breaking the syntax leaves only syntax errors, fixing it brings the
+     * types back */
+    let end = offset(&document, "(8);");
+    document.edit(Some(end..end + 1), "");
+    assert!(document
+        .diagnostics()
+        .iter()
+        .all(|diagnostic| diagnostic.code == Some("E0003")));
+    assert_eq!(document.hover(call), None);
+    assert_eq!(document.format(100), None);
+    document.edit(Some(end..end + 1), "(\"8\"");
+    assert_eq!(document.diagnostics()[0].code, Some("E0006"));
+    document.edit(None, "let b: str = \"{1 + 2}\";");
+    assert!(document.diagnostics().is_empty());
+    assert_eq!(document.hover(16).unwrap().1, "int");
+    assert_eq!(document.format(100).unwrap(), "let b: str = \"{1 + 2}\";\n");
+
+    /* Tokens are taken from the reparsed tree, so they move with edits */
+    document.edit(Some(0..0), "# b\n");
+    let fresh = Document::new("a.ch", document.text().to_string(), 1, false);
+    assert_eq!(document.semantic_tokens(), fresh.semantic_tokens());
+}
+
+#[test]
+fn accepts_empty_buffers() {
+    let mut document = Document::new("a.ch", String::new(), 1, false);
+    assert!(document.diagnostics().is_empty());
+    document.edit(None, "# todo\n");
+    assert!(document.diagnostics().is_empty());
+}
@@ -0,0 +1,3 @@
+pub mod document;
+pub mod protocol;
+pub mod server;
@@ -0,0 +1,149 @@
+use crate::dump::json::Json;
+use crate::source::map::SourceFile;
+use crate::source::span::Span;
+use std::io::{self, BufRead, Write};
+
+/* JSON-RPC error codes, and the ones the language server protocol adds */
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const SERVER_NOT_INITIALIZED: i64 = -32002;
+
+/* Reads one message, a `Content-Length` header and a blank line before
+ * that many bytes of JSON. Returns None at the end of the input, and an
+ * InvalidData error for a message that is not JSON, after which the next
+ * message can still be read. */
+pub fn read(input: &mut dyn BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(invalid("a message without a Content-Length"));
+    };
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| invalid("a message that is not UTF-8"))?;
+    Json::parse(&body)
+        .map(Some)
+        .map_err(|error| invalid(&error))
+}
+
+pub fn write(out: &mut dyn Write, message: &Json) -> io::Result<()> {
+    let body = message.compact();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn response(id: Json, result: Json) -> Json {
+    Json::object([("jsonrpc", string("2.0")), ("id", id), ("result", result)])
+}
+
+pub fn error(id: Json, code: i64, message: &str) -> Json {
+    Json::object([
+        ("jsonrpc", string("2.0")),
+        ("id", id),
+        (
+            "error",
+            Json::object([("code", Json::number(code)), ("message", string(message))]),
+        ),
+    ])
+}
+
+pub fn notification(method: &str, params: Json) -> Json {
+    Json::object([
+        ("jsonrpc", string("2.0")),
+        ("method", string(method)),
+        ("params", params),
+    ])
+}
+
+pub fn string(text: &str) -> Json {
+    Json::String(text.to_string())
+}
+
+pub fn number(n: usize) -> Json {
+    Json::number(n as i64)
+}
+
+/* Positions count lines and UTF-16 code units from 0, the protocol's
+ * default encoding */
+pub fn position(file: &SourceFile, offset: usize) -> Json {
+    let location = file.location(offset);
+    Json::object([
+        ("line", number(location.line)),
+        ("character", number(location.column_utf16)),
+    ])
+}
+
+pub fn range(file: &SourceFile, span: Span) -> Json {
+    Json::object([
+        ("start", position(file, span.start)),
+        ("end", position(file, span.end)),
+    ])
+}
+
+/* The byte offset of a position, if it is one */
+pub fn offset(file: &SourceFile, position: &Json) -> Option<usize> {
+    let line = position.get("line")?.as_i64()?;
+    let character = position.get("character")?.as_i64()?;
+    if line < 0 || character < 0 {
+        return None;
+    }
+    Some(file.offset_utf16(line as usize, character as usize))
+}
+
+#[test]
+fn frames_messages() {
+    let message = notification("exit", Json::Null);
+    let mut framed = vec![];
+    write(&mut framed, &message).unwrap();
+    assert_eq!(
+        String::from_utf8(framed.clone()).unwrap(),
+        "Content-Length: 52\r\n\r\n{\"jsonrpc\": \"2.0\", \"method\": \"exit\", \"params\": null}"
+    );
+}
+
+#[test]
+fn skips_other_headers_and_bad_bodies() {
+    let message = notification("exit", Json::Null);
+    let mut framed = vec![];
+    write(&mut framed, &message).unwrap();
+
+    /* A bad body does not lose the next */
+    let mut input = b"Content-Type: x\r\nContent-Length: 3\r\n\r\n{]}".to_vec();
+    input.extend(&framed);
+    let mut input = io::Cursor::new(input);
+    assert_eq!(
+        read(&mut input).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert_eq!(read(&mut input).unwrap(), Some(message));
+    assert_eq!(read(&mut input).unwrap(), None);
+}
+
+#[test]
+fn converts_utf16_positions() {
+    let file = SourceFile::new("a.ch".to_string(), "let é𝄞 = 1;\nx".to_string());
+    let start = Json::object([("line", number(0)), ("character", number(7))]);
+    assert_eq!(offset(&file, &start), Some(10));
+    assert_eq!(position(&file, 10), start);
+}
@@ -0,0 +1,596 @@
+use super::document::{self, Document, Outline};
+use super::protocol::{self, number, string};
+use crate::diagnostics::diagnostic::{Diagnostic, Severity};
+use crate::dump::json::Json;
+use crate::format::format::DEFAULT_WIDTH;
+use crate::source::map::SourceFile;
+use crate::source::span::Span;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+/* A language server for the documents a client has open, keyed by URI.
+ * It only ever answers, so every message it sends is a response or a
+ * notification. */
+pub struct Server {
+    pratt: bool,
+    documents: HashMap<String, Document>,
+    initialized: bool,
+    shutdown: bool,
+    exited: bool,
+}
+
+impl Server {
+    pub fn new(pratt: bool) -> Server {
+        Server {
+            pratt,
+            documents: HashMap::new(),
+            initialized: false,
+            shutdown: false,
+            exited: false,
+        }
+    }
+
+    /* Whether the client asked the server to exit, after which it reads
+     * nothing more */
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /* Handles a message from the client and returns the ones to send back */
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let params = message.get("params").unwrap_or(&Json::Null);
+        match (
+            message.get("method").and_then(Json::as_str),
+            message.get("id"),
+        ) {
+            (Some(method), Some(id)) => vec![self.request(id.clone(), method, params)],
+            (Some(method), None) => self.notification(method, params),
+            /* Responses, though the server never sends requests */
+            (None, _) => vec![],
+        }
+    }
+
+    fn request(&mut self, id: Json, method: &str, params: &Json) -> Json {
+        if method == "initialize" {
+            self.initialized = true;
+            return protocol::response(id, capabilities());
+        }
+        if !self.initialized {
+            return protocol::error(id, protocol::SERVER_NOT_INITIALIZED, "initialize first");
+        }
+        if self.shutdown {
+            return protocol::error(id, protocol::INVALID_REQUEST, "the server is shut down");
+        }
+        let result = match method {
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/documentSymbol" => self.document(params).map(|(_, document)| {
+                Json::Array(
+                    document
+                        .outline()
+                        .iter()
+                        .map(|entry| outline(document.file(), entry))
+                        .collect(),
+                )
+            }),
+            "textDocument/semanticTokens/full" => self.document(params).map(|(_, document)| {
+                let data = document.semantic_tokens();
+                let data = data.into_iter().map(|n| Json::number(n.into())).collect();
+                Json::object([("data", Json::Array(data))])
+            }),
+            "textDocument/formatting" => self.formatting(params),
+            _ => {
+                let message = format!("unknown method `{}`", method);
+                return protocol::error(id, protocol::METHOD_NOT_FOUND, &message);
+            }
+        };
+        match result {
+            Ok(result) => protocol::response(id, result),
+            Err(message) => protocol::error(id, protocol::INVALID_PARAMS, &message),
+        }
+    }
+
+    /* Notifications cannot be answered, so bad ones are only logged */
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        if method == "exit" {
+            self.exited = true;
+            return vec![];
+        }
+        if !self.initialized {
+            return vec![];
+        }
+        let result = match method {
+            "textDocument/didOpen" => self.open(params),
+            "textDocument/didChange" => self.change(params),
+            "textDocument/didClose" => self.close(params),
+            _ => Ok(vec![]),
+        };
+        result.unwrap_or_else(|message| {
+            let params = Json::object([("type", number(1)), ("message", string(&message))]);
+            vec![protocol::notification("window/logMessage", params)]
+        })
+    }
+
+    fn open(&mut self, params: &Json) -> Result<Vec<Json>, String> {
+        let item = field(params, "textDocument")?;
+        let uri = text(item, "uri")?;
+        let version = field(item, "version")?.as_i64().unwrap_or_default();
+        let document = Document::new(uri, text(item, "text")?.to_string(), version, self.pratt);
+        let published = publish(uri, &document);
+        self.documents.insert(uri.to_string(), document);
+        Ok(vec![published])
+    }
+
+    /* Applies changes in order, each either replacing a range or the
+     * whole text */
+    fn change(&mut self, params: &Json) -> Result<Vec<Json>, String> {
+        let item = field(params, "textDocument")?;
+        let uri = text(item, "uri")?;
+        let document = self
+            .documents
+            .get_mut(uri)
+            .ok_or_else(|| format!("`{}` is not open", uri))?;
+        let changes = field(params, "contentChanges")?
+            .as_array()
+            .unwrap_or_default();
+        for change in changes {
+            let range = match change.get("range") {
+                Some(range) => {
+                    let start = position(document.file(), field(range, "start")?)?;
+                    let end = position(document.file(), field(range, "end")?)?;
+                    Some(start.min(end)..end.max(start))
+                }
+                None => None,
+            };
+            document.edit(range, text(change, "text")?);
+        }
+        if let Some(version) = item.get("version").and_then(Json::as_i64) {
+            document.version = version;
+        }
+        Ok(vec![publish(uri, document)])
+    }
+
+    /* Closing a document clears its diagnostics */
+    fn close(&mut self, params: &Json) -> Result<Vec<Json>, String> {
+        let uri = text(field(params, "textDocument")?, "uri")?;
+        self.documents.remove(uri);
+        let params = Json::object([("uri", string(uri)), ("diagnostics", Json::Array(vec![]))]);
+        Ok(vec![protocol::notification(
+            "textDocument/publishDiagnostics",
+            params,
+        )])
+    }
+
+    fn hover(&self, params: &Json) -> Result<Json, String> {
+        let (document, offset) = self.position(params)?;
+        let Some((span, shown)) = document.hover(offset) else {
+            return Ok(Json::Null);
+        };
+        let contents = Json::object([
+            ("kind", string("markdown")),
+            ("value", string(&format!("```cheetah\n{}\n```", shown))),
+        ]);
+        Ok(Json::object([
+            ("contents", contents),
+            ("range", protocol::range(document.file(), span)),
+        ]))
+    }
+
+    fn definition(&self, params: &Json) -> Result<Json, String> {
+        let (uri, document) = self.document(params)?;
+        let (_, offset) = self.position(params)?;
+        Ok(match document.definition(offset) {
+            Some(span) => location(uri, document.file(), span),
+            None => Json::Null,
+        })
+    }
+
+    /* A single edit replacing the whole text, none if it is formatted
+     * already, or null when it has syntax errors */
+    fn formatting(&self, params: &Json) -> Result<Json, String> {
+        let (_, document) = self.document(params)?;
+        let Some(formatted) = document.format(DEFAULT_WIDTH) else {
+            return Ok(Json::Null);
+        };
+        if formatted == document.text() {
+            return Ok(Json::Array(vec![]));
+        }
+        let whole = Span::new(Default::default(), 0, document.text().len());
+        Ok(Json::Array(vec![Json::object([
+            ("range", protocol::range(document.file(), whole)),
+            ("newText", string(&formatted)),
+        ])]))
+    }
+
+    /* The open document named by `params` */
+    fn document<'a>(&'a self, params: &'a Json) -> Result<(&'a str, &'a Document), String> {
+        let uri = text(field(params, "textDocument")?, "uri")?;
+        match self.documents.get(uri) {
+            Some(document) => Ok((uri, document)),
+            None => Err(format!("`{}` is not open", uri)),
+        }
+    }
+
+    /* The open document and byte offset `params` point at */
+    fn position<'a>(&'a self, params: &'a Json) -> Result<(&'a Document, usize), String> {
+        let (_, document) = self.document(params)?;
+        let offset = position(document.file(), field(params, "position")?)?;
+        Ok((document, offset))
+    }
+}
+
+fn capabilities() -> Json {
+    let names = |names: &[&str]| Json::Array(names.iter().map(|name| string(name)).collect());
+    let legend = Json::object([
+        ("tokenTypes", names(document::TOKEN_TYPES)),
+        ("tokenModifiers", names(document::TOKEN_MODIFIERS)),
+    ]);
+    Json::object([
+        (
+            "capabilities",
+            Json::object([
+                ("positionEncoding", string("utf-16")),
+                (
+                    "textDocumentSync",
+                    /* 2 is incremental, changes come as edits */
+                    Json::object([("openClose", Json::Bool(true)), ("change", number(2))]),
+                ),
+                ("hoverProvider", Json::Bool(true)),
+                ("definitionProvider", Json::Bool(true)),
+                ("documentSymbolProvider", Json::Bool(true)),
+                ("documentFormattingProvider", Json::Bool(true)),
+                (
+                    "semanticTokensProvider",
+                    Json::object([("legend", legend), ("full", Json::Bool(true))]),
+                ),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object([
+                ("name", string("cheetah-lsp")),
+                ("version", string(env!("CARGO_PKG_VERSION"))),
+            ]),
+        ),
+    ])
+}
+
+fn publish(uri: &str, document: &Document) -> Json {
+    let diagnostics = document
+        .diagnostics()
+        .iter()
+        .map(|found| diagnostic(uri, document.file(), found))
+        .collect();
+    let params = Json::object([
+        ("uri", string(uri)),
+        ("version", Json::number(document.version)),
+        ("diagnostics", Json::Array(diagnostics)),
+    ]);
+    protocol::notification("textDocument/publishDiagnostics", params)
+}
+
+/* Notes and help have nowhere else to go, so they follow the message the
+ * way the emitter prints them, and labels become related information */
+fn diagnostic(uri: &str, file: &SourceFile, diagnostic: &Diagnostic) -> Json {
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        message += &format!("\nnote: {}", note);
+    }
+    if let Some(help) = &diagnostic.help {
+        message += &format!("\nhelp: {}", help);
+    }
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+    };
+
+    let mut fields = vec![
+        ("range", protocol::range(file, diagnostic.primary_span)),
+        ("severity", Json::number(severity)),
+    ];
+    if let Some(code) = diagnostic.code {
+        fields.push(("code", string(code)));
+    }
+    fields.push(("source", string("cheetah")));
+    fields.push(("message", string(&message)));
+    if !diagnostic.labels.is_empty() {
+        let related = diagnostic
+            .labels
+            .iter()
+            .map(|label| {
+                Json::object([
+                    ("location", location(uri, file, label.span)),
+                    ("message", string(&label.message)),
+                ])
+            })
+            .collect();
+        fields.push(("relatedInformation", Json::Array(related)));
+    }
+    Json::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn location(uri: &str, file: &SourceFile, span: Span) -> Json {
+    Json::object([("uri", string(uri)), ("range", protocol::range(file, span))])
+}
+
+fn outline(file: &SourceFile, entry: &Outline) -> Json {
+    /* The protocol's numbers for the kinds of symbol */
+    let kind = match entry.function {
+        true => 12,
+        false => 13,
+    };
+    Json::object([
+        ("name", string(&entry.name)),
+        ("detail", string(&entry.detail)),
+        ("kind", Json::number(kind)),
+        ("range", protocol::range(file, entry.span)),
+        ("selectionRange", protocol::range(file, entry.selection)),
+        (
+            "children",
+            Json::Array(
+                entry
+                    .children
+                    .iter()
+                    .map(|child| outline(file, child))
+                    .collect(),
+            ),
+        ),
+    ])
+}
+
+fn field<'a>(params: &'a Json, name: &str) -> Result<&'a Json, String> {
+    params
+        .get(name)
+        .ok_or_else(|| format!("missing `{}`", name))
+}
+
+fn text<'a>(params: &'a Json, name: &str) -> Result<&'a str, String> {
+    field(params, name)?
+        .as_str()
+        .ok_or_else(|| format!("`{}` is not a string", name))
+}
+
+fn position(file: &SourceFile, position: &Json) -> Result<usize, String> {
+    protocol::offset(file, position)
+        .ok_or_else(|| "a position needs a line and a character".to_string())
+}
+
+/* Serves the client on the other end of `input` and `out` until it says
+ * to exit. The exit code is 0 if it asked the server to shut down first,
+ * as the protocol has it, and 1 otherwise, the input ending included. */
+pub fn run(server: &mut Server, input: &mut dyn BufRead, out: &mut dyn Write) -> io::Result<i32> {
+    while !server.exited() {
+        let replies = match protocol::read(input) {
+            Ok(Some(message)) => server.handle(&message),
+            Ok(None) => return Ok(1),
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                let message = error.to_string();
+                vec![protocol::error(Json::Null, protocol::PARSE_ERROR, &message)]
+            }
+            Err(error) => return Err(error),
+        };
+        for reply in replies {
+            protocol::write(out, &reply)?;
+        }
+    }
+    Ok(if server.shutdown { 0 } else { 1 })
+}
+
+#[cfg(test)]
+const URI: &str = "file:///a.ch";
+
+/* Params naming the test document, with `extra` fields */
+#[cfg(test)]
+fn document(extra: &str) -> String {
+    format!(r#"{{"textDocument": {{"uri": "{}"}}{}}}"#, URI, extra)
+}
+
+#[cfg(test)]
+fn at(line: usize, character: usize) -> String {
+    document(&format!(
+        r#", "position": {{"line": {}, "character": {}}}"#,
+        line, character
+    ))
+}
+
+/* Initializes the server and opens the test document with `text` */
+#[cfg(test)]
+fn opened(text: &str) -> Vec<(String, String)> {
+    vec![
+        (
+            r#""id": 1, "method": "initialize""#.to_string(),
+            "{}".to_string(),
+        ),
+        (r#""method": "initialized""#.to_string(), "{}".to_string()),
+        (
+            r#""method": "textDocument/didOpen""#.to_string(),
+            format!(
+                r#"{{"textDocument": {{"uri": "{}", "version": 1, "text": {}}}}}"#,
+                URI,
+                Json::String(text.to_string()).compact()
+            ),
+        ),
+    ]
+}
+
+/* Runs the server on messages made of header fields and params, giving
+ * back its exit code and everything it sent */
+#[cfg(test)]
+fn session(script: Vec<(String, String)>) -> (i32, Vec<Json>) {
+    let mut input = vec![];
+    for (head, params) in script {
+        let message = Json::parse(&format!(
+            r#"{{"jsonrpc": "2.0", {}, "params": {}}}"#,
+            head, params
+        ))
+        .unwrap();
+        protocol::write(&mut input, &message).unwrap();
+    }
+
+    let mut out = vec![];
+    let code = run(
+        &mut Server::new(false),
+        &mut io::Cursor::new(input),
+        &mut out,
+    )
+    .unwrap();
+    let mut out = io::Cursor::new(out);
+    let mut replies = vec![];
+    while let Some(reply) = protocol::read(&mut out).unwrap() {
+        replies.push(reply);
+    }
+    (code, replies)
+}
+
+/* The result or error of the reply to request `id` */
+#[cfg(test)]
+fn result(replies: &[Json], id: i64) -> String {
+    let reply = replies
+        .iter()
+        .find(|reply| reply.get("id") == Some(&Json::number(id)))
+        .unwrap();
+    reply
+        .get("result")
+        .or(reply.get("error"))
+        .unwrap()
+        .compact()
+}
+
+#[test]
+fn refuses_requests_before_initialize() {
+    let mut script = vec![(
+        r#""id": 0, "method": "textDocument/hover""#.to_string(),
+        at(0, 0),
+    )];
+    script.extend(opened(""));
+    let (_, replies) = session(script);
+    assert_eq!(
+        result(&replies, 0),
+        r#"{"code": -32002, "message": "initialize first"}"#
+    );
+    assert!(result(&replies, 1).contains(r#""textDocumentSync": {"openClose": true, "change": 2}"#));
+}
+
+#[test]
+fn reads_numbers_of_any_kind_in_requests() {
+    let (_, replies) = session(vec![(
+        r#""id": 1, "method": "initialize""#.to_string(),
+        r#"{"processId": 42, "capabilities": {"general": {"staleRequestSupport": {"retryOnContentModified": []}}, "experimental": {"timeout": 2.5e3}}}"#.to_string(),
+    )]);
+    assert!(result(&replies, 1).contains(r#""textDocumentSync""#));
+}
+
+#[test]
+fn publishes_diagnostics_on_open_and_change() {
+    let mut script = opened("let a: int = 1 +;\nprint(a);");
+    script.push((
+        r#""method": "textDocument/didChange""#.to_string(),
+        document(
+            r#", "contentChanges": [{"range": {"start": {"line": 0, "character": 16}, "end": {"line": 0, "character": 16}}, "text": "2"}]"#,
+        )
+        .replacen(r#""}"#, r#"", "version": 2}"#, 1),
+    ));
+    let (_, replies) = session(script);
+    let published: Vec<_> = replies
+        .iter()
+        .filter(|reply| {
+            reply.get("method").and_then(Json::as_str) == Some("textDocument/publishDiagnostics")
+        })
+        .map(|reply| reply.get("params").unwrap())
+        .collect();
+    assert_eq!(published.len(), 2);
+    assert!(published[0].compact().contains(
+        r#""range": {"start": {"line": 0, "character": 16}, "end": {"line": 0, "character": 17}}, "severity": 1, "code": "E0003""#
+    ));
+    assert_eq!(
+        published[1].compact(),
+        r#"{"uri": "file:///a.ch", "version": 2, "diagnostics": []}"#
+    );
+}
+
+#[test]
+fn answers_requests_about_open_documents() {
+    let mut script = opened("let a: int = 1 + 2;\nprint(a);");
+    script.extend([
+        (
+            r#""id": 2, "method": "textDocument/hover""#.to_string(),
+            at(1, 6),
+        ),
+        (
+            r#""id": 3, "method": "textDocument/definition""#.to_string(),
+            at(1, 6),
+        ),
+        (
+            r#""id": 4, "method": "textDocument/documentSymbol""#.to_string(),
+            document(""),
+        ),
+        (
+            r#""id": 5, "method": "textDocument/semanticTokens/full""#.to_string(),
+            document(""),
+        ),
+    ]);
+    let (_, replies) = session(script);
+    assert_eq!(
+        result(&replies, 2),
+        r#"{"contents": {"kind": "markdown", "value": "```cheetah\nlet a: int\n```"}, "range": {"start": {"line": 1, "character": 6}, "end": {"line": 1, "character": 7}}}"#
+    );
+    assert_eq!(
+        result(&replies, 3),
+        r#"{"uri": "file:///a.ch", "range": {"start": {"line": 0, "character": 4}, "end": {"line": 0, "character": 5}}}"#
+    );
+    assert!(result(&replies, 4).starts_with(r#"[{"name": "a", "detail": "int", "kind": 13, "#));
+    assert!(result(&replies, 5)
+        .starts_with(r#"{"data": [0, 0, 3, 0, 0, 0, 4, 1, 4, 1, 0, 3, 3, 1, 0, "#));
+}
+
+#[test]
+fn formats_whole_documents() {
+    let mut script = opened("let a: int = 1+2;\nprint(a);");
+    script.push((
+        r#""id": 6, "method": "textDocument/formatting""#.to_string(),
+        document(""),
+    ));
+    let (_, replies) = session(script);
+    assert_eq!(
+        result(&replies, 6),
+        r#"[{"range": {"start": {"line": 0, "character": 0}, "end": {"line": 1, "character": 9}}, "newText": "let a: int = 1 + 2;\nprint(a);\n"}]"#
+    );
+}
+
+#[test]
+fn rejects_unknown_methods_and_shuts_down() {
+    let mut script = opened("");
+    script.extend([
+        (
+            r#""id": 7, "method": "textDocument/rename""#.to_string(),
+            document(""),
+        ),
+        (
+            r#""id": 8, "method": "shutdown""#.to_string(),
+            "null".to_string(),
+        ),
+        (r#""method": "exit""#.to_string(), "null".to_string()),
+    ]);
+    let (code, replies) = session(script);
+    assert_eq!(
+        result(&replies, 7),
+        r#"{"code": -32601, "message": "unknown method `textDocument/rename`"}"#
+    );
+    assert_eq!(result(&replies, 8), "null");
+    assert_eq!(code, 0);
+
+    /* Exiting without a shutdown is an error */
+    let mut script = opened("");
+    script.push((r#""method": "exit""#.to_string(), "null".to_string()));
+    assert_eq!(session(script).0, 1);
+}
@@ -1,3 +1,4 @@
 pub mod node;
+#[allow(clippy::module_inception, reason = "the token stream and packrat state both parsers share")]
 pub mod parser;
 pub mod pratt;
@@ -1,12 +1,19 @@
+#![allow(clippy::needless_return, reason = "every rule returns its parse the same way")]
+
 use super::parser::Parser;
 use crate::lexer::tokens::{Token, TokenKind};
+use crate::source::span::Span;
 use std::fmt;
-use super::pratt::parse_expression;
+use std::rc::Rc;
+use super::pratt::{parse_expression, parse_range};
 
+/* Children are shared, as the memo table hands out copies of the same
+ * nodes over and over */
 #[derive(Clone, Debug)]
 pub struct Node {
     kind: NodeType,
-    children: Option<Vec<Node>>,
+    children: Option<Rc<Vec<Node>>>,
+    span: Span,
 }
 
 impl fmt::Display for Node {
@@ -14,7 +21,7 @@ impl fmt::Display for Node {
         match &self.kind {
             NodeType::Cons(kind) => {
                 let children = self.children.as_ref().expect("Node of Cons type does has None children")
-                    .into_iter()
+                    .iter()
                     .map(|child| child.to_string())
                     .collect::<Vec<String>>()
                     .join(" ");
@@ -23,7 +30,7 @@ impl fmt::Display for Node {
             NodeType::Atom(kind) => {
                 if let Some(children) = &self.children {
                     let children = children
-                        .into_iter()
+                        .iter()
                         .map(|child| child.to_string())
                         .collect::<Vec<String>>()
                         .join(" ");
@@ -38,7 +45,48 @@ impl fmt::Display for Node {
 
 impl Node {
     pub fn new(kind: NodeType, children: Option<Vec<Node>>) -> Node {
-        Node { kind, children }
+        /* Pratt nodes keep their operator in the atom and the operands as
+         * children, so the span is the union of everything rather than
+         * the first child to the last. */
+        let mut node = Node { kind, children: children.map(Rc::new), span: Span::default() };
+        node.span = node.covered();
+        node
+    }
+
+    fn covered(&self) -> Span {
+        let own = match &self.kind {
+            NodeType::Atom(token) => Some(token.span),
+            NodeType::Cons(_) => None,
+        };
+        self.children()
+            .iter()
+            .map(|child| child.span)
+            .chain(own)
+            .reduce(Span::to)
+            .unwrap_or_default()
+    }
+
+    /* Moves the node and everything in it `delta` bytes along the file */
+    pub fn shift(&mut self, delta: isize) {
+        if let NodeType::Atom(token) = &mut self.kind {
+            token.span = token.span.shift(delta);
+        }
+        for children in self.children.iter_mut() {
+            Rc::make_mut(children).iter_mut().for_each(|child| child.shift(delta));
+        }
+        self.span = self.covered();
+    }
+
+    pub fn kind(&self) -> &NodeType {
+        &self.kind
+    }
+
+    pub fn children(&self) -> &[Node] {
+        self.children.as_deref().map_or(&[], Vec::as_slice)
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
     }
 }
 
@@ -51,6 +99,11 @@ pub enum NodeType {
 #[derive(Clone, Debug, Copy, Eq, PartialEq, Hash)]
 pub enum NodeKind {
     Prog,
+    Items,
+    Item,
+    FunctionDef,
+    Parameters,
+    Parameter,
     Block,
     BlockExpr,
     Statements,
@@ -61,6 +114,9 @@ pub enum NodeKind {
     IfStmt,
     ElseStmt,
     WhileStmt,
+    ForStmt,
+    Range,
+    ReturnStmt,
     LogicOr,
     LogicAnd,
     LogicNot,
@@ -72,25 +128,33 @@ pub enum NodeKind {
     Sum,
     Term,
     Factor,
+    Index,
+    Call,
+    Arguments,
     Primary,
+    List,
+    Elements,
+    Template,
     DataType,
+    Error,
 }
 
 /* If NodeKind is left recursive */
-impl Into<bool> for NodeKind {
-    fn into(self) -> bool {
-        match self {
-            NodeKind::Statements
-            | NodeKind::LogicOr
-            | NodeKind::LogicAnd
-            | NodeKind::BitwiseOr
-            | NodeKind::BitwiseXor
-            | NodeKind::BitwiseAnd
-            | NodeKind::BitwiseShift
-            | NodeKind::Sum
-            | NodeKind::Term => true,
-            _ => false,
-        }
+impl From<NodeKind> for bool {
+    fn from(kind: NodeKind) -> bool {
+        matches!(
+            kind,
+            NodeKind::LogicOr
+                | NodeKind::LogicAnd
+                | NodeKind::BitwiseOr
+                | NodeKind::BitwiseXor
+                | NodeKind::BitwiseAnd
+                | NodeKind::BitwiseShift
+                | NodeKind::Sum
+                | NodeKind::Term
+                | NodeKind::Index
+                | NodeKind::Call
+        )
     }
 }
 
@@ -98,6 +162,11 @@ impl NodeKind {
     pub fn parse(self, parser: &mut Parser) -> Option<Node> {
         match self {
             NodeKind::Prog => parser.memoize(prog, self),
+            NodeKind::Items => parser.memoize(items, self),
+            NodeKind::Item => parser.memoize(item, self),
+            NodeKind::FunctionDef => parser.memoize(function_def, self),
+            NodeKind::Parameters => parser.memoize(parameters, self),
+            NodeKind::Parameter => parser.memoize(parameter, self),
             NodeKind::Block => parser.memoize(block, self),
             NodeKind::BlockExpr => parser.memoize(block_expr, self),
             NodeKind::Statements => parser.memoize(statements, self),
@@ -108,6 +177,9 @@ impl NodeKind {
             NodeKind::IfStmt => parser.memoize(if_stmt, self),
             NodeKind::ElseStmt => parser.memoize(else_stmt, self),
             NodeKind::WhileStmt => parser.memoize(while_stmt, self),
+            NodeKind::ForStmt => parser.memoize(for_stmt, self),
+            NodeKind::Range => parser.memoize(range, self),
+            NodeKind::ReturnStmt => parser.memoize(return_stmt, self),
             NodeKind::LogicOr => parser.memoize(logic_or, self),
             NodeKind::LogicAnd => parser.memoize(logic_and, self),
             NodeKind::LogicNot => parser.memoize(logic_not, self),
@@ -119,8 +191,15 @@ impl NodeKind {
             NodeKind::Sum => parser.memoize(sum, self),
             NodeKind::Term => parser.memoize(term, self),
             NodeKind::Factor => parser.memoize(factor, self),
+            NodeKind::Index => parser.memoize(index, self),
+            NodeKind::Call => parser.memoize(call, self),
+            NodeKind::Arguments => parser.memoize(arguments, self),
             NodeKind::Primary => parser.memoize(primary, self),
+            NodeKind::List => parser.memoize(list, self),
+            NodeKind::Elements => parser.memoize(elements, self),
+            NodeKind::Template => parser.memoize(template, self),
             NodeKind::DataType => parser.memoize(datatype, self),
+            NodeKind::Error => None,
         }
     }
 }
@@ -138,8 +217,13 @@ fn parse_productions(
     let start = parser.mark();
     for prod in productions {
         parser.reset(start);
-        if let Some(mut children) = production(parser, &prod) {
+        if let Some(mut children) = production(parser, prod) {
             if children.len() == 1 {return children.pop()} // Makes parse trees a lot smaller
+            /* Nothing to take a span from, so it sits where the parser is */
+            if children.is_empty() {
+                let span = Span::point(parser.lex.file(), parser.mark().0);
+                return Some(Node { kind, children: Some(Rc::new(children)), span });
+            }
             return Some(Node::new(kind, Some(children)));
         }
     }
@@ -151,13 +235,9 @@ fn production(parser: &mut Parser, rules: &Vec<Rules>) -> Option<Vec<Node>> {
     let mut children: Vec<Node> = vec![];
     for rule in rules {
         let child: Option<Node> = match rule {
-            Rules::Terminal(kind) => {
-                if let Some(child) = parser.expect(*kind) {
-                    Some(Node::new(NodeType::Atom(child), None))
-                } else {
-                    None
-                }
-            }
+            Rules::Terminal(kind) => parser
+                .expect(*kind)
+                .map(|child| Node::new(NodeType::Atom(child), None)),
             Rules::NonTerminal(kind) => (*kind).parse(parser),
         };
         if let Some(child) = child {
@@ -170,11 +250,112 @@ fn production(parser: &mut Parser, rules: &Vec<Rules>) -> Option<Vec<Node>> {
 }
 
 fn prog(parser: &mut Parser) -> Option<Node> {
+    if parser.recovering {
+        let mut children: Vec<Node> = recover_statements(parser, true).into_iter().collect();
+        let eof = parser.expect(TokenKind::EOF)?;
+        children.push(Node::new(NodeType::Atom(eof), None));
+        return Some(Node::new(NodeType::Cons(NodeKind::Prog), Some(children)));
+    }
+
     let kind = NodeType::Cons(NodeKind::Prog);
     let productions = [vec![
-        Rules::NonTerminal(NodeKind::Statements),
+        Rules::NonTerminal(NodeKind::Items),
         Rules::Terminal(TokenKind::EOF),
     ]];
+    if let Some(tree) = parse_productions(parser, &productions, kind) {
+        return Some(tree);
+    }
+    /* Empty, or only comments. Built by hand so the lone EOF is not
+     * collapsed into the program. */
+    let eof = parser.expect(TokenKind::EOF)?;
+    let children = vec![Node::new(NodeType::Atom(eof), None)];
+    return Some(Node::new(NodeType::Cons(NodeKind::Prog), Some(children)));
+}
+
+/* Functions may only be defined at the top level */
+fn items(parser: &mut Parser) -> Option<Node> {
+    let kind = NodeType::Cons(NodeKind::Items);
+    let productions = [
+        vec![
+            Rules::NonTerminal(NodeKind::Item),
+            Rules::NonTerminal(NodeKind::Items),
+        ],
+        vec![Rules::NonTerminal(NodeKind::Item)],
+    ];
+    return parse_productions(parser, &productions, kind);
+}
+
+fn item(parser: &mut Parser) -> Option<Node> {
+    let kind = NodeType::Cons(NodeKind::Item);
+    let productions = [
+        vec![Rules::NonTerminal(NodeKind::FunctionDef)],
+        vec![Rules::NonTerminal(NodeKind::Statement)],
+    ];
+    return parse_productions(parser, &productions, kind);
+}
+
+/* Functions */
+fn function_def(parser: &mut Parser) -> Option<Node> {
+    let kind = NodeType::Cons(NodeKind::FunctionDef);
+    let productions = [
+        vec![
+            Rules::Terminal(TokenKind::DEF),
+            Rules::Terminal(TokenKind::ID),
+            Rules::Terminal(TokenKind::LPAREN),
+            Rules::NonTerminal(NodeKind::Parameters),
+            Rules::Terminal(TokenKind::RPAREN),
+            Rules::Terminal(TokenKind::ARROW),
+            Rules::NonTerminal(NodeKind::DataType),
+            Rules::NonTerminal(NodeKind::Block),
+        ],
+        vec![
+            Rules::Terminal(TokenKind::DEF),
+            Rules::Terminal(TokenKind::ID),
+            Rules::Terminal(TokenKind::LPAREN),
+            Rules::Terminal(TokenKind::RPAREN),
+            Rules::Terminal(TokenKind::ARROW),
+            Rules::NonTerminal(NodeKind::DataType),
+            Rules::NonTerminal(NodeKind::Block),
+        ],
+        vec![
+            Rules::Terminal(TokenKind::DEF),
+            Rules::Terminal(TokenKind::ID),
+            Rules::Terminal(TokenKind::LPAREN),
+            Rules::NonTerminal(NodeKind::Parameters),
+            Rules::Terminal(TokenKind::RPAREN),
+            Rules::NonTerminal(NodeKind::Block),
+        ],
+        vec![
+            Rules::Terminal(TokenKind::DEF),
+            Rules::Terminal(TokenKind::ID),
+            Rules::Terminal(TokenKind::LPAREN),
+            Rules::Terminal(TokenKind::RPAREN),
+            Rules::NonTerminal(NodeKind::Block),
+        ],
+    ];
+    return parse_productions(parser, &productions, kind);
+}
+
+fn parameters(parser: &mut Parser) -> Option<Node> {
+    let kind = NodeType::Cons(NodeKind::Parameters);
+    let productions = [
+        vec![
+            Rules::NonTerminal(NodeKind::Parameter),
+            Rules::Terminal(TokenKind::COMMA),
+            Rules::NonTerminal(NodeKind::Parameters),
+        ],
+        vec![Rules::NonTerminal(NodeKind::Parameter)],
+    ];
+    return parse_productions(parser, &productions, kind);
+}
+
+fn parameter(parser: &mut Parser) -> Option<Node> {
+    let kind = NodeType::Cons(NodeKind::Parameter);
+    let productions = [vec![
+        Rules::Terminal(TokenKind::ID),
+        Rules::Terminal(TokenKind::COLON),
+        Rules::NonTerminal(NodeKind::DataType),
+    ]];
     return parse_productions(parser, &productions, kind);
 }
 
@@ -188,6 +369,10 @@ fn block(parser: &mut Parser) -> Option<Node> {
             Rules::Terminal(TokenKind::RCURLY),
         ],
         vec![Rules::NonTerminal(NodeKind::BlockExpr)],
+        vec![
+            Rules::Terminal(TokenKind::LCURLY),
+            Rules::Terminal(TokenKind::RCURLY),
+        ],
     ];
     return parse_productions(parser, &productions, kind);
 }
@@ -211,6 +396,10 @@ fn block_expr(parser: &mut Parser) -> Option<Node> {
 }
 
 fn statements(parser: &mut Parser) -> Option<Node> {
+    if parser.recovering {
+        return recover_statements(parser, false);
+    }
+
     let kind = NodeType::Cons(NodeKind::Statements);
     let productions = [
         vec![
@@ -222,6 +411,88 @@ fn statements(parser: &mut Parser) -> Option<Node> {
     return parse_productions(parser, &productions, kind);
 }
 
+/* Tokens that start a statement, which are safe to resume parsing at */
+const STATEMENT_START: &[TokenKind] = &[
+    TokenKind::LET,
+    TokenKind::IF,
+    TokenKind::WHILE,
+    TokenKind::FOR,
+    TokenKind::DEF,
+    TokenKind::RETURN,
+];
+
+/* Parses statements one at a time, reporting each one that fails and
+ * replacing it with an Error node holding the tokens skipped over. At the
+ * top level functions are parsed too, inside a block it stops at the
+ * closing '}' or at a trailing expression. */
+fn recover_statements(parser: &mut Parser, top_level: bool) -> Option<Node> {
+    let mut children: Vec<Node> = vec![];
+    loop {
+        let next = parser.lex.peek();
+        if next.kind == TokenKind::EOF || (next.kind == TokenKind::RCURLY && !top_level) {
+            break;
+        }
+
+        let start = parser.mark();
+        parser.clear_failure();
+        let rule = if top_level { NodeKind::Item } else { NodeKind::Statement };
+        if let Some(statement) = rule.parse(parser) {
+            children.push(statement);
+            continue;
+        }
+        if !top_level {
+            parser.reset(start);
+            if NodeKind::Expression.parse(parser).is_some() && parser.expect(TokenKind::RCURLY).is_some() {
+                parser.reset(start);
+                break;
+            }
+        }
+
+        parser.reset(start);
+        parser.report_failure();
+        children.push(synchronize(parser, top_level));
+    }
+
+    /* Nest the statements the same way the items and statements rules do */
+    let kind = if top_level { NodeKind::Items } else { NodeKind::Statements };
+    let mut statements = children.pop()?;
+    while let Some(statement) = children.pop() {
+        statements = Node::new(NodeType::Cons(kind), Some(vec![statement, statements]));
+    }
+    Some(statements)
+}
+
+/* Skips at least one token, stopping after a ';' or before the start of
+ * the next statement or the '}' closing the current block. */
+fn synchronize(parser: &mut Parser, top_level: bool) -> Node {
+    let mut skipped: Vec<Node> = vec![];
+    let mut depth = 0;
+    loop {
+        let next = parser.lex.peek();
+        let at_boundary = depth == 0 && !skipped.is_empty();
+        match next.kind {
+            TokenKind::EOF => break,
+            TokenKind::RCURLY if depth == 0 && !top_level => break,
+            kind if at_boundary && STATEMENT_START.contains(&kind) => break,
+            _ => {}
+        }
+
+        let token = parser.lex.next();
+        match token.kind {
+            TokenKind::LCURLY => depth += 1,
+            TokenKind::RCURLY => depth = (depth - 1).max(0),
+            TokenKind::ERROR => parser.report_lex_error(&token),
+            _ => {}
+        }
+        let kind = token.kind;
+        skipped.push(Node::new(NodeType::Atom(token), None));
+        if kind == TokenKind::SEMICOLON && depth == 0 {
+            break;
+        }
+    }
+    Node::new(NodeType::Cons(NodeKind::Error), Some(skipped))
+}
+
 fn statement(parser: &mut Parser) -> Option<Node> {
     let kind = NodeType::Cons(NodeKind::Statement);
     let productions = [
@@ -235,14 +506,20 @@ fn statement(parser: &mut Parser) -> Option<Node> {
         ],
         vec![Rules::NonTerminal(NodeKind::IfStmt)],
         vec![Rules::NonTerminal(NodeKind::WhileStmt)],
+        vec![Rules::NonTerminal(NodeKind::ForStmt)],
         vec![Rules::NonTerminal(NodeKind::Block)],
+        vec![Rules::NonTerminal(NodeKind::ReturnStmt)],
+        vec![
+            Rules::NonTerminal(NodeKind::Expression),
+            Rules::Terminal(TokenKind::SEMICOLON),
+        ],
     ];
     return parse_productions(parser, &productions, kind);
 }
 
 fn expression(parser: &mut Parser) -> Option<Node> {
     if parser.pratt {
-        Some(parse_expression(parser))
+        parse_expression(parser)
     } else {
         let kind = NodeType::Cons(NodeKind::Expression);
         let productions = [
@@ -266,10 +543,12 @@ fn declaration(parser: &mut Parser) -> Option<Node> {
     return parse_productions(parser, &productions, kind);
 }
 
+/* Any index expression is accepted as the target, lowering rejects the
+ * ones which are not an ID or an index */
 fn assignment(parser: &mut Parser) -> Option<Node> {
     let kind = NodeType::Cons(NodeKind::Assignment);
     let productions = [vec![
-        Rules::Terminal(TokenKind::ID),
+        Rules::NonTerminal(NodeKind::Index),
         Rules::Terminal(TokenKind::ASSIGN),
         Rules::NonTerminal(NodeKind::Expression),
     ]];
@@ -321,6 +600,60 @@ fn while_stmt(parser: &mut Parser) -> Option<Node> {
     return parse_productions(parser, &productions, kind);
 }
 
+/* For Statement */
+fn for_stmt(parser: &mut Parser) -> Option<Node> {
+    let kind = NodeType::Cons(NodeKind::ForStmt);
+    let productions = [
+        vec![
+            Rules::Terminal(TokenKind::FOR),
+            Rules::Terminal(TokenKind::ID),
+            Rules::Terminal(TokenKind::IN),
+            Rules::NonTerminal(NodeKind::Range),
+            Rules::NonTerminal(NodeKind::Block),
+        ],
+        vec![
+            Rules::Terminal(TokenKind::FOR),
+            Rules::Terminal(TokenKind::ID),
+            Rules::Terminal(TokenKind::IN),
+            Rules::NonTerminal(NodeKind::Expression),
+            Rules::NonTerminal(NodeKind::Block),
+        ],
+    ];
+    return parse_productions(parser, &productions, kind);
+}
+
+/* Half-open range of integers, `start..end` */
+fn range(parser: &mut Parser) -> Option<Node> {
+    if parser.pratt {
+        parse_range(parser)
+    } else {
+        let kind = NodeType::Cons(NodeKind::Range);
+        let productions = [vec![
+            Rules::NonTerminal(NodeKind::LogicOr),
+            Rules::Terminal(TokenKind::DOTDOT),
+            Rules::NonTerminal(NodeKind::LogicOr),
+        ]];
+        parse_productions(parser, &productions, kind)
+    }
+}
+
+/* Return Statement */
+fn return_stmt(parser: &mut Parser) -> Option<Node> {
+    let kind = NodeType::Cons(NodeKind::ReturnStmt);
+    let productions = [
+        vec![
+            Rules::Terminal(TokenKind::RETURN),
+            Rules::NonTerminal(NodeKind::Expression),
+            Rules::Terminal(TokenKind::SEMICOLON),
+        ],
+        vec![
+            Rules::Terminal(TokenKind::RETURN),
+            Rules::Terminal(TokenKind::SEMICOLON),
+        ],
+    ];
+    return parse_productions(parser, &productions, kind);
+}
+
 /* Logic Operators */
 fn logic_or(parser: &mut Parser) -> Option<Node> {
     let kind = NodeType::Cons(NodeKind::LogicOr);
@@ -512,11 +845,65 @@ fn factor(parser: &mut Parser) -> Option<Node> {
             Rules::Terminal(TokenKind::BIT_NOT),
             Rules::NonTerminal(NodeKind::Factor),
         ],
+        vec![Rules::NonTerminal(NodeKind::Index)],
+    ];
+    return parse_productions(parser, &productions, kind);
+}
+
+/* Indexing and slicing */
+fn index(parser: &mut Parser) -> Option<Node> {
+    let kind = NodeType::Cons(NodeKind::Index);
+    let productions = [
+        vec![
+            Rules::NonTerminal(NodeKind::Index),
+            Rules::Terminal(TokenKind::LBRACE),
+            Rules::NonTerminal(NodeKind::Range),
+            Rules::Terminal(TokenKind::RBRACE),
+        ],
+        vec![
+            Rules::NonTerminal(NodeKind::Index),
+            Rules::Terminal(TokenKind::LBRACE),
+            Rules::NonTerminal(NodeKind::Expression),
+            Rules::Terminal(TokenKind::RBRACE),
+        ],
+        vec![Rules::NonTerminal(NodeKind::Call)],
+    ];
+    return parse_productions(parser, &productions, kind);
+}
+
+/* Calls */
+fn call(parser: &mut Parser) -> Option<Node> {
+    let kind = NodeType::Cons(NodeKind::Call);
+    let productions = [
+        vec![
+            Rules::NonTerminal(NodeKind::Call),
+            Rules::Terminal(TokenKind::LPAREN),
+            Rules::NonTerminal(NodeKind::Arguments),
+            Rules::Terminal(TokenKind::RPAREN),
+        ],
+        vec![
+            Rules::NonTerminal(NodeKind::Call),
+            Rules::Terminal(TokenKind::LPAREN),
+            Rules::Terminal(TokenKind::RPAREN),
+        ],
         vec![Rules::NonTerminal(NodeKind::Primary)],
     ];
     return parse_productions(parser, &productions, kind);
 }
 
+fn arguments(parser: &mut Parser) -> Option<Node> {
+    let kind = NodeType::Cons(NodeKind::Arguments);
+    let productions = [
+        vec![
+            Rules::NonTerminal(NodeKind::Expression),
+            Rules::Terminal(TokenKind::COMMA),
+            Rules::NonTerminal(NodeKind::Arguments),
+        ],
+        vec![Rules::NonTerminal(NodeKind::Expression)],
+    ];
+    return parse_productions(parser, &productions, kind);
+}
+
 /* Atoms */
 fn primary(parser: &mut Parser) -> Option<Node> {
     let kind = NodeType::Cons(NodeKind::Primary);
@@ -526,10 +913,11 @@ fn primary(parser: &mut Parser) -> Option<Node> {
             Rules::NonTerminal(NodeKind::Expression),
             Rules::Terminal(TokenKind::RPAREN),
         ],
-        vec![Rules::NonTerminal(NodeKind::BlockExpr)],
+        vec![Rules::NonTerminal(NodeKind::List)],
+        vec![Rules::NonTerminal(NodeKind::Block)],
         vec![Rules::NonTerminal(NodeKind::IfStmt)],
         vec![Rules::Terminal(TokenKind::NUMBER)],
-        vec![Rules::Terminal(TokenKind::STRING)],
+        vec![Rules::NonTerminal(NodeKind::Template)],
         vec![Rules::Terminal(TokenKind::ID)],
         vec![Rules::Terminal(TokenKind::TRUE)],
         vec![Rules::Terminal(TokenKind::FALSE)],
@@ -537,12 +925,65 @@ fn primary(parser: &mut Parser) -> Option<Node> {
     return parse_productions(parser, &productions, kind);
 }
 
+/* List literals */
+fn list(parser: &mut Parser) -> Option<Node> {
+    let kind = NodeType::Cons(NodeKind::List);
+    let productions = [
+        vec![
+            Rules::Terminal(TokenKind::LBRACE),
+            Rules::NonTerminal(NodeKind::Elements),
+            Rules::Terminal(TokenKind::RBRACE),
+        ],
+        vec![
+            Rules::Terminal(TokenKind::LBRACE),
+            Rules::Terminal(TokenKind::RBRACE),
+        ],
+    ];
+    return parse_productions(parser, &productions, kind);
+}
+
+fn elements(parser: &mut Parser) -> Option<Node> {
+    let kind = NodeType::Cons(NodeKind::Elements);
+    let productions = [
+        vec![
+            Rules::NonTerminal(NodeKind::Expression),
+            Rules::Terminal(TokenKind::COMMA),
+            Rules::NonTerminal(NodeKind::Elements),
+        ],
+        vec![Rules::NonTerminal(NodeKind::Expression)],
+    ];
+    return parse_productions(parser, &productions, kind);
+}
+
+/* Strings, with their interpolations parsed out of the STRING token */
+fn template(parser: &mut Parser) -> Option<Node> {
+    let token = parser.expect(TokenKind::STRING)?;
+    Some(parser.string(token))
+}
+
 fn datatype(parser: &mut Parser) -> Option<Node> {
     let kind = NodeType::Cons(NodeKind::DataType);
     let productions = [
+        vec![
+            Rules::Terminal(TokenKind::LBRACE),
+            Rules::NonTerminal(NodeKind::DataType),
+            Rules::Terminal(TokenKind::RBRACE),
+        ],
         vec![Rules::Terminal(TokenKind::INT)],
         vec![Rules::Terminal(TokenKind::BOOL)],
         vec![Rules::Terminal(TokenKind::STR)],
     ];
     return parse_productions(parser, &productions, kind);
 }
+
+#[test]
+fn empty_nodes_sit_where_the_parser_is() {
+    use crate::lexer::lex::Lexer;
+    use crate::source::span::FileId;
+
+    let mut parser = Parser::new(Lexer::with_file("a = 1;".to_string(), FileId(1)));
+    parser.expect(TokenKind::ID);
+    let kind = NodeType::Cons(NodeKind::Statements);
+    let node = parse_productions(&mut parser, &[vec![]], kind).unwrap();
+    assert_eq!(node.span(), Span::point(FileId(1), 1));
+}
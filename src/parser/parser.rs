@@ -1,14 +1,59 @@
-use super::node::{Node, NodeKind};
+use super::node::{Node, NodeKind, NodeType};
+use super::pratt::{is_binary_operator, EXPRESSION_START};
+use crate::diagnostics::diagnostic::Diagnostic;
 use crate::lexer::{
-    lex::Lexer,
+    lex::{self, Lexer},
+    strings::{self, Segment},
     tokens::{Token, TokenKind},
 };
+use crate::source::span::Span;
 use std::collections::HashMap;
+use std::rc::Rc;
+
+/* What a rule made of the input at some position: the node, where it
+ * ended and how far the lexer looked to get there, along with the
+ * failures and diagnostics it ran into, which are replayed on a hit so
+ * that reusing it changes nothing. `shift` is how far edits before the
+ * entry have moved it since, which the node and failure catch up with on
+ * the next hit. */
+#[derive(Clone)]
+struct Entry {
+    node: Option<Node>,
+    end: (usize, usize),
+    furthest: usize,
+    failure: Option<Rc<Failure>>,
+    diagnostics: Vec<Diagnostic>,
+    shift: isize,
+}
+
+/* A rule and the lexer position it ran at */
+type Key = (NodeKind, (usize, usize));
+
+type Memo = HashMap<Key, Entry>;
+
+/* The furthest token any rule failed on, and every token that would have
+ * been accepted in its place */
+#[derive(Clone)]
+struct Failure {
+    found: Token,
+    expected: Vec<TokenKind>,
+}
 
 pub struct Parser {
     pub lex: Lexer,
     pub pratt: bool,
-    cache: HashMap<(NodeKind, (usize, usize)), (Option<Node>, (usize, usize))>,
+    /* Set while reparsing a program that failed to parse, so that the
+     * statement rules skip over errors instead of failing */
+    pub recovering: bool,
+    cache: Memo,
+    /* The recovery pass gets rules to parse differently, so it has a memo
+     * table of its own */
+    recovery_cache: Memo,
+    /* Shared with the memo entries that ran into it */
+    failure: Option<Rc<Failure>>,
+    /* The last program failed to parse only because it ended too soon */
+    incomplete: bool,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Parser {
@@ -16,7 +61,12 @@ impl Parser {
         Parser {
             lex,
             pratt: false,
+            recovering: false,
             cache: HashMap::new(),
+            recovery_cache: HashMap::new(),
+            failure: None,
+            incomplete: false,
+            diagnostics: vec![],
         }
     }
 
@@ -29,28 +79,102 @@ impl Parser {
     }
 
     pub fn expect(&mut self, tok: TokenKind) -> Option<Token> {
-        if tok == self.lex.peek().kind {
+        let next = self.lex.peek();
+        if tok == next.kind {
             Some(self.lex.next())
         } else {
+            self.fail_at(next, &[tok]);
             None
         }
     }
 
+    /* Records that the next token is not one of `expected` */
+    pub fn fail(&mut self, expected: &[TokenKind]) {
+        let found = self.lex.peek();
+        self.fail_at(found, expected);
+    }
+
+    fn fail_at(&mut self, found: Token, expected: &[TokenKind]) {
+        match &mut self.failure {
+            Some(failure) if failure.found.span.start > found.span.start => {}
+            Some(failure) if failure.found.span.start == found.span.start => {
+                let failure = Rc::make_mut(failure);
+                for kind in expected {
+                    if !failure.expected.contains(kind) {
+                        failure.expected.push(*kind);
+                    }
+                }
+            }
+            _ => {
+                self.failure = Some(Rc::new(Failure {
+                    found,
+                    expected: expected.to_vec(),
+                }))
+            }
+        }
+    }
+
+    pub fn clear_failure(&mut self) {
+        self.failure = None;
+    }
+
+    /* Reports the furthest failure since the last `clear_failure` */
+    pub fn report_failure(&mut self) {
+        if self.failure.is_some() {
+            let diagnostic = self.failure_diagnostic();
+            self.report(diagnostic);
+        }
+    }
+
+    pub fn report_lex_error(&mut self, token: &Token) {
+        self.report(lex::error_diagnostic(token));
+    }
+
+    /* Rules can run more than once at the same position, so the same
+     * diagnostic is only kept once */
+    fn report(&mut self, diagnostic: Diagnostic) {
+        if !self.diagnostics.contains(&diagnostic) {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn memo(&mut self) -> &mut Memo {
+        if self.recovering { &mut self.recovery_cache } else { &mut self.cache }
+    }
+
+    #[allow(clippy::needless_return, reason = "every branch hands back its node the same way")]
     pub fn memoize(
         &mut self,
         f: fn(&mut Parser) -> Option<Node>,
         kind: NodeKind,
     ) -> Option<Node> {
         let start_position = self.mark();
-        let key = (kind.clone(), start_position);
+        let key = (kind, start_position);
 
-        if let Some((node, end_position)) = self.cache.get(&key) {
-            let node = node.clone();
-            self.reset(end_position.clone());
+        if let Some(entry) = self.memo().get_mut(&key) {
+            if entry.shift != 0 {
+                let shift = std::mem::take(&mut entry.shift);
+                entry.node.iter_mut().for_each(|node| node.shift(shift));
+                for failure in entry.failure.iter_mut() {
+                    let found = &mut Rc::make_mut(failure).found;
+                    found.span = found.span.shift(shift);
+                }
+            }
+            let (node, end_position) = (entry.node.clone(), entry.end);
+            let (furthest, failure, diagnostics) = (entry.furthest, entry.failure.clone(), entry.diagnostics.clone());
+            self.replay(furthest, failure.as_ref(), &diagnostics);
+            self.reset(end_position);
             return node;
-        } else if kind.clone().into() {
+        }
+
+        /* The rule's own failures and diagnostics are kept apart from the
+         * ones before it, to be stored along with its result */
+        let outer = (self.failure.take(), std::mem::take(&mut self.diagnostics), self.lex.furthest());
+        self.lex.set_furthest(0);
+        let (node, end_position) = if kind.into() {
             let (mut last_node, mut last_position) = (None, start_position);
-            self.cache.insert(key.clone(), (None, start_position));
+            let seed = self.entry(None, start_position);
+            self.memo().insert(key, seed);
             loop {
                 self.reset(start_position);
 
@@ -59,23 +183,359 @@ impl Parser {
 
                 if end_position.1 <= last_position.1 {
                     self.reset(last_position);
-                    return last_node;
+                    break (last_node, last_position);
                 }
 
                 (last_node, last_position) = (node, end_position);
-                self.cache
-                    .insert(key.clone(), (last_node.clone(), last_position));
+                let entry = self.entry(last_node.clone(), last_position);
+                self.memo().insert(key, entry);
             }
         } else {
             let node = f(self);
-            let end_position = self.mark();
-            self.cache.insert(key.clone(), (node.clone(), end_position));
-            return node;
+            (node, self.mark())
+        };
+
+        let entry = self.entry(node.clone(), end_position);
+        (self.failure, self.diagnostics) = (outer.0, outer.1);
+        self.replay(outer.2, entry.failure.as_ref(), &entry.diagnostics);
+        self.memo().insert(key, entry);
+        return node;
+    }
+
+    /* The result of the rule running now, with what it ran into so far */
+    fn entry(&self, node: Option<Node>, end: (usize, usize)) -> Entry {
+        Entry {
+            node,
+            end,
+            furthest: self.lex.furthest(),
+            failure: self.failure.clone(),
+            diagnostics: self.diagnostics.clone(),
+            shift: 0,
+        }
+    }
+
+    /* Goes through what a rule ran into again, as if it had just run */
+    fn replay(&mut self, furthest: usize, failure: Option<&Rc<Failure>>, diagnostics: &[Diagnostic]) {
+        self.lex.set_furthest(self.lex.furthest().max(furthest));
+        match (&self.failure, failure) {
+            (_, None) => {}
+            (None, Some(failure)) => self.failure = Some(failure.clone()),
+            (Some(_), Some(failure)) => self.fail_at(failure.found.clone(), &failure.expected),
+        }
+        for diagnostic in diagnostics {
+            self.report(diagnostic.clone());
+        }
+    }
+
+    /* Replaces `range` of the input, in the byte offsets spans use, with
+     * `text` and parses it again. Memo entries that looked at any of the
+     * replaced bytes are dropped, and the ones after them moved along, so
+     * only the rules around the edit have to run again. */
+    pub fn edit(&mut self, range: std::ops::Range<usize>, text: &str) -> Option<Node> {
+        let range = self.lex.edit(range, text);
+        let shift = text.len() as isize - range.len() as isize;
+        for memo in [&mut self.cache, &mut self.recovery_cache] {
+            /* Entries before the edit stay where they are */
+            let after: Vec<_> = memo.extract_if(|_, entry| entry.furthest > range.start).collect();
+            memo.extend(after.into_iter().filter_map(|(key, entry)| moved(key, entry, &range, shift)));
         }
+        self.failure = None;
+        self.diagnostics.clear();
+        self.parse(self.pratt)
     }
 
+    /* Parses a whole program. A program with syntax errors is parsed a
+     * second time in recovery mode, which reports every error and returns
+     * a tree with Error nodes in place of the statements that failed. */
     pub fn parse(&mut self, pratt: bool) -> Option<Node> {
         self.pratt = pratt;
-        NodeKind::Prog.parse(self)
+        self.recovering = false;
+        self.incomplete = false;
+        let start = self.mark();
+        if let Some(tree) = NodeKind::Prog.parse(self) {
+            return Some(tree);
+        }
+        let failure = self.failure_diagnostic();
+        self.incomplete = self.failure.as_ref().is_some_and(|failure| match failure.found.kind {
+            TokenKind::EOF => true,
+            TokenKind::ERROR => lex::is_unterminated(&failure.found),
+            _ => false,
+        });
+
+        self.reset(start);
+        self.recovering = true;
+        let tree = NodeKind::Prog.parse(self);
+        self.recovering = false;
+
+        if !self.has_errors() {
+            self.report(failure);
+        }
+        tree
     }
+
+    /* A string literal without interpolations is kept as a STRING token,
+     * one with them becomes a Template of STRING_PART tokens with the
+     * interpolated expressions in between. Bad escapes are reported here,
+     * even though lowering is what replaces them. */
+    pub fn string(&mut self, token: Token) -> Node {
+        let segments = strings::segments(&token);
+        if let [Segment::Text(_)] = segments.as_slice() {
+            for diagnostic in strings::unescape(&token).1 {
+                self.report(diagnostic);
+            }
+            return Node::new(NodeType::Atom(token), None);
+        }
+
+        let mut children = vec![];
+        let mut trivia = String::new();
+        for segment in segments {
+            match segment {
+                Segment::Text(part) => {
+                    for diagnostic in strings::unescape(&part).1 {
+                        self.report(diagnostic);
+                    }
+                    let part = match trivia.is_empty() {
+                        true => part,
+                        false => part.with_trivia(&std::mem::take(&mut trivia)),
+                    };
+                    children.push(Node::new(NodeType::Atom(part), None));
+                }
+                Segment::Code { source, offset } => {
+                    let (node, closing) = self.interpolation(source, offset);
+                    /* `{}` leaves nothing to keep, and an empty node has no span */
+                    if node.kind() != &NodeType::Cons(NodeKind::Error) || !node.children().is_empty() {
+                        children.push(node);
+                    }
+                    trivia = closing;
+                }
+            }
+        }
+        Node::new(NodeType::Cons(NodeKind::Template), Some(children))
+    }
+
+    /* Parses the expression inside `{...}` with a parser of its own. The
+     * closing '}' is lexed along with it, so that errors point at it
+     * rather than at the end of the input, but it belongs to the next
+     * STRING_PART, as does the trivia before it, which is returned along
+     * with the expression. If the expression does not parse, its tokens
+     * are kept in an Error node. */
+    fn interpolation(&mut self, source: String, offset: usize) -> (Node, String) {
+        let end = offset + source.len() + 1;
+        let lex = Lexer::with_offset(source + "}", self.lex.file(), offset);
+        let mut parser = Parser::new(lex);
+        parser.pratt = self.pratt;
+        let start = parser.mark();
+        let expression = NodeKind::Expression.parse(&mut parser);
+        let closing = expression.as_ref().and_then(|_| parser.expect(TokenKind::RCURLY));
+        let (node, trivia) = match (expression, closing) {
+            (Some(node), Some(closing)) => (node, closing.trivia),
+            _ => {
+                parser.report_failure();
+                parser.reset(start);
+                let mut skipped = vec![];
+                let mut trivia = loop {
+                    let token = parser.lex.next();
+                    if token.kind == TokenKind::EOF {
+                        break token.trivia;
+                    }
+                    skipped.push(token);
+                };
+                /* A comment can swallow the '}', which must not show up
+                 * in the tree twice */
+                match skipped.last_mut() {
+                    Some(last) if last.kind == TokenKind::RCURLY && last.span.end == end => {
+                        trivia = skipped.pop().expect("checked above").trivia;
+                    }
+                    Some(last) if last.kind == TokenKind::ERROR && last.span.end == end => {
+                        last.lexeme.as_mut().map(String::pop);
+                        last.span.end -= 1;
+                    }
+                    _ => {
+                        trivia.pop();
+                    }
+                }
+                let skipped = skipped.into_iter().map(|token| Node::new(NodeType::Atom(token), None));
+                (Node::new(NodeType::Cons(NodeKind::Error), Some(skipped.collect())), trivia)
+            }
+        };
+        for diagnostic in parser.take_diagnostics() {
+            self.report(diagnostic);
+        }
+        (node, trivia)
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    /* If the last `parse` failed because the input stopped in the middle
+     * of something, like an open `{` or string, rather than on a wrong
+     * token. More input could still make it parse. */
+    pub fn incomplete(&self) -> bool {
+        self.incomplete
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn failure_diagnostic(&self) -> Diagnostic {
+        let Some(failure) = &self.failure else {
+            return Diagnostic::error("could not parse program", Span::point(self.lex.file(), 0));
+        };
+        let found = &failure.found;
+        if found.kind == TokenKind::ERROR {
+            return lex::error_diagnostic(found);
+        }
+
+        let expected = describe_expected(&failure.expected);
+        Diagnostic::error(format!("expected {}, found {}", expected, found.describe()), found.span)
+            .with_code("E0003")
+            .with_label(found.span, format!("expected {}", expected))
+    }
+}
+
+/* Where a memo entry is after replacing `edited` with text `shift` bytes
+ * longer, if it is still right. An entry is wrong once the edit touches
+ * the bytes it looked at, from its start up to `furthest`, which for an
+ * insertion includes one right at its start. Entries with diagnostics are
+ * only kept where they are, as there is no moving those. */
+fn moved(key: Key, mut entry: Entry, edited: &std::ops::Range<usize>, shift: isize) -> Option<(Key, Entry)> {
+    let (kind, (position, lookahead)) = key;
+    if entry.furthest <= edited.start {
+        return Some((key, entry));
+    }
+    if position < edited.end || position == edited.start || (shift != 0 && !entry.diagnostics.is_empty()) {
+        return None;
+    }
+    let moved = |offset: usize| offset.checked_add_signed(shift).expect("entries after an edit stay after it");
+    entry.end = (moved(entry.end.0), moved(entry.end.1));
+    entry.furthest = moved(entry.furthest);
+    entry.shift += shift;
+    Some(((kind, (moved(position), moved(lookahead))), entry))
+}
+
+/* Lists the expected tokens, folding everything that can start an
+ * expression into "expression" and binary operators, along with the '('
+ * of a call and the '[' of an index, into "an operator" */
+fn describe_expected(expected: &[TokenKind]) -> String {
+    let mut items: Vec<&str> = vec![];
+    let mut remaining = expected.to_vec();
+    let atoms = [TokenKind::LPAREN, TokenKind::NUMBER, TokenKind::ID];
+    if atoms.iter().all(|kind| expected.contains(kind)) {
+        items.push("expression");
+        remaining.retain(|kind| !EXPRESSION_START.contains(kind));
+    }
+    let operators = remaining.iter().filter(|kind| is_binary_operator(**kind)).count();
+    if operators > 1 {
+        remaining.retain(|kind| {
+            !is_binary_operator(*kind) && !matches!(kind, TokenKind::LPAREN | TokenKind::LBRACE)
+        });
+    }
+    items.extend(remaining.iter().map(|kind| kind.describe()));
+    if operators > 1 {
+        items.push("an operator");
+    }
+
+    match items.as_slice() {
+        [] => "nothing".into(),
+        [one] => one.to_string(),
+        [first, second] => format!("{} or {}", first, second),
+        [rest @ .., last] => format!("one of {}, or {}", rest.join(", "), last),
+    }
+}
+
+#[test]
+fn reports_furthest_failure() {
+    for pratt in [false, true] {
+        let lex = Lexer::new("let a: int = (1 + 2;\nwhile a { a = 1; }".to_string());
+        let mut parser = Parser::new(lex);
+        parser.parse(pratt);
+
+        let diagnostics = parser.take_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, Some("E0003"));
+        assert_eq!(diagnostics[0].message, "expected `)` or an operator, found `;`");
+        assert_eq!(diagnostics[0].primary_span, Span::new(Default::default(), 19, 20));
+    }
+}
+
+#[test]
+fn parses_empty_programs() {
+    for input in ["", "\n  ", "# nothing yet\n/* at all */\n"] {
+        for pratt in [false, true] {
+            let mut parser = Parser::new(Lexer::new(input.to_string()));
+            let tree = parser.parse(pratt).expect("an empty program parses");
+            assert!(parser.diagnostics().is_empty(), "{:?}", input);
+            assert_eq!(tree.to_string(), "(Prog EOF)");
+        }
+    }
+}
+
+#[test]
+fn recovers_from_every_error() {
+    let input = "let a: int = (1 + 2;\nlet b: bool = true\nwhile a < 10 {\n    a = a * ;\n    let c: int = $ 4;\n}\nlet d: int = 3;";
+    for pratt in [false, true] {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let tree = parser.parse(pratt).expect("recovery always produces a tree");
+
+        let lines: Vec<usize> = parser
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| input[..diagnostic.primary_span.start].matches('\n').count() + 1)
+            .collect();
+        assert_eq!(lines, vec![1, 3, 4, 5]);
+        assert_eq!(tree.to_string().matches("(Error").count(), 4);
+        assert!(tree.to_string().contains("(Declaration LET ID COLON INT ASSIGN NUMBER)"));
+    }
+}
+
+#[test]
+fn reparses_edits_like_a_fresh_parse() {
+    let base = "def f(a: int) -> int { return a * 2; }\nlet s: str = \"x{f(1) + 2}y\\n\";\n/* a /* b */ */ let a: int = (1 + 2) * 3; # c\nwhile a < 10 { a = a + 1; }\n";
+    let pieces = [" ", "\n", "1", "a", "+", "*", "(", ")", "{", "}", ";", "let b: int = ", "\"", "/*", "*/", "#", "if a { 1 } else { 2 }", "f(", ",", "[1, 2]", "=="];
+    let mut seed: u64 = 1;
+    let mut random = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) as usize % n
+    };
+
+    for pratt in [false, true] {
+        let mut text = base.to_string();
+        let mut parser = Parser::new(Lexer::new(text.clone()));
+        parser.parse(pratt);
+        for _ in 0..400 {
+            /* Start over now and then, so that not every program is broken */
+            let (range, insert) = match random(25) {
+                0 => (0..text.len(), base),
+                _ => {
+                    let start = random(text.len() + 1);
+                    let end = text.len().min(start + random(4));
+                    (start..end, if random(3) == 0 { "" } else { pieces[random(pieces.len())] })
+                }
+            };
+            text.replace_range(range.clone(), insert);
+            let tree = parser.edit(range, insert);
+
+            let mut fresh = Parser::new(Lexer::new(text.clone()));
+            assert_eq!(format!("{:?}", tree), format!("{:?}", fresh.parse(pratt)), "{:?}", text);
+            assert_eq!(parser.diagnostics(), fresh.diagnostics(), "{:?}", text);
+            assert_eq!(parser.incomplete(), fresh.incomplete(), "{:?}", text);
+        }
+    }
+}
+
+#[test]
+fn small_edits_keep_most_of_the_memo_table() {
+    let text = "let a: int = 1 + 2;\n".repeat(200);
+    let mut parser = Parser::new(Lexer::new(text.clone()));
+    parser.parse(false);
+    let one = text.len() / 2 + 13;
+    let entries = parser.cache.len();
+    let kept = parser.cache.iter().filter_map(|(key, entry)| moved(*key, entry.clone(), &(one..one + 1), 1)).count();
+    assert!(kept > entries * 9 / 10, "kept {} of {}", kept, entries);
 }
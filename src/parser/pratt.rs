@@ -1,77 +1,189 @@
-use crate::lexer::tokens::{TokenKind, Token};
+use crate::lexer::tokens::TokenKind;
 
 use super::node::{NodeKind, NodeType, Node};
 use super::parser::Parser;
 
-pub fn parse_expression(parser: &mut Parser) -> Node {
+/* Every token that can start an expression */
+pub const EXPRESSION_START: &[TokenKind] = &[
+    TokenKind::BOOL_NOT,
+    TokenKind::PLUS,
+    TokenKind::MINUS,
+    TokenKind::BIT_NOT,
+    TokenKind::LPAREN,
+    TokenKind::LBRACE,
+    TokenKind::LCURLY,
+    TokenKind::IF,
+    TokenKind::NUMBER,
+    TokenKind::STRING,
+    TokenKind::ID,
+    TokenKind::TRUE,
+    TokenKind::FALSE,
+];
+
+pub const BINARY_OPERATORS: &[TokenKind] = &[
+    TokenKind::BOOL_OR,
+    TokenKind::BOOL_AND,
+    TokenKind::EQ,
+    TokenKind::NE,
+    TokenKind::LE,
+    TokenKind::LT,
+    TokenKind::GE,
+    TokenKind::GT,
+    TokenKind::BIT_OR,
+    TokenKind::BIT_XOR,
+    TokenKind::BIT_AND,
+    TokenKind::BIT_LEFT,
+    TokenKind::BIT_RIGHT,
+    TokenKind::PLUS,
+    TokenKind::MINUS,
+    TokenKind::MULTIPLY,
+    TokenKind::MODULUS,
+    TokenKind::DIVIDE,
+];
+
+/* Calls and indexing bind tighter than every prefix and infix operator */
+const POSTFIX_BP: u8 = 23;
+
+pub fn parse_expression(parser: &mut Parser) -> Option<Node> {
     expression(parser, 0)
 }
 
-fn expression(parser: &mut Parser, min_bp: u8) -> Node {
+/* Builds the same Range node as the packrat rule, `start '..' end` */
+pub fn parse_range(parser: &mut Parser) -> Option<Node> {
+    let start = expression(parser, 0)?;
+    let dotdot = parser.expect(TokenKind::DOTDOT)?;
+    let end = expression(parser, 0)?;
+    let children = vec![start, Node::new(NodeType::Atom(dotdot), None), end];
+    Some(Node::new(NodeType::Cons(NodeKind::Range), Some(children)))
+}
+
+fn expression(parser: &mut Parser, min_bp: u8) -> Option<Node> {
     let start = parser.mark();
     let lhs = parser.lex.next();
-    let mut lhs = match is_op(&lhs) {
-        /* not an operator */
-        false => Node::new(NodeType::Atom(lhs), None),
-
-        /* operator */
-        true => match &lhs.kind {
-            TokenKind::LPAREN => {
-                let lhs = expression(parser, 0);
-                assert_eq!(parser.lex.next().kind, TokenKind::RPAREN);
-                lhs
-            },
-            TokenKind::LCURLY => {
-                parser.reset(start);
-                NodeKind::BlockExpr.parse(parser).unwrap()
-            },
-            TokenKind::IF => {
-                parser.reset(start);
-                NodeKind::IfStmt.parse(parser).unwrap()
-            },
-            _ => {
-                let ((), r_bp) = prefix_bp(&lhs);
-                let rhs = expression(parser, r_bp);
-                Node::new(NodeType::Atom(lhs), Some(vec![rhs]))
-            }
+    let mut lhs = match &lhs.kind {
+        TokenKind::NUMBER
+        | TokenKind::ID
+        | TokenKind::TRUE
+        | TokenKind::FALSE => Node::new(NodeType::Atom(lhs), None),
+
+        /* Parentheses are kept in a Primary node, as the packrat parser does */
+        TokenKind::LPAREN => {
+            let inner = expression(parser, 0)?;
+            let rparen = parser.expect(TokenKind::RPAREN)?;
+            let children = vec![
+                Node::new(NodeType::Atom(lhs), None),
+                inner,
+                Node::new(NodeType::Atom(rparen), None),
+            ];
+            Node::new(NodeType::Cons(NodeKind::Primary), Some(children))
+        },
+        TokenKind::STRING => {
+            parser.reset(start);
+            NodeKind::Template.parse(parser)?
         },
+        TokenKind::LBRACE => {
+            parser.reset(start);
+            NodeKind::List.parse(parser)?
+        },
+        TokenKind::LCURLY => {
+            parser.reset(start);
+            NodeKind::Block.parse(parser)?
+        },
+        TokenKind::IF => {
+            parser.reset(start);
+            NodeKind::IfStmt.parse(parser)?
+        },
+        _ => {
+            let Some(((), r_bp)) = prefix_bp(lhs.kind) else {
+                parser.reset(start);
+                parser.fail(EXPRESSION_START);
+                return None;
+            };
+            let rhs = expression(parser, r_bp)?;
+            Node::new(NodeType::Atom(lhs), Some(vec![rhs]))
+        }
     };
 
     loop {
         let op = parser.lex.peek();
-        if !is_op(&op) {
-            if op.kind == TokenKind::EOF { break; }
-            panic!("{:?} atom found at {:?}", &op, &op.position)
+        if op.kind == TokenKind::LPAREN || op.kind == TokenKind::LBRACE {
+            if POSTFIX_BP < min_bp {
+                break;
+            }
+            lhs = match op.kind {
+                TokenKind::LPAREN => call(parser, lhs)?,
+                _ => index(parser, lhs)?,
+            };
+            continue;
         }
-
-        if let Some((l_bp, r_bp)) = infix_bp(&op) {
+        if let Some((l_bp, r_bp)) = infix_bp(op.kind) {
             if l_bp < min_bp {
                 break;
             }
 
             parser.lex.next();
-            let rhs = expression(parser, r_bp);
+            let rhs = expression(parser, r_bp)?;
             lhs = Node::new(NodeType::Atom(op), Some(vec![lhs, rhs]));
             continue;
         }
+        parser.fail(BINARY_OPERATORS);
+        parser.fail(&[TokenKind::LPAREN, TokenKind::LBRACE]);
         break;
     }
-    lhs
+    Some(lhs)
+}
+
+/* Builds the same Index node as the packrat index rule, the index being
+ * a range when slicing */
+fn index(parser: &mut Parser, indexed: Node) -> Option<Node> {
+    let lbrace = parser.lex.next();
+    let start = parser.mark();
+    let index = match NodeKind::Range.parse(parser) {
+        Some(range) => range,
+        None => {
+            parser.reset(start);
+            expression(parser, 0)?
+        }
+    };
+    let rbrace = parser.expect(TokenKind::RBRACE)?;
+    let children = vec![
+        indexed,
+        Node::new(NodeType::Atom(lbrace), None),
+        index,
+        Node::new(NodeType::Atom(rbrace), None),
+    ];
+    Some(Node::new(NodeType::Cons(NodeKind::Index), Some(children)))
+}
+
+/* Builds the same Call node as the packrat call rule */
+fn call(parser: &mut Parser, callee: Node) -> Option<Node> {
+    let lparen = parser.lex.next();
+    let mut children = vec![callee, Node::new(NodeType::Atom(lparen), None)];
+    if parser.lex.peek().kind != TokenKind::RPAREN {
+        children.push(NodeKind::Arguments.parse(parser)?);
+    }
+    let rparen = parser.expect(TokenKind::RPAREN)?;
+    children.push(Node::new(NodeType::Atom(rparen), None));
+    Some(Node::new(NodeType::Cons(NodeKind::Call), Some(children)))
 }
 
-fn prefix_bp(tok: &Token) -> ((), u8) {
-    match tok.kind {
-        TokenKind::BOOL_NOT => ((), 5),
+pub fn prefix_bp(kind: TokenKind) -> Option<((), u8)> {
+    match kind {
+        TokenKind::BOOL_NOT => Some(((), 5)),
         TokenKind::PLUS
         | TokenKind::BIT_NOT
-        | TokenKind::MINUS => ((), 21),
+        | TokenKind::MINUS => Some(((), 21)),
 
-        _ => panic!("bad token {:?}", tok),
+        _ => None,
     }
 }
 
-fn infix_bp(tok: &Token) -> Option<(u8, u8)> {
-    match tok.kind {
+pub fn is_binary_operator(kind: TokenKind) -> bool {
+    BINARY_OPERATORS.contains(&kind)
+}
+
+pub fn infix_bp(kind: TokenKind) -> Option<(u8, u8)> {
+    match kind {
         TokenKind::BOOL_OR => Some((1,2)),
         TokenKind::BOOL_AND => Some((3,4)),
         TokenKind::EQ
@@ -98,17 +210,3 @@ fn infix_bp(tok: &Token) -> Option<(u8, u8)> {
     }
 }
 
-
-fn is_op(tok: &Token) -> bool {
-    match tok.kind {
-        TokenKind::EOF
-        | TokenKind::INT
-        | TokenKind::BOOL
-        | TokenKind::STR
-        | TokenKind::TRUE
-        | TokenKind::FALSE
-        | TokenKind::ID
-        | TokenKind::NUMBER => false,
-        _ => true,
-    }
-}
@@ -0,0 +1,2 @@
+#[allow(clippy::module_inception, reason = "the read-eval-print loop, named like the other stages")]
+pub mod repl;
@@ -0,0 +1,369 @@
+use crate::ast::ast::{Program, Stmt};
+use crate::ast::lower::lower;
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::diagnostics::emitter::Emitter;
+use crate::dump::sexpr;
+use crate::interp::interp::Interpreter;
+use crate::interp::value::Value;
+use crate::lexer::lex::Lexer;
+use crate::parser::node::Node;
+use crate::parser::parser::Parser;
+use crate::sema::check::Checker;
+use crate::source::map::SourceMap;
+use std::io::{self, BufRead, Write};
+
+pub const PROMPT: &str = ">> ";
+/* Shown while an entry is still open, like a `{` without its `}` */
+pub const CONTINUE: &str = ".. ";
+
+const HELP: &str = "\
+Enter statements or expressions; bindings and functions stay defined.
+An expression does not need its closing `;`, and an unfinished entry
+carries on to the next line, or ends at an empty one.
+
+:type EXPR     the type of an expression, without running it
+:ast EXPR      the syntax tree of an expression after lowering
+:tokens TEXT   the tokens of some text
+:help          this message
+:quit          leave, as does end of input
+";
+
+/* What became of a line of input */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Reply {
+    /* The entry is unfinished, so the next line continues it */
+    More,
+    /* The entry was handled, with this to show for it */
+    Done(String),
+    Quit,
+}
+
+/* An entry that could be parsed, or what to show instead */
+enum Parsed {
+    Tree(Node),
+    More,
+    Failed(String),
+}
+
+/* A read-eval-print loop over a checker and an interpreter that live as
+ * long as it does. An entry that fails, whether it does not check or
+ * stops with a runtime error, is undone so that none of its bindings are
+ * left half defined. Deep recursion needs a large stack, see
+ * `on_large_stack`. */
+pub struct Repl {
+    pratt: bool,
+    sources: SourceMap,
+    checker: Checker,
+    interpreter: Interpreter,
+    /* Every entry that ran, to check again when the checker is rebuilt */
+    history: Vec<Program>,
+    /* The lines of an unfinished entry */
+    buffer: String,
+    entries: usize,
+}
+
+impl Repl {
+    pub fn new(pratt: bool) -> Repl {
+        Repl {
+            pratt,
+            sources: SourceMap::new(),
+            checker: Checker::new(),
+            interpreter: Interpreter::capturing(),
+            history: vec![],
+            buffer: String::new(),
+            entries: 0,
+        }
+    }
+
+    pub fn is_continuing(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    pub fn line(&mut self, line: &str) -> Reply {
+        let line = line.trim_end_matches(['\n', '\r']);
+        if !self.is_continuing() {
+            if let Some(command) = line.trim_start().strip_prefix(':') {
+                return self.command(command);
+            }
+            if line.trim().is_empty() {
+                return Reply::Done(String::new());
+            }
+        }
+
+        /* An empty line ends an unfinished entry, errors and all */
+        let force = self.is_continuing() && line.trim().is_empty();
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
+        let source = self.buffer.clone();
+        let tree = match self.parse(&source, force) {
+            Parsed::Tree(tree) => tree,
+            Parsed::More => return Reply::More,
+            Parsed::Failed(errors) => {
+                self.buffer.clear();
+                return Reply::Done(errors);
+            }
+        };
+        self.buffer.clear();
+        Reply::Done(self.eval(&tree))
+    }
+
+    /* Parses an entry. An expression may leave out its `;`, so input that
+     * ends too soon is tried again with one before asking for more. */
+    fn parse(&mut self, source: &str, force: bool) -> Parsed {
+        self.entries += 1;
+        let name = format!("<repl {}>", self.entries);
+        let file = self.sources.add_file(name, source.to_string());
+        let mut parser = Parser::new(Lexer::with_file(source.to_string(), file));
+        if let (Some(tree), false) = (parser.parse(self.pratt), parser.has_errors()) {
+            return Parsed::Tree(tree);
+        }
+        if !parser.incomplete() {
+            return Parsed::Failed(self.render(parser.diagnostics()));
+        }
+
+        /* The `;` goes where the line ends, so the entry is still shown
+         * as it was typed */
+        let closed = format!("{};", source.trim_end());
+        let mut retry = Parser::new(Lexer::with_file(closed, file));
+        match (retry.parse(self.pratt), retry.has_errors()) {
+            (Some(tree), false) => Parsed::Tree(tree),
+            _ if force => Parsed::Failed(self.render(parser.diagnostics())),
+            _ => {
+                self.entries -= 1;
+                Parsed::More
+            }
+        }
+    }
+
+    /* Checks and runs an entry, undoing it if either fails */
+    fn eval(&mut self, tree: &Node) -> String {
+        let program = match lower(tree) {
+            Ok(program) => program,
+            Err(diagnostics) => return self.render(&diagnostics),
+        };
+        self.checker.check_program(&program);
+        let diagnostics = self.checker.take_diagnostics();
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            self.rebuild_checker();
+            return self.render(&diagnostics);
+        }
+        let mut shown = self.render(&diagnostics);
+
+        let snapshot = self.interpreter.save();
+        let result = self.interpreter.run(&program);
+        shown += &self.interpreter.take_output();
+        match result {
+            Ok(value) => {
+                /* A block's value is its tail's, so that has the type */
+                let last = match program.stmts.last() {
+                    Some(Stmt::Expr(expr)) => Some(expr),
+                    Some(Stmt::Block(block)) => block.tail.as_deref(),
+                    _ => None,
+                };
+                let ty = last.and_then(|expr| self.checker.type_of(expr));
+                if let Some(ty) = ty.filter(|_| value != Value::Unit) {
+                    shown += &format!("{}: {}\n", show(&value), ty);
+                }
+                self.history.push(program);
+            }
+            Err(error) => {
+                self.interpreter.restore(snapshot);
+                self.rebuild_checker();
+                shown += &self.render(&[*error]);
+            }
+        }
+        shown
+    }
+
+    /* Forgets the entry that just failed by checking every earlier one
+     * again, which is what the checker knew before it */
+    fn rebuild_checker(&mut self) {
+        self.checker = Checker::new();
+        for program in &self.history {
+            self.checker.check_program(program);
+        }
+        self.checker.take_diagnostics();
+    }
+
+    fn command(&mut self, command: &str) -> Reply {
+        let (name, argument) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let argument = argument.trim();
+        let shown = match name {
+            "q" | "quit" => return Reply::Quit,
+            "h" | "help" => HELP.to_string(),
+            "tokens" => {
+                let file = self
+                    .sources
+                    .add_file("<tokens>".to_string(), argument.to_string());
+                sexpr::tokens(&Lexer::with_file(argument.to_string(), file).tokens())
+            }
+            "type" | "ast" if argument.is_empty() => format!(":{} needs an expression\n", name),
+            "type" => self.expression(argument, |repl, program| {
+                let Some(Stmt::Expr(expr)) = program.stmts.first() else {
+                    unreachable!("`expression` only passes on expressions")
+                };
+                repl.checker.check_program(program);
+                let diagnostics = repl.checker.take_diagnostics();
+                let shown = match repl.checker.type_of(expr) {
+                    Some(ty) if !diagnostics.iter().any(Diagnostic::is_error) => {
+                        format!("{}\n", ty)
+                    }
+                    _ => repl.render(&diagnostics),
+                };
+                /* Only entries that ran are kept, not queries */
+                repl.rebuild_checker();
+                shown
+            }),
+            "ast" => self.expression(argument, |_, program| sexpr::stmt(&program.stmts[0])),
+            _ => format!("unknown command `:{}`, see :help\n", name),
+        };
+        Reply::Done(shown)
+    }
+
+    /* Parses `text` as a single expression and hands it on, lowered */
+    fn expression(
+        &mut self,
+        text: &str,
+        then: impl FnOnce(&mut Repl, &Program) -> String,
+    ) -> String {
+        let tree = match self.parse(text, true) {
+            Parsed::Tree(tree) => tree,
+            Parsed::More => unreachable!("forced parses always finish"),
+            Parsed::Failed(errors) => return errors,
+        };
+        match lower(&tree) {
+            Ok(program)
+                if matches!(program.stmts.as_slice(), [Stmt::Expr(_)])
+                    && program.functions.is_empty() =>
+            {
+                then(self, &program)
+            }
+            Ok(_) => "expected a single expression\n".to_string(),
+            Err(diagnostics) => self.render(&diagnostics),
+        }
+    }
+
+    fn render(&self, diagnostics: &[Diagnostic]) -> String {
+        let emitter = Emitter::new(&self.sources);
+        diagnostics
+            .iter()
+            .map(|diagnostic| emitter.render(diagnostic))
+            .collect()
+    }
+}
+
+/* Like the value's own text, but with strings quoted so `"1"` and `1`
+ * look different */
+fn show(value: &Value) -> String {
+    match value {
+        Value::Str(text) => format!("{:?}", text),
+        value => value.to_string(),
+    }
+}
+
+/* Reads lines from `input` until it ends or `:quit`, with prompts and
+ * replies written to `out` */
+pub fn run(repl: &mut Repl, input: &mut dyn BufRead, out: &mut dyn Write) -> io::Result<()> {
+    loop {
+        write!(
+            out,
+            "{}",
+            if repl.is_continuing() {
+                CONTINUE
+            } else {
+                PROMPT
+            }
+        )?;
+        out.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(out)?;
+            return Ok(());
+        }
+        match repl.line(&line) {
+            Reply::More => {}
+            Reply::Done(shown) => write!(out, "{}", shown)?,
+            Reply::Quit => return Ok(()),
+        }
+    }
+}
+
+/* The output of a line that finishes an entry */
+#[cfg(test)]
+fn done(repl: &mut Repl, line: &str) -> String {
+    match repl.line(line) {
+        Reply::Done(shown) => shown,
+        reply => panic!("{:?} for {:?}", reply, line),
+    }
+}
+
+#[test]
+fn keeps_bindings_between_entries() {
+    let mut repl = Repl::new(false);
+    assert_eq!(done(&mut repl, "let a: int = 20;"), "");
+    assert_eq!(done(&mut repl, "a + 1"), "21: int\n");
+    assert_eq!(done(&mut repl, "\"a\""), "\"a\": str\n");
+}
+
+#[test]
+fn answers_commands() {
+    let mut repl = Repl::new(false);
+    done(&mut repl, "let a: int = 20;");
+    assert_eq!(done(&mut repl, ":type a > 1"), "bool\n");
+    assert_eq!(
+        done(&mut repl, ":ast -a"),
+        "(Expr 0..2\n  (Unary - 0..2\n    (Ident \"a\" 1..2)))\n"
+    );
+    assert_eq!(
+        done(&mut repl, ":tokens a+"),
+        "ID \"a\" 0..1\nPLUS \"+\" 1..2\nEOF 2..2\n"
+    );
+    assert_eq!(repl.line(":quit"), Reply::Quit);
+}
+
+#[test]
+fn failed_entries_leave_nothing_behind() {
+    let mut repl = Repl::new(false);
+    /* `b` is not half defined */
+    assert!(done(&mut repl, "let b: int = 1 / 0;").contains("divide by zero"));
+    assert!(done(&mut repl, "b").contains("E0005"));
+    assert!(done(&mut repl, "let c: int = true;").contains("E0006"));
+    assert!(done(&mut repl, "c").contains("E0005"));
+}
+
+#[test]
+fn continues_unfinished_entries() {
+    let mut repl = Repl::new(false);
+    done(&mut repl, "let a: int = 20;");
+    assert_eq!(repl.line("def double(x: int) -> int {"), Reply::More);
+    assert!(repl.is_continuing());
+    assert_eq!(repl.line("    x * 2"), Reply::More);
+    assert_eq!(repl.line("}"), Reply::Done(String::new()));
+    assert_eq!(repl.line("print(double(a)); [a,"), Reply::More);
+    assert_eq!(
+        repl.line("a]"),
+        Reply::Done("40\n[20, 20]: [int]\n".to_string())
+    );
+    /* An empty line gives up on the entry */
+    assert_eq!(repl.line("(1 +"), Reply::More);
+    assert!(matches!(repl.line(""), Reply::Done(errors) if errors.contains("E0003")));
+    assert!(!repl.is_continuing());
+}
+
+#[test]
+fn forgets_what_queries_declare() {
+    let mut repl = Repl::new(false);
+    done(&mut repl, "let a: int = 20;");
+    let symbols = repl.checker.symbols().symbols().count();
+    assert_eq!(done(&mut repl, ":type 1 + { let q: int = a; q }"), "int\n");
+    assert_eq!(repl.checker.symbols().symbols().count(), symbols);
+}
+
+#[test]
+fn shows_entries_as_they_were_typed() {
+    let mut repl = Repl::new(false);
+    let error = done(&mut repl, "1 / 0");
+    assert!(error.contains("1 | 1 / 0\n"), "{}", error);
+}
@@ -0,0 +1,28 @@
+use std::fmt;
+
+/* Functions provided by the language rather than defined in the program.
+ * They live in the global scope, so a program cannot redefine them. */
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Builtin {
+    /* `print(value)` writes any value followed by a newline */
+    Print,
+    /* `len(list)` is the number of elements in a list */
+    Len,
+}
+
+impl Builtin {
+    pub const ALL: &'static [Builtin] = &[Builtin::Print, Builtin::Len];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Print => "print",
+            Builtin::Len => "len",
+        }
+    }
+}
+
+impl fmt::Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
@@ -0,0 +1,648 @@
+use super::builtins::Builtin;
+use super::resolve::{Resolver, SymbolId, SymbolKind, SymbolTable};
+use super::types::Ty;
+use crate::ast::ast::{BinOp, Block, Expr, Function, Ident, Literal, Program, Stmt, UnOp};
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::source::span::Span;
+use std::collections::HashMap;
+use std::mem::{self, Discriminant};
+
+/* Expressions made up while lowering can share a span with the one they
+ * came from, so the kind of expression is part of the key */
+type TypeKey = (Span, Discriminant<Expr>);
+
+/* Resolves names, then infers the type of every expression and reports type
+ * mismatches. The global scope outlives `check_program`, so a checker can
+ * be fed a program piece by piece. */
+pub struct Checker {
+    resolver: Resolver,
+    types: HashMap<TypeKey, Ty>,
+    /* The types of `for` loop variables, inferred from what they iterate over */
+    loop_variables: HashMap<SymbolId, Ty>,
+    /* The return type of the function being checked */
+    returns: Option<Ty>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Default for Checker {
+    fn default() -> Checker {
+        Checker::new()
+    }
+}
+
+impl Checker {
+    pub fn new() -> Checker {
+        Checker {
+            resolver: Resolver::new(),
+            types: HashMap::new(),
+            loop_variables: HashMap::new(),
+            returns: None,
+            diagnostics: vec![],
+        }
+    }
+
+    pub fn check_program(&mut self, program: &Program) {
+        self.resolver.resolve_program(program);
+        self.diagnostics.extend(self.resolver.take_diagnostics());
+        for function in &program.functions {
+            self.check_function(function);
+        }
+        for stmt in &program.stmts {
+            self.check_stmt(stmt);
+        }
+    }
+
+    /* The type inferred for `expr` */
+    pub fn type_of(&self, expr: &Expr) -> Option<&Ty> {
+        self.types.get(&(expr.span(), mem::discriminant(expr)))
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        self.resolver.table()
+    }
+
+    /* Every inferred type, along with the span of its expression */
+    pub fn types(&self) -> impl Iterator<Item = (Span, &Ty)> {
+        self.types.iter().map(|((span, _), ty)| (*span, ty))
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    fn check_function(&mut self, function: &Function) {
+        let returns = function.ret.as_ref().map_or(Ty::Unit, Ty::from);
+        self.returns = Some(returns.clone());
+        let found = self.check_block(&function.body);
+        let span = function
+            .body
+            .tail
+            .as_ref()
+            .map_or(function.body.span, |tail| tail.span());
+        self.expect(&returns, &found, span, Some(function.ret_span));
+        self.returns = None;
+    }
+
+    /* Names in `stmt` must have been resolved by `check_program`. Returns
+     * whether control never makes it past the statement. */
+    fn check_stmt(&mut self, stmt: &Stmt) -> bool {
+        let ty = match stmt {
+            Stmt::Let {
+                ty, ty_span, value, ..
+            } => {
+                let found = self.check_expr(value);
+                self.expect(&Ty::from(ty), &found, value.span(), Some(*ty_span));
+                found
+            }
+            Stmt::Assign { target, value, .. } => {
+                let found = self.check_expr(value);
+                let expected = self.type_of_name(target);
+                self.expect(&expected, &found, value.span(), None);
+                found
+            }
+            Stmt::IndexAssign {
+                list, index, value, ..
+            } => {
+                let found = self.check_expr(value);
+                let expected = self.check_index(list, index, true);
+                self.expect(&expected, &found, value.span(), None);
+                found
+            }
+            Stmt::While { cond, body, .. } => {
+                self.check_condition(cond);
+                self.check_block(body);
+                Ty::Unit
+            }
+            Stmt::For {
+                var, iter, body, ..
+            } => {
+                let item = self.check_iterable(iter);
+                if let Some(symbol) = self.resolver.table().resolution(var.span) {
+                    self.loop_variables.insert(symbol, item);
+                }
+                self.check_block(body);
+                Ty::Unit
+            }
+            Stmt::Return { value, span } => {
+                let found = match value {
+                    Some(value) => self.check_expr(value),
+                    None => Ty::Unit,
+                };
+                /* `return` outside of a function is reported by the resolver */
+                if let Some(returns) = self.returns.clone() {
+                    let span = value.as_ref().map_or(*span, Expr::span);
+                    self.expect(&returns, &found, span, None);
+                }
+                Ty::Never
+            }
+            Stmt::Block(block) => self.check_block(block),
+            Stmt::Expr(expr) => self.check_expr(expr),
+        };
+        ty == Ty::Never
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Ty {
+        let ty = match expr {
+            Expr::Literal { value, .. } => match value {
+                Literal::Int(_) => Ty::Int,
+                Literal::Bool(_) => Ty::Bool,
+                Literal::Str(_) => Ty::Str,
+            },
+            Expr::Ident(ident) => self.type_of_name(ident),
+            Expr::Call { callee, args, .. } => self.check_call(callee, args),
+            Expr::Unary {
+                op,
+                op_span,
+                operand,
+                ..
+            } => self.check_unary(*op, *op_span, operand),
+            Expr::Binary {
+                op,
+                op_span,
+                lhs,
+                rhs,
+                ..
+            } => self.check_binary(*op, *op_span, lhs, rhs),
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.check_condition(cond);
+                let then_ty = self.check_block(then_branch);
+                match else_branch {
+                    Some(else_branch) => {
+                        let else_ty = self.check_expr(else_branch);
+                        self.unify_branches(then_ty, then_branch, else_ty, else_branch)
+                    }
+                    None => Ty::Unit,
+                }
+            }
+            Expr::Index { list, index, .. } => self.check_index(list, index, false),
+            Expr::List { elements, .. } => {
+                let mut element = Ty::Never;
+                for expr in elements {
+                    let found = self.check_expr(expr);
+                    match element.join(&found) {
+                        Some(joined) => element = joined,
+                        None => self.expect(&element, &found, expr.span(), None),
+                    }
+                }
+                Ty::List(Box::new(element))
+            }
+            Expr::Template { parts, .. } => {
+                for part in parts {
+                    let found = self.check_expr(part);
+                    if found == Ty::Unit {
+                        self.diagnostics.push(
+                            Diagnostic::error("`()` cannot be interpolated", part.span())
+                                .with_code("E0022")
+                                .with_label(part.span(), "this has no value"),
+                        );
+                    }
+                }
+                Ty::Str
+            }
+            Expr::Range { start, end, .. } => {
+                for bound in [start, end] {
+                    let found = self.check_expr(bound);
+                    self.expect(&Ty::Int, &found, bound.span(), None);
+                }
+                Ty::Range
+            }
+            Expr::Block(block) => self.check_block(block),
+        };
+        let key = (expr.span(), mem::discriminant(expr));
+        self.types.insert(key, ty.clone());
+        ty
+    }
+
+    fn check_block(&mut self, block: &Block) -> Ty {
+        let mut diverges = false;
+        for stmt in &block.stmts {
+            diverges |= self.check_stmt(stmt);
+        }
+        match &block.tail {
+            Some(tail) => self.check_expr(tail),
+            None if diverges => Ty::Never,
+            None => Ty::Unit,
+        }
+    }
+
+    fn check_call(&mut self, callee: &Ident, args: &[Expr]) -> Ty {
+        let found: Vec<Ty> = args.iter().map(|arg| self.check_expr(arg)).collect();
+        let symbols = self.resolver.table();
+        let Some(id) = symbols.resolution(callee.span) else {
+            return Ty::Error;
+        };
+        let symbol = symbols.symbol(id);
+
+        let (params, returns) = match &symbol.kind {
+            SymbolKind::Function { params, ret } => (
+                params.iter().map(Ty::from).collect::<Vec<Ty>>(),
+                ret.as_ref().map_or(Ty::Unit, Ty::from),
+            ),
+            /* `print` takes a single value of any type */
+            SymbolKind::Builtin(Builtin::Print) => {
+                let any = found.first().cloned().unwrap_or(Ty::Error);
+                (vec![any], Ty::Unit)
+            }
+            /* `len` takes a string or a list of any type */
+            SymbolKind::Builtin(Builtin::Len) => {
+                let list = match (found.first(), args.first()) {
+                    (Some(ty @ (Ty::Str | Ty::List(_) | Ty::Error)), _) => ty.clone(),
+                    (Some(ty), Some(arg)) => {
+                        self.diagnostics.push(
+                            Diagnostic::error("mismatched types", arg.span())
+                                .with_code("E0006")
+                                .with_label(
+                                    arg.span(),
+                                    format!("expected a string or a list, found `{}`", ty),
+                                ),
+                        );
+                        Ty::Error
+                    }
+                    _ => Ty::Error,
+                };
+                (vec![list], Ty::Int)
+            }
+            kind @ (SymbolKind::Variable(_)
+            | SymbolKind::Parameter(_)
+            | SymbolKind::LoopVariable) => {
+                let ty = match kind {
+                    SymbolKind::Variable(ty) | SymbolKind::Parameter(ty) => Ty::from(ty),
+                    _ => self.loop_variables.get(&id).cloned().unwrap_or(Ty::Error),
+                };
+                let diagnostic =
+                    Diagnostic::error(format!("`{}` is not a function", callee.name), callee.span)
+                        .with_code("E0015")
+                        .with_label(
+                            callee.span,
+                            format!("`{}` is of type `{}`", callee.name, ty),
+                        )
+                        .with_label(symbol.span, format!("`{}` is declared here", callee.name));
+                self.diagnostics.push(diagnostic);
+                return Ty::Error;
+            }
+        };
+
+        if params.len() != found.len() {
+            let plural = |n: usize| if n == 1 { "" } else { "s" };
+            let mut diagnostic = Diagnostic::error(
+                format!(
+                    "`{}` takes {} argument{} but {} {} supplied",
+                    callee.name,
+                    params.len(),
+                    plural(params.len()),
+                    found.len(),
+                    if found.len() == 1 { "was" } else { "were" }
+                ),
+                callee.span,
+            )
+            .with_code("E0016")
+            .with_label(
+                callee.span,
+                format!("expected {} argument{}", params.len(), plural(params.len())),
+            );
+            if !symbol.span.is_empty() {
+                diagnostic = diagnostic.with_label(symbol.span, "function defined here");
+            }
+            self.diagnostics.push(diagnostic);
+            return returns;
+        }
+        for ((expected, found), arg) in params.iter().zip(&found).zip(args) {
+            self.expect(expected, found, arg.span(), None);
+        }
+        returns
+    }
+
+    /* Returns the type of the element at `list[index]`, or of the slice
+     * `list[start..end]`. Strings can be indexed, each character being a
+     * string, but not assigned to. */
+    fn check_index(&mut self, list: &Expr, index: &Expr, assign: bool) -> Ty {
+        let found = self.check_expr(list);
+        let index_ty = self.check_expr(index);
+        let slice = index_ty == Ty::Range;
+        if !slice {
+            self.expect(&Ty::Int, &index_ty, index.span(), None);
+        }
+        if slice && assign {
+            self.diagnostics.push(
+                Diagnostic::error("cannot assign to a slice", index.span())
+                    .with_code("E0019")
+                    .with_label(index.span(), "assign to the elements one at a time instead"),
+            );
+            return Ty::Error;
+        }
+        let message = match found {
+            Ty::List(_) | Ty::Str if slice => return found,
+            Ty::List(element) => return *element,
+            Ty::Str if !assign => return Ty::Str,
+            Ty::Error => return Ty::Error,
+            Ty::Str => "cannot assign to a character of a `str`".to_string(),
+            _ => format!("cannot index into a value of type `{}`", found),
+        };
+        self.diagnostics.push(
+            Diagnostic::error(message, list.span())
+                .with_code("E0020")
+                .with_label(list.span(), format!("this is of type `{}`", found)),
+        );
+        Ty::Error
+    }
+
+    /* Returns the type of the items `iter` yields */
+    fn check_iterable(&mut self, iter: &Expr) -> Ty {
+        let found = self.check_expr(iter);
+        if let Some(item) = found.item() {
+            return item;
+        }
+        self.diagnostics.push(
+            Diagnostic::error(format!("`{}` is not iterable", found), iter.span())
+                .with_code("E0018")
+                .with_label(iter.span(), format!("this is of type `{}`", found))
+                .with_help("`for` loops iterate over ranges such as `0..n` and strings"),
+        );
+        Ty::Error
+    }
+
+    fn check_condition(&mut self, cond: &Expr) {
+        let found = self.check_expr(cond);
+        self.expect(&Ty::Bool, &found, cond.span(), None);
+    }
+
+    fn check_unary(&mut self, op: UnOp, op_span: Span, operand: &Expr) -> Ty {
+        let found = self.check_expr(operand);
+        let expected = match op {
+            UnOp::Not => Ty::Bool,
+            UnOp::BitNot | UnOp::Neg | UnOp::Plus => Ty::Int,
+        };
+        if !expected.accepts(&found) {
+            self.diagnostics.push(
+                Diagnostic::error(
+                    format!("cannot apply unary operator `{}` to `{}`", op, found),
+                    op_span,
+                )
+                .with_code("E0008")
+                .with_label(operand.span(), format!("this is of type `{}`", found))
+                .with_help(format!("`{}` can only be applied to `{}`", op, expected)),
+            );
+        }
+        expected
+    }
+
+    fn check_binary(&mut self, op: BinOp, op_span: Span, lhs: &Expr, rhs: &Expr) -> Ty {
+        let left = self.check_expr(lhs);
+        let right = self.check_expr(rhs);
+
+        let (operands_ok, result) = match op {
+            /* `+` also concatenates strings */
+            BinOp::Add if left == Ty::Str || right == Ty::Str => {
+                (left == Ty::Str && right == Ty::Str, Ty::Str)
+            }
+            BinOp::Add
+            | BinOp::Sub
+            | BinOp::Mul
+            | BinOp::Div
+            | BinOp::Mod
+            | BinOp::BitOr
+            | BinOp::BitXor
+            | BinOp::BitAnd
+            | BinOp::Shl
+            | BinOp::Shr => (left == Ty::Int && right == Ty::Int, Ty::Int),
+            BinOp::And | BinOp::Or => (left == Ty::Bool && right == Ty::Bool, Ty::Bool),
+            BinOp::Eq | BinOp::Ne => (left.join(&right).is_some() && left != Ty::Unit, Ty::Bool),
+            /* Strings compare by their characters */
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+                let ordered = matches!(left, Ty::Int | Ty::Str);
+                (ordered && left == right, Ty::Bool)
+            }
+        };
+
+        if !operands_ok && !left.is_error() && !right.is_error() {
+            self.diagnostics.push(
+                Diagnostic::error(
+                    format!("cannot apply `{}` to `{}` and `{}`", op, left, right),
+                    op_span,
+                )
+                .with_code("E0007")
+                .with_label(lhs.span(), format!("`{}`", left))
+                .with_label(rhs.span(), format!("`{}`", right)),
+            );
+        }
+        result
+    }
+
+    fn unify_branches(
+        &mut self,
+        then_ty: Ty,
+        then_branch: &Block,
+        else_ty: Ty,
+        else_branch: &Expr,
+    ) -> Ty {
+        if then_ty.accepts(&else_ty) {
+            return match then_ty {
+                Ty::Error | Ty::Never => else_ty,
+                _ => then_ty,
+            };
+        }
+
+        let then_span = then_branch
+            .tail
+            .as_ref()
+            .map_or(then_branch.span, |tail| tail.span());
+        let else_span = match else_branch {
+            Expr::Block(block) => block.tail.as_ref().map_or(block.span, |tail| tail.span()),
+            _ => else_branch.span(),
+        };
+        self.diagnostics.push(
+            Diagnostic::error("`if` and `else` have incompatible types", else_span)
+                .with_code("E0009")
+                .with_label(
+                    else_span,
+                    format!("expected `{}`, found `{}`", then_ty, else_ty),
+                )
+                .with_label(then_span, format!("this is of type `{}`", then_ty)),
+        );
+        Ty::Error
+    }
+
+    /* Reports a mismatch if `found` cannot be used where `expected` is.
+     * `reason` points at whatever made the checker expect that type. */
+    fn expect(&mut self, expected: &Ty, found: &Ty, span: Span, reason: Option<Span>) {
+        if expected.accepts(found) {
+            return;
+        }
+        let mut diagnostic = Diagnostic::error("mismatched types", span)
+            .with_code("E0006")
+            .with_label(span, format!("expected `{}`, found `{}`", expected, found));
+        if let Some(reason) = reason {
+            diagnostic = diagnostic.with_label(reason, "expected due to this");
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    /* Unresolved names have already been reported by the resolver */
+    fn type_of_name(&mut self, ident: &Ident) -> Ty {
+        let symbols = self.resolver.table();
+        let Some(id) = symbols.resolution(ident.span) else {
+            return Ty::Error;
+        };
+        let symbol = symbols.symbol(id);
+        if let Some(ty) = symbol.ty() {
+            return Ty::from(ty);
+        }
+        if symbol.kind == SymbolKind::LoopVariable {
+            return self.loop_variables.get(&id).cloned().unwrap_or(Ty::Error);
+        }
+        let diagnostic = Diagnostic::error(
+            format!("expected a value, found function `{}`", ident.name),
+            ident.span,
+        )
+        .with_code("E0015")
+        .with_label(ident.span, "not a value")
+        .with_help(format!("call it with `{}(...)`", ident.name));
+        self.diagnostics.push(diagnostic);
+        Ty::Error
+    }
+}
+
+#[cfg(test)]
+fn check(input: &str) -> Vec<(&'static str, &str)> {
+    let (_, checker) = crate::testing::pipeline::check(input);
+    crate::testing::pipeline::codes(input, checker.diagnostics())
+}
+
+#[test]
+fn reports_mismatched_types() {
+    assert_eq!(check("let a: bool = 1 + 2;"), vec![("E0006", "1 + 2")]);
+    assert_eq!(check("while 1 { let a: int = 1; }"), vec![("E0006", "1")]);
+}
+
+#[test]
+fn reports_operators_on_the_wrong_types() {
+    assert_eq!(check("let a: int = 1 + true;"), vec![("E0007", "+")]);
+    assert_eq!(
+        check("let a: bool = !1; let b: int = -(1 < 2);"),
+        vec![("E0008", "!"), ("E0008", "-")]
+    );
+}
+
+#[test]
+fn reports_incompatible_branches() {
+    assert_eq!(
+        check("let a: int = if 1 == 1 { 1 } else { true };"),
+        vec![("E0009", "true")]
+    );
+}
+
+#[test]
+fn reports_names_out_of_scope() {
+    assert_eq!(
+        check("let a: int = { let b: int = 1; b }; b = 2;"),
+        vec![("E0011", "b")]
+    );
+}
+
+#[test]
+fn checks_calls_and_returns() {
+    assert_eq!(
+        check("def f(a: int) -> bool { if a > 0 { return 1; } true } let b: bool = f(1, 2);"),
+        vec![("E0006", "1"), ("E0016", "f")]
+    );
+    assert_eq!(
+        check("def f() -> int { let a: int = 1; } print(f);"),
+        vec![("E0006", "{ let a: int = 1; }"), ("E0015", "f")]
+    );
+    assert!(check(
+        "def f(a: int) -> int { if a > 0 { return 1; } else { return 2; } } print(f(1));"
+    )
+    .is_empty());
+}
+
+#[test]
+fn checks_for_loops() {
+    assert_eq!(
+        check("for i in 0..true { let s: str = i; } for c in \"ab\" { c = 1; } for x in 3 {}"),
+        vec![
+            ("E0006", "true"),
+            ("E0006", "i"),
+            ("E0006", "1"),
+            ("E0018", "3")
+        ]
+    );
+}
+
+#[test]
+fn checks_lists_and_indexing() {
+    assert_eq!(
+        check(
+            "let xs: [int] = [1, true]; xs[true] = \"a\"; let s: str = \"ab\"; s[0] = s[1]; 1[0];"
+        ),
+        vec![
+            ("E0006", "true"),
+            ("E0006", "true"),
+            ("E0006", "\"a\""),
+            ("E0020", "s"),
+            ("E0020", "1")
+        ]
+    );
+    assert!(check(
+        "let xs: [[int]] = [[], [1]]; let ys: [bool] = []; let n: int = len(xs[1]) + xs[0][0];"
+    )
+    .is_empty());
+}
+
+#[test]
+fn checks_strings_and_interpolation() {
+    assert_eq!(
+        check(
+            "def f() {} let s: str = \"{f()} {1}\" + 1; let b: bool = s < \"a\" && s[0..1] == 2;"
+        ),
+        vec![("E0022", "f()"), ("E0007", "+"), ("E0007", "==")]
+    );
+    assert!(check(
+        "let s: str = \"x\"; let t: str = \"{s}{len(s) * 2} {[s]}\" + s[0..len(s)] + s[0];"
+    )
+    .is_empty());
+}
+
+#[test]
+fn accepts_well_typed_operators() {
+    assert!(
+        check("let s: str = \"a\"; let b: bool = s == \"b\" && (1 << 2 | 3) >= 4 || !true;")
+            .is_empty()
+    );
+}
+
+#[test]
+fn keeps_types_of_expressions_sharing_a_span() {
+    use crate::ast::ast::BinOp;
+    use crate::source::span::FileId;
+
+    /* As if lowering turned `1 == 2` into a comparison spanning both */
+    let span = Span::new(FileId::default(), 0, 6);
+    let operand = |value| Expr::Literal {
+        value: Literal::Int(value),
+        span,
+    };
+    let expr = Expr::Binary {
+        op: BinOp::Eq,
+        op_span: span,
+        lhs: Box::new(operand(1)),
+        rhs: Box::new(operand(2)),
+        span,
+    };
+    let mut checker = Checker::new();
+    assert_eq!(checker.check_expr(&expr), Ty::Bool);
+    assert_eq!(checker.type_of(&expr), Some(&Ty::Bool));
+    assert_eq!(checker.type_of(&operand(1)), Some(&Ty::Int));
+}
@@ -0,0 +1,4 @@
+pub mod builtins;
+pub mod check;
+pub mod resolve;
+pub mod types;
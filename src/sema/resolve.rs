@@ -0,0 +1,701 @@
+use super::builtins::Builtin;
+use crate::ast::ast::{Block, Expr, Function, Ident, Program, Stmt, Type};
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::source::span::{FileId, Span};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct ScopeId(pub usize);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct SymbolId(pub usize);
+
+/* A lexical scope. Every block gets one, `while` bodies and `if`/`else`
+ * branches included, the parameters of a function get one around its
+ * body, as does the variable of a `for` loop, and the program itself is
+ * the global scope. */
+#[derive(Clone, Debug)]
+pub struct Scope {
+    pub parent: Option<ScopeId>,
+    pub children: Vec<ScopeId>,
+    pub span: Span,
+    pub symbols: Vec<SymbolId>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SymbolKind {
+    Variable(Type),
+    Parameter(Type),
+    /* The variable of a `for` loop, its type comes from what it iterates
+     * over so only the checker knows it */
+    LoopVariable,
+    Function {
+        params: Vec<Type>,
+        ret: Option<Type>,
+    },
+    Builtin(Builtin),
+}
+
+/* Anything with a name. `span` is the name in its declaration, builtins
+ * have an empty one. */
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub span: Span,
+    pub scope: ScopeId,
+}
+
+impl Symbol {
+    /* The type of a variable or parameter */
+    pub fn ty(&self) -> Option<&Type> {
+        match &self.kind {
+            SymbolKind::Variable(ty) | SymbolKind::Parameter(ty) => Some(ty),
+            SymbolKind::LoopVariable | SymbolKind::Function { .. } | SymbolKind::Builtin(_) => None,
+        }
+    }
+
+    pub fn is_callable(&self) -> bool {
+        matches!(
+            self.kind,
+            SymbolKind::Function { .. } | SymbolKind::Builtin(_)
+        )
+    }
+}
+
+/* The result of name resolution: the scope tree, every declared symbol and
+ * the symbol each identifier refers to, keyed by the identifier's span. */
+pub struct SymbolTable {
+    scopes: Vec<Scope>,
+    symbols: Vec<Symbol>,
+    resolutions: HashMap<Span, SymbolId>,
+}
+
+impl Default for SymbolTable {
+    fn default() -> SymbolTable {
+        SymbolTable::new()
+    }
+}
+
+impl SymbolTable {
+    pub const GLOBAL: ScopeId = ScopeId(0);
+
+    pub fn new() -> SymbolTable {
+        let global = Scope {
+            parent: None,
+            children: vec![],
+            span: Span::default(),
+            symbols: vec![],
+        };
+        SymbolTable {
+            scopes: vec![global],
+            symbols: vec![],
+            resolutions: HashMap::new(),
+        }
+    }
+
+    pub fn scope(&self, id: ScopeId) -> &Scope {
+        &self.scopes[id.0]
+    }
+
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0]
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = (SymbolId, &Symbol)> {
+        self.symbols
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| (SymbolId(i), symbol))
+    }
+
+    /* The symbol named by the identifier at `span`, be it a use or the
+     * declaration itself */
+    pub fn resolution(&self, span: Span) -> Option<SymbolId> {
+        self.resolutions.get(&span).copied()
+    }
+
+    /* Spans of every identifier referring to `symbol`, in source order */
+    pub fn references(&self, symbol: SymbolId) -> Vec<Span> {
+        let mut spans: Vec<Span> = self
+            .resolutions
+            .iter()
+            .filter(|(_, id)| **id == symbol)
+            .map(|(span, _)| *span)
+            .collect();
+        spans.sort_by_key(|span| (span.file, span.start));
+        spans
+    }
+
+    /* The innermost scope containing `offset` */
+    pub fn scope_at(&self, file: FileId, offset: usize) -> ScopeId {
+        let mut scope = SymbolTable::GLOBAL;
+        while let Some(child) = self.scope(scope).children.iter().find(|child| {
+            let span = self.scope(**child).span;
+            span.file == file && span.start <= offset && offset < span.end
+        }) {
+            scope = *child;
+        }
+        scope
+    }
+
+    /* The most recent declaration of `name` visible from `scope` */
+    pub fn lookup(&self, scope: ScopeId, name: &str) -> Option<SymbolId> {
+        let mut scope = Some(scope);
+        while let Some(id) = scope {
+            let found = self
+                .scope(id)
+                .symbols
+                .iter()
+                .rev()
+                .find(|symbol| self.symbol(**symbol).name == name);
+            if found.is_some() {
+                return found.copied();
+            }
+            scope = self.scope(id).parent;
+        }
+        None
+    }
+
+    fn add_scope(&mut self, parent: ScopeId, span: Span) -> ScopeId {
+        let id = ScopeId(self.scopes.len());
+        self.scopes.push(Scope {
+            parent: Some(parent),
+            children: vec![],
+            span,
+            symbols: vec![],
+        });
+        self.scopes[parent.0].children.push(id);
+        id
+    }
+
+    fn add_symbol(&mut self, scope: ScopeId, name: &Ident, kind: SymbolKind) -> SymbolId {
+        let id = SymbolId(self.symbols.len());
+        self.symbols.push(Symbol {
+            name: name.name.clone(),
+            kind,
+            span: name.span,
+            scope,
+        });
+        self.scopes[scope.0].symbols.push(id);
+        if !name.span.is_empty() {
+            self.resolutions.insert(name.span, id);
+        }
+        id
+    }
+}
+
+/* A scope being resolved. `bindings` holds the names declared so far and
+ * `pending` the `let`s further down the same block, which are only used to
+ * explain why a name could not be found. Functions cannot see the
+ * variables around them, only other functions, so lookups only look for
+ * those past a frame holding parameters. */
+struct Frame {
+    scope: ScopeId,
+    bindings: HashMap<String, SymbolId>,
+    pending: Vec<Ident>,
+    parameters: bool,
+}
+
+/* Binds every identifier to its declaration. Like the checker, the global
+ * scope outlives `resolve_program` so a program can be resolved piece by
+ * piece. Functions are visible throughout the program they are defined
+ * in, so they can call each other in any order. */
+pub struct Resolver {
+    table: SymbolTable,
+    frames: Vec<Frame>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Default for Resolver {
+    fn default() -> Resolver {
+        Resolver::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        let mut resolver = Resolver {
+            table: SymbolTable::new(),
+            frames: vec![Frame {
+                scope: SymbolTable::GLOBAL,
+                bindings: HashMap::new(),
+                pending: vec![],
+                parameters: false,
+            }],
+            diagnostics: vec![],
+        };
+        for builtin in Builtin::ALL {
+            let name = Ident {
+                name: builtin.name().to_string(),
+                span: Span::default(),
+            };
+            resolver.bind_new(&name, SymbolKind::Builtin(*builtin));
+        }
+        resolver
+    }
+
+    pub fn resolve_program(&mut self, program: &Program) {
+        for function in &program.functions {
+            self.declare_function(function);
+        }
+        self.frames[0].pending = declarations(&program.stmts);
+        for stmt in &program.stmts {
+            self.resolve_stmt(stmt);
+        }
+        self.frames[0].pending.clear();
+
+        /* Last, so that globals used in a function are known to exist */
+        for function in &program.functions {
+            self.resolve_function(function);
+        }
+    }
+
+    pub fn table(&self) -> &SymbolTable {
+        &self.table
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn declare_function(&mut self, function: &Function) {
+        let name = &function.name;
+        if let Some(previous) = self.frames[0].bindings.get(&name.name) {
+            let previous = self.table.symbol(*previous);
+            if previous.is_callable() {
+                let mut diagnostic = Diagnostic::error(
+                    format!("the name `{}` is defined multiple times", name.name),
+                    name.span,
+                )
+                .with_code("E0013")
+                .with_label(name.span, format!("`{}` redefined here", name.name));
+                diagnostic = match previous.kind {
+                    SymbolKind::Builtin(_) => {
+                        diagnostic.with_note(format!("`{}` is a builtin function", name.name))
+                    }
+                    _ => diagnostic.with_label(
+                        previous.span,
+                        format!("previous definition of `{}` here", name.name),
+                    ),
+                };
+                self.diagnostics.push(diagnostic);
+                return;
+            }
+        }
+        let kind = SymbolKind::Function {
+            params: function
+                .params
+                .iter()
+                .map(|param| param.ty.clone())
+                .collect(),
+            ret: function.ret.clone(),
+        };
+        let frame = &mut self.frames[0];
+        let symbol = self.table.add_symbol(frame.scope, name, kind);
+        frame.bindings.insert(name.name.clone(), symbol);
+    }
+
+    fn resolve_function(&mut self, function: &Function) {
+        self.frames.push(Frame {
+            scope: self.table.add_scope(SymbolTable::GLOBAL, function.span),
+            bindings: HashMap::new(),
+            pending: vec![],
+            parameters: true,
+        });
+        for param in &function.params {
+            let frame = self.frames.last().expect("the parameters were just pushed");
+            if let Some(previous) = frame.bindings.get(&param.name.name) {
+                let previous = self.table.symbol(*previous).span;
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        format!("parameter `{}` is declared more than once", param.name.name),
+                        param.name.span,
+                    )
+                    .with_code("E0013")
+                    .with_label(param.name.span, "used as a parameter more than once")
+                    .with_label(previous, "first declared here"),
+                );
+            }
+            self.bind_new(&param.name, SymbolKind::Parameter(param.ty.clone()));
+        }
+        self.resolve_block(&function.body);
+        self.frames.pop();
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let {
+                name, ty, value, ..
+            } => {
+                /* The value is resolved first, `let a: int = a;` refers to an outer `a` */
+                self.resolve_expr(value);
+                self.declare(name, SymbolKind::Variable(ty.clone()));
+            }
+            Stmt::Return { value, span } => {
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+                if !self.frames.iter().any(|frame| frame.parameters) {
+                    self.diagnostics.push(
+                        Diagnostic::error("`return` outside of a function", *span)
+                            .with_code("E0017")
+                            .with_label(*span, "cannot return from the top level"),
+                    );
+                }
+            }
+            Stmt::Assign { target, value, .. } => {
+                self.resolve_expr(value);
+                if !self.bind(target) {
+                    self.undeclared_assignment(target);
+                }
+            }
+            Stmt::IndexAssign {
+                list, index, value, ..
+            } => {
+                self.resolve_expr(value);
+                self.resolve_expr(list);
+                self.resolve_expr(index);
+            }
+            Stmt::While { cond, body, .. } => {
+                self.resolve_expr(cond);
+                self.resolve_block(body);
+            }
+            Stmt::For {
+                var,
+                iter,
+                body,
+                span,
+            } => {
+                self.resolve_expr(iter);
+                self.push_scope(*span);
+                self.declare(var, SymbolKind::LoopVariable);
+                self.resolve_block(body);
+                self.frames.pop();
+            }
+            Stmt::Block(block) => self.resolve_block(block),
+            Stmt::Expr(expr) => self.resolve_expr(expr),
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal { .. } => {}
+            Expr::Ident(ident) => {
+                if !self.bind(ident) {
+                    self.unknown_name(ident);
+                }
+            }
+            Expr::Call { callee, args, .. } => {
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+                /* Lowering leaves the name empty if the callee was not one */
+                if !callee.name.is_empty() && !self.bind(callee) {
+                    self.unknown_function(callee);
+                }
+            }
+            Expr::Unary { operand, .. } => self.resolve_expr(operand),
+            Expr::Binary { lhs, rhs, .. } => {
+                self.resolve_expr(lhs);
+                self.resolve_expr(rhs);
+            }
+            Expr::Index { list, index, .. } => {
+                self.resolve_expr(list);
+                self.resolve_expr(index);
+            }
+            Expr::List { elements, .. }
+            | Expr::Template {
+                parts: elements, ..
+            } => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            Expr::Range { start, end, .. } => {
+                self.resolve_expr(start);
+                self.resolve_expr(end);
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.resolve_expr(cond);
+                self.resolve_block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_expr(else_branch);
+                }
+            }
+            Expr::Block(block) => self.resolve_block(block),
+        }
+    }
+
+    fn resolve_block(&mut self, block: &Block) {
+        self.push_scope(block.span);
+        self.frames
+            .last_mut()
+            .expect("the block's scope was just pushed")
+            .pending = declarations(&block.stmts);
+        for stmt in &block.stmts {
+            self.resolve_stmt(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            self.resolve_expr(tail);
+        }
+        self.frames.pop();
+    }
+
+    /* Opens a scope nested in the current one */
+    fn push_scope(&mut self, span: Span) {
+        let parent = self
+            .frames
+            .last()
+            .expect("there is always a global scope")
+            .scope;
+        self.frames.push(Frame {
+            scope: self.table.add_scope(parent, span),
+            bindings: HashMap::new(),
+            pending: vec![],
+            parameters: false,
+        });
+    }
+
+    fn declare(&mut self, name: &Ident, kind: SymbolKind) {
+        let previous = self
+            .lookup(&name.name)
+            .map(|previous| self.table.symbol(previous));
+        if let Some(previous) = previous.filter(|previous| !previous.span.is_empty()) {
+            let previous = previous.span;
+            self.diagnostics.push(
+                Diagnostic::warning(
+                    format!("`{}` shadows an earlier declaration", name.name),
+                    name.span,
+                )
+                .with_code("W0001")
+                .with_label(name.span, "shadows the earlier declaration")
+                .with_label(previous, format!("`{}` was first declared here", name.name)),
+            );
+        }
+
+        self.bind_new(name, kind);
+    }
+
+    /* Adds a symbol to the innermost scope */
+    fn bind_new(&mut self, name: &Ident, kind: SymbolKind) {
+        let frame = self
+            .frames
+            .last_mut()
+            .expect("there is always a global scope");
+        let symbol = self.table.add_symbol(frame.scope, name, kind);
+        frame.bindings.insert(name.name.clone(), symbol);
+        frame.pending.retain(|pending| pending.span != name.span);
+    }
+
+    /* Records what `ident` refers to, returns false if nothing is in scope */
+    fn bind(&mut self, ident: &Ident) -> bool {
+        match self.lookup(&ident.name) {
+            Some(symbol) => {
+                self.table.resolutions.insert(ident.span, symbol);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<SymbolId> {
+        let mut outside_function = false;
+        for frame in self.frames.iter().rev() {
+            if let Some(symbol) = frame.bindings.get(name) {
+                if !outside_function || self.table.symbol(*symbol).is_callable() {
+                    return Some(*symbol);
+                }
+            }
+            outside_function |= frame.parameters;
+        }
+        None
+    }
+
+    /* The frames a name can be looked up in, up to the enclosing function */
+    fn visible_frames(&self) -> impl Iterator<Item = &Frame> {
+        let start = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.parameters)
+            .unwrap_or(0);
+        self.frames[start..].iter().rev()
+    }
+
+    /* A declaration of `ident` further down an enclosing block */
+    fn later_declaration(&self, ident: &Ident) -> Option<Span> {
+        self.visible_frames()
+            .flat_map(|frame| &frame.pending)
+            .find(|pending| pending.name == ident.name && pending.span.start >= ident.span.end)
+            .map(|pending| pending.span)
+    }
+
+    /* A variable `ident` could refer to if it were not used inside a function */
+    fn outer_variable(&self, ident: &Ident) -> Option<Span> {
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|frame| frame.bindings.get(&ident.name))
+            .map(|symbol| self.table.symbol(*symbol).span)
+    }
+
+    fn unknown_name(&mut self, ident: &Ident) {
+        if let Some(declaration) = self.later_declaration(ident) {
+            return self.used_before_declaration(ident, declaration);
+        }
+        if let Some(declaration) = self.outer_variable(ident) {
+            return self.captured(ident, declaration);
+        }
+        self.diagnostics.push(
+            Diagnostic::error(
+                format!("cannot find value `{}` in this scope", ident.name),
+                ident.span,
+            )
+            .with_code("E0005")
+            .with_label(ident.span, "not found in this scope"),
+        );
+    }
+
+    fn unknown_function(&mut self, callee: &Ident) {
+        self.diagnostics.push(
+            Diagnostic::error(
+                format!("cannot find function `{}` in this scope", callee.name),
+                callee.span,
+            )
+            .with_code("E0005")
+            .with_label(callee.span, "not found in this scope"),
+        );
+    }
+
+    fn undeclared_assignment(&mut self, target: &Ident) {
+        if let Some(declaration) = self.later_declaration(target) {
+            return self.used_before_declaration(target, declaration);
+        }
+        if let Some(declaration) = self.outer_variable(target) {
+            return self.captured(target, declaration);
+        }
+        self.diagnostics.push(
+            Diagnostic::error(
+                format!("cannot assign to undeclared variable `{}`", target.name),
+                target.span,
+            )
+            .with_code("E0011")
+            .with_label(target.span, "not declared in this scope")
+            .with_help(format!(
+                "declare it with `let {}: <type> = ...;` instead",
+                target.name
+            )),
+        );
+    }
+
+    fn captured(&mut self, ident: &Ident, declaration: Span) {
+        self.diagnostics.push(
+            Diagnostic::error(
+                format!("cannot use variable `{}` inside a function", ident.name),
+                ident.span,
+            )
+            .with_code("E0014")
+            .with_label(ident.span, "used inside a function")
+            .with_label(declaration, "declared outside of it")
+            .with_help(format!(
+                "pass `{}` to the function as a parameter",
+                ident.name
+            )),
+        );
+    }
+
+    fn used_before_declaration(&mut self, ident: &Ident, declaration: Span) {
+        self.diagnostics.push(
+            Diagnostic::error(
+                format!("cannot use `{}` before its declaration", ident.name),
+                ident.span,
+            )
+            .with_code("E0010")
+            .with_label(ident.span, "used here")
+            .with_label(declaration, "declared here"),
+        );
+    }
+}
+
+/* The names declared directly in a list of statements */
+fn declarations(stmts: &[Stmt]) -> Vec<Ident> {
+    stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Let { name, .. } => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+const SHADOWED: &str = "let a: int = 1;
+    let b: int = { let a: bool = true; let c: int = if a { 1 } else { 2 }; c };
+    while a < b { a = a + 1; c = 1; }
+    let d: int = e;
+    let e: int = 0;";
+
+#[cfg(test)]
+fn resolve(input: &str) -> Resolver {
+    let mut resolver = Resolver::new();
+    resolver.resolve_program(&crate::testing::pipeline::program(input));
+    resolver
+}
+
+#[test]
+fn reports_shadowing_and_unknown_names() {
+    let resolver = resolve(SHADOWED);
+    assert_eq!(
+        crate::testing::pipeline::codes(SHADOWED, resolver.diagnostics()),
+        vec![("W0001", "a"), ("E0011", "c"), ("E0010", "e")]
+    );
+}
+
+#[test]
+fn resolves_uses_to_the_nearest_declaration() {
+    let resolver = resolve(SHADOWED);
+    let table = resolver.table();
+    let outer = table.lookup(SymbolTable::GLOBAL, "a").unwrap();
+    let inner_block = SHADOWED.find("{ let").unwrap()..SHADOWED.find("c }").unwrap();
+    let uses = table.references(outer);
+    assert_eq!(uses.len(), 4);
+    for span in uses {
+        assert_eq!(&SHADOWED[span.start..span.end], "a");
+        assert!(!inner_block.contains(&span.start));
+    }
+
+    let inner_if = SHADOWED.find("if a").unwrap() + 3;
+    let scope = table.scope_at(FileId::default(), inner_if);
+    let inner = table
+        .resolution(Span::new(FileId::default(), inner_if, inner_if + 1))
+        .unwrap();
+    assert_eq!(table.lookup(scope, "a"), Some(inner));
+    assert_eq!(table.symbol(inner).ty(), Some(&Type::Bool));
+    assert_ne!(table.symbol(inner).scope, SymbolTable::GLOBAL);
+}
+
+#[test]
+fn scopes_loop_variables_to_the_loop() {
+    let input = "let b: int = 3; for i in 0..b { b = b + i; } i = 0;";
+    let resolver = resolve(input);
+    assert_eq!(
+        crate::testing::pipeline::codes(input, resolver.diagnostics()),
+        vec![("E0011", "i")]
+    );
+    let table = resolver.table();
+    let use_of_i = input.find("+ i").unwrap() + 2;
+    let i = table
+        .resolution(Span::new(FileId::default(), use_of_i, use_of_i + 1))
+        .unwrap();
+    assert!(matches!(table.symbol(i).kind, SymbolKind::LoopVariable));
+    assert_ne!(table.symbol(i).scope, SymbolTable::GLOBAL);
+}
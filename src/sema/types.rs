@@ -0,0 +1,82 @@
+use crate::ast::ast::Type;
+use std::fmt;
+
+/* The type of a value as seen by the checker. `Error` is given to
+ * expressions that already failed to check, and is compatible with every
+ * other type so that one mistake does not cascade into many. `Never` is
+ * the type of blocks that always return, which fit anywhere. `Range` is
+ * only ever the iterable of a `for` loop. */
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Ty {
+    Int,
+    Bool,
+    Str,
+    /* The empty list `[]` is a `[!]`, which fits any list */
+    List(Box<Ty>),
+    Unit,
+    Range,
+    Never,
+    Error,
+}
+
+impl Ty {
+    pub fn is_error(&self) -> bool {
+        *self == Ty::Error
+    }
+
+    /* The type of the loop variable when iterating over this type */
+    pub fn item(&self) -> Option<Ty> {
+        match self {
+            Ty::Range => Some(Ty::Int),
+            Ty::Str => Some(Ty::Str),
+            Ty::List(element) => Some((**element).clone()),
+            Ty::Error => Some(Ty::Error),
+            _ => None,
+        }
+    }
+
+    /* Whether a value of type `other` can be used where `self` is expected */
+    pub fn accepts(&self, other: &Ty) -> bool {
+        match (self, other) {
+            (Ty::List(expected), Ty::List(found)) => expected.accepts(found),
+            _ => self.is_error() || other.is_error() || *other == Ty::Never || self == other,
+        }
+    }
+
+    /* The type both `self` and `other` fit in, if there is one */
+    pub fn join(&self, other: &Ty) -> Option<Ty> {
+        if other.accepts(self) {
+            Some(other.clone())
+        } else if self.accepts(other) {
+            Some(self.clone())
+        } else {
+            None
+        }
+    }
+}
+
+impl From<&Type> for Ty {
+    fn from(ty: &Type) -> Ty {
+        match ty {
+            Type::Int => Ty::Int,
+            Type::Bool => Ty::Bool,
+            Type::Str => Ty::Str,
+            Type::List(element) => Ty::List(Box::new(Ty::from(&**element))),
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ty::Int => write!(f, "int"),
+            Ty::Bool => write!(f, "bool"),
+            Ty::Str => write!(f, "str"),
+            Ty::List(element) => write!(f, "[{}]", element),
+            Ty::Unit => write!(f, "()"),
+            Ty::Range => write!(f, "range"),
+            Ty::Never => write!(f, "!"),
+            Ty::Error => write!(f, "{{error}}"),
+        }
+    }
+}
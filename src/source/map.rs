@@ -0,0 +1,148 @@
+use super::span::{FileId, Span};
+use std::fmt;
+
+/* A position in a file. Lines and columns start at 0, `column` counts
+ * characters while the other two count UTF-8 bytes and UTF-16 code units. */
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    pub column_utf8: usize,
+    pub column_utf16: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.column + 1)
+    }
+}
+
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: String, source: String) -> SourceFile {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceFile { name, source, line_starts }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /* Index of the line containing the byte `offset` */
+    pub fn line_index(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        }
+    }
+
+    /* Text of a line without its line terminator */
+    pub fn line(&self, line: usize) -> &str {
+        let start = self.line_starts[line];
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map_or(self.source.len(), |next| next - 1);
+        self.source[start..end].trim_end_matches('\r')
+    }
+
+    pub fn location(&self, offset: usize) -> Location {
+        let offset = self.floor_char_boundary(offset);
+        let line = self.line_index(offset);
+        let before = &self.source[self.line_starts[line]..offset];
+        Location {
+            line,
+            column: before.chars().count(),
+            column_utf8: before.len(),
+            column_utf16: before.encode_utf16().count(),
+        }
+    }
+
+    /* Inverse of `location` for editors that address text in UTF-16 code
+     * units. Columns past the end of the line are clamped to it. */
+    pub fn offset_utf16(&self, line: usize, column_utf16: usize) -> usize {
+        if line >= self.line_count() {
+            return self.source.len();
+        }
+        let start = self.line_starts[line];
+        let mut units = 0;
+        for (i, c) in self.line(line).char_indices() {
+            if units >= column_utf16 {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        start + self.line(line).len()
+    }
+
+    fn floor_char_boundary(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+}
+
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap { files: vec![] }
+    }
+
+    pub fn add_file(&mut self, name: String, source: String) -> FileId {
+        self.files.push(SourceFile::new(name, source));
+        FileId(self.files.len() - 1)
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0]
+    }
+
+    pub fn location(&self, file: FileId, offset: usize) -> Location {
+        self.file(file).location(offset)
+    }
+
+    /* Locations of the first and one past the last character of `span` */
+    pub fn span_locations(&self, span: Span) -> (Location, Location) {
+        let file = self.file(span.file);
+        (file.location(span.start), file.location(span.end))
+    }
+
+    pub fn snippet(&self, span: Span) -> &str {
+        &self.file(span.file).source[span.start..span.end]
+    }
+}
+
+#[test]
+fn utf8_and_utf16_columns() {
+    let mut map = SourceMap::new();
+    let file = map.add_file("test.ch".into(), "let a: str = \"é\";\r\nlet 😀b\n".into());
+
+    let (start, end) = map.span_locations(Span::new(file, 4, 5));
+    assert_eq!((start.line, start.column), (0, 4));
+    assert_eq!((end.line, end.column), (0, 5));
+
+    let b = map.file(file).source.find('b').unwrap();
+    let location = map.location(file, b);
+    assert_eq!(location.line, 1);
+    assert_eq!(location.column, 5);
+    assert_eq!(location.column_utf8, 8);
+    assert_eq!(location.column_utf16, 6);
+    assert_eq!(location.to_string(), "2:6");
+
+    assert_eq!(map.file(file).line(0), "let a: str = \"é\";");
+    assert_eq!(map.file(file).offset_utf16(1, 6), b);
+    assert_eq!(map.file(file).offset_utf16(1, 100), b + 1);
+}
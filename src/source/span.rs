@@ -0,0 +1,59 @@
+use std::fmt;
+
+/* Identifies a file registered in a SourceMap */
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct FileId(pub usize);
+
+/* A half-open range of byte offsets, [start, end), into a single file */
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Span {
+    pub file: FileId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(file: FileId, start: usize, end: usize) -> Span {
+        debug_assert!(start <= end, "span starts after it ends");
+        Span { file, start, end }
+    }
+
+    /* An empty span right before `offset` */
+    pub fn point(file: FileId, offset: usize) -> Span {
+        Span::new(file, offset, offset)
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+
+    /* The same span `delta` bytes further along the file, or back */
+    pub fn shift(self, delta: isize) -> Span {
+        let moved = |offset: usize| {
+            offset
+                .checked_add_signed(delta)
+                .expect("span shifted before the start")
+        };
+        Span::new(self.file, moved(self.start), moved(self.end))
+    }
+
+    /* The smallest span covering both `self` and `other` */
+    pub fn to(self, other: Span) -> Span {
+        debug_assert_eq!(self.file, other.file, "joining spans of different files");
+        Span::new(self.file, self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
@@ -0,0 +1,187 @@
+use super::pipeline::check;
+use crate::ast::ast::Program;
+use crate::interp::interp::{on_large_stack, Interpreter, Result};
+use crate::interp::value::Value;
+use crate::sema::check::Checker;
+use crate::source::map::SourceMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/* Programs that every backend runs the same as the interpreter, each in
+ * a file called `test.ch` */
+
+/* Programs with only `int` and `bool` values, which every backend runs */
+pub const SCALARS: &[&str] = &[
+    "print(1 + 2 * 3); print(7 / 2); print(-7 / 2); print(-7 % 3); print(1 << 62 >> 60); 6 * 7;",
+    "print(true && !false); print(1 < 2 || 1 / 0 == 0); print(false || 1 < 2); print(3 != 3); 2 >= 2;",
+    "print(~5 & 12 | 1 ^ 3); print(-8 >> 1); print(-9223372036854775807 - 1); print(+4);
+    print(true == (!true)); print(!(2 > 1) || ~5 == -6 && -(3 - 5) >= +2);",
+    "def fib(n: int) -> int { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }
+    print(fib(20));",
+    "def gcd(a: int, b: int) -> int { while b != 0 { let t: int = b; b = a % b; a = t; } a }
+    print(gcd(1071, 462)); print(gcd(17, 5));",
+    "def sum(a: int, b: int, c: int, d: int, e: int, f: int, g: int, h: int) -> int {
+        a - b + c - d + e - f + g * 10 + h * 100
+    }
+    print(sum(1, 2, 3, 4, 5, 6, 7, 8)); print(1 + sum(8, 7, 6, 5, 4, 3, 2, 1));",
+    "def odd(n: int) -> bool { if n == 0 { return false; } even(n - 1) }
+    def even(n: int) -> bool { if n == 0 { return true; } odd(n - 1) }
+    print(even(10)); print(odd(7)); odd(4);",
+    "def sign(n: int) -> int { if n < 0 { return -1; } else { return 1; } }
+    print(sign(-4)); print(sign(4)); sign(0);",
+    "def find(n: int) -> int { let i: int = 0; while true { if i * i >= n { return i; } i = i + 1; } 0 }
+    print(find(50)); print(print(0));",
+    "let total: int = 0;
+    for i in 0..10 { if i % 2 == 0 { continue_with(i); } total = total + i; }
+    def continue_with(i: int) { print(i); }
+    print(total);
+    let x: int = { let x: int = 3; x * x }; print(x);
+    { let y: int = if x > 5 { x } else { 0 }; y + 1 }",
+    "let n: int = 4; def scaled(k: int) -> int { k * 3 } print(scaled(n));
+    let n: bool = true; print(n); print(print(0));",
+    "def fact(n: int) -> int { if n < 2 { return 1; } n * fact(n - 1) }
+    def down(n: int) -> int { if n == 0 { 0 } else { down(n - 1) } }
+    print(fact(20)); print(down(200)); down(20000);",
+    "def f() { print(1); return; } f(); { }",
+    "let i: int = 9223372036854775807; print(i); i + 1;",
+    "print(1); -(-9223372036854775807 - 1);",
+    "let zero: int = 0; print(10 / zero);",
+    "print(1); 10 % (2 - 2);",
+    "(-9223372036854775807 - 1) / -1;",
+    "(-9223372036854775807 - 1) - 1;",
+    "let a: int = 1 << 64;",
+    "let a: int = 64; 1 << a;",
+    "let a: int = -1; print(1 >> a);",
+    "3037000500 * 3037000500;",
+    "3037000500 * -3037000500;",
+    "print(-1 * (-9223372036854775807 - 1));",
+    "def down(n: int) -> int { down(n + 1) + 1 } print(down(0));",
+];
+
+/* Programs that use strings as well */
+pub const STRINGS: &[&str] = &[
+    "print(\"b\" > \"abc\"); print(\"abc\" < \"abd\" && \"b\" >= \"ab\" || false); \"x\" == \"x\";",
+    "def greet(name: str) -> str { \"hello, \" + name + \"!\" }
+    let who: str = \"wörld\"; print(greet(who)); print(len(who)); print(who[1]);
+    print(who[1..4]); print(\"{who} has {len(who)} letters, {len(who) > 3}\");",
+    "let s: str = \"\"; for c in \"héllo\" { s = c + s; } print(s);
+    let total: int = 0; for i in 0..10 { total = total + i; } total;",
+    "def f(a: int) -> int { print(a); a } print(f(1) - f(2) * f(3));
+    let x: int = { let y: int = f(4); y * 2 }; print(if x > 5 { \"big\" } else { \"small\" });",
+    "let n: int = 3; let n: bool = n > 2; { print(n); let n: str = \"shadowed\"; n }",
+    "let n: int = 10;
+    let a: int = 0;
+    let b: int = 1;
+    while n > 0 { let c: int = a + b; a = b; b = c; n = n - 1; }
+    let big: bool = { let limit: int = 50; if a > limit { true } else { false } };
+    let s: str = if big && a % 5 == 0 { \"big\" } else { \"small\" };
+    print(s + \" \" + \"{a}\");
+    { a * 2 }",
+    "def find(s: str, c: str) -> int {
+        let i: int = 0;
+        for x in s { if x == c { return i; } i = i + 1; }
+        0 - 1
+    }
+    let sum: int = 0;
+    for i in 1..find(\"cheetah\", \"t\") + 1 { for j in 0..i { sum = sum + j; } i = 0; }
+    print(sum);
+    print(1 + { let x: int = 2; let y: int = { let z: int = x * 3; z + 1 }; x + y });",
+    "\"abc\"[3];",
+    "\"abc\"[2..1];",
+    "let s: str = \"abc\"; let t: str = s[2..4];",
+];
+
+/* Programs that use lists as well */
+pub const LISTS: &[&str] = &[
+    "let xs: [[int]] = [[1, 2], [3]];
+    let ys: [[int]] = xs;
+    ys[1] = [4, 5];
+    xs[0][1] = len(ys[1]) * 10;
+    for row in xs { for x in row { print(x); } }
+    xs;",
+    "let name: str = \"wörld\";
+    let xs: [str] = [name[1..3], name[4..5]];
+    let s: str = \"{name[0]}{\"}\"} {xs} {len(name) > 4}\\n\" + \"ok\";
+    print(s);
+    print((7 ^ 2) | (12 & 10) << 1 >> 2);",
+    "let xs: [int] = [1]; let i: int = 0 - 1; xs[i] = 2;",
+];
+
+/* What a program printed, and the error it stopped with the way the
+ * compiled programs write it to stderr */
+pub type Outcome = (String, Option<String>);
+
+/* What a run printed and how it ended, as an `Outcome`. A value the
+ * program ended with is printed like the REPL would. */
+pub fn outcome(mut output: String, result: Result<Value>, sources: &SourceMap) -> Outcome {
+    let error = match result {
+        Ok(Value::Unit) => None,
+        Ok(value) => {
+            output += &format!("{}\n", value);
+            None
+        }
+        Err(error) => Some(format!(
+            "error: {}\n --> test.ch:{}\n",
+            error.message,
+            sources.location(error.primary_span.file, error.primary_span.start)
+        )),
+    };
+    (output, error)
+}
+
+/* Runs every program with the interpreter and with `run`, which returns
+ * what it printed and the value or error it ended with */
+pub fn compare(programs: &[&str], mut run: impl FnMut(&Program) -> (String, Result<Value>)) {
+    compare_outcomes(programs, |_, program, _, sources| {
+        let (output, result) = run(program);
+        outcome(output, result, sources)
+    });
+}
+
+/* Runs every program with the interpreter and with `run`, which gets the
+ * index of the program and the checked program, and compares what they
+ * print and how they fail */
+pub fn compare_outcomes(
+    programs: &[&str],
+    mut run: impl FnMut(usize, &Program, &Checker, &SourceMap) -> Outcome,
+) {
+    for (i, input) in programs.iter().enumerate() {
+        /* The first file of a map has the id the front end gives spans */
+        let mut sources = SourceMap::new();
+        sources.add_file("test.ch".to_string(), input.to_string());
+        let (program, checker) = check(input);
+        assert!(!checker.has_errors(), "{:?}", checker.diagnostics());
+
+        let expected = on_large_stack(|| {
+            let mut interpreter = Interpreter::capturing();
+            let result = interpreter.run(&program);
+            outcome(interpreter.take_output(), result, &sources)
+        });
+        assert_eq!(run(i, &program, &checker, &sources), expected, "{}", input);
+    }
+}
+
+/* Runs a compiled program and reads its `Outcome` from what it printed
+ * and its exit code. Run again with both streams going to one file, the
+ * output has to come before the error. */
+pub fn executable(path: &Path) -> Outcome {
+    let ran = Command::new(path).output().unwrap();
+    let (out, err) = (
+        String::from_utf8(ran.stdout).unwrap(),
+        String::from_utf8(ran.stderr).unwrap(),
+    );
+    let both = path.with_extension("out");
+    let file = fs::File::create(&both).unwrap();
+    Command::new(path)
+        .stdout(file.try_clone().unwrap())
+        .stderr(file)
+        .status()
+        .unwrap();
+    assert_eq!(fs::read_to_string(&both).unwrap(), out.clone() + &err);
+    match ran.status.code() {
+        Some(0) if err.is_empty() => (out, None),
+        Some(3) => (out, Some(err)),
+        code => panic!("exited with {:?}\n{}", code, err),
+    }
+}
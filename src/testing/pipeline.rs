@@ -0,0 +1,46 @@
+use crate::ast::ast::Program;
+use crate::ast::lower::lower;
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::lexer::lex::Lexer;
+use crate::parser::node::Node;
+use crate::parser::parser::Parser;
+use crate::sema::check::Checker;
+
+/* Takes test programs through the front end, stopping at the first stage
+ * a test is about. Every stage before it has to succeed. */
+
+pub fn parse(input: &str, pratt: bool) -> Node {
+    let mut parser = Parser::new(Lexer::new(input.to_string()));
+    let tree = parser.parse(pratt).unwrap();
+    assert!(!parser.has_errors(), "{:?}", parser.diagnostics());
+    tree
+}
+
+pub fn program(input: &str) -> Program {
+    lower(&parse(input, false)).unwrap()
+}
+
+/* The checker is returned whether the program type checks or not */
+pub fn check(input: &str) -> (Program, Checker) {
+    let program = program(input);
+    let mut checker = Checker::new();
+    checker.check_program(&program);
+    (program, checker)
+}
+
+pub fn checked(input: &str) -> Program {
+    let (program, checker) = check(input);
+    assert!(!checker.has_errors(), "{:?}", checker.diagnostics());
+    program
+}
+
+/* The code of every diagnostic along with the source it points at */
+pub fn codes<'a>(input: &'a str, diagnostics: &[Diagnostic]) -> Vec<(&'static str, &'a str)> {
+    diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let span = diagnostic.primary_span;
+            (diagnostic.code.unwrap(), &input[span.start..span.end])
+        })
+        .collect()
+}
@@ -0,0 +1,2 @@
+#[allow(clippy::module_inception, reason = "the stack machine, named like the other stages")]
+pub mod vm;
@@ -0,0 +1,291 @@
+use crate::ast::ast::BinOp;
+use crate::bytecode::bytecode::{Module, Op};
+use crate::diagnostics::diagnostic::Diagnostic;
+use crate::interp::interp::{self, Result, MAX_CALL_DEPTH};
+use crate::interp::value::Value;
+use std::io::Write;
+
+/* Where `print` writes to */
+enum Output {
+    Stdout,
+    Captured(String),
+}
+
+/* Where a caller left off: its function, the instruction after the call,
+ * and the bottom of its frame on the stack */
+struct Frame {
+    function: usize,
+    ip: usize,
+    base: usize,
+}
+
+/* Runs compiled modules on a stack of values. It behaves like the tree
+ * walking interpreter, runtime errors included, but calls do not nest on
+ * the Rust stack, so it needs no large one. */
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    globals: Vec<Value>,
+    output: Output,
+}
+
+impl Default for Vm {
+    fn default() -> Vm {
+        Vm::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        Vm {
+            stack: vec![],
+            frames: vec![],
+            globals: vec![],
+            output: Output::Stdout,
+        }
+    }
+
+    /* A vm that keeps what the program prints, see `take_output` */
+    pub fn capturing() -> Vm {
+        Vm {
+            output: Output::Captured(String::new()),
+            ..Vm::new()
+        }
+    }
+
+    pub fn take_output(&mut self) -> String {
+        match &mut self.output {
+            Output::Captured(output) => std::mem::take(output),
+            Output::Stdout => String::new(),
+        }
+    }
+
+    pub fn global(&self, module: &Module, name: &str) -> Option<&Value> {
+        let slot = module.globals.iter().position(|global| global == name)?;
+        self.globals.get(slot)
+    }
+
+    /* Runs the script of `module`, returning its value */
+    pub fn run(&mut self, module: &Module) -> Result<Value> {
+        self.stack.clear();
+        self.frames.clear();
+        self.globals = vec![Value::Unit; module.globals.len()];
+        let result = self.execute(module);
+        self.stack.clear();
+        self.frames.clear();
+        result
+    }
+
+    fn execute(&mut self, module: &Module) -> Result<Value> {
+        let (mut function, mut ip, mut base) = (Module::SCRIPT, 0, 0);
+        loop {
+            let chunk = &module.functions[function].chunk;
+            let at = ip;
+            let op = Op::from_byte(chunk.code[at]).expect("the compiler only writes valid code");
+            ip += 1 + op.operand_size();
+            let operand = || chunk.u16(at + 1) as usize;
+
+            match op {
+                Op::CONSTANT => self.stack.push(chunk.constants[operand()].clone()),
+                Op::UNIT => self.stack.push(Value::Unit),
+                Op::TRUE => self.stack.push(Value::Bool(true)),
+                Op::FALSE => self.stack.push(Value::Bool(false)),
+                Op::POP => {
+                    self.pop();
+                }
+                Op::POP_N => {
+                    let len = self.stack.len() - operand();
+                    self.stack.truncate(len);
+                }
+                Op::SLIDE => {
+                    let top = self.pop();
+                    let len = self.stack.len() - operand();
+                    self.stack.truncate(len);
+                    self.stack.push(top);
+                }
+                Op::GET_LOCAL => self.stack.push(self.stack[base + operand()].clone()),
+                Op::SET_LOCAL => {
+                    let value = self.pop();
+                    self.stack[base + operand()] = value;
+                }
+                Op::GET_GLOBAL => self.stack.push(self.globals[operand()].clone()),
+                Op::SET_GLOBAL => self.globals[operand()] = self.pop(),
+
+                Op::NOT => {
+                    let value = self.pop().as_bool();
+                    self.stack.push(Value::Bool(!value));
+                }
+                Op::BIT_NOT => {
+                    let value = self.pop().as_int();
+                    self.stack.push(Value::Int(!value));
+                }
+                Op::NEG => match self.pop().as_int().checked_neg() {
+                    Some(value) => self.stack.push(Value::Int(value)),
+                    None => return Err(interp::overflow("negate", chunk.spans(at).0)),
+                },
+
+                Op::JUMP => ip = ip.wrapping_add_signed(chunk.i32(at + 1) as isize),
+                Op::JUMP_IF_FALSE => {
+                    if !self.pop().as_bool() {
+                        ip = ip.wrapping_add_signed(chunk.i32(at + 1) as isize);
+                    }
+                }
+                Op::CALL => {
+                    if self.frames.len() == MAX_CALL_DEPTH {
+                        let span = chunk.spans(at).0;
+                        let name = &module.functions[operand()].name;
+                        let error = Diagnostic::error("stack overflow", span)
+                            .with_label(span, format!("`{}` was called here", name))
+                            .with_note(format!("calls may only be nested {} deep", MAX_CALL_DEPTH));
+                        return Err(Box::new(error));
+                    }
+                    self.frames.push(Frame { function, ip, base });
+                    function = operand();
+                    ip = 0;
+                    base = self.stack.len() - module.functions[function].arity;
+                }
+                Op::RETURN => {
+                    let value = self.pop();
+                    self.stack.truncate(base);
+                    let Some(caller) = self.frames.pop() else {
+                        return Ok(value);
+                    };
+                    self.stack.push(value);
+                    (function, ip, base) = (caller.function, caller.ip, caller.base);
+                }
+                Op::PRINT => {
+                    let line = format!("{}\n", self.pop());
+                    match &mut self.output {
+                        Output::Stdout => std::io::stdout()
+                            .write_all(line.as_bytes())
+                            .expect("could not write to stdout"),
+                        Output::Captured(output) => output.push_str(&line),
+                    }
+                    self.stack.push(Value::Unit);
+                }
+                Op::LEN => {
+                    let len = match self.pop() {
+                        Value::Str(value) => value.chars().count(),
+                        Value::List(elements) => elements.borrow().len(),
+                        value => panic!("cannot take the length of {:?}", value),
+                    };
+                    self.stack.push(Value::Int(len as i64));
+                }
+
+                Op::LIST => {
+                    let elements = self.stack.split_off(self.stack.len() - operand());
+                    self.stack.push(Value::list(elements));
+                }
+                Op::TEMPLATE => {
+                    let parts = self.stack.split_off(self.stack.len() - operand());
+                    let text = parts.iter().map(ToString::to_string).collect();
+                    self.stack.push(Value::Str(text));
+                }
+                Op::INDEX => {
+                    let i = self.pop().as_int();
+                    let span = chunk.spans(at).0;
+                    let value = match self.pop() {
+                        Value::List(elements) => {
+                            let elements = elements.borrow();
+                            elements[interp::position(i, elements.len(), span)?].clone()
+                        }
+                        Value::Str(value) => {
+                            let len = value.chars().count();
+                            let c = value.chars().nth(interp::position(i, len, span)?);
+                            Value::Str(c.expect("checked by position").to_string())
+                        }
+                        value => panic!("cannot index into {:?}", value),
+                    };
+                    self.stack.push(value);
+                }
+                Op::SLICE => {
+                    let end = self.pop().as_int();
+                    let start = self.pop().as_int();
+                    let list = self.pop();
+                    let slice = interp::slice(list, start, end, chunk.spans(at).0)?;
+                    self.stack.push(slice);
+                }
+                Op::SET_INDEX => {
+                    let i = self.pop().as_int();
+                    let Value::List(elements) = self.pop() else {
+                        panic!("only lists can be assigned to by index")
+                    };
+                    let value = self.pop();
+                    let len = elements.borrow().len();
+                    let slot = interp::position(i, len, chunk.spans(at).0)?;
+                    elements.borrow_mut()[slot] = value;
+                }
+                Op::ITER => {
+                    let items = self.pop().items();
+                    self.stack.push(Value::list(items));
+                }
+
+                op => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    /* Integers that do not overflow need none of the checks */
+                    let value = match (op, &left, &right) {
+                        (Op::ADD, Value::Int(a), Value::Int(b)) => {
+                            a.checked_add(*b).map(Value::Int)
+                        }
+                        (Op::SUB, Value::Int(a), Value::Int(b)) => {
+                            a.checked_sub(*b).map(Value::Int)
+                        }
+                        (Op::LT, Value::Int(a), Value::Int(b)) => Some(Value::Bool(a < b)),
+                        (Op::GT, Value::Int(a), Value::Int(b)) => Some(Value::Bool(a > b)),
+                        _ => None,
+                    };
+                    let value = match value {
+                        Some(value) => value,
+                        None => {
+                            let (span, rhs) = chunk.spans(at);
+                            interp::binary(binary(op), left, right, rhs, span)?
+                        }
+                    };
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack
+            .pop()
+            .expect("the compiler keeps the stack balanced")
+    }
+}
+
+fn binary(op: Op) -> BinOp {
+    match op {
+        Op::ADD => BinOp::Add,
+        Op::SUB => BinOp::Sub,
+        Op::MUL => BinOp::Mul,
+        Op::DIV => BinOp::Div,
+        Op::MOD => BinOp::Mod,
+        Op::BIT_AND => BinOp::BitAnd,
+        Op::BIT_OR => BinOp::BitOr,
+        Op::BIT_XOR => BinOp::BitXor,
+        Op::SHL => BinOp::Shl,
+        Op::SHR => BinOp::Shr,
+        Op::EQ => BinOp::Eq,
+        Op::NE => BinOp::Ne,
+        Op::LT => BinOp::Lt,
+        Op::LE => BinOp::Le,
+        Op::GT => BinOp::Gt,
+        Op::GE => BinOp::Ge,
+        op => unreachable!("{:?} is not a binary operator", op),
+    }
+}
+
+#[test]
+fn runs_like_the_interpreter() {
+    use crate::bytecode::compile::compile;
+    use crate::testing::differential::{compare, LISTS, SCALARS, STRINGS};
+
+    compare(&[SCALARS, STRINGS, LISTS].concat(), |program| {
+        let module = compile(program).unwrap();
+        let mut vm = Vm::capturing();
+        let result = vm.run(&module);
+        (vm.take_output(), result)
+    });
+}